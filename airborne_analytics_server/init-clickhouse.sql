@@ -20,6 +20,7 @@ CREATE TABLE IF NOT EXISTS ota_events_raw
 
     -- Release information
     releaseId           Nullable(String),
+    variantId           Nullable(String),
     currentJsVersion    Nullable(String),
     targetJsVersion     Nullable(String),
     rolloutPercentage   Nullable(UInt8),
@@ -48,13 +49,62 @@ CREATE TABLE IF NOT EXISTS ota_events_raw
     ipAddress           Nullable(String),
 
     -- Ingestion metadata
-    ingestedAt          DateTime64(3, 'UTC') DEFAULT now64(3)
+    ingestedAt          DateTime64(3, 'UTC') DEFAULT now64(3),
+    -- Set per organisation at insert time (RETENTION_DAYS, RETENTION_ORG_DAYS),
+    -- and updated on startup when the policy changed
+    retentionDays       UInt16 DEFAULT 365,
+
+    -- Device timelines look up one device at a time
+    INDEX idx_device_id deviceId TYPE bloom_filter(0.01) GRANULARITY 4
+)
+-- One row per event id; a retried event is folded into the stored one when
+-- parts merge, and reads use FINAL until then. The key and the (single)
+-- partition leave out the timestamp, which a retry may carry differently.
+-- Tables created with another engine or key are rebuilt on startup
+ENGINE = ReplacingMergeTree
+ORDER BY (orgId, appId, eventId)
+TTL eventDate + toIntervalDay(retentionDays)
+SETTINGS index_granularity = 8192, non_replicated_deduplication_window = 1000;
+
+-- Lets a retried insert with the same deduplication token be dropped, for
+-- tables created before the setting above was added
+ALTER TABLE ota_events_raw MODIFY SETTING non_replicated_deduplication_window = 1000;
+
+-- Same device index for tables created before it was added. Parts written
+-- earlier are indexed as they merge, or at once with
+-- ALTER TABLE ota_events_raw MATERIALIZE INDEX idx_device_id
+ALTER TABLE ota_events_raw ADD INDEX IF NOT EXISTS idx_device_id deviceId TYPE bloom_filter(0.01) GRANULARITY 4;
+
+-- Per-organisation retention for tables created before it was added. Rows
+-- stored earlier get their organisation's window on startup
+ALTER TABLE ota_events_raw ADD COLUMN IF NOT EXISTS retentionDays UInt16 DEFAULT 365 AFTER ingestedAt;
+
+-- Experiment variants, for tables created before they were recorded
+ALTER TABLE ota_events_raw ADD COLUMN IF NOT EXISTS variantId Nullable(String) AFTER releaseId;
+ALTER TABLE ota_events_raw MODIFY TTL eventDate + toIntervalDay(retentionDays);
+
+-- One row per state of a device erasure request; the latest wins. Devices
+-- are identified by a digest so the receipt does not retain the erased id
+CREATE TABLE IF NOT EXISTS erasure_receipts
+(
+    receiptId           UUID,
+    orgId               String,
+    appId               String,
+    deviceIdSha256      String,
+    requestedBy         Nullable(String),
+    reason              Nullable(String),
+    status              String,
+    eventsDeleted       Nullable(UInt64),
+    error               Nullable(String),
+    requestedAt         DateTime64(3, 'UTC'),
+    updatedAt           DateTime64(3, 'UTC')
 )
-ENGINE = MergeTree
-PARTITION BY toYYYYMM(eventDate)
-ORDER BY (orgId, appId, eventType, timestamp)
-TTL eventDate + INTERVAL 365 DAY  -- Keep raw data for 1 year
-SETTINGS index_granularity = 8192;
+ENGINE = ReplacingMergeTree(updatedAt)
+ORDER BY (orgId, appId, receiptId);
+
+-- Ids of stored events used to be kept here; ota_events_raw deduplicates
+-- by id itself now
+DROP TABLE IF EXISTS ota_event_ids;
 
 -- =============================================================================
 -- 2. MATERIALIZED VIEWS FOR ANALYTICS
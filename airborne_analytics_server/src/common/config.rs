@@ -1,21 +1,29 @@
-use std::env;
+use std::{collections::HashMap, env};
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use crate::common::models::LoggingInfra;
+use crate::common::models::{ExportFormat, LoggingInfra};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub server: ServerConfig,
     pub kafka: KafkaConfig,
     pub clickhouse: ClickHouseConfig,
+    pub ingest: IngestConfig,
+    pub query_auth: QueryAuthConfig,
+    pub privacy: PrivacyConfig,
+    pub alerts: AlertsConfig,
+    pub export: ExportConfig,
     pub logging_infrastructure: LoggingInfra, // "kafka-clickhouse" or "victoria-metrics" (default: "victoria-metrics")
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub port: u16,
+    /// Bearer token for the `/analytics/admin` endpoints; they are disabled
+    /// without one.
+    pub admin_token: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +35,10 @@ pub struct KafkaConfig {
     pub sasl_mechanisms: Option<String>,
     pub sasl_username: Option<String>,
     pub sasl_password: Option<String>,
+    /// Topic receiving messages the consumer could not parse or store.
+    pub dlq_topic: String,
+    /// Attempts at storing a batch before its messages are dead-lettered.
+    pub batch_max_attempts: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,6 +49,155 @@ pub struct ClickHouseConfig {
     pub password: Option<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestConfig {
+    /// Reject events that do not carry a valid ingest key.
+    pub require_key: bool,
+    /// Airborne server endpoint that resolves ingest keys.
+    pub verify_url: Option<String>,
+    pub verify_token: Option<String>,
+    pub key_cache_ttl_secs: u64,
+    /// Used for keys that do not set their own limit.
+    pub key_rate_limit_per_minute: u32,
+    pub device_rate_limit_per_minute: u32,
+    /// How far a signed request's timestamp may be from the server's clock.
+    pub signature_tolerance_secs: i64,
+    pub max_batch_events: usize,
+    /// Older events, after clock-skew correction, are rejected.
+    pub max_event_age_secs: i64,
+    /// How long the VictoriaMetrics path remembers event ids to drop retries.
+    pub dedup_window_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryAuthConfig {
+    /// Require a bearer token with `analytics.read` on the queried app.
+    pub require_auth: bool,
+    /// Airborne server's `/authz/me/enforce-batch` endpoint.
+    pub authz_url: Option<String>,
+    pub cache_ttl_secs: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AlertsConfig {
+    /// Serve and evaluate alert rules on this instance.
+    pub enabled: bool,
+    /// Rules, silences and alert state, for a single instance.
+    pub store_path: String,
+    /// PostgreSQL shared by all replicas; replaces `store_path`, and one
+    /// replica at a time evaluates the rules.
+    pub database_url: Option<String>,
+    pub evaluation_interval_secs: u64,
+    /// Deliveries of one notification to one webhook before it is dropped.
+    pub webhook_max_attempts: u32,
+    pub webhook_timeout_secs: u64,
+    /// Signs webhook bodies in `x-airborne-signature`, like signed ingest.
+    pub webhook_secret: Option<String>,
+}
+
+// The webhook secret and database credentials are left out of the logged
+// configuration.
+impl std::fmt::Debug for AlertsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AlertsConfig")
+            .field("enabled", &self.enabled)
+            .field("store_path", &self.store_path)
+            .field("database", &self.database_url.is_some())
+            .field("evaluation_interval_secs", &self.evaluation_interval_secs)
+            .field("webhook_max_attempts", &self.webhook_max_attempts)
+            .field("webhook_timeout_secs", &self.webhook_timeout_secs)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportConfig {
+    /// Bucket the daily export job writes to; the job is off without one.
+    pub s3_bucket: Option<String>,
+    pub s3_prefix: String,
+    pub format: ExportFormat,
+    /// For S3-compatible stores, e.g. localstack or MinIO.
+    pub s3_force_path_style: bool,
+}
+
+/// How client IP addresses are stored.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    strum_macros::Display,
+    strum_macros::EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum IpMode {
+    Full,
+    /// Keep the /24 of IPv4 and the /48 of IPv6 addresses.
+    Truncate,
+    /// Keep a salted SHA-256 of the address.
+    Hash,
+    Drop,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PrivacyConfig {
+    /// How long events are kept, unless their organisation has its own window.
+    pub retention_days: u16,
+    pub org_retention_days: HashMap<String, u16>,
+    pub ip_mode: IpMode,
+    pub ip_hash_salt: Option<String>,
+}
+
+impl PrivacyConfig {
+    pub fn retention_days_for(&self, org_id: &str) -> u16 {
+        self.org_retention_days
+            .get(org_id)
+            .copied()
+            .unwrap_or(self.retention_days)
+    }
+}
+
+// The salt is a secret, so it is left out of the logged configuration.
+impl std::fmt::Debug for PrivacyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PrivacyConfig")
+            .field("retention_days", &self.retention_days)
+            .field("org_retention_days", &self.org_retention_days)
+            .field("ip_mode", &self.ip_mode)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Parses `org=days` pairs separated by commas, e.g. `acme=90,globex=30`.
+fn parse_org_retention(name: &str) -> HashMap<String, u16> {
+    env::var(name)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    pair.split_once('=')
+                        .and_then(|(org, days)| {
+                            Some((org.trim().to_string(), days.trim().parse().ok()?))
+                        })
+                        .unwrap_or_else(|| panic!("{} must look like org=days,org=days", name))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn parse_env<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name).map_or(default, |v| {
+        v.parse()
+            .unwrap_or_else(|_| panic!("{} must be a valid value", name))
+    })
+}
+
 impl Config {
     pub fn load() -> Result<Self> {
         dotenv::dotenv().ok();
@@ -46,6 +207,7 @@ impl Config {
                 port: env::var("SERVER_PORT").map_or(6400, |v| {
                     v.parse().expect("SERVER_PORT must be a valid number")
                 }),
+                admin_token: env::var("ANALYTICS_ADMIN_TOKEN").ok(),
             },
             kafka: KafkaConfig {
                 brokers: env::var("KAFKA_BROKERS").unwrap_or_else(|_| "localhost:9092".to_string()),
@@ -56,6 +218,9 @@ impl Config {
                 sasl_mechanisms: env::var("KAFKA_SASL_MECHANISMS").ok(),
                 sasl_username: env::var("KAFKA_SASL_USERNAME").ok(),
                 sasl_password: env::var("KAFKA_SASL_PASSWORD").ok(),
+                dlq_topic: env::var("KAFKA_DLQ_TOPIC")
+                    .unwrap_or_else(|_| "ota-events-dlq".to_string()),
+                batch_max_attempts: parse_env("KAFKA_BATCH_MAX_ATTEMPTS", 3),
             },
             clickhouse: ClickHouseConfig {
                 url: env::var("CLICKHOUSE_URL")
@@ -65,6 +230,46 @@ impl Config {
                 username: env::var("CLICKHOUSE_USERNAME").ok(),
                 password: env::var("CLICKHOUSE_PASSWORD").ok(),
             },
+            ingest: IngestConfig {
+                require_key: parse_env("INGEST_REQUIRE_KEY", true),
+                verify_url: env::var("INGEST_KEY_VERIFY_URL").ok(),
+                verify_token: env::var("INGEST_KEY_VERIFY_TOKEN").ok(),
+                key_cache_ttl_secs: parse_env("INGEST_KEY_CACHE_TTL_SECS", 60),
+                key_rate_limit_per_minute: parse_env("INGEST_KEY_RATE_LIMIT_PER_MINUTE", 6000),
+                device_rate_limit_per_minute: parse_env("INGEST_DEVICE_RATE_LIMIT_PER_MINUTE", 120),
+                signature_tolerance_secs: parse_env("INGEST_SIGNATURE_TOLERANCE_SECS", 300),
+                max_batch_events: parse_env("INGEST_MAX_BATCH_EVENTS", 500),
+                max_event_age_secs: parse_env("INGEST_MAX_EVENT_AGE_SECS", 7 * 24 * 60 * 60),
+                dedup_window_secs: parse_env("INGEST_DEDUP_WINDOW_SECS", 24 * 60 * 60),
+            },
+            query_auth: QueryAuthConfig {
+                require_auth: parse_env("QUERY_REQUIRE_AUTH", true),
+                authz_url: env::var("QUERY_AUTHZ_URL").ok(),
+                cache_ttl_secs: parse_env("QUERY_AUTHZ_CACHE_TTL_SECS", 30),
+            },
+            privacy: PrivacyConfig {
+                retention_days: parse_env("RETENTION_DAYS", 365),
+                org_retention_days: parse_org_retention("RETENTION_ORG_DAYS"),
+                ip_mode: parse_env("INGEST_IP_MODE", IpMode::Full),
+                ip_hash_salt: env::var("INGEST_IP_HASH_SALT").ok(),
+            },
+            alerts: AlertsConfig {
+                enabled: parse_env("ALERTS_ENABLED", true),
+                store_path: env::var("ALERTS_STORE_PATH")
+                    .unwrap_or_else(|_| "alerts.json".to_string()),
+                database_url: env::var("ALERTS_DATABASE_URL").ok(),
+                evaluation_interval_secs: parse_env("ALERTS_EVALUATION_INTERVAL_SECS", 60),
+                webhook_max_attempts: parse_env("ALERTS_WEBHOOK_MAX_ATTEMPTS", 5),
+                webhook_timeout_secs: parse_env("ALERTS_WEBHOOK_TIMEOUT_SECS", 10),
+                webhook_secret: env::var("ALERTS_WEBHOOK_SECRET").ok(),
+            },
+            export: ExportConfig {
+                s3_bucket: env::var("EXPORT_S3_BUCKET").ok(),
+                s3_prefix: env::var("EXPORT_S3_PREFIX")
+                    .unwrap_or_else(|_| "ota-analytics".to_string()),
+                format: parse_env("EXPORT_FORMAT", ExportFormat::Parquet),
+                s3_force_path_style: parse_env("EXPORT_S3_FORCE_PATH_STYLE", false),
+            },
             logging_infrastructure: env::var("LOGGING_INFRASTRUCTURE")
                 .map_or(Ok(LoggingInfra::VictoriaMetrics), |v| {
                     v.parse::<LoggingInfra>()
@@ -72,6 +277,28 @@ impl Config {
                 .map_err(anyhow::Error::msg)?,
         };
 
+        if config.ingest.require_key
+            && (config.ingest.verify_url.is_none() || config.ingest.verify_token.is_none())
+        {
+            anyhow::bail!(
+                "INGEST_KEY_VERIFY_URL and INGEST_KEY_VERIFY_TOKEN must be set when INGEST_REQUIRE_KEY is enabled"
+            );
+        }
+
+        if config.query_auth.require_auth && config.query_auth.authz_url.is_none() {
+            anyhow::bail!("QUERY_AUTHZ_URL must be set when QUERY_REQUIRE_AUTH is enabled");
+        }
+
+        if config.privacy.ip_mode == IpMode::Hash && config.privacy.ip_hash_salt.is_none() {
+            anyhow::bail!("INGEST_IP_HASH_SALT must be set when INGEST_IP_MODE is hash");
+        }
+
+        if config.alerts.evaluation_interval_secs == 0 || config.alerts.webhook_max_attempts == 0 {
+            anyhow::bail!(
+                "ALERTS_EVALUATION_INTERVAL_SECS and ALERTS_WEBHOOK_MAX_ATTEMPTS must be positive"
+            );
+        }
+
         Ok(config)
     }
 }
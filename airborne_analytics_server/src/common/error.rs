@@ -1,5 +1,5 @@
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -32,6 +32,18 @@ pub enum AppError {
     #[error("Not found: {0}")]
     NotFound(String),
 
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Rate limit exceeded, retry in {0}s")]
+    RateLimited(u64),
+
+    #[error("Not supported: {0}")]
+    Unsupported(String),
+
     #[error("Internal server error: {0}")]
     Internal(String),
 }
@@ -46,6 +58,10 @@ impl IntoResponse for AppError {
             AppError::Config(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Configuration error"),
             AppError::Validation(_) => (StatusCode::BAD_REQUEST, "Validation error"),
             AppError::NotFound(_) => (StatusCode::NOT_FOUND, "Resource not found"),
+            AppError::Unauthorized(_) => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+            AppError::Forbidden(_) => (StatusCode::FORBIDDEN, "Forbidden"),
+            AppError::RateLimited(_) => (StatusCode::TOO_MANY_REQUESTS, "Too many requests"),
+            AppError::Unsupported(_) => (StatusCode::NOT_IMPLEMENTED, "Not supported"),
             AppError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
         };
 
@@ -57,7 +73,13 @@ impl IntoResponse for AppError {
             code: status.as_u16(),
         };
 
-        (status, Json(error_response)).into_response()
+        let mut response = (status, Json(error_response)).into_response();
+        if let AppError::RateLimited(retry_after_secs) = self {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+        }
+        response
     }
 }
 
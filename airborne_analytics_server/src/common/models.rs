@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 use axum::{
     http::StatusCode,
@@ -12,7 +12,7 @@ use uuid::Uuid;
 
 use crate::{
     common::config::Config,
-    core::{clickhouse, victoria},
+    core::{access, alerts, clickhouse, ingest, victoria},
     kafka,
 };
 
@@ -21,6 +21,10 @@ pub struct AppState {
     pub clickhouse: Option<Arc<clickhouse::Client>>,
     pub victoria: Option<Arc<victoria::Client>>,
     pub kafka: Option<Arc<kafka::Producer>>,
+    pub ingest: Arc<ingest::Guard>,
+    pub access: Arc<access::Authorizer>,
+    /// `None` when alerting is disabled on this instance
+    pub alerts: Option<Arc<alerts::Engine>>,
     pub config: Arc<Config>,
 }
 
@@ -49,6 +53,9 @@ pub struct OtaEvent {
 
     // Release information
     pub release_id: Option<String>,
+    /// Experiment variant the device was served, for releases still being
+    /// rolled out
+    pub variant_id: Option<String>,
     pub current_js_version: Option<String>,
     pub target_js_version: Option<String>,
     pub rollout_percentage: Option<u8>,
@@ -78,7 +85,9 @@ pub struct OtaEvent {
 }
 
 /// OTA Event types as defined in the analytics requirements
-#[derive(Debug, Clone, Serialize, Deserialize, strum_macros::Display)]
+#[derive(
+    Debug, Clone, PartialEq, Serialize, Deserialize, strum_macros::Display, strum_macros::EnumString,
+)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 pub enum OtaEventType {
@@ -107,6 +116,24 @@ pub enum AnalyticsInterval {
     Month,
 }
 
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    strum_macros::Display,
+    strum_macros::EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+    Parquet,
+}
+
 /// Request structure for ingesting OTA events via API
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OtaEventIngestRequest {
@@ -116,8 +143,17 @@ pub struct OtaEventIngestRequest {
     pub session_id: Option<String>,
     pub event_type: OtaEventType,
 
+    // Client-assigned identity and time, for buffered events and retries
+    #[serde(default)]
+    pub event_id: Option<Uuid>,
+    #[serde(default)]
+    pub timestamp: Option<DateTime<Utc>>,
+
     // Release information
     pub release_id: Option<String>,
+    /// Experiment variant the device was served, for releases still being
+    /// rolled out
+    pub variant_id: Option<String>,
     pub current_js_version: Option<String>,
     pub target_js_version: Option<String>,
     pub rollout_percentage: Option<u8>,
@@ -142,6 +178,26 @@ pub struct OtaEventIngestRequest {
     pub payload: Option<Value>,
 }
 
+/// Request structure for ingesting several OTA events at once. Events are
+/// kept as raw values so one malformed event does not reject the batch.
+#[derive(Debug, Deserialize)]
+pub struct OtaEventBatchRequest {
+    /// Client clock when the batch was sent, used to correct event timestamps
+    /// for clock skew.
+    pub sent_at: Option<DateTime<Utc>>,
+    pub events: Vec<Value>,
+}
+
+/// Outcome of one event in a batch
+#[derive(Debug, Serialize)]
+pub struct BatchEventResult {
+    pub index: usize,
+    pub event_id: Option<Uuid>,
+    pub accepted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
 /// Adoption metrics response
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AdoptionMetrics {
@@ -153,6 +209,8 @@ pub struct AdoptionMetrics {
     // pub success_rate: f64,
     // pub failure_rate: f64,
     // pub rollback_rate: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<Breakdown<AdoptionCounts>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -185,6 +243,51 @@ pub struct VersionMetrics {
     pub percentage: f64,
 }
 
+/// One device's events, oldest first
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceTimeline {
+    pub org_id: String,
+    pub app_id: String,
+    pub device_id: String,
+    pub session_id: Option<String>,
+    pub events: Vec<OtaEvent>,
+    /// More events matched than `limit`; narrow the time range to see them.
+    pub truncated: bool,
+}
+
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, strum_macros::Display, strum_macros::EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ErasureStatus {
+    Pending,
+    Completed,
+    Failed,
+}
+
+/// Record of a request to erase one device's events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErasureReceipt {
+    pub receipt_id: Uuid,
+    pub org_id: String,
+    pub app_id: String,
+    /// Only in the response to the request itself; stored receipts keep the
+    /// digest alone.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    pub device_id_sha256: String,
+    pub requested_by: Option<String>,
+    pub reason: Option<String>,
+    pub status: ErasureStatus,
+    /// Events the deletion removed, once completed; unknown on Victoria
+    /// Metrics.
+    pub events_deleted: Option<u64>,
+    pub error: Option<String>,
+    pub requested_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
 /// Active devices metrics
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ActiveDevicesMetrics {
@@ -306,6 +409,8 @@ pub struct FailureMetrics {
     pub total_failures: u64,
     pub failure_rate: f64,
     pub common_errors: Vec<ErrorFrequency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<Breakdown<FailureCounts>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -316,4 +421,297 @@ pub struct PerformanceMetrics {
     pub avg_download_time_ms: f64,
     pub avg_apply_time_ms: f64,
     pub avg_download_size_bytes: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<Breakdown<PerformanceAverages>>,
+}
+
+/// Device context an analytics breakdown can group or filter by
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    strum_macros::Display,
+    strum_macros::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Dimension {
+    OsVersion,
+    AppVersion,
+    DeviceType,
+    NetworkType,
+    ReleaseId,
+    VariantId,
+}
+
+/// A metric split by device context. Groups are ordered by size; the ones
+/// beyond `top` are folded into a single group with `other` set.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Breakdown<T> {
+    pub group_by: Vec<Dimension>,
+    pub filters: BTreeMap<Dimension, String>,
+    pub groups: Vec<BreakdownGroup<T>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BreakdownGroup<T> {
+    /// The group's value of each `group_by` dimension; "other" for the
+    /// folded group
+    pub group: BTreeMap<Dimension, String>,
+    pub other: bool,
+    pub total: T,
+    pub series: Vec<BreakdownPoint<T>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BreakdownPoint<T> {
+    pub time_slot: DateTime<Utc>,
+    #[serde(flatten)]
+    pub values: T,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct AdoptionCounts {
+    pub download_success: u64,
+    pub download_failures: u64,
+    pub apply_success: u64,
+    pub apply_failures: u64,
+    pub rollbacks_initiated: u64,
+    pub rollbacks_completed: u64,
+    pub rollback_failures: u64,
+    pub update_checks: u64,
+    pub update_available: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct FailureCounts {
+    /// Completed or failed downloads and applies
+    pub attempts: u64,
+    pub failures: u64,
+    pub rollbacks: u64,
+    /// Percentage of attempts that failed
+    pub failure_rate: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct PerformanceAverages {
+    pub downloads: u64,
+    pub applies: u64,
+    pub avg_download_time_ms: f64,
+    pub avg_apply_time_ms: f64,
+    pub avg_download_size_bytes: f64,
+}
+
+/// An experiment's experimental variants compared against its control.
+/// Rates and timings are per event; device counts and shares, which only
+/// ClickHouse can provide, are per device.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExperimentComparison {
+    pub release_id: String,
+    pub control: String,
+    pub start_date: DateTime<Utc>,
+    pub end_date: DateTime<Utc>,
+    pub confidence: f64,
+    /// Significance level of each comparison, after the Bonferroni
+    /// correction across the experimental variants
+    pub alpha: f64,
+    /// What rates and timings count, `event`. A device's retries are not
+    /// independent of each other, so intervals and p-values are narrower
+    /// than per-device ones would be.
+    pub observation_unit: String,
+    pub variants: Vec<VariantSummary>,
+    pub comparisons: Vec<VariantComparison>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VariantSummary {
+    pub variant_id: String,
+    pub control: bool,
+    pub devices: Option<u64>,
+    /// Fraction of the app's devices the variant is served to, from the
+    /// experiment's traffic percentage
+    pub expected_share: Option<f64>,
+    /// Fraction of the app's active devices that reported the variant
+    pub observed_share: Option<f64>,
+    pub apply_success_rate: RateEstimate,
+    pub failure_rate: RateEstimate,
+    pub download_time_ms: MeanEstimate,
+    pub apply_time_ms: MeanEstimate,
+}
+
+/// A proportion with its Wilson score interval
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RateEstimate {
+    pub count: u64,
+    pub total: u64,
+    pub rate: f64,
+    pub ci_low: f64,
+    pub ci_high: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MeanEstimate {
+    pub observations: u64,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub ci_low: f64,
+    pub ci_high: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VariantComparison {
+    pub variant_id: String,
+    /// Missing when device counts are unavailable
+    pub sample_ratio: Option<SampleRatioCheck>,
+    /// Each difference is missing when either side has no observations
+    pub apply_success_rate: Option<MetricDifference>,
+    pub failure_rate: Option<MetricDifference>,
+    pub download_time_ms: Option<MetricDifference>,
+    pub apply_time_ms: Option<MetricDifference>,
+}
+
+/// Variant minus control, with its confidence interval and two-sided
+/// p-value
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricDifference {
+    pub difference: f64,
+    pub ci_low: f64,
+    pub ci_high: f64,
+    pub p_value: f64,
+    pub significant: bool,
+}
+
+/// Every variant gets the same traffic, so devices should split evenly
+/// between a variant and the control. A mismatch means assignment or
+/// reporting is broken and the comparison cannot be trusted.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SampleRatioCheck {
+    pub variant_devices: u64,
+    pub control_devices: u64,
+    pub p_value: f64,
+    pub mismatch: bool,
+}
+
+/// What an alert rule measures over its window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertMetric {
+    /// Number of `event_type` events
+    Count { event_type: OtaEventType },
+    /// Percentage of the `of` events that are `event_type`. Windows with
+    /// fewer than `min_events` of them are not evaluated.
+    Rate {
+        event_type: OtaEventType,
+        of: Vec<OtaEventType>,
+        #[serde(default)]
+        min_events: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, strum_macros::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum AlertComparison {
+    Above,
+    Below,
+}
+
+/// Fields of an alert rule that its author chooses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRuleSpec {
+    pub org_id: String,
+    pub app_id: String,
+    pub name: String,
+    pub metric: AlertMetric,
+    pub comparison: AlertComparison,
+    pub threshold: f64,
+    pub window_minutes: u32,
+    /// How long the condition must hold before the alert fires
+    #[serde(default)]
+    pub for_minutes: u32,
+    /// Only events of this release are counted
+    pub release_id: Option<String>,
+    /// Evaluates every release separately, so each can fire on its own
+    #[serde(default)]
+    pub per_release: bool,
+    pub webhooks: Vec<String>,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub rule_id: Uuid,
+    #[serde(flatten)]
+    pub spec: AlertRuleSpec,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, strum_macros::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum AlertState {
+    Pending,
+    Firing,
+    Resolved,
+}
+
+/// One rule's alert, for one release when the rule is `per_release`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub rule_id: Uuid,
+    pub org_id: String,
+    pub app_id: String,
+    pub rule_name: String,
+    pub release_id: Option<String>,
+    pub state: AlertState,
+    /// The metric at the last evaluation
+    pub value: f64,
+    pub threshold: f64,
+    /// When the condition started to hold
+    pub active_since: DateTime<Utc>,
+    pub fired_at: Option<DateTime<Utc>>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub evaluated_at: DateTime<Utc>,
+    /// Notifications are held back while a silence matches
+    pub silenced: bool,
+}
+
+/// Mutes notifications of an application's alerts between `starts_at` and
+/// `ends_at`, optionally only for one rule or release. Alerts keep being
+/// evaluated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Silence {
+    pub silence_id: Uuid,
+    pub org_id: String,
+    pub app_id: String,
+    pub rule_id: Option<Uuid>,
+    pub release_id: Option<String>,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub comment: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Body posted to a rule's webhooks when its alert fires or resolves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertNotification {
+    pub notification_id: Uuid,
+    pub alert: Alert,
+    pub comparison: AlertComparison,
+    pub window_minutes: u32,
+    pub sent_at: DateTime<Utc>,
 }
@@ -1,5 +1,13 @@
+pub mod access;
+pub mod alerts;
+pub mod breakdown;
 pub mod clickhouse;
+pub mod dedup;
+pub mod experiment;
+pub mod export;
+pub mod ingest;
 pub mod kafka;
+pub mod privacy;
 pub mod victoria;
 
 use std::{fs, sync::Arc};
@@ -11,7 +19,8 @@ use crate::{common::config::Config, common::utils::strip_sql_comments};
 
 pub async fn bootstrap_clickhouse(config: &Config) -> Result<Arc<clickhouse::Client>> {
     // Initialize ClickHouse client
-    let clickhouse_client = Arc::new(clickhouse::Client::new(&config.clickhouse).await?);
+    let clickhouse_client =
+        Arc::new(clickhouse::Client::new(&config.clickhouse, &config.privacy).await?);
     info!("Connected to ClickHouse");
 
     // Initialize tables and views
@@ -23,12 +32,17 @@ pub async fn bootstrap_clickhouse(config: &Config) -> Result<Arc<clickhouse::Cli
             continue;
         }
         info!("Running ClickHouse migrations");
+        // Changing the TTL applies to new parts only, instead of rewriting
+        // every stored part on each start
         clickhouse_client
             .query(stmt)
+            .with_option("materialize_ttl_after_modify", "0")
             .execute()
             .await
             .map_err(|e| anyhow::anyhow!("Failed to run statement `{}`: {}", stmt, e))?;
     }
+    clickhouse_client.migrate_events_engine().await?;
+    clickhouse_client.apply_retention().await?;
 
     Ok(clickhouse_client)
 }
@@ -0,0 +1,199 @@
+//! Access control for the analytics query, erasure, alerting and export
+//! endpoints. Callers present the same bearer tokens the Airborne server
+//! accepts; the Airborne server validates them and decides whether the
+//! caller holds `analytics.read` (or `analytics.erase`, `analytics.alerts`,
+//! `analytics.export`) on the organisation and application.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use axum::http::{header, HeaderMap};
+use reqwest::{Client as HttpClient, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::{error, warn};
+
+use crate::common::{
+    config::QueryAuthConfig,
+    error::{AppError, AppResult},
+};
+
+pub const RESOURCE: &str = "analytics";
+pub const READ: &str = "read";
+pub const ERASE: &str = "erase";
+pub const ALERTS: &str = "alerts";
+pub const EXPORT: &str = "export";
+
+const MAX_CACHED_DECISIONS: usize = 10_000;
+
+#[derive(Clone, PartialEq)]
+enum Decision {
+    /// Carries the caller's subject.
+    Allowed(String),
+    /// The token itself was rejected.
+    InvalidToken,
+    /// The token is valid but lacks access to the application.
+    Denied,
+}
+
+struct CachedDecision {
+    decision: Decision,
+    expires_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct EnforceBatchResponse {
+    subject: String,
+    results: Vec<PermissionResult>,
+}
+
+#[derive(Deserialize)]
+struct PermissionResult {
+    allowed: bool,
+}
+
+/// Answers whether a request may read or erase an application's analytics,
+/// caching the Airborne server's decisions. Revoked tokens and permissions
+/// therefore take up to the cache TTL to be refused.
+pub struct Authorizer {
+    config: QueryAuthConfig,
+    http: HttpClient,
+    cache: Mutex<HashMap<String, CachedDecision>>,
+}
+
+impl Authorizer {
+    pub fn new(config: &QueryAuthConfig) -> Result<Self> {
+        Ok(Self {
+            config: config.clone(),
+            http: HttpClient::builder()
+                .timeout(Duration::from_secs(5))
+                .build()?,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Checks that the request's bearer token grants `analytics.read` on
+    /// `org_id`/`app_id`. Passes every request when auth is not required.
+    pub async fn authorize(
+        &self,
+        headers: &HeaderMap,
+        org_id: &str,
+        app_id: &str,
+    ) -> AppResult<()> {
+        self.authorize_action(headers, org_id, app_id, READ)
+            .await
+            .map(|_| ())
+    }
+
+    /// Checks that the request's bearer token grants `analytics.<action>` on
+    /// `org_id`/`app_id`, returning the caller's subject. Passes every
+    /// request, without a subject, when auth is not required.
+    pub async fn authorize_action(
+        &self,
+        headers: &HeaderMap,
+        org_id: &str,
+        app_id: &str,
+        action: &str,
+    ) -> AppResult<Option<String>> {
+        let (true, Some(authz_url)) = (self.config.require_auth, &self.config.authz_url) else {
+            return Ok(None);
+        };
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| AppError::Unauthorized("Missing bearer token".to_string()))?;
+
+        let mut digest = Sha256::new();
+        for part in [token, org_id, app_id, action] {
+            digest.update(part.as_bytes());
+            digest.update([0]);
+        }
+        let cache_key = hex::encode(digest.finalize());
+        let now = Instant::now();
+        let cached = self
+            .cache
+            .lock()
+            .unwrap()
+            .get(&cache_key)
+            .filter(|cached| cached.expires_at > now)
+            .map(|cached| cached.decision.clone());
+        let decision = match cached {
+            Some(decision) => decision,
+            None => {
+                let decision = self
+                    .enforce(authz_url, token, org_id, app_id, action)
+                    .await
+                    .map_err(|e| {
+                        error!("Could not check analytics access: {:?}", e);
+                        AppError::Internal("Could not check analytics access".to_string())
+                    })?;
+                let mut cache = self.cache.lock().unwrap();
+                if cache.len() >= MAX_CACHED_DECISIONS {
+                    cache.retain(|_, cached| cached.expires_at > now);
+                }
+                cache.insert(
+                    cache_key,
+                    CachedDecision {
+                        decision: decision.clone(),
+                        expires_at: now + Duration::from_secs(self.config.cache_ttl_secs),
+                    },
+                );
+                decision
+            }
+        };
+
+        match decision {
+            Decision::Allowed(subject) => Ok(Some(subject)),
+            Decision::InvalidToken => Err(AppError::Unauthorized("Invalid token".to_string())),
+            Decision::Denied => Err(AppError::Forbidden(format!(
+                "No {}.{} access to {}/{}",
+                RESOURCE, action, org_id, app_id
+            ))),
+        }
+    }
+
+    async fn enforce(
+        &self,
+        authz_url: &str,
+        token: &str,
+        org_id: &str,
+        app_id: &str,
+        action: &str,
+    ) -> Result<Decision> {
+        let response = self
+            .http
+            .post(authz_url)
+            .bearer_auth(token)
+            .header("x-organisation", org_id)
+            .header("x-application", app_id)
+            .json(&json!({
+                "checks": [{ "resource": RESOURCE, "action": action, "scope": "auto" }]
+            }))
+            .send()
+            .await?;
+        match response.status() {
+            status if status.is_success() => {
+                let body = response.json::<EnforceBatchResponse>().await?;
+                let allowed = body.results.first().is_some_and(|result| result.allowed);
+                Ok(if allowed {
+                    Decision::Allowed(body.subject)
+                } else {
+                    Decision::Denied
+                })
+            }
+            StatusCode::UNAUTHORIZED => Ok(Decision::InvalidToken),
+            // No membership in the organisation or application
+            StatusCode::FORBIDDEN | StatusCode::NOT_FOUND => Ok(Decision::Denied),
+            status => {
+                warn!("Analytics access check failed with status {}", status);
+                anyhow::bail!("access check returned {}", status);
+            }
+        }
+    }
+}
@@ -0,0 +1,838 @@
+//! Alert rules over the OTA analytics. Every evaluation interval each
+//! enabled rule's metric is computed over its window from the breakdown
+//! counts of the configured backend. An alert is pending while the
+//! condition holds for less than the rule's `for_minutes`, then firing, and
+//! resolved once it stops holding; firing and resolving notify the rule's
+//! webhooks unless a silence matches.
+
+pub mod notifier;
+pub mod store;
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        config::AlertsConfig,
+        error::{AppError, AppResult},
+        models::{
+            Alert, AlertComparison, AlertMetric, AlertNotification, AlertRule, AlertRuleSpec,
+            AlertState, AppState, Dimension, LoggingInfra, Silence,
+        },
+    },
+    core::{
+        alerts::{
+            notifier::Notifier,
+            store::{Backend, Lease, Store},
+        },
+        breakdown::{self, BreakdownCounts, BreakdownRequest, BreakdownRow, TimeRange},
+    },
+};
+
+const MAX_RULES_PER_APP: usize = 100;
+const MAX_NAME_LENGTH: usize = 200;
+const MAX_WEBHOOKS: usize = 5;
+const MAX_WINDOW_MINUTES: u32 = 24 * 60;
+const MAX_SILENCE_DAYS: i64 = 30;
+const MAX_COMMENT_LENGTH: usize = 1000;
+// Resolved alerts and ended silences stay listed this long
+const KEEP_ENDED: chrono::Duration = chrono::Duration::hours(24);
+
+pub struct Engine {
+    backend: Backend,
+    notifier: Notifier,
+    interval: Duration,
+}
+
+/// Notifications to send once the state that caused them is saved
+type Outbox = Vec<(Vec<String>, AlertNotification)>;
+
+impl Engine {
+    pub async fn new(config: &AlertsConfig) -> Result<Self> {
+        Ok(Self {
+            backend: Backend::new(config).await?,
+            notifier: Notifier::new(config)?,
+            interval: Duration::from_secs(config.evaluation_interval_secs),
+        })
+    }
+
+    pub async fn list_rules(&self, org_id: &str, app_id: &str) -> AppResult<Vec<AlertRule>> {
+        let store = self.backend.read().await?;
+        Ok(store
+            .rules
+            .into_values()
+            .filter(|rule| rule.spec.org_id == org_id && rule.spec.app_id == app_id)
+            .collect())
+    }
+
+    pub async fn create_rule(
+        &self,
+        spec: AlertRuleSpec,
+        created_by: Option<String>,
+    ) -> AppResult<AlertRule> {
+        validate(&spec)?;
+        check_webhooks(&spec.webhooks).await?;
+        self.backend
+            .update(move |store| {
+                let rules = store
+                    .rules
+                    .values()
+                    .filter(|rule| {
+                        rule.spec.org_id == spec.org_id && rule.spec.app_id == spec.app_id
+                    })
+                    .count();
+                if rules >= MAX_RULES_PER_APP {
+                    return Err(AppError::Validation(format!(
+                        "An application has at most {} alert rules",
+                        MAX_RULES_PER_APP
+                    )));
+                }
+
+                let now = Utc::now();
+                let rule = AlertRule {
+                    rule_id: Uuid::new_v4(),
+                    spec,
+                    created_by,
+                    created_at: now,
+                    updated_at: now,
+                };
+                store.rules.insert(rule.rule_id, rule.clone());
+                Ok(rule)
+            })
+            .await
+    }
+
+    /// Replaces a rule's spec. Its alerts restart from scratch, since they
+    /// were evaluated against the old condition.
+    pub async fn update_rule(&self, rule_id: Uuid, spec: AlertRuleSpec) -> AppResult<AlertRule> {
+        validate(&spec)?;
+        check_webhooks(&spec.webhooks).await?;
+        self.backend
+            .update(move |store| {
+                let rule = store
+                    .rules
+                    .get_mut(&rule_id)
+                    .filter(|rule| {
+                        rule.spec.org_id == spec.org_id && rule.spec.app_id == spec.app_id
+                    })
+                    .ok_or_else(|| {
+                        AppError::NotFound(format!("Alert rule {} not found", rule_id))
+                    })?;
+                rule.spec = spec;
+                rule.updated_at = Utc::now();
+                let rule = rule.clone();
+                store.alerts.retain(|alert| alert.rule_id != rule_id);
+                Ok(rule)
+            })
+            .await
+    }
+
+    pub async fn delete_rule(&self, org_id: &str, app_id: &str, rule_id: Uuid) -> AppResult<()> {
+        let (org_id, app_id) = (org_id.to_string(), app_id.to_string());
+        self.backend
+            .update(move |store| {
+                if !store
+                    .rules
+                    .get(&rule_id)
+                    .is_some_and(|rule| rule.spec.org_id == org_id && rule.spec.app_id == app_id)
+                {
+                    return Err(AppError::NotFound(format!(
+                        "Alert rule {} not found",
+                        rule_id
+                    )));
+                }
+                store.rules.remove(&rule_id);
+                store.alerts.retain(|alert| alert.rule_id != rule_id);
+                Ok(())
+            })
+            .await
+    }
+
+    /// Pending and firing alerts, and those resolved within the last day
+    pub async fn list_alerts(&self, org_id: &str, app_id: &str) -> AppResult<Vec<Alert>> {
+        let store = self.backend.read().await?;
+        Ok(store
+            .alerts
+            .into_iter()
+            .filter(|alert| alert.org_id == org_id && alert.app_id == app_id)
+            .collect())
+    }
+
+    pub async fn list_silences(&self, org_id: &str, app_id: &str) -> AppResult<Vec<Silence>> {
+        let store = self.backend.read().await?;
+        Ok(store
+            .silences
+            .into_values()
+            .filter(|silence| silence.org_id == org_id && silence.app_id == app_id)
+            .collect())
+    }
+
+    pub async fn create_silence(&self, mut silence: Silence) -> AppResult<Silence> {
+        if silence.ends_at <= silence.starts_at || silence.ends_at <= Utc::now() {
+            return Err(AppError::Validation(
+                "ends_at must be in the future and after starts_at".to_string(),
+            ));
+        }
+        if silence.ends_at - silence.starts_at > chrono::Duration::days(MAX_SILENCE_DAYS) {
+            return Err(AppError::Validation(format!(
+                "A silence lasts at most {} days",
+                MAX_SILENCE_DAYS
+            )));
+        }
+        if silence
+            .comment
+            .as_ref()
+            .is_some_and(|comment| comment.len() > MAX_COMMENT_LENGTH)
+        {
+            return Err(AppError::Validation(format!(
+                "comment may be at most {} bytes",
+                MAX_COMMENT_LENGTH
+            )));
+        }
+
+        self.backend
+            .update(move |store| {
+                if let Some(rule_id) = silence.rule_id {
+                    if !store.rules.get(&rule_id).is_some_and(|rule| {
+                        rule.spec.org_id == silence.org_id && rule.spec.app_id == silence.app_id
+                    }) {
+                        return Err(AppError::NotFound(format!(
+                            "Alert rule {} not found",
+                            rule_id
+                        )));
+                    }
+                }
+                silence.silence_id = Uuid::new_v4();
+                store.silences.insert(silence.silence_id, silence.clone());
+                Ok(silence)
+            })
+            .await
+    }
+
+    pub async fn delete_silence(
+        &self,
+        org_id: &str,
+        app_id: &str,
+        silence_id: Uuid,
+    ) -> AppResult<()> {
+        let (org_id, app_id) = (org_id.to_string(), app_id.to_string());
+        self.backend
+            .update(move |store| {
+                if !store
+                    .silences
+                    .get(&silence_id)
+                    .is_some_and(|silence| silence.org_id == org_id && silence.app_id == app_id)
+                {
+                    return Err(AppError::NotFound(format!(
+                        "Silence {} not found",
+                        silence_id
+                    )));
+                }
+                store.silences.remove(&silence_id);
+                Ok(())
+            })
+            .await
+    }
+
+    /// Evaluates every enabled rule each interval, until the process exits.
+    /// With several replicas only the one holding the evaluator lease does,
+    /// so each transition is notified once.
+    pub async fn run_evaluator(&self, state: AppState) {
+        let mut ticker = tokio::time::interval(self.interval);
+        let mut lease: Lease = None;
+        loop {
+            ticker.tick().await;
+            if self.backend.hold_lease(&mut lease).await {
+                self.evaluate(&state).await;
+            }
+        }
+    }
+
+    async fn evaluate(&self, state: &AppState) {
+        // The store is not locked while the backend is queried
+        let rules: Vec<AlertRule> = match self.backend.read().await {
+            Ok(store) => store
+                .rules
+                .into_values()
+                .filter(|rule| rule.spec.enabled)
+                .collect(),
+            Err(_) => return,
+        };
+
+        let now = Utc::now();
+        let mut results = Vec::with_capacity(rules.len());
+        for rule in rules {
+            match fetch_counts(state, &rule.spec, now).await {
+                Ok(counts) => results.push((rule, counts)),
+                Err(e) => error!("Could not evaluate alert rule {}: {:?}", rule.rule_id, e),
+            }
+        }
+
+        let outbox = self
+            .backend
+            .update(move |store| {
+                let mut outbox = Outbox::new();
+                for (rule, counts) in results {
+                    // The rule may have changed while it was being evaluated
+                    if store
+                        .rules
+                        .get(&rule.rule_id)
+                        .is_some_and(|current| current.updated_at == rule.updated_at)
+                    {
+                        apply(store, &rule, counts, now, &mut outbox);
+                    }
+                }
+                prune(store, now);
+                Ok(outbox)
+            })
+            .await;
+        // Nothing is sent for transitions that were not saved, as they will
+        // happen again on the next evaluation
+        for (webhooks, notification) in outbox.unwrap_or_default() {
+            self.notifier.send(&webhooks, &notification);
+        }
+    }
+}
+
+/// Drops alerts of deleted or disabled rules, and resolved alerts and
+/// silences that ended over a day ago
+fn prune(store: &mut Store, now: DateTime<Utc>) {
+    let live: HashSet<Uuid> = store
+        .rules
+        .values()
+        .filter(|rule| rule.spec.enabled)
+        .map(|rule| rule.rule_id)
+        .collect();
+    store.alerts.retain(|alert| {
+        live.contains(&alert.rule_id)
+            && alert
+                .resolved_at
+                .is_none_or(|resolved_at| now - resolved_at < KEEP_ENDED)
+    });
+    store
+        .silences
+        .retain(|_, silence| now - silence.ends_at < KEEP_ENDED);
+}
+
+/// Moves the rule's alerts through pending, firing and resolved.
+fn apply(
+    store: &mut Store,
+    rule: &AlertRule,
+    mut counts: BTreeMap<Option<String>, BreakdownCounts>,
+    now: DateTime<Utc>,
+    outbox: &mut Outbox,
+) {
+    // Alerts of releases that went quiet are evaluated on zero counts
+    for alert in &store.alerts {
+        if alert.rule_id == rule.rule_id && alert.state != AlertState::Resolved {
+            counts.entry(alert.release_id.clone()).or_default();
+        }
+    }
+
+    for (release_id, counts) in counts {
+        let value = metric_value(&rule.spec.metric, &counts);
+        let breached = value.is_some_and(|value| match rule.spec.comparison {
+            AlertComparison::Above => value > rule.spec.threshold,
+            AlertComparison::Below => value < rule.spec.threshold,
+        });
+        let silenced = store
+            .silences
+            .values()
+            .any(|silence| silences(silence, rule, release_id.as_deref(), now));
+        let index = store.alerts.iter().position(|alert| {
+            alert.rule_id == rule.rule_id
+                && alert.release_id == release_id
+                && alert.state != AlertState::Resolved
+        });
+
+        let Some(index) = index else {
+            if breached {
+                store.alerts.push(Alert {
+                    rule_id: rule.rule_id,
+                    org_id: rule.spec.org_id.clone(),
+                    app_id: rule.spec.app_id.clone(),
+                    rule_name: rule.spec.name.clone(),
+                    release_id,
+                    state: AlertState::Pending,
+                    value: value.unwrap_or_default(),
+                    threshold: rule.spec.threshold,
+                    active_since: now,
+                    fired_at: None,
+                    resolved_at: None,
+                    evaluated_at: now,
+                    silenced,
+                });
+                let index = store.alerts.len() - 1;
+                fire_if_due(&mut store.alerts[index], rule, now, outbox);
+            }
+            continue;
+        };
+
+        let alert = &mut store.alerts[index];
+        let was_silenced = alert.silenced;
+        alert.value = value.unwrap_or(alert.value);
+        alert.evaluated_at = now;
+        alert.silenced = silenced;
+        match (alert.state, breached) {
+            (AlertState::Pending, true) => fire_if_due(alert, rule, now, outbox),
+            (AlertState::Pending, false) => {
+                store.alerts.remove(index);
+            }
+            (AlertState::Firing, true) => {
+                // Held back while silenced; sent once the silence ends
+                if was_silenced && !silenced {
+                    notify(alert, rule, now, outbox);
+                }
+            }
+            (AlertState::Firing, false) => {
+                alert.state = AlertState::Resolved;
+                alert.resolved_at = Some(now);
+                info!(
+                    "Alert {} of rule {} resolved",
+                    alert.release_id.as_deref().unwrap_or("(all releases)"),
+                    rule.rule_id
+                );
+                if !silenced {
+                    notify(alert, rule, now, outbox);
+                }
+            }
+            (AlertState::Resolved, _) => {}
+        }
+    }
+}
+
+fn fire_if_due(alert: &mut Alert, rule: &AlertRule, now: DateTime<Utc>, outbox: &mut Outbox) {
+    if now - alert.active_since < chrono::Duration::minutes(i64::from(rule.spec.for_minutes)) {
+        return;
+    }
+    alert.state = AlertState::Firing;
+    alert.fired_at = Some(now);
+    warn!(
+        "Alert {} of rule {} firing: {} is {} {}",
+        alert.release_id.as_deref().unwrap_or("(all releases)"),
+        rule.rule_id,
+        alert.value,
+        rule.spec.comparison,
+        rule.spec.threshold
+    );
+    if !alert.silenced {
+        notify(alert, rule, now, outbox);
+    }
+}
+
+fn notify(alert: &Alert, rule: &AlertRule, now: DateTime<Utc>, outbox: &mut Outbox) {
+    if rule.spec.webhooks.is_empty() {
+        return;
+    }
+    outbox.push((
+        rule.spec.webhooks.clone(),
+        AlertNotification {
+            notification_id: Uuid::new_v4(),
+            alert: alert.clone(),
+            comparison: rule.spec.comparison,
+            window_minutes: rule.spec.window_minutes,
+            sent_at: now,
+        },
+    ));
+}
+
+fn silences(
+    silence: &Silence,
+    rule: &AlertRule,
+    release_id: Option<&str>,
+    now: DateTime<Utc>,
+) -> bool {
+    silence.org_id == rule.spec.org_id
+        && silence.app_id == rule.spec.app_id
+        && silence.starts_at <= now
+        && now < silence.ends_at
+        && silence
+            .rule_id
+            .is_none_or(|rule_id| rule_id == rule.rule_id)
+        && silence
+            .release_id
+            .as_deref()
+            .is_none_or(|silenced| Some(silenced) == release_id)
+}
+
+/// The metric over one group's counts, or `None` when there is too little
+/// data to judge
+fn metric_value(metric: &AlertMetric, counts: &BreakdownCounts) -> Option<f64> {
+    match metric {
+        AlertMetric::Count { event_type } => counts.count_of(event_type).map(|count| count as f64),
+        AlertMetric::Rate {
+            event_type,
+            of,
+            min_events,
+        } => {
+            let total: u64 = of.iter().filter_map(|of| counts.count_of(of)).sum();
+            if total == 0 || total < *min_events {
+                return None;
+            }
+            Some(counts.count_of(event_type)? as f64 * 100.0 / total as f64)
+        }
+    }
+}
+
+/// Counts over the rule's window ending at `now`, per release when the rule
+/// is `per_release`, else under the rule's own `release_id`
+async fn fetch_counts(
+    state: &AppState,
+    spec: &AlertRuleSpec,
+    now: DateTime<Utc>,
+) -> Result<BTreeMap<Option<String>, BreakdownCounts>> {
+    let end = now.timestamp();
+    let step = i64::from(spec.window_minutes) * 60;
+    let request = BreakdownRequest {
+        group_by: if spec.per_release {
+            vec![Dimension::ReleaseId]
+        } else {
+            Vec::new()
+        },
+        filters: spec
+            .release_id
+            .iter()
+            .map(|release_id| (Dimension::ReleaseId, release_id.clone()))
+            .collect(),
+        top: breakdown::MAX_TOP,
+        range: TimeRange {
+            start: end - step,
+            end,
+            step,
+        },
+    };
+
+    let rows: Vec<BreakdownRow> = match (
+        &state.config.logging_infrastructure,
+        state.clickhouse.as_ref(),
+        state.victoria.as_ref(),
+    ) {
+        (LoggingInfra::KafkaClickhouse, Some(clickhouse), _) => {
+            clickhouse
+                .get_breakdown_rows(&spec.org_id, &spec.app_id, &request)
+                .await?
+        }
+        (LoggingInfra::VictoriaMetrics, _, Some(victoria)) => {
+            victoria
+                .get_breakdown_rows(&spec.org_id, &spec.app_id, &request)
+                .await?
+        }
+        _ => anyhow::bail!("No analytics backend is initialized"),
+    };
+
+    let mut counts = BTreeMap::new();
+    if !spec.per_release {
+        counts.insert(spec.release_id.clone(), BreakdownCounts::default());
+    }
+    for row in rows {
+        let key = if spec.per_release {
+            match row.group.into_iter().next() {
+                // Events outside any release are not one release's problem
+                Some(release_id) if release_id != breakdown::UNKNOWN => Some(release_id),
+                _ => continue,
+            }
+        } else {
+            spec.release_id.clone()
+        };
+        counts
+            .entry(key)
+            .or_insert_with(BreakdownCounts::default)
+            .add(&row.counts);
+    }
+    Ok(counts)
+}
+
+fn validate(spec: &AlertRuleSpec) -> AppResult<()> {
+    if spec.org_id.is_empty() || spec.app_id.is_empty() {
+        return Err(AppError::Validation(
+            "org_id and app_id cannot be empty".to_string(),
+        ));
+    }
+    if spec.name.trim().is_empty() || spec.name.len() > MAX_NAME_LENGTH {
+        return Err(AppError::Validation(format!(
+            "name must be between 1 and {} bytes",
+            MAX_NAME_LENGTH
+        )));
+    }
+    if spec.window_minutes == 0 || spec.window_minutes > MAX_WINDOW_MINUTES {
+        return Err(AppError::Validation(format!(
+            "window_minutes must be between 1 and {}",
+            MAX_WINDOW_MINUTES
+        )));
+    }
+    if spec.for_minutes > MAX_WINDOW_MINUTES {
+        return Err(AppError::Validation(format!(
+            "for_minutes must be at most {}",
+            MAX_WINDOW_MINUTES
+        )));
+    }
+    if spec.per_release && spec.release_id.is_some() {
+        return Err(AppError::Validation(
+            "per_release cannot be combined with release_id".to_string(),
+        ));
+    }
+    if !spec.threshold.is_finite() || spec.threshold < 0.0 {
+        return Err(AppError::Validation(
+            "threshold must be a non-negative number".to_string(),
+        ));
+    }
+
+    let counted = |event_type| {
+        if BreakdownCounts::default().count_of(event_type).is_some() {
+            Ok(())
+        } else {
+            Err(AppError::Validation(format!(
+                "{} events cannot be alerted on",
+                event_type
+            )))
+        }
+    };
+    match &spec.metric {
+        AlertMetric::Count { event_type } => counted(event_type)?,
+        AlertMetric::Rate { event_type, of, .. } => {
+            counted(event_type)?;
+            for (index, other) in of.iter().enumerate() {
+                counted(other)?;
+                if of[..index].contains(other) {
+                    return Err(AppError::Validation(format!(
+                        "A rate's of lists {} twice",
+                        other
+                    )));
+                }
+            }
+            if !of.contains(event_type) {
+                return Err(AppError::Validation(
+                    "A rate's of must include its event_type".to_string(),
+                ));
+            }
+            if spec.threshold > 100.0 {
+                return Err(AppError::Validation(
+                    "A rate's threshold is a percentage, at most 100".to_string(),
+                ));
+            }
+        }
+    }
+
+    if spec.webhooks.len() > MAX_WEBHOOKS {
+        return Err(AppError::Validation(format!(
+            "A rule has at most {} webhooks",
+            MAX_WEBHOOKS
+        )));
+    }
+    Ok(())
+}
+
+/// Refuses webhooks that resolve to internal addresses
+async fn check_webhooks(webhooks: &[String]) -> AppResult<()> {
+    for url in webhooks {
+        airborne_egress::check(url).await.map_err(|reason| {
+            AppError::Validation(format!("Invalid webhook URL '{}': {}", url, reason))
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::models::OtaEventType;
+
+    fn failure_rate() -> AlertRuleSpec {
+        AlertRuleSpec {
+            org_id: "acme".to_string(),
+            app_id: "shop".to_string(),
+            name: "Apply failures".to_string(),
+            metric: AlertMetric::Rate {
+                event_type: OtaEventType::ApplyFailure,
+                of: vec![OtaEventType::ApplySuccess, OtaEventType::ApplyFailure],
+                min_events: 10,
+            },
+            comparison: AlertComparison::Above,
+            threshold: 5.0,
+            window_minutes: 15,
+            for_minutes: 5,
+            release_id: None,
+            per_release: true,
+            webhooks: vec!["https://hooks.example.com/alerts".to_string()],
+            enabled: true,
+        }
+    }
+
+    fn rule(spec: AlertRuleSpec) -> AlertRule {
+        let now = Utc::now();
+        AlertRule {
+            rule_id: Uuid::new_v4(),
+            spec,
+            created_by: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn silence(rule: &AlertRule, now: DateTime<Utc>) -> Silence {
+        Silence {
+            silence_id: Uuid::new_v4(),
+            org_id: rule.spec.org_id.clone(),
+            app_id: rule.spec.app_id.clone(),
+            rule_id: None,
+            release_id: None,
+            starts_at: now - chrono::Duration::minutes(5),
+            ends_at: now + chrono::Duration::minutes(5),
+            comment: None,
+            created_by: None,
+            created_at: now,
+        }
+    }
+
+    fn counts(
+        release_id: &str,
+        success: u64,
+        failures: u64,
+    ) -> BTreeMap<Option<String>, BreakdownCounts> {
+        let counts = BreakdownCounts {
+            apply_success: success,
+            apply_failures: failures,
+            ..Default::default()
+        };
+        BTreeMap::from([(Some(release_id.to_string()), counts)])
+    }
+
+    #[test]
+    fn rules_are_validated() {
+        assert!(validate(&failure_rate()).is_ok());
+
+        let mut spec = failure_rate();
+        spec.metric = AlertMetric::Rate {
+            event_type: OtaEventType::ApplyFailure,
+            of: vec![OtaEventType::ApplySuccess],
+            min_events: 0,
+        };
+        assert!(validate(&spec).is_err(), "of without event_type");
+
+        let mut spec = failure_rate();
+        spec.threshold = 150.0;
+        assert!(validate(&spec).is_err(), "rate above 100%");
+
+        let mut spec = failure_rate();
+        spec.release_id = Some("r1".to_string());
+        assert!(validate(&spec).is_err(), "per_release with release_id");
+
+        let mut spec = failure_rate();
+        spec.metric = AlertMetric::Count {
+            event_type: OtaEventType::DownloadProgress,
+        };
+        assert!(validate(&spec).is_err(), "uncounted event type");
+
+        let mut spec = failure_rate();
+        spec.window_minutes = 0;
+        assert!(validate(&spec).is_err(), "empty window");
+    }
+
+    #[test]
+    fn silences_match_their_scope() {
+        let rule = rule(failure_rate());
+        let now = Utc::now();
+        assert!(silences(&silence(&rule, now), &rule, Some("r1"), now));
+
+        let mut other_rule = silence(&rule, now);
+        other_rule.rule_id = Some(Uuid::new_v4());
+        assert!(!silences(&other_rule, &rule, Some("r1"), now));
+
+        let mut release = silence(&rule, now);
+        release.release_id = Some("r1".to_string());
+        assert!(silences(&release, &rule, Some("r1"), now));
+        assert!(!silences(&release, &rule, Some("r2"), now));
+        assert!(!silences(&release, &rule, None, now));
+
+        let ended = silence(&rule, now - chrono::Duration::minutes(10));
+        assert!(!silences(&ended, &rule, Some("r1"), now));
+        let upcoming = silence(&rule, now + chrono::Duration::minutes(10));
+        assert!(!silences(&upcoming, &rule, Some("r1"), now));
+
+        let mut other_app = silence(&rule, now);
+        other_app.app_id = "other".to_string();
+        assert!(!silences(&other_app, &rule, Some("r1"), now));
+    }
+
+    #[test]
+    fn alerts_fire_after_for_minutes_and_resolve() {
+        let rule = rule(failure_rate());
+        let mut store = Store::default();
+        let mut outbox = Outbox::new();
+        let start = Utc::now();
+
+        // 10% failures: pending first, nothing sent
+        apply(&mut store, &rule, counts("r1", 90, 10), start, &mut outbox);
+        assert_eq!(store.alerts.len(), 1);
+        assert_eq!(store.alerts[0].state, AlertState::Pending);
+        assert!(outbox.is_empty());
+
+        // Still breached once for_minutes passed: firing, and notified
+        let later = start + chrono::Duration::minutes(5);
+        apply(&mut store, &rule, counts("r1", 90, 10), later, &mut outbox);
+        assert_eq!(store.alerts[0].state, AlertState::Firing);
+        assert_eq!(outbox.len(), 1);
+        assert_eq!(outbox[0].0, rule.spec.webhooks);
+        assert_eq!(outbox[0].1.alert.state, AlertState::Firing);
+
+        // Firing again sends nothing new
+        let later = later + chrono::Duration::minutes(1);
+        apply(&mut store, &rule, counts("r1", 90, 10), later, &mut outbox);
+        assert_eq!(outbox.len(), 1);
+
+        // Back under the threshold: resolved, and notified
+        let later = later + chrono::Duration::minutes(1);
+        apply(&mut store, &rule, counts("r1", 99, 1), later, &mut outbox);
+        assert_eq!(store.alerts[0].state, AlertState::Resolved);
+        assert_eq!(store.alerts[0].resolved_at, Some(later));
+        assert_eq!(outbox.len(), 2);
+        assert_eq!(outbox[1].1.alert.state, AlertState::Resolved);
+    }
+
+    #[test]
+    fn pending_alerts_are_dropped_when_the_condition_clears() {
+        let rule = rule(failure_rate());
+        let mut store = Store::default();
+        let mut outbox = Outbox::new();
+        let now = Utc::now();
+
+        apply(&mut store, &rule, counts("r1", 90, 10), now, &mut outbox);
+        assert_eq!(store.alerts.len(), 1);
+        // A release that went quiet is judged on zero counts, which are
+        // below min_events
+        let later = now + chrono::Duration::minutes(1);
+        apply(&mut store, &rule, BTreeMap::new(), later, &mut outbox);
+        assert!(store.alerts.is_empty());
+        assert!(outbox.is_empty());
+    }
+
+    #[test]
+    fn silenced_alerts_notify_once_the_silence_ends() {
+        let mut spec = failure_rate();
+        spec.for_minutes = 0;
+        let rule = rule(spec);
+        let now = Utc::now();
+        let mut store = Store::default();
+        let mut quiet = silence(&rule, now);
+        quiet.release_id = Some("r1".to_string());
+        store.silences.insert(quiet.silence_id, quiet.clone());
+        let mut outbox = Outbox::new();
+
+        apply(&mut store, &rule, counts("r1", 90, 10), now, &mut outbox);
+        assert_eq!(store.alerts[0].state, AlertState::Firing);
+        assert!(store.alerts[0].silenced);
+        assert!(outbox.is_empty());
+
+        let later = quiet.ends_at;
+        apply(&mut store, &rule, counts("r1", 90, 10), later, &mut outbox);
+        assert!(!store.alerts[0].silenced);
+        assert_eq!(outbox.len(), 1);
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{header::CONTENT_TYPE, Client as HttpClient};
+use sha2::Sha256;
+use tracing::{info, warn};
+
+use crate::{
+    common::{config::AlertsConfig, models::AlertNotification},
+    core::ingest::SIGNATURE_HEADER,
+};
+
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Posts alert notifications to webhooks. Each delivery runs in the
+/// background and is retried with exponential backoff, so one slow endpoint
+/// holds up neither evaluation nor other endpoints. Deliveries still being
+/// retried are lost on restart.
+#[derive(Clone)]
+pub struct Notifier {
+    http: HttpClient,
+    max_attempts: u32,
+    secret: Option<String>,
+}
+
+impl Notifier {
+    pub fn new(config: &AlertsConfig) -> Result<Self> {
+        Ok(Self {
+            http: airborne_egress::client_builder()
+                .timeout(Duration::from_secs(config.webhook_timeout_secs))
+                .build()?,
+            max_attempts: config.webhook_max_attempts,
+            secret: config.webhook_secret.clone(),
+        })
+    }
+
+    pub fn send(&self, webhooks: &[String], notification: &AlertNotification) {
+        let body = match serde_json::to_vec(notification) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!(
+                    "Could not encode notification {}: {:?}",
+                    notification.notification_id, e
+                );
+                return;
+            }
+        };
+        for url in webhooks {
+            let notifier = self.clone();
+            let url = url.clone();
+            let body = body.clone();
+            let notification_id = notification.notification_id;
+            tokio::spawn(async move {
+                notifier.deliver(&url, body, notification_id).await;
+            });
+        }
+    }
+
+    async fn deliver(&self, url: &str, body: Vec<u8>, notification_id: uuid::Uuid) {
+        // Rules saved before the check existed, or hosts whose DNS changed
+        if let Err(reason) = airborne_egress::check(url).await {
+            warn!(
+                "Dropped notification {} for {}: {}",
+                notification_id, url, reason
+            );
+            return;
+        }
+        let mut backoff = Duration::from_secs(1);
+        for attempt in 1..=self.max_attempts {
+            let mut request = self
+                .http
+                .post(url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(signature) = self.sign(&body) {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+
+            // Client errors other than throttling will not improve on retry
+            let retry = match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    info!("Delivered notification {} to {}", notification_id, url);
+                    return;
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    warn!(
+                        "Webhook {} answered notification {} with {} (attempt {}/{})",
+                        url, notification_id, status, attempt, self.max_attempts
+                    );
+                    status.is_server_error() || status.as_u16() == 429
+                }
+                Err(e) => {
+                    warn!(
+                        "Could not deliver notification {} to {} (attempt {}/{}): {:?}",
+                        notification_id, url, attempt, self.max_attempts, e
+                    );
+                    true
+                }
+            };
+            if !retry {
+                break;
+            }
+            if attempt < self.max_attempts {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+        warn!("Dropped notification {} for {}", notification_id, url);
+    }
+
+    /// `t=<unix seconds>,v1=<hex HMAC-SHA256 of "<t>.<body>">`, the scheme
+    /// signed ingest requests use
+    fn sign(&self, body: &[u8]) -> Option<String> {
+        let secret = self.secret.as_ref()?;
+        let timestamp = Utc::now().timestamp();
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).ok()?;
+        mac.update(timestamp.to_string().as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        Some(format!(
+            "t={},v1={}",
+            timestamp,
+            hex::encode(mac.finalize().into_bytes())
+        ))
+    }
+}
@@ -0,0 +1,247 @@
+//! Where alerting keeps rules, silences and alert state. A single instance
+//! can use a JSON file. With several replicas they must share one PostgreSQL
+//! database instead, where one row holds the same document and every change
+//! is made under a row lock, and a session advisory lock elects the replica
+//! that evaluates rules and sends notifications.
+
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use diesel::{
+    pg::PgConnection,
+    r2d2::{ConnectionManager, Pool, PooledConnection},
+    sql_query,
+    sql_types::{Bool, Jsonb},
+    Connection, QueryableByName, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::common::{
+    config::AlertsConfig,
+    error::{AppError, AppResult},
+    models::{Alert, AlertRule, Silence},
+};
+
+type PgPool = Pool<ConnectionManager<PgConnection>>;
+
+/// A connection holding the evaluator lock; the lock lasts as long as the
+/// session.
+pub type Lease = Option<PooledConnection<ConnectionManager<PgConnection>>>;
+
+// "alerts" in ASCII, an advisory lock id no other user of the database is
+// likely to pick
+const EVALUATOR_LOCK_ID: i64 = 0x616c_6572_7473;
+
+/// Everything alerting keeps between restarts
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Store {
+    pub rules: BTreeMap<Uuid, AlertRule>,
+    pub silences: BTreeMap<Uuid, Silence>,
+    pub alerts: Vec<Alert>,
+}
+
+#[derive(QueryableByName)]
+struct DocumentRow {
+    #[diesel(sql_type = Jsonb)]
+    document: serde_json::Value,
+}
+
+#[derive(QueryableByName)]
+struct LockRow {
+    #[diesel(sql_type = Bool)]
+    acquired: bool,
+}
+
+pub enum Backend {
+    File { path: PathBuf, store: Mutex<Store> },
+    Postgres(PgPool),
+}
+
+impl Backend {
+    pub async fn new(config: &AlertsConfig) -> Result<Self> {
+        let Some(url) = &config.database_url else {
+            let path = PathBuf::from(&config.store_path);
+            let store = match tokio::fs::read(&path).await {
+                Ok(bytes) => serde_json::from_slice(&bytes)?,
+                Err(e) if e.kind() == ErrorKind::NotFound => Store::default(),
+                Err(e) => return Err(e.into()),
+            };
+            info!(
+                "Loaded {} alert rules and {} silences from {}",
+                store.rules.len(),
+                store.silences.len(),
+                path.display()
+            );
+            return Ok(Self::File {
+                path,
+                store: Mutex::new(store),
+            });
+        };
+
+        let pool = Pool::builder()
+            .max_size(4)
+            .build(ConnectionManager::<PgConnection>::new(url))
+            .context("Could not connect to the alerting database")?;
+        let setup = pool.clone();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let mut conn = setup.get()?;
+            sql_query(
+                "CREATE TABLE IF NOT EXISTS analytics_alert_store (
+                    id SMALLINT PRIMARY KEY,
+                    document JSONB NOT NULL,
+                    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+                )",
+            )
+            .execute(&mut conn)?;
+            sql_query(
+                "INSERT INTO analytics_alert_store (id, document) VALUES (1, $1)
+                 ON CONFLICT (id) DO NOTHING",
+            )
+            .bind::<Jsonb, _>(serde_json::to_value(Store::default())?)
+            .execute(&mut conn)?;
+            Ok(())
+        })
+        .await??;
+        info!("Keeping alerting state in PostgreSQL");
+        Ok(Self::Postgres(pool))
+    }
+
+    /// A snapshot of the store
+    pub async fn read(&self) -> AppResult<Store> {
+        match self {
+            Self::File { store, .. } => Ok(store.lock().await.clone()),
+            Self::Postgres(pool) => {
+                let pool = pool.clone();
+                let result = tokio::task::spawn_blocking(move || -> Result<Store> {
+                    let mut conn = pool.get()?;
+                    let row: DocumentRow =
+                        sql_query("SELECT document FROM analytics_alert_store WHERE id = 1")
+                            .get_result(&mut conn)?;
+                    Ok(serde_json::from_value(row.document)?)
+                })
+                .await;
+                flatten(result, "read")
+            }
+        }
+    }
+
+    /// Runs `change` on the current store and saves the result, unless
+    /// `change` fails. Changes from all replicas are serialized.
+    pub async fn update<T, F>(&self, change: F) -> AppResult<T>
+    where
+        F: FnOnce(&mut Store) -> AppResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        match self {
+            Self::File { path, store } => {
+                let mut store = store.lock().await;
+                let mut changed = store.clone();
+                let value = change(&mut changed)?;
+                if let Err(e) = save_file(path, &changed).await {
+                    return flatten(Ok(Err(e)), "save");
+                }
+                *store = changed;
+                Ok(value)
+            }
+            Self::Postgres(pool) => {
+                let pool = pool.clone();
+                let result = tokio::task::spawn_blocking(move || -> Result<AppResult<T>> {
+                    let mut conn = pool.get()?;
+                    conn.transaction(|conn| {
+                        let row: DocumentRow = sql_query(
+                            "SELECT document FROM analytics_alert_store WHERE id = 1 FOR UPDATE",
+                        )
+                        .get_result(conn)?;
+                        let mut store: Store = serde_json::from_value(row.document)?;
+                        let value = change(&mut store);
+                        if value.is_ok() {
+                            sql_query(
+                                "UPDATE analytics_alert_store
+                                 SET document = $1, updated_at = NOW() WHERE id = 1",
+                            )
+                            .bind::<Jsonb, _>(serde_json::to_value(&store)?)
+                            .execute(conn)?;
+                        }
+                        Ok(value)
+                    })
+                })
+                .await;
+                flatten(result, "save")?
+            }
+        }
+    }
+
+    /// Whether this replica is the one that evaluates. A file belongs to one
+    /// instance, which always is. With PostgreSQL the lease is taken when
+    /// free and checked on every call, so a replica that lost its database
+    /// session stops evaluating and another takes over.
+    pub async fn hold_lease(&self, lease: &mut Lease) -> bool {
+        let Self::Postgres(pool) = self else {
+            return true;
+        };
+        let pool = pool.clone();
+        let held = lease.take();
+        let had_lease = held.is_some();
+        let result = tokio::task::spawn_blocking(move || -> Result<Lease> {
+            if let Some(mut conn) = held {
+                return Ok(sql_query("SELECT 1")
+                    .execute(&mut conn)
+                    .is_ok()
+                    .then_some(conn));
+            }
+            let mut conn = pool.get()?;
+            let row: LockRow = sql_query("SELECT pg_try_advisory_lock($1) AS acquired")
+                .bind::<diesel::sql_types::BigInt, _>(EVALUATOR_LOCK_ID)
+                .get_result(&mut conn)?;
+            Ok(row.acquired.then_some(conn))
+        })
+        .await;
+        *lease = match result {
+            Ok(Ok(new_lease)) => new_lease,
+            Ok(Err(e)) => {
+                warn!("Could not check the alert evaluator lock: {:?}", e);
+                None
+            }
+            Err(e) => {
+                warn!("Alert evaluator lock check panicked: {:?}", e);
+                None
+            }
+        };
+        match (had_lease, lease.is_some()) {
+            (false, true) => info!("This instance now evaluates alert rules"),
+            (true, false) => warn!("Lost the alert evaluator lock"),
+            _ => {}
+        }
+        lease.is_some()
+    }
+}
+
+/// Replaces the file through a rename, so a crash never leaves it
+/// half-written.
+async fn save_file(path: &Path, store: &Store) -> Result<()> {
+    let staging = path.with_extension("tmp");
+    tokio::fs::write(&staging, serde_json::to_vec_pretty(store)?).await?;
+    tokio::fs::rename(&staging, path).await?;
+    Ok(())
+}
+
+fn flatten<T>(result: Result<Result<T>, tokio::task::JoinError>, operation: &str) -> AppResult<T> {
+    let error = match result {
+        Ok(Ok(value)) => return Ok(value),
+        Ok(Err(e)) => e,
+        Err(e) => e.into(),
+    };
+    error!("Could not {} alert store: {:?}", operation, error);
+    Err(AppError::Internal(format!(
+        "Could not {} alerting state",
+        operation
+    )))
+}
@@ -0,0 +1,624 @@
+//! Dimensional breakdowns of the adoption, failure and performance metrics.
+//! Both backends report additive counts per group and time slot; the
+//! largest `top` groups are kept and the rest summed into one "other" group,
+//! so averages and rates stay exact for every group.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use chrono::{DateTime, Utc};
+
+use crate::common::{
+    error::{AppError, AppResult},
+    models::{
+        AdoptionCounts, Breakdown, BreakdownGroup, BreakdownPoint, Dimension, FailureCounts,
+        OtaEventType, PerformanceAverages,
+    },
+};
+
+/// Stands in for a missing context value, on both backends.
+pub const UNKNOWN: &str = "unknown";
+const OTHER: &str = "other";
+
+pub const MAX_GROUP_BY: usize = 2;
+pub const DEFAULT_TOP: usize = 10;
+pub const MAX_TOP: usize = 50;
+// Keeps a breakdown to a bounded number of points per group
+const MAX_SLOTS: i64 = 400;
+
+pub const HOUR_SECS: i64 = 3600;
+pub const DAY_SECS: i64 = 86400;
+
+impl Dimension {
+    /// Column in `ota_events_raw`
+    pub fn column(self) -> &'static str {
+        match self {
+            Self::OsVersion => "osVersion",
+            Self::AppVersion => "appVersion",
+            Self::DeviceType => "deviceType",
+            Self::NetworkType => "networkType",
+            Self::ReleaseId => "releaseId",
+            Self::VariantId => "variantId",
+        }
+    }
+
+    /// Label on the Victoria Metrics context counters
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::OsVersion => "os_version",
+            Self::AppVersion => "app_version",
+            Self::DeviceType => "device_type",
+            Self::NetworkType => "network_type",
+            Self::ReleaseId => "release_id",
+            Self::VariantId => "variant_id",
+        }
+    }
+}
+
+/// Slots of `step` seconds from `start` (inclusive) to `end` (exclusive),
+/// both on slot boundaries, in Unix seconds.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeRange {
+    pub start: i64,
+    pub end: i64,
+    pub step: i64,
+}
+
+impl TimeRange {
+    /// The hours of the UTC day containing `ts_secs`.
+    pub fn hours_of_day(ts_secs: i64) -> Self {
+        let start = ts_secs - ts_secs.rem_euclid(DAY_SECS);
+        Self {
+            start,
+            end: start + DAY_SECS,
+            step: HOUR_SECS,
+        }
+    }
+
+    /// The UTC days from the one containing `start_secs` to the one
+    /// containing `end_secs`, inclusive.
+    pub fn days_between(start_secs: i64, end_secs: i64) -> Self {
+        Self {
+            start: start_secs - start_secs.rem_euclid(DAY_SECS),
+            end: end_secs - end_secs.rem_euclid(DAY_SECS) + DAY_SECS,
+            step: DAY_SECS,
+        }
+    }
+
+    /// The last `days` UTC days, today included.
+    pub fn last_days(days: u32) -> Self {
+        let now = Utc::now().timestamp();
+        let end = now - now.rem_euclid(DAY_SECS) + DAY_SECS;
+        Self {
+            start: end - i64::from(days) * DAY_SECS,
+            end,
+            step: DAY_SECS,
+        }
+    }
+
+    pub fn slots(&self) -> impl Iterator<Item = i64> {
+        let step = self.step;
+        (self.start..self.end).step_by(step as usize)
+    }
+}
+
+/// Which groups and filters a query asked for
+#[derive(Debug, Clone)]
+pub struct BreakdownRequest {
+    pub group_by: Vec<Dimension>,
+    pub filters: BTreeMap<Dimension, String>,
+    pub top: usize,
+    pub range: TimeRange,
+}
+
+impl BreakdownRequest {
+    /// Parses `group_by=os_version,network_type` and
+    /// `filter=os_version:9,network_type:2g`. An explicit `release_id` is
+    /// applied as a filter too. Returns `None` when neither `group_by` nor
+    /// `filter` was given.
+    pub fn parse(
+        group_by: Option<&str>,
+        filter: Option<&str>,
+        release_id: Option<&str>,
+        top: Option<usize>,
+        range: TimeRange,
+    ) -> AppResult<Option<Self>> {
+        if group_by.is_none() && filter.is_none() {
+            return Ok(None);
+        }
+
+        let mut dimensions = Vec::new();
+        for name in split_list(group_by) {
+            let dimension = parse_dimension(name)?;
+            if dimensions.contains(&dimension) {
+                return Err(AppError::Validation(format!(
+                    "group_by lists '{}' twice",
+                    name
+                )));
+            }
+            dimensions.push(dimension);
+        }
+        if dimensions.len() > MAX_GROUP_BY {
+            return Err(AppError::Validation(format!(
+                "group_by takes at most {} dimensions",
+                MAX_GROUP_BY
+            )));
+        }
+
+        let mut filters = BTreeMap::new();
+        for pair in split_list(filter) {
+            let (name, value) = pair
+                .split_once(':')
+                .filter(|(_, value)| !value.is_empty())
+                .ok_or_else(|| {
+                    AppError::Validation(format!(
+                        "Invalid filter '{}'; expected dimension:value",
+                        pair
+                    ))
+                })?;
+            if filters
+                .insert(parse_dimension(name)?, value.to_string())
+                .is_some()
+            {
+                return Err(AppError::Validation(format!(
+                    "filter lists '{}' twice",
+                    name
+                )));
+            }
+        }
+        if let Some(release_id) = release_id {
+            filters
+                .entry(Dimension::ReleaseId)
+                .or_insert_with(|| release_id.to_string());
+        }
+
+        let top = top.unwrap_or(DEFAULT_TOP);
+        if top == 0 || top > MAX_TOP {
+            return Err(AppError::Validation(format!(
+                "top must be between 1 and {}",
+                MAX_TOP
+            )));
+        }
+        if range.end <= range.start {
+            return Err(AppError::Validation("The time range is empty".to_string()));
+        }
+        if (range.end - range.start) / range.step > MAX_SLOTS {
+            return Err(AppError::Validation(format!(
+                "A breakdown covers at most {} time slots",
+                MAX_SLOTS
+            )));
+        }
+
+        Ok(Some(Self {
+            group_by: dimensions,
+            filters,
+            top,
+            range,
+        }))
+    }
+}
+
+fn split_list(value: Option<&str>) -> impl Iterator<Item = &str> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+}
+
+fn parse_dimension(name: &str) -> AppResult<Dimension> {
+    Dimension::from_str(name).map_err(|_| {
+        AppError::Validation(format!(
+            "Unknown dimension '{}'; expected one of os_version, app_version, device_type, network_type, release_id, variant_id",
+            name
+        ))
+    })
+}
+
+/// Additive counts behind every breakdown measure
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BreakdownCounts {
+    pub events: u64,
+    pub download_success: u64,
+    pub download_failures: u64,
+    pub apply_success: u64,
+    pub apply_failures: u64,
+    pub rollbacks_initiated: u64,
+    pub rollbacks_completed: u64,
+    pub rollback_failures: u64,
+    pub update_checks: u64,
+    pub update_available: u64,
+    pub download_time_ms_sum: u64,
+    pub download_time_count: u64,
+    pub apply_time_ms_sum: u64,
+    pub apply_time_count: u64,
+    pub download_size_bytes_sum: u64,
+    pub download_size_count: u64,
+    // Sums of squares, for the variance of the timings
+    pub download_time_ms_sq_sum: f64,
+    pub apply_time_ms_sq_sum: f64,
+}
+
+impl BreakdownCounts {
+    pub fn add(&mut self, other: &Self) {
+        self.events += other.events;
+        self.download_success += other.download_success;
+        self.download_failures += other.download_failures;
+        self.apply_success += other.apply_success;
+        self.apply_failures += other.apply_failures;
+        self.rollbacks_initiated += other.rollbacks_initiated;
+        self.rollbacks_completed += other.rollbacks_completed;
+        self.rollback_failures += other.rollback_failures;
+        self.update_checks += other.update_checks;
+        self.update_available += other.update_available;
+        self.download_time_ms_sum += other.download_time_ms_sum;
+        self.download_time_count += other.download_time_count;
+        self.apply_time_ms_sum += other.apply_time_ms_sum;
+        self.apply_time_count += other.apply_time_count;
+        self.download_size_bytes_sum += other.download_size_bytes_sum;
+        self.download_size_count += other.download_size_count;
+        self.download_time_ms_sq_sum += other.download_time_ms_sq_sum;
+        self.apply_time_ms_sq_sum += other.apply_time_ms_sq_sum;
+    }
+
+    /// Counts `count` events of `event_type`.
+    pub fn add_events(&mut self, event_type: &str, count: u64) {
+        self.events += count;
+        match event_type {
+            "DOWNLOAD_COMPLETED" => self.download_success += count,
+            "DOWNLOAD_FAILED" => self.download_failures += count,
+            "APPLY_SUCCESS" => self.apply_success += count,
+            "APPLY_FAILURE" => self.apply_failures += count,
+            "ROLLBACK_INITIATED" => self.rollbacks_initiated += count,
+            "ROLLBACK_COMPLETED" => self.rollbacks_completed += count,
+            "ROLLBACK_FAILED" => self.rollback_failures += count,
+            "UPDATE_CHECK" => self.update_checks += count,
+            "UPDATE_AVAILABLE" => self.update_available += count,
+            _ => {}
+        }
+    }
+
+    /// Adds `count` observations summing to `sum` of a measure:
+    /// `download_time_ms`, `apply_time_ms` or `download_size_bytes`.
+    pub fn add_measure(&mut self, measure: &str, sum: u64, count: u64) {
+        match measure {
+            "download_time_ms" => {
+                self.download_time_ms_sum += sum;
+                self.download_time_count += count;
+            }
+            "apply_time_ms" => {
+                self.apply_time_ms_sum += sum;
+                self.apply_time_count += count;
+            }
+            "download_size_bytes" => {
+                self.download_size_bytes_sum += sum;
+                self.download_size_count += count;
+            }
+            _ => {}
+        }
+    }
+
+    /// Adds a sum of squares of a timing measure: `download_time_ms` or
+    /// `apply_time_ms`.
+    pub fn add_measure_squares(&mut self, measure: &str, squares: f64) {
+        match measure {
+            "download_time_ms" => self.download_time_ms_sq_sum += squares,
+            "apply_time_ms" => self.apply_time_ms_sq_sum += squares,
+            _ => {}
+        }
+    }
+
+    /// Events of `event_type`, for the types counted here.
+    pub fn count_of(&self, event_type: &OtaEventType) -> Option<u64> {
+        match event_type {
+            OtaEventType::DownloadCompleted => Some(self.download_success),
+            OtaEventType::DownloadFailed => Some(self.download_failures),
+            OtaEventType::ApplySuccess => Some(self.apply_success),
+            OtaEventType::ApplyFailure => Some(self.apply_failures),
+            OtaEventType::RollbackInitiated => Some(self.rollbacks_initiated),
+            OtaEventType::RollbackCompleted => Some(self.rollbacks_completed),
+            OtaEventType::RollbackFailed => Some(self.rollback_failures),
+            OtaEventType::UpdateCheck => Some(self.update_checks),
+            OtaEventType::UpdateAvailable => Some(self.update_available),
+            OtaEventType::UpdateNotAvailable
+            | OtaEventType::DownloadStarted
+            | OtaEventType::DownloadProgress
+            | OtaEventType::ApplyStarted => None,
+        }
+    }
+
+    pub fn failures(&self) -> u64 {
+        self.download_failures + self.apply_failures
+    }
+}
+
+/// Counts of one group in one time slot. `group` holds the values of the
+/// request's `group_by` dimensions, in order.
+#[derive(Debug)]
+pub struct BreakdownRow {
+    pub group: Vec<String>,
+    pub time_slot: i64,
+    pub counts: BreakdownCounts,
+}
+
+fn average(sum: u64, count: u64) -> f64 {
+    if count == 0 {
+        0.0
+    } else {
+        sum as f64 / count as f64
+    }
+}
+
+impl From<&BreakdownCounts> for AdoptionCounts {
+    fn from(counts: &BreakdownCounts) -> Self {
+        Self {
+            download_success: counts.download_success,
+            download_failures: counts.download_failures,
+            apply_success: counts.apply_success,
+            apply_failures: counts.apply_failures,
+            rollbacks_initiated: counts.rollbacks_initiated,
+            rollbacks_completed: counts.rollbacks_completed,
+            rollback_failures: counts.rollback_failures,
+            update_checks: counts.update_checks,
+            update_available: counts.update_available,
+        }
+    }
+}
+
+impl From<&BreakdownCounts> for FailureCounts {
+    fn from(counts: &BreakdownCounts) -> Self {
+        let attempts = counts.download_success + counts.apply_success + counts.failures();
+        Self {
+            attempts,
+            failures: counts.failures(),
+            rollbacks: counts.rollbacks_initiated,
+            failure_rate: average(counts.failures(), attempts) * 100.0,
+        }
+    }
+}
+
+impl From<&BreakdownCounts> for PerformanceAverages {
+    fn from(counts: &BreakdownCounts) -> Self {
+        Self {
+            downloads: counts.download_success,
+            applies: counts.apply_success,
+            avg_download_time_ms: average(counts.download_time_ms_sum, counts.download_time_count),
+            avg_apply_time_ms: average(counts.apply_time_ms_sum, counts.apply_time_count),
+            avg_download_size_bytes: average(
+                counts.download_size_bytes_sum,
+                counts.download_size_count,
+            ),
+        }
+    }
+}
+
+fn to_group<T>(
+    request: &BreakdownRequest,
+    values: Vec<String>,
+    other: bool,
+    total: &BreakdownCounts,
+    slots: &BTreeMap<i64, BreakdownCounts>,
+) -> BreakdownGroup<T>
+where
+    T: for<'a> From<&'a BreakdownCounts>,
+{
+    let empty = BreakdownCounts::default();
+    BreakdownGroup {
+        group: request.group_by.iter().copied().zip(values).collect(),
+        other,
+        total: T::from(total),
+        series: request
+            .range
+            .slots()
+            .map(|slot| BreakdownPoint {
+                time_slot: DateTime::from_timestamp(slot, 0).unwrap_or_else(Utc::now),
+                values: T::from(slots.get(&slot).unwrap_or(&empty)),
+            })
+            .collect(),
+    }
+}
+
+/// Orders the groups by `rank`, largest first, and folds those beyond
+/// `request.top` into an "other" group.
+pub fn assemble<T>(
+    request: &BreakdownRequest,
+    rows: Vec<BreakdownRow>,
+    rank: impl Fn(&BreakdownCounts) -> u64,
+) -> Breakdown<T>
+where
+    T: for<'a> From<&'a BreakdownCounts>,
+{
+    let mut by_group: BTreeMap<Vec<String>, BTreeMap<i64, BreakdownCounts>> = BTreeMap::new();
+    for row in rows {
+        by_group
+            .entry(row.group)
+            .or_default()
+            .entry(row.time_slot)
+            .or_default()
+            .add(&row.counts);
+    }
+
+    let mut ranked: Vec<_> = by_group
+        .into_iter()
+        .map(|(values, slots)| {
+            let mut total = BreakdownCounts::default();
+            slots.values().for_each(|counts| total.add(counts));
+            (values, total, slots)
+        })
+        .collect();
+    ranked.sort_by(|a, b| rank(&b.1).cmp(&rank(&a.1)).then_with(|| a.0.cmp(&b.0)));
+    let rest = ranked.split_off(ranked.len().min(request.top));
+
+    let mut groups: Vec<BreakdownGroup<T>> = ranked
+        .into_iter()
+        .map(|(values, total, slots)| to_group(request, values, false, &total, &slots))
+        .collect();
+    if !rest.is_empty() {
+        let mut total = BreakdownCounts::default();
+        let mut slots: BTreeMap<i64, BreakdownCounts> = BTreeMap::new();
+        for (_, group_total, group_slots) in &rest {
+            total.add(group_total);
+            for (slot, counts) in group_slots {
+                slots.entry(*slot).or_default().add(counts);
+            }
+        }
+        let values = vec![OTHER.to_string(); request.group_by.len()];
+        groups.push(to_group(request, values, true, &total, &slots));
+    }
+
+    Breakdown {
+        group_by: request.group_by.clone(),
+        filters: request.filters.clone(),
+        groups,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day() -> TimeRange {
+        TimeRange::hours_of_day(1_700_000_000)
+    }
+
+    fn parse(group_by: Option<&str>, filter: Option<&str>) -> AppResult<Option<BreakdownRequest>> {
+        BreakdownRequest::parse(group_by, filter, None, None, day())
+    }
+
+    #[test]
+    fn dimensions_and_filters_are_parsed() {
+        let request = BreakdownRequest::parse(
+            Some("os_version, network_type"),
+            Some("device_type:phone,app_version:2.1"),
+            Some("rel-1"),
+            Some(3),
+            day(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            request.group_by,
+            vec![Dimension::OsVersion, Dimension::NetworkType]
+        );
+        assert_eq!(request.filters.len(), 3);
+        assert_eq!(request.filters[&Dimension::DeviceType], "phone");
+        assert_eq!(request.filters[&Dimension::AppVersion], "2.1");
+        assert_eq!(request.filters[&Dimension::ReleaseId], "rel-1");
+        assert_eq!(request.top, 3);
+
+        assert!(parse(None, None).unwrap().is_none());
+        assert_eq!(
+            parse(Some("os_version"), None).unwrap().unwrap().top,
+            DEFAULT_TOP
+        );
+    }
+
+    #[test]
+    fn filter_on_release_wins_over_the_release_parameter() {
+        let request =
+            BreakdownRequest::parse(None, Some("release_id:rel-2"), Some("rel-1"), None, day())
+                .unwrap()
+                .unwrap();
+        assert_eq!(request.filters[&Dimension::ReleaseId], "rel-2");
+    }
+
+    #[test]
+    fn invalid_requests_are_rejected() {
+        for (group_by, filter) in [
+            (Some("os_version,carrier"), None),
+            (None, Some("carrier:acme")),
+            (Some("os_version,os_version"), None),
+            (Some("os_version,app_version,device_type"), None),
+            (None, Some("os_version")),
+            (None, Some("os_version:")),
+            (None, Some("os_version:9,os_version:10")),
+        ] {
+            assert!(
+                matches!(parse(group_by, filter), Err(AppError::Validation(_))),
+                "accepted group_by={:?} filter={:?}",
+                group_by,
+                filter
+            );
+        }
+        for top in [0, MAX_TOP + 1] {
+            assert!(
+                BreakdownRequest::parse(Some("os_version"), None, None, Some(top), day()).is_err()
+            );
+        }
+        let range = TimeRange {
+            start: 0,
+            end: (MAX_SLOTS + 1) * HOUR_SECS,
+            step: HOUR_SECS,
+        };
+        assert!(BreakdownRequest::parse(Some("os_version"), None, None, None, range).is_err());
+    }
+
+    fn row(group: &str, time_slot: i64, downloads: u64) -> BreakdownRow {
+        let mut counts = BreakdownCounts::default();
+        counts.add_events("DOWNLOAD_COMPLETED", downloads);
+        BreakdownRow {
+            group: vec![group.to_string()],
+            time_slot,
+            counts,
+        }
+    }
+
+    #[test]
+    fn groups_beyond_top_are_folded_into_other() {
+        let range = day();
+        let request = BreakdownRequest::parse(Some("os_version"), None, None, Some(2), range)
+            .unwrap()
+            .unwrap();
+        let (first, second) = (range.start, range.start + HOUR_SECS);
+        let rows = vec![
+            row("12", first, 5),
+            row("13", first, 20),
+            row("13", second, 10),
+            row("11", first, 8),
+            row("10", first, 3),
+            row("10", second, 4),
+            row("9", second, 1),
+        ];
+        let breakdown: Breakdown<PerformanceAverages> =
+            assemble(&request, rows, |counts| counts.download_success);
+
+        let groups: Vec<_> = breakdown
+            .groups
+            .iter()
+            .map(|group| {
+                (
+                    group.group[&Dimension::OsVersion].as_str(),
+                    group.other,
+                    group.total.downloads,
+                )
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            vec![("13", false, 30), ("11", false, 8), (OTHER, true, 13)]
+        );
+
+        let other = &breakdown.groups[2];
+        assert_eq!(other.series.len(), 24);
+        assert_eq!(other.series[0].values.downloads, 8);
+        assert_eq!(other.series[1].values.downloads, 5);
+        assert_eq!(other.series[2].values.downloads, 0);
+    }
+
+    #[test]
+    fn no_other_group_when_everything_fits() {
+        let range = day();
+        let request = BreakdownRequest::parse(Some("os_version"), None, None, Some(2), range)
+            .unwrap()
+            .unwrap();
+        let rows = vec![row("12", range.start, 5), row("13", range.start, 5)];
+        let breakdown: Breakdown<PerformanceAverages> =
+            assemble(&request, rows, |counts| counts.download_success);
+        // Ties are broken by group value
+        let values: Vec<_> = breakdown
+            .groups
+            .iter()
+            .map(|group| (group.group[&Dimension::OsVersion].as_str(), group.other))
+            .collect();
+        assert_eq!(values, vec![("12", false), ("13", false)]);
+    }
+}
@@ -1,25 +1,35 @@
+pub mod erasure;
 pub mod models;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 
 use anyhow::{anyhow, Ok, Result};
 use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
-use clickhouse::{Client as ClickHouseClient, Row};
+use clickhouse::{query::RowCursor, Client as ClickHouseClient, Row};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use time::{Date, Duration, OffsetDateTime, Time};
-use tracing::info;
+use tracing::{info, warn};
 use uuid::Uuid;
 
 use crate::{
     common::{
-        config::ClickHouseConfig,
+        config::{ClickHouseConfig, PrivacyConfig},
         models::{
             ActiveDevicesMetrics, AdoptionMetrics, AdoptionTimeSeries, AnalyticsInterval,
-            DailyActiveDevices, DailyFailures, ErrorFrequency, FailureAnalytics, OtaEvent,
-            VersionDistribution, VersionMetrics,
+            DailyActiveDevices, DailyFailures, Dimension, ErrorFrequency, FailureAnalytics,
+            OtaEvent, VersionDistribution, VersionMetrics,
         },
     },
-    core::clickhouse::models::OtaEventRow,
+    core::{
+        breakdown::{self, BreakdownCounts, BreakdownRequest, BreakdownRow, TimeRange},
+        clickhouse::erasure::SharedErasedDevices,
+        clickhouse::models::{
+            AppRow, BreakdownCountsRow, OtaEventRow, RetentionRow, StoredOtaEventRow,
+            VariantDevicesRow,
+        },
+        export::EventFilter,
+    },
 };
 
 #[derive(Clone)]
@@ -27,10 +37,12 @@ pub struct Client {
     pub client: ClickHouseClient,
     #[allow(dead_code)]
     pub database: String,
+    privacy: PrivacyConfig,
+    erased: SharedErasedDevices,
 }
 
 impl Client {
-    pub async fn new(config: &ClickHouseConfig) -> Result<Self> {
+    pub async fn new(config: &ClickHouseConfig, privacy: &PrivacyConfig) -> Result<Self> {
         let mut client = ClickHouseClient::default()
             .with_url(&config.url)
             .with_database(&config.database);
@@ -46,6 +58,8 @@ impl Client {
         let client_instance = Self {
             client,
             database: config.database.clone(),
+            privacy: privacy.clone(),
+            erased: SharedErasedDevices::default(),
         };
 
         // The schema is now initialized via init-clickhouse.sql
@@ -75,6 +89,7 @@ impl Client {
             timestamp: event.timestamp.timestamp(),
             event_date: (event.timestamp.num_days_from_ce() - 719_163) as u16, // Convert to ClickHouse date format (days since 1970-01-01),
             release_id: event.release_id.clone(),
+            variant_id: event.variant_id.clone(),
             current_js_version: event.current_js_version.clone(),
             target_js_version: event.target_js_version.clone(),
             rollout_percentage: event.rollout_percentage,
@@ -95,6 +110,7 @@ impl Client {
                 .unwrap_or_else(|| "{}".to_string()),
             user_agent: event.user_agent.clone(),
             ip_address: event.ip_address.clone(),
+            retention_days: self.privacy.retention_days_for(&event.org_id),
         };
 
         let mut insert = self.client.insert("ota_events_raw")?;
@@ -127,16 +143,38 @@ impl Client {
         Self::try_days_from_epoch_chrono(ts_secs).unwrap_or(20_219)
     }
 
+    /// Inserts events. `ota_events_raw` keeps one row per `event_id`: a
+    /// retried event stored again is folded into the first copy when parts
+    /// merge, and reads use `FINAL` so it is counted once before that. A
+    /// replayed batch carries the same deduplication token, so ClickHouse
+    /// drops it outright, materialized views included.
     pub async fn insert_ota_events_batch(&self, events: Vec<OtaEvent>) -> Result<()> {
+        let mut events: Vec<OtaEvent> = events
+            .into_iter()
+            .map(|mut event| {
+                event.event_id.get_or_insert_with(Uuid::new_v4);
+                event
+            })
+            .collect();
+        let mut batch_ids = HashSet::new();
+        events.retain(|event| event.event_id.is_some_and(|id| batch_ids.insert(id)));
+        self.drop_erased(&mut events).await?;
         if events.is_empty() {
             return Ok(());
         }
 
+        let mut token = Sha256::new();
+        for event_id in events.iter().filter_map(|event| event.event_id) {
+            token.update(event_id.as_bytes());
+        }
+        let token = hex::encode(token.finalize());
+
         let events_len = events.len();
         let rows: Vec<OtaEventRow> = events
             .into_iter()
             .map(|event| {
                 OtaEventRow {
+                    retention_days: self.privacy.retention_days_for(&event.org_id),
                     org_id: event.org_id,
                     app_id: event.app_id,
                     device_id: event.device_id,
@@ -146,6 +184,7 @@ impl Client {
                     timestamp: event.timestamp.timestamp_millis(),
                     event_date: Self::days_from_epoch_chrono(event.timestamp.timestamp()), // Convert to ClickHouse date format (days since 1970-01-01)
                     release_id: event.release_id,
+                    variant_id: event.variant_id,
                     current_js_version: event.current_js_version,
                     target_js_version: event.target_js_version,
                     rollout_percentage: event.rollout_percentage,
@@ -172,7 +211,12 @@ impl Client {
 
         info!("Inserting {}", serde_json::json!(rows));
 
-        let mut insert = self.client.insert("ota_events_raw")?;
+        let mut insert = self
+            .client
+            .clone()
+            .with_option("insert_deduplication_token", token)
+            .with_option("deduplicate_blocks_in_dependent_materialized_views", "1")
+            .insert("ota_events_raw")?;
         for row in rows {
             insert.write(&row).await?;
         }
@@ -200,52 +244,55 @@ impl Client {
                 .unwrap_or_else(Utc::now)
         }
 
-        let make_fetch = |view_name: &'static str, column_alias: &'static str| {
+        // Counted from the raw table rather than the hourly_* views: FINAL
+        // folds retried events into one row, while the views have already
+        // summed every copy that was inserted
+        let make_fetch = |event_type: &'static str| {
             let client = self.client.clone();
             let org_id = org_id.to_string();
             let app_id = app_id.to_string();
             let release_id = release_id.to_string();
             async move {
-                let sql = format!(
-                    r#"
+                let sql = r#"
                     SELECT
-                        event_hour   AS hour,
-                        {col}        AS cnt
-                    FROM {view}
+                        toHour(timestamp) AS hour,
+                        count()           AS cnt
+                    FROM ota_events_raw FINAL
                     WHERE
-                          org_id      = '{org}'
-                      AND app_id      = '{app}'
-                      AND release_id  = '{release}'
-                      AND event_date  = toDate(toDateTime64('{ts}', 3))
-                    "#,
-                    col = column_alias,
-                    view = view_name,
-                    org = org_id,
-                    app = app_id,
-                    release = release_id,
-                    ts = ts_millis
-                );
-
-                let mut cursor = client.query(&sql).fetch::<(u8, u64)>()?;
+                          orgId = ?
+                      AND appId = ?
+                      AND ifNull(releaseId, 'default') = ?
+                      AND eventType = ?
+                      AND eventDate = toDate(fromUnixTimestamp64Milli(?))
+                    GROUP BY hour
+                    "#;
+
+                let mut cursor = client
+                    .query(sql)
+                    .bind(&org_id)
+                    .bind(&app_id)
+                    .bind(&release_id)
+                    .bind(event_type)
+                    .bind(ts_millis)
+                    .fetch::<(u8, u64)>()?;
                 let mut rows = Vec::new();
                 while let Some((hour, cnt)) = cursor.next().await? {
                     rows.push((hour, cnt));
                 }
-                info!("Fetched {} rows from {}", rows.len(), view_name);
+                info!("Fetched {} hourly rows for {}", rows.len(), event_type);
                 Ok(rows)
             }
         };
 
-        let downloads_fut = make_fetch("hourly_downloads", "download_count");
-        let applies_fut = make_fetch("hourly_applies", "apply_count");
-        let dl_failures_fut = make_fetch("hourly_download_failures", "download_failure_count");
-        let ap_failures_fut = make_fetch("hourly_apply_failures", "apply_failure_count");
-        let rb_inits_fut = make_fetch("hourly_rollback_initiates", "rollback_initiate_count");
-        let rollbacks_fut = make_fetch("hourly_rollback_completes", "rollback_complete_count");
-        let rb_failures_fut = make_fetch("hourly_rollback_failures", "rollback_failures_count");
-        let update_checks_fut = make_fetch("hourly_update_checks", "update_check_count");
-        let update_available_fut =
-            make_fetch("hourly_update_availables", "update_availability_count");
+        let downloads_fut = make_fetch("DOWNLOAD_COMPLETED");
+        let applies_fut = make_fetch("APPLY_SUCCESS");
+        let dl_failures_fut = make_fetch("DOWNLOAD_FAILED");
+        let ap_failures_fut = make_fetch("APPLY_FAILURE");
+        let rb_inits_fut = make_fetch("ROLLBACK_INITIATED");
+        let rollbacks_fut = make_fetch("ROLLBACK_COMPLETED");
+        let rb_failures_fut = make_fetch("ROLLBACK_FAILED");
+        let update_checks_fut = make_fetch("UPDATE_CHECK");
+        let update_available_fut = make_fetch("UPDATE_AVAILABLE");
 
         let (
             downloads_res,
@@ -436,60 +483,62 @@ impl Client {
             event_date: time::Date,
         }
 
-        let make_fetch = |view_name: &'static str, column_alias: &'static str| {
+        // Counted from the raw table with FINAL, like the hourly metrics, so
+        // retried events are only counted once
+        let make_fetch = |event_type: &'static str| {
             let client = self.client.clone();
             let org_id = org_id.to_string();
             let app_id = app_id.to_string();
             let release_id = release_id.to_string();
             async move {
-                let sql = format!(
-                    r#"
+                let sql = r#"
                     SELECT
-                        {col}        AS cnt,
-                        event_date   AS event_date
-                    FROM {view}
+                        count()   AS cnt,
+                        eventDate AS event_date
+                    FROM ota_events_raw FINAL
                     WHERE
-                          org_id      = '{org}'
-                      AND app_id      = '{app}'
-                      AND release_id  = '{release}'
-                      AND event_date >= toDate(toDateTime64('{ts_start}', 3))
-                      AND event_date <= toDate(toDateTime64('{ts_end}', 3))
-                    "#,
-                    col = column_alias,
-                    view = view_name,
-                    org = org_id,
-                    app = app_id,
-                    release = release_id,
-                    ts_start = start_date_millis,
-                    ts_end = end_date_millis
-                );
-
-                let mut cursor = client.query(&sql).fetch::<RawCHEventAggregate>()?;
+                          orgId = ?
+                      AND appId = ?
+                      AND ifNull(releaseId, 'default') = ?
+                      AND eventType = ?
+                      AND eventDate >= toDate(fromUnixTimestamp64Milli(?))
+                      AND eventDate <= toDate(fromUnixTimestamp64Milli(?))
+                    GROUP BY event_date
+                    "#;
+
+                let mut cursor = client
+                    .query(sql)
+                    .bind(&org_id)
+                    .bind(&app_id)
+                    .bind(&release_id)
+                    .bind(event_type)
+                    .bind(start_date_millis)
+                    .bind(end_date_millis)
+                    .fetch::<RawCHEventAggregate>()?;
                 let mut rows = Vec::new();
                 while let Some(raw_aggregate) = cursor.next().await? {
                     rows.push(raw_aggregate);
                 }
                 info!(
-                    "Fetched {} rows from {} and rows: {:?}",
+                    "Fetched {} daily rows for {} and rows: {:?}",
                     rows.len(),
-                    view_name,
+                    event_type,
                     rows
                 );
                 Ok(rows)
             }
         };
 
-        // Spawn six futures in parallel:
-        let downloads_fut = make_fetch("daily_downloads", "download_count");
-        let applies_fut = make_fetch("daily_applies", "apply_count");
-        let dl_failures_fut = make_fetch("daily_download_failures", "download_failure_count");
-        let ap_failures_fut = make_fetch("daily_apply_failures", "apply_failure_count");
-        let rb_inits_fut = make_fetch("daily_rollback_initiates", "rollback_initiate_count");
-        let rollbacks_fut = make_fetch("daily_rollback_completes", "rollback_complete_count");
-        let rb_failures_fut = make_fetch("daily_rollback_failures", "rollback_failures_count");
-        let update_checks_fut = make_fetch("daily_update_checks", "update_check_count");
-        let update_available_fut =
-            make_fetch("daily_update_availables", "update_availability_count");
+        // Spawn the futures in parallel:
+        let downloads_fut = make_fetch("DOWNLOAD_COMPLETED");
+        let applies_fut = make_fetch("APPLY_SUCCESS");
+        let dl_failures_fut = make_fetch("DOWNLOAD_FAILED");
+        let ap_failures_fut = make_fetch("APPLY_FAILURE");
+        let rb_inits_fut = make_fetch("ROLLBACK_INITIATED");
+        let rollbacks_fut = make_fetch("ROLLBACK_COMPLETED");
+        let rb_failures_fut = make_fetch("ROLLBACK_FAILED");
+        let update_checks_fut = make_fetch("UPDATE_CHECK");
+        let update_available_fut = make_fetch("UPDATE_AVAILABLE");
 
         // Run them concurrently
         let (
@@ -675,6 +724,7 @@ impl Client {
                     app_id: app_id.to_string(),
                     release_id: release_id.to_string(),
                     time_breakdown: hourly_adoption_metrics.await?,
+                    breakdown: None,
                 })
             }
             AnalyticsInterval::Day => {
@@ -691,6 +741,7 @@ impl Client {
                     app_id: app_id.to_string(),
                     release_id: release_id.to_string(),
                     time_breakdown: daywise_adoption_metrics.await?,
+                    breakdown: None,
                 })
             }
             AnalyticsInterval::Week => todo!(),
@@ -709,7 +760,7 @@ impl Client {
             SELECT 
                 ifNull(currentJsVersion, '') as js_version,
                 uniq(deviceId) as device_count
-            FROM ota_events_raw 
+            FROM ota_events_raw FINAL
             WHERE 
                   orgId = '{}' 
               AND appId = '{}' 
@@ -762,7 +813,7 @@ impl Client {
         SELECT
             eventDate AS event_date,
             uniq(deviceId) AS active_devices
-        FROM ota_events_raw
+        FROM ota_events_raw FINAL
         WHERE
               orgId    = '{}'
           AND appId    = '{}'
@@ -825,7 +876,7 @@ impl Client {
             SELECT 
                 eventDate as event_date,
                 uniq(deviceId) as active_devices
-            FROM ota_events_raw 
+            FROM ota_events_raw FINAL
             WHERE
                   orgId = '{}' 
               AND appId = '{}' 
@@ -884,7 +935,7 @@ impl Client {
             SELECT 
                 countIf(eventType IN ('APPLY_FAILURE', 'DOWNLOAD_FAILED')) as total_failures,
                 countIf(eventType = 'rollback_triggered') as total_rollbacks
-            FROM ota_events_raw 
+            FROM ota_events_raw FINAL
             WHERE {}
             "#,
             where_clause
@@ -904,7 +955,7 @@ impl Client {
                 toDate(timestamp) as event_date,
                 countIf(eventType IN ('APPLY_FAILURE', 'DOWNLOAD_FAILED')) as failures,
                 countIf(eventType = 'rollback_triggered') as rollbacks
-            FROM ota_events_raw 
+            FROM ota_events_raw FINAL
             WHERE {}
             GROUP BY event_date
             ORDER BY event_date
@@ -932,7 +983,7 @@ impl Client {
             SELECT 
                 errorCode,
                 count() as frequency
-            FROM ota_events_raw 
+            FROM ota_events_raw FINAL
             WHERE {} 
               AND eventType IN ('APPLY_FAILURE', 'DOWNLOAD_FAILED')
               AND errorCode IS NOT NULL
@@ -978,4 +1029,406 @@ impl Client {
             failure_rate_trend,
         })
     }
+
+    /// Events of one device (optionally one session) between `start_ms` and
+    /// `end_ms`, oldest first, at most `limit` of them. The `deviceId` bloom
+    /// filter index keeps this from scanning the application's whole range.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_device_timeline(
+        &self,
+        org_id: &str,
+        app_id: &str,
+        device_id: &str,
+        session_id: Option<&str>,
+        start_ms: i64,
+        end_ms: i64,
+        limit: usize,
+    ) -> Result<Vec<OtaEvent>> {
+        // eventDate is repeated so whole partitions outside the range are skipped
+        let mut sql = String::from(
+            r#"
+            SELECT ?fields
+            FROM ota_events_raw FINAL
+            WHERE orgId = ?
+              AND appId = ?
+              AND deviceId = ?
+              AND eventDate BETWEEN toDate(fromUnixTimestamp64Milli(?)) AND toDate(fromUnixTimestamp64Milli(?))
+              AND timestamp >= fromUnixTimestamp64Milli(?)
+              AND timestamp < fromUnixTimestamp64Milli(?)
+            "#,
+        );
+        if session_id.is_some() {
+            sql.push_str(" AND sessionId = ?");
+        }
+        sql.push_str(" ORDER BY timestamp, ingestedAt LIMIT ?");
+
+        let mut query = self
+            .client
+            .query(&sql)
+            .bind(org_id)
+            .bind(app_id)
+            .bind(device_id)
+            .bind(start_ms)
+            .bind(end_ms)
+            .bind(start_ms)
+            .bind(end_ms);
+        if let Some(session_id) = session_id {
+            query = query.bind(session_id);
+        }
+        let rows = query
+            .bind(limit as u64)
+            .fetch_all::<StoredOtaEventRow>()
+            .await?;
+
+        let mut events = Vec::with_capacity(rows.len());
+        for row in rows {
+            let Some(event_type) = row.event_type.parse().ok() else {
+                warn!(
+                    "Skipping event {} with unknown type {}",
+                    row.event_id, row.event_type
+                );
+                continue;
+            };
+            events.push(OtaEvent {
+                org_id: row.org_id,
+                app_id: row.app_id,
+                device_id: row.device_id,
+                session_id: row.session_id,
+                event_type,
+                event_id: Some(row.event_id),
+                timestamp: DateTime::from_timestamp_millis(row.timestamp)
+                    .ok_or_else(|| anyhow!("Invalid timestamp {}", row.timestamp))?,
+                release_id: row.release_id,
+                variant_id: row.variant_id,
+                current_js_version: row.current_js_version,
+                target_js_version: row.target_js_version,
+                rollout_percentage: row.rollout_percentage,
+                os_version: row.os_version,
+                app_version: row.app_version,
+                device_type: row.device_type,
+                network_type: row.network_type,
+                error_code: row.error_code,
+                error_message: row.error_message,
+                stack_trace: row.stack_trace,
+                download_size_bytes: row.download_size_bytes,
+                download_time_ms: row.download_time_ms,
+                apply_time_ms: row.apply_time_ms,
+                payload: serde_json::from_str(&row.payload).ok(),
+                user_agent: row.user_agent,
+                ip_address: row.ip_address,
+            });
+        }
+        Ok(events)
+    }
+
+    /// Counts per breakdown group and time slot, read from the raw events
+    /// since the aggregate views keep no device context.
+    pub async fn get_breakdown_rows(
+        &self,
+        org_id: &str,
+        app_id: &str,
+        request: &BreakdownRequest,
+    ) -> Result<Vec<BreakdownRow>> {
+        let context = |dimension: Dimension| {
+            format!("ifNull({}, '{}')", dimension.column(), breakdown::UNKNOWN)
+        };
+        let group_key = request
+            .group_by
+            .iter()
+            .map(|dimension| context(*dimension))
+            .collect::<Vec<_>>()
+            .join(", ");
+        // eventDate is repeated so whole partitions outside the range are skipped
+        let mut sql = format!(
+            r#"
+            SELECT
+                CAST([{group_key}], 'Array(String)') AS group_key,
+                toInt64(toUnixTimestamp(toStartOfInterval(timestamp, INTERVAL {step} SECOND))) AS slot,
+                count() AS events,
+                countIf(eventType = 'DOWNLOAD_COMPLETED') AS download_success,
+                countIf(eventType = 'DOWNLOAD_FAILED') AS download_failures,
+                countIf(eventType = 'APPLY_SUCCESS') AS apply_success,
+                countIf(eventType = 'APPLY_FAILURE') AS apply_failures,
+                countIf(eventType = 'ROLLBACK_INITIATED') AS rollbacks_initiated,
+                countIf(eventType = 'ROLLBACK_COMPLETED') AS rollbacks_completed,
+                countIf(eventType = 'ROLLBACK_FAILED') AS rollback_failures,
+                countIf(eventType = 'UPDATE_CHECK') AS update_checks,
+                countIf(eventType = 'UPDATE_AVAILABLE') AS update_available,
+                sumIf(assumeNotNull(downloadTimeMs), eventType = 'DOWNLOAD_COMPLETED' AND downloadTimeMs IS NOT NULL) AS download_time_ms_sum,
+                countIf(eventType = 'DOWNLOAD_COMPLETED' AND downloadTimeMs IS NOT NULL) AS download_time_count,
+                sumIf(assumeNotNull(applyTimeMs), eventType = 'APPLY_SUCCESS' AND applyTimeMs IS NOT NULL) AS apply_time_ms_sum,
+                countIf(eventType = 'APPLY_SUCCESS' AND applyTimeMs IS NOT NULL) AS apply_time_count,
+                sumIf(assumeNotNull(downloadSizeBytes), eventType = 'DOWNLOAD_COMPLETED' AND downloadSizeBytes IS NOT NULL) AS download_size_bytes_sum,
+                countIf(eventType = 'DOWNLOAD_COMPLETED' AND downloadSizeBytes IS NOT NULL) AS download_size_count,
+                sumIf(pow(toFloat64(assumeNotNull(downloadTimeMs)), 2), eventType = 'DOWNLOAD_COMPLETED' AND downloadTimeMs IS NOT NULL) AS download_time_ms_sq_sum,
+                sumIf(pow(toFloat64(assumeNotNull(applyTimeMs)), 2), eventType = 'APPLY_SUCCESS' AND applyTimeMs IS NOT NULL) AS apply_time_ms_sq_sum
+            FROM ota_events_raw FINAL
+            WHERE orgId = ?
+              AND appId = ?
+              AND eventDate BETWEEN toDate(fromUnixTimestamp(?)) AND toDate(fromUnixTimestamp(?))
+              AND timestamp >= fromUnixTimestamp(?)
+              AND timestamp < fromUnixTimestamp(?)
+            "#,
+            group_key = group_key,
+            step = request.range.step,
+        );
+        for dimension in request.filters.keys() {
+            sql.push_str(&format!(" AND {} = ?", context(*dimension)));
+        }
+        sql.push_str(" GROUP BY group_key, slot");
+
+        let range = request.range;
+        let mut query = self
+            .client
+            .query(&sql)
+            .bind(org_id)
+            .bind(app_id)
+            .bind(range.start)
+            .bind(range.end - 1)
+            .bind(range.start)
+            .bind(range.end);
+        for value in request.filters.values() {
+            query = query.bind(value.as_str());
+        }
+        let rows = query.fetch_all::<BreakdownCountsRow>().await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| BreakdownRow {
+                group: row.group_key,
+                time_slot: row.slot,
+                counts: BreakdownCounts {
+                    events: row.events,
+                    download_success: row.download_success,
+                    download_failures: row.download_failures,
+                    apply_success: row.apply_success,
+                    apply_failures: row.apply_failures,
+                    rollbacks_initiated: row.rollbacks_initiated,
+                    rollbacks_completed: row.rollbacks_completed,
+                    rollback_failures: row.rollback_failures,
+                    update_checks: row.update_checks,
+                    update_available: row.update_available,
+                    download_time_ms_sum: row.download_time_ms_sum,
+                    download_time_count: row.download_time_count,
+                    apply_time_ms_sum: row.apply_time_ms_sum,
+                    apply_time_count: row.apply_time_count,
+                    download_size_bytes_sum: row.download_size_bytes_sum,
+                    download_size_count: row.download_size_count,
+                    download_time_ms_sq_sum: row.download_time_ms_sq_sum,
+                    apply_time_ms_sq_sum: row.apply_time_ms_sq_sum,
+                },
+            })
+            .collect())
+    }
+
+    /// Distinct devices per variant of a release, and distinct devices of
+    /// the whole app, over `range`.
+    pub async fn get_variant_devices(
+        &self,
+        org_id: &str,
+        app_id: &str,
+        release_id: &str,
+        range: TimeRange,
+    ) -> Result<(BTreeMap<String, u64>, u64)> {
+        let rows = self
+            .client
+            .query(&format!(
+                r#"
+                SELECT
+                    ifNull(variantId, '{unknown}') AS variant_id,
+                    uniqExact(deviceId) AS devices
+                FROM ota_events_raw FINAL
+                WHERE orgId = ?
+                  AND appId = ?
+                  AND releaseId = ?
+                  AND eventDate BETWEEN toDate(fromUnixTimestamp(?)) AND toDate(fromUnixTimestamp(?))
+                  AND timestamp >= fromUnixTimestamp(?)
+                  AND timestamp < fromUnixTimestamp(?)
+                GROUP BY variant_id
+                "#,
+                unknown = breakdown::UNKNOWN,
+            ))
+            .bind(org_id)
+            .bind(app_id)
+            .bind(release_id)
+            .bind(range.start)
+            .bind(range.end - 1)
+            .bind(range.start)
+            .bind(range.end)
+            .fetch_all::<VariantDevicesRow>()
+            .await?;
+
+        let total = self
+            .client
+            .query(
+                r#"
+                SELECT uniqExact(deviceId)
+                FROM ota_events_raw FINAL
+                WHERE orgId = ?
+                  AND appId = ?
+                  AND eventDate BETWEEN toDate(fromUnixTimestamp(?)) AND toDate(fromUnixTimestamp(?))
+                  AND timestamp >= fromUnixTimestamp(?)
+                  AND timestamp < fromUnixTimestamp(?)
+                "#,
+            )
+            .bind(org_id)
+            .bind(app_id)
+            .bind(range.start)
+            .bind(range.end - 1)
+            .bind(range.start)
+            .bind(range.end)
+            .fetch_one::<u64>()
+            .await?;
+
+        Ok((
+            rows.into_iter()
+                .map(|row| (row.variant_id, row.devices))
+                .collect(),
+            total,
+        ))
+    }
+
+    /// The filter's events of one UTC day, oldest first, read as they
+    /// arrive. Sorting a day at a time keeps ClickHouse from sorting the
+    /// whole range at once.
+    pub fn event_cursor(
+        &self,
+        filter: &EventFilter,
+        day: NaiveDate,
+    ) -> Result<RowCursor<StoredOtaEventRow>> {
+        let mut sql = String::from(
+            r#"
+            SELECT ?fields
+            FROM ota_events_raw FINAL
+            WHERE orgId = ?
+              AND appId = ?
+              AND eventDate = toDate(?)
+              AND timestamp >= fromUnixTimestamp64Milli(?)
+              AND timestamp < fromUnixTimestamp64Milli(?)
+            "#,
+        );
+        if filter.release_id.is_some() {
+            sql.push_str(" AND releaseId = ?");
+        }
+        if filter.event_type.is_some() {
+            sql.push_str(" AND eventType = ?");
+        }
+        sql.push_str(" ORDER BY timestamp, eventId");
+
+        let mut query = self
+            .client
+            .query(&sql)
+            .bind(&filter.org_id)
+            .bind(&filter.app_id)
+            .bind(day.format("%Y-%m-%d").to_string())
+            .bind(filter.start_ms)
+            .bind(filter.end_ms);
+        if let Some(release_id) = &filter.release_id {
+            query = query.bind(release_id);
+        }
+        if let Some(event_type) = &filter.event_type {
+            query = query.bind(event_type.to_string());
+        }
+        Ok(query.fetch::<StoredOtaEventRow>()?)
+    }
+
+    /// Rebuilds an `ota_events_raw` created before events were deduplicated
+    /// by id as a ReplacingMergeTree keyed by `(orgId, appId, eventId)`,
+    /// copying its rows. Tables that were already replacing but also keyed
+    /// and partitioned by time are rebuilt too, since a retried event with a
+    /// different timestamp never merged with the first copy there. This
+    /// takes a while on a large table, and events other instances store
+    /// meanwhile are lost, so upgrade with a single instance running.
+    pub async fn migrate_events_engine(&self) -> Result<()> {
+        let (engine, sorting_key) = self
+            .client
+            .query(
+                "SELECT engine, sorting_key FROM system.tables WHERE database = currentDatabase() AND name = 'ota_events_raw'",
+            )
+            .fetch_one::<(String, String)>()
+            .await?;
+        if engine == "ReplacingMergeTree" && sorting_key == "orgId, appId, eventId" {
+            return Ok(());
+        }
+
+        warn!("Rebuilding ota_events_raw to keep one row per event id");
+        for stmt in [
+            "DROP TABLE IF EXISTS ota_events_raw_dedup",
+            "CREATE TABLE ota_events_raw_dedup AS ota_events_raw
+             ENGINE = ReplacingMergeTree
+             ORDER BY (orgId, appId, eventId)
+             TTL eventDate + toIntervalDay(retentionDays)
+             SETTINGS index_granularity = 8192, non_replicated_deduplication_window = 1000",
+            "ALTER TABLE ota_events_raw_dedup ADD INDEX IF NOT EXISTS idx_device_id deviceId TYPE bloom_filter(0.01) GRANULARITY 4",
+            "INSERT INTO ota_events_raw_dedup SELECT * FROM ota_events_raw",
+            // Materialized views follow the table name, so they keep
+            // receiving new events
+            "EXCHANGE TABLES ota_events_raw AND ota_events_raw_dedup",
+            "DROP TABLE ota_events_raw_dedup",
+        ] {
+            self.client
+                .query(stmt)
+                .execute()
+                .await
+                .map_err(|e| anyhow!("Failed to run statement `{}`: {}", stmt, e))?;
+        }
+        info!("ota_events_raw now keeps one row per event id");
+        Ok(())
+    }
+
+    /// Brings the `retentionDays` of stored rows in line with the current
+    /// policy. Rows record their window at insert, so without this a changed
+    /// `RETENTION_DAYS` or `RETENTION_ORG_DAYS` would only reach new rows.
+    /// The updates run as background mutations; rewritten parts get their
+    /// TTL recalculated, and rows past the new window go at the next TTL
+    /// merge.
+    pub async fn apply_retention(&self) -> Result<()> {
+        let stored = self
+            .client
+            .query("SELECT DISTINCT orgId, retentionDays FROM ota_events_raw")
+            .fetch_all::<RetentionRow>()
+            .await?;
+        let outdated: BTreeMap<String, u16> = stored
+            .into_iter()
+            .map(|row| {
+                let days = self.privacy.retention_days_for(&row.org_id);
+                (row, days)
+            })
+            .filter(|(row, days)| row.retention_days != *days)
+            .map(|(row, days)| (row.org_id, days))
+            .collect();
+
+        for (org_id, days) in &outdated {
+            self.client
+                .query(
+                    "ALTER TABLE ota_events_raw UPDATE retentionDays = ? WHERE orgId = ? AND retentionDays != ?",
+                )
+                .bind(days)
+                .bind(org_id)
+                .bind(days)
+                .execute()
+                .await?;
+            info!(
+                "Applying a {} day retention window to stored events of {}",
+                days, org_id
+            );
+        }
+        Ok(())
+    }
+
+    /// Applications with events on `day`
+    pub async fn list_apps_with_events(&self, day: NaiveDate) -> Result<Vec<(String, String)>> {
+        let rows = self
+            .client
+            .query(
+                "SELECT DISTINCT orgId, appId FROM ota_events_raw WHERE eventDate = toDate(?) ORDER BY orgId, appId",
+            )
+            .bind(day.format("%Y-%m-%d").to_string())
+            .fetch_all::<AppRow>()
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.org_id, row.app_id))
+            .collect())
+    }
 }
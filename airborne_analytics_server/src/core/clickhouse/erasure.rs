@@ -0,0 +1,273 @@
+//! Device erasure on ClickHouse. A request only records a pending receipt;
+//! a worker on every instance deletes the device's events and completes it,
+//! retrying until the deletion is confirmed, so a restart or a failed
+//! mutation never leaves a request forgotten. Receipts identify the device
+//! by digest alone, and the deletion matches rows by the same digest.
+//!
+//! Events of an erased device that were still queued in Kafka are dropped
+//! when the consumer stores them. Deletion waits until every instance has
+//! seen the receipt, so no such event can be stored after it.
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::{
+    common::models::{ErasureReceipt, ErasureStatus, OtaEvent},
+    core::{
+        clickhouse::{
+            models::{ErasedDeviceRow, ErasureReceiptRow},
+            Client,
+        },
+        privacy,
+    },
+};
+
+// How stale an instance's list of erased devices may get
+const ERASED_REFRESH: Duration = Duration::from_secs(60);
+// Pending requests are picked up this long after they were made, once
+// every instance has refreshed its list of erased devices
+const ERASURE_DELAY: chrono::Duration = chrono::Duration::minutes(2);
+const WORKER_INTERVAL: Duration = Duration::from_secs(60);
+
+// The digest of `privacy::device_id_digest`, computed by ClickHouse
+const DEVICE_DIGEST: &str =
+    "lower(hex(SHA256(concat(orgId, '\\0', appId, '\\0', deviceId, '\\0'))))";
+
+/// When each erased device's request was made, by device digest
+#[derive(Default)]
+pub struct ErasedDevices {
+    requested_at: HashMap<String, DateTime<Utc>>,
+    loaded_at: Option<Instant>,
+}
+
+pub type SharedErasedDevices = Arc<Mutex<ErasedDevices>>;
+
+impl Client {
+    /// Drops events of erased devices that happened before the erasure was
+    /// requested, e.g. ones that were waiting in Kafka.
+    pub(super) async fn drop_erased(&self, events: &mut Vec<OtaEvent>) -> Result<()> {
+        let mut erased = self.erased.lock().await;
+        if erased
+            .loaded_at
+            .is_none_or(|loaded_at| loaded_at.elapsed() >= ERASED_REFRESH)
+        {
+            let rows = self
+                .client
+                .query(
+                    "SELECT deviceIdSha256, max(requestedAt) AS requestedAt FROM erasure_receipts GROUP BY deviceIdSha256",
+                )
+                .fetch_all::<ErasedDeviceRow>()
+                .await?;
+            erased.requested_at = rows
+                .into_iter()
+                .filter_map(|row| {
+                    Some((
+                        row.device_id_sha256,
+                        DateTime::from_timestamp_millis(row.requested_at)?,
+                    ))
+                })
+                .collect();
+            erased.loaded_at = Some(Instant::now());
+        }
+        if erased.requested_at.is_empty() {
+            return Ok(());
+        }
+
+        let before = events.len();
+        events.retain(|event| {
+            let digest = privacy::device_id_digest(&event.org_id, &event.app_id, &event.device_id);
+            erased
+                .requested_at
+                .get(&digest)
+                .is_none_or(|requested_at| event.timestamp > *requested_at)
+        });
+        if events.len() < before {
+            info!("Dropped {} events of erased devices", before - events.len());
+        }
+        Ok(())
+    }
+
+    /// Completes pending erasure requests, until the process exits.
+    pub async fn run_erasure_worker(&self) {
+        let mut ticker = tokio::time::interval(WORKER_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let pending = match self.list_pending_erasures(Utc::now() - ERASURE_DELAY).await {
+                Ok(pending) => pending,
+                Err(e) => {
+                    error!("Could not list pending erasures: {:?}", e);
+                    continue;
+                }
+            };
+            for receipt in pending {
+                self.complete_erasure(receipt).await;
+            }
+        }
+    }
+
+    /// Deletes the receipt's events and records the outcome. A failure
+    /// leaves the receipt pending with the error, to be retried.
+    async fn complete_erasure(&self, mut receipt: ErasureReceipt) {
+        let result = self.erase_device_events(&receipt).await;
+        // Another instance may have completed it meanwhile, having counted
+        // the events this one found gone
+        match self.erasure_status(&receipt).await {
+            Ok(ErasureStatus::Pending) => {}
+            Ok(_) => return,
+            Err(e) => warn!("Could not re-read erasure {}: {:?}", receipt.receipt_id, e),
+        }
+
+        receipt.updated_at = Utc::now();
+        match result {
+            Ok(deleted) => {
+                receipt.status = ErasureStatus::Completed;
+                receipt.events_deleted = Some(deleted);
+                receipt.error = None;
+                info!(
+                    "Erasure {} completed: {} events deleted",
+                    receipt.receipt_id, deleted
+                );
+            }
+            Err(e) => {
+                error!("Erasure {} failed, will retry: {:?}", receipt.receipt_id, e);
+                receipt.error = Some(e.to_string());
+            }
+        }
+        if let Err(e) = self.insert_erasure_receipt(&receipt).await {
+            error!(
+                "Could not record outcome of erasure {}: {:?}",
+                receipt.receipt_id, e
+            );
+        }
+    }
+
+    /// Deletes the device's events, returning how many there were once the
+    /// deletion is confirmed.
+    async fn erase_device_events(&self, receipt: &ErasureReceipt) -> Result<u64> {
+        let found = self.count_device_events(receipt).await?;
+        if found > 0 {
+            self.client
+                .query(&format!(
+                    "ALTER TABLE ota_events_raw DELETE WHERE orgId = ? AND appId = ? AND {} = ?",
+                    DEVICE_DIGEST
+                ))
+                .with_option("mutations_sync", "1")
+                .bind(&receipt.org_id)
+                .bind(&receipt.app_id)
+                .bind(&receipt.device_id_sha256)
+                .execute()
+                .await?;
+        }
+        let remaining = self.count_device_events(receipt).await?;
+        if remaining > 0 {
+            bail!("{} events remain after the deletion", remaining);
+        }
+        Ok(found)
+    }
+
+    async fn count_device_events(&self, receipt: &ErasureReceipt) -> Result<u64> {
+        let count = self
+            .client
+            .query(&format!(
+                "SELECT count() FROM ota_events_raw FINAL WHERE orgId = ? AND appId = ? AND {} = ?",
+                DEVICE_DIGEST
+            ))
+            .bind(&receipt.org_id)
+            .bind(&receipt.app_id)
+            .bind(&receipt.device_id_sha256)
+            .fetch_one::<u64>()
+            .await?;
+        Ok(count)
+    }
+
+    async fn erasure_status(&self, receipt: &ErasureReceipt) -> Result<ErasureStatus> {
+        let status = self
+            .client
+            .query(
+                "SELECT status FROM erasure_receipts FINAL WHERE orgId = ? AND appId = ? AND receiptId = ?",
+            )
+            .bind(&receipt.org_id)
+            .bind(&receipt.app_id)
+            .bind(receipt.receipt_id.to_string())
+            .fetch_one::<String>()
+            .await?;
+        Ok(status.parse()?)
+    }
+
+    async fn list_pending_erasures(&self, before: DateTime<Utc>) -> Result<Vec<ErasureReceipt>> {
+        let rows = self
+            .client
+            .query(
+                "SELECT ?fields FROM erasure_receipts FINAL WHERE status = 'pending' AND requestedAt < fromUnixTimestamp64Milli(?) ORDER BY requestedAt",
+            )
+            .bind(before.timestamp_millis())
+            .fetch_all::<ErasureReceiptRow>()
+            .await?;
+        rows.into_iter().map(receipt_from_row).collect()
+    }
+
+    pub async fn insert_erasure_receipt(&self, receipt: &ErasureReceipt) -> Result<()> {
+        let row = ErasureReceiptRow {
+            receipt_id: receipt.receipt_id,
+            org_id: receipt.org_id.clone(),
+            app_id: receipt.app_id.clone(),
+            device_id_sha256: receipt.device_id_sha256.clone(),
+            requested_by: receipt.requested_by.clone(),
+            reason: receipt.reason.clone(),
+            status: receipt.status.to_string(),
+            events_deleted: receipt.events_deleted,
+            error: receipt.error.clone(),
+            requested_at: receipt.requested_at.timestamp_millis(),
+            updated_at: receipt.updated_at.timestamp_millis(),
+        };
+        let mut insert = self.client.insert("erasure_receipts")?;
+        insert.write(&row).await?;
+        insert.end().await?;
+        Ok(())
+    }
+
+    /// Latest state of each erasure request of an application, newest first.
+    pub async fn list_erasure_receipts(
+        &self,
+        org_id: &str,
+        app_id: &str,
+    ) -> Result<Vec<ErasureReceipt>> {
+        let rows = self
+            .client
+            .query(
+                "SELECT ?fields FROM erasure_receipts FINAL WHERE orgId = ? AND appId = ? ORDER BY requestedAt DESC",
+            )
+            .bind(org_id)
+            .bind(app_id)
+            .fetch_all::<ErasureReceiptRow>()
+            .await?;
+        rows.into_iter().map(receipt_from_row).collect()
+    }
+}
+
+fn receipt_from_row(row: ErasureReceiptRow) -> Result<ErasureReceipt> {
+    Ok(ErasureReceipt {
+        receipt_id: row.receipt_id,
+        org_id: row.org_id,
+        app_id: row.app_id,
+        device_id: None,
+        device_id_sha256: row.device_id_sha256,
+        requested_by: row.requested_by,
+        reason: row.reason,
+        status: row.status.parse()?,
+        events_deleted: row.events_deleted,
+        error: row.error,
+        requested_at: DateTime::from_timestamp_millis(row.requested_at)
+            .ok_or_else(|| anyhow!("Invalid timestamp {}", row.requested_at))?,
+        updated_at: DateTime::from_timestamp_millis(row.updated_at)
+            .ok_or_else(|| anyhow!("Invalid timestamp {}", row.updated_at))?,
+    })
+}
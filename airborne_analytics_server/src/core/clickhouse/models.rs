@@ -1,5 +1,5 @@
 use clickhouse::Row;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Row, Serialize)]
 pub struct OtaEventRow {
@@ -20,6 +20,8 @@ pub struct OtaEventRow {
     pub event_date: u16,
     #[serde(rename = "releaseId")]
     pub release_id: Option<String>,
+    #[serde(rename = "variantId")]
+    pub variant_id: Option<String>,
     #[serde(rename = "currentJsVersion")]
     pub current_js_version: Option<String>,
     #[serde(rename = "targetJsVersion")]
@@ -52,4 +54,142 @@ pub struct OtaEventRow {
     #[serde(rename = "ipAddress")]
     pub ip_address: Option<String>,
     // ingestedAt is removed since it has a DEFAULT value in ClickHouse
+    #[serde(rename = "retentionDays")]
+    pub retention_days: u16,
+}
+
+/// An event as read back from `ota_events_raw`.
+#[derive(Row, Deserialize)]
+pub struct StoredOtaEventRow {
+    #[serde(rename = "orgId")]
+    pub org_id: String,
+    #[serde(rename = "appId")]
+    pub app_id: String,
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: Option<String>,
+    #[serde(rename = "eventType")]
+    pub event_type: String,
+    #[serde(rename = "eventId", with = "clickhouse::serde::uuid")]
+    pub event_id: uuid::Uuid,
+    /// Milliseconds since the epoch
+    pub timestamp: i64,
+    #[serde(rename = "releaseId")]
+    pub release_id: Option<String>,
+    #[serde(rename = "variantId")]
+    pub variant_id: Option<String>,
+    #[serde(rename = "currentJsVersion")]
+    pub current_js_version: Option<String>,
+    #[serde(rename = "targetJsVersion")]
+    pub target_js_version: Option<String>,
+    #[serde(rename = "rolloutPercentage")]
+    pub rollout_percentage: Option<u8>,
+    #[serde(rename = "osVersion")]
+    pub os_version: Option<String>,
+    #[serde(rename = "appVersion")]
+    pub app_version: Option<String>,
+    #[serde(rename = "deviceType")]
+    pub device_type: Option<String>,
+    #[serde(rename = "networkType")]
+    pub network_type: Option<String>,
+    #[serde(rename = "errorCode")]
+    pub error_code: Option<String>,
+    #[serde(rename = "errorMessage")]
+    pub error_message: Option<String>,
+    #[serde(rename = "stackTrace")]
+    pub stack_trace: Option<String>,
+    #[serde(rename = "downloadSizeBytes")]
+    pub download_size_bytes: Option<u64>,
+    #[serde(rename = "downloadTimeMs")]
+    pub download_time_ms: Option<u64>,
+    #[serde(rename = "applyTimeMs")]
+    pub apply_time_ms: Option<u64>,
+    pub payload: String,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    #[serde(rename = "ipAddress")]
+    pub ip_address: Option<String>,
+}
+
+#[derive(Row, Serialize, Deserialize)]
+pub struct ErasureReceiptRow {
+    #[serde(rename = "receiptId", with = "clickhouse::serde::uuid")]
+    pub receipt_id: uuid::Uuid,
+    #[serde(rename = "orgId")]
+    pub org_id: String,
+    #[serde(rename = "appId")]
+    pub app_id: String,
+    #[serde(rename = "deviceIdSha256")]
+    pub device_id_sha256: String,
+    #[serde(rename = "requestedBy")]
+    pub requested_by: Option<String>,
+    pub reason: Option<String>,
+    pub status: String,
+    #[serde(rename = "eventsDeleted")]
+    pub events_deleted: Option<u64>,
+    pub error: Option<String>,
+    /// Milliseconds since the epoch
+    #[serde(rename = "requestedAt")]
+    pub requested_at: i64,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: i64,
+}
+
+#[derive(Row, Deserialize)]
+pub struct ErasedDeviceRow {
+    #[serde(rename = "deviceIdSha256")]
+    pub device_id_sha256: String,
+    /// Milliseconds since the epoch
+    #[serde(rename = "requestedAt")]
+    pub requested_at: i64,
+}
+
+/// Counts of one breakdown group in one time slot, in the order of
+/// `BreakdownCounts`.
+#[derive(Row, Deserialize)]
+pub struct BreakdownCountsRow {
+    pub group_key: Vec<String>,
+    /// Unix seconds
+    pub slot: i64,
+    pub events: u64,
+    pub download_success: u64,
+    pub download_failures: u64,
+    pub apply_success: u64,
+    pub apply_failures: u64,
+    pub rollbacks_initiated: u64,
+    pub rollbacks_completed: u64,
+    pub rollback_failures: u64,
+    pub update_checks: u64,
+    pub update_available: u64,
+    pub download_time_ms_sum: u64,
+    pub download_time_count: u64,
+    pub apply_time_ms_sum: u64,
+    pub apply_time_count: u64,
+    pub download_size_bytes_sum: u64,
+    pub download_size_count: u64,
+    pub download_time_ms_sq_sum: f64,
+    pub apply_time_ms_sq_sum: f64,
+}
+
+#[derive(Row, Deserialize)]
+pub struct VariantDevicesRow {
+    pub variant_id: String,
+    pub devices: u64,
+}
+
+#[derive(Row, Deserialize)]
+pub struct RetentionRow {
+    #[serde(rename = "orgId")]
+    pub org_id: String,
+    #[serde(rename = "retentionDays")]
+    pub retention_days: u16,
+}
+
+#[derive(Row, Deserialize)]
+pub struct AppRow {
+    #[serde(rename = "orgId")]
+    pub org_id: String,
+    #[serde(rename = "appId")]
+    pub app_id: String,
 }
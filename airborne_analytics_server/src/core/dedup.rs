@@ -0,0 +1,42 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+// Expired ids are swept once this many are tracked.
+const SWEEP_THRESHOLD: usize = 1_000_000;
+
+/// Remembers recently stored event ids, so a retried event is stored once.
+/// The window is per process and is lost on restart.
+pub struct DedupCache {
+    window: Duration,
+    seen: Mutex<HashMap<Uuid, Instant>>,
+}
+
+impl DedupCache {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records `event_id` and returns whether it was new.
+    pub fn first_sighting(&self, event_id: Uuid) -> bool {
+        let now = Instant::now();
+        let mut seen = self.seen.lock().unwrap();
+        if seen.len() >= SWEEP_THRESHOLD {
+            seen.retain(|_, seen_at| now.duration_since(*seen_at) < self.window);
+        }
+        match seen.get(&event_id) {
+            Some(seen_at) if now.duration_since(*seen_at) < self.window => false,
+            _ => {
+                seen.insert(event_id, now);
+                true
+            }
+        }
+    }
+}
@@ -0,0 +1,538 @@
+//! Statistical comparison of an experiment's variants. Each experimental
+//! variant is compared with the control: rates with a pooled two-proportion
+//! z-test, timings with a Welch z-test. Samples are large enough for the
+//! normal approximation. The significance level is split across the
+//! experimental variants (Bonferroni), so adding variants does not inflate
+//! the chance of a false positive.
+//!
+//! Rates and timings are over events, not devices. A device that retries
+//! contributes every attempt, and attempts of one device are not
+//! independent, so intervals and p-values are narrower than a per-device
+//! analysis would give. The response says so in `observation_unit`.
+
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    common::{
+        error::{AppError, AppResult},
+        models::{
+            Dimension, ExperimentComparison, MeanEstimate, MetricDifference, RateEstimate,
+            SampleRatioCheck, VariantComparison, VariantSummary,
+        },
+    },
+    core::breakdown::{self, BreakdownCounts, BreakdownRequest, BreakdownRow, TimeRange},
+};
+
+pub const DEFAULT_DAYS: u32 = 14;
+pub const MAX_DAYS: u32 = 90;
+pub const DEFAULT_CONFIDENCE: f64 = 0.95;
+const MAX_VARIANTS: usize = 10;
+// Sample ratio mismatches are flagged conservatively; a false alarm stops
+// a healthy experiment from being concluded
+const SRM_ALPHA: f64 = 0.001;
+// The breakdown counts events; neither backend can aggregate them per
+// device first
+const OBSERVATION_UNIT: &str = "event";
+
+/// Which experiment to compare, and over which days
+#[derive(Debug, Clone)]
+pub struct ExperimentRequest {
+    pub release_id: String,
+    pub control: String,
+    pub variants: Vec<String>,
+    /// Percentage of devices served each variant, control included
+    pub traffic_percentage: Option<f64>,
+    pub confidence: f64,
+    /// A single slot spanning the whole period
+    pub range: TimeRange,
+}
+
+impl ExperimentRequest {
+    /// Parses the comma-separated `variants` and checks the parameters
+    /// against each other.
+    pub fn parse(
+        release_id: String,
+        control: String,
+        variants: &str,
+        traffic_percentage: Option<f64>,
+        confidence: Option<f64>,
+        days: Option<u32>,
+    ) -> AppResult<Self> {
+        if release_id.is_empty() || control.is_empty() {
+            return Err(AppError::Validation(
+                "release_id and control cannot be empty".to_string(),
+            ));
+        }
+
+        let mut seen = HashSet::new();
+        let variants: Vec<String> = variants
+            .split(',')
+            .map(str::trim)
+            .filter(|variant| !variant.is_empty())
+            .map(str::to_string)
+            .collect();
+        for variant in &variants {
+            if *variant == control {
+                return Err(AppError::Validation(format!(
+                    "'{}' is the control; variants lists the experimental variants",
+                    variant
+                )));
+            }
+            if !seen.insert(variant) {
+                return Err(AppError::Validation(format!(
+                    "variants lists '{}' twice",
+                    variant
+                )));
+            }
+        }
+        if variants.is_empty() || variants.len() > MAX_VARIANTS {
+            return Err(AppError::Validation(format!(
+                "variants takes between 1 and {} experimental variants",
+                MAX_VARIANTS
+            )));
+        }
+
+        if let Some(traffic) = traffic_percentage {
+            let arms = variants.len() + 1;
+            if !(traffic > 0.0 && traffic * arms as f64 <= 100.0) {
+                return Err(AppError::Validation(format!(
+                    "traffic_percentage must be above 0 and at most {:.2} for {} variants including the control",
+                    100.0 / arms as f64,
+                    arms
+                )));
+            }
+        }
+
+        let confidence = confidence.unwrap_or(DEFAULT_CONFIDENCE);
+        if !(0.5..=0.999).contains(&confidence) {
+            return Err(AppError::Validation(
+                "confidence must be between 0.5 and 0.999".to_string(),
+            ));
+        }
+
+        let days = days.unwrap_or(DEFAULT_DAYS);
+        if days == 0 || days > MAX_DAYS {
+            return Err(AppError::Validation(format!(
+                "days must be between 1 and {}",
+                MAX_DAYS
+            )));
+        }
+        let range = TimeRange::last_days(days);
+
+        Ok(Self {
+            release_id,
+            control,
+            variants,
+            traffic_percentage,
+            confidence,
+            range: TimeRange {
+                step: range.end - range.start,
+                ..range
+            },
+        })
+    }
+
+    /// The breakdown of the release's events by variant
+    pub fn breakdown(&self) -> BreakdownRequest {
+        BreakdownRequest {
+            group_by: vec![Dimension::VariantId],
+            filters: BTreeMap::from([(Dimension::ReleaseId, self.release_id.clone())]),
+            top: breakdown::MAX_TOP,
+            range: self.range,
+        }
+    }
+}
+
+/// Complementary error function, with a fractional error below 1.2e-7
+/// (Numerical Recipes' erfcc).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t
+        * (-z * z - 1.265_512_23
+            + t * (1.000_023_68
+                + t * (0.374_091_96
+                    + t * (0.096_784_18
+                        + t * (-0.186_288_06
+                            + t * (0.278_868_07
+                                + t * (-1.135_203_98
+                                    + t * (1.488_515_87
+                                        + t * (-0.822_152_23 + t * 0.170_872_77)))))))))
+            .exp();
+    if x >= 0.0 {
+        r
+    } else {
+        2.0 - r
+    }
+}
+
+/// Two-sided p-value of a standard normal statistic
+fn p_value(z: f64) -> f64 {
+    erfc(z.abs() / std::f64::consts::SQRT_2)
+}
+
+/// Critical value of a two-sided test at `alpha`, found by bisection
+fn critical_value(alpha: f64) -> f64 {
+    let (mut low, mut high) = (0.0, 10.0);
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+        if p_value(mid) > alpha {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.0
+}
+
+fn rate(count: u64, total: u64, z: f64) -> RateEstimate {
+    if total == 0 {
+        return RateEstimate {
+            count,
+            total,
+            rate: 0.0,
+            ci_low: 0.0,
+            ci_high: 1.0,
+        };
+    }
+    let n = total as f64;
+    let p = count as f64 / n;
+    let z2 = z * z;
+    let denominator = 1.0 + z2 / n;
+    let center = (p + z2 / (2.0 * n)) / denominator;
+    let half = z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / denominator;
+    RateEstimate {
+        count,
+        total,
+        rate: p,
+        ci_low: (center - half).max(0.0),
+        ci_high: (center + half).min(1.0),
+    }
+}
+
+fn mean(sum: u64, sq_sum: f64, observations: u64, z: f64) -> MeanEstimate {
+    if observations == 0 {
+        return MeanEstimate {
+            observations,
+            mean: 0.0,
+            std_dev: 0.0,
+            ci_low: 0.0,
+            ci_high: 0.0,
+        };
+    }
+    let n = observations as f64;
+    let mean = sum as f64 / n;
+    let std_dev = if observations > 1 {
+        ((sq_sum - n * mean * mean) / (n - 1.0)).max(0.0).sqrt()
+    } else {
+        0.0
+    };
+    let half = z * std_dev / n.sqrt();
+    MeanEstimate {
+        observations,
+        mean,
+        std_dev,
+        ci_low: mean - half,
+        ci_high: mean + half,
+    }
+}
+
+fn difference(
+    difference: f64,
+    test_se: f64,
+    interval_se: f64,
+    z: f64,
+    alpha: f64,
+) -> MetricDifference {
+    let p_value = if test_se > 0.0 {
+        p_value(difference / test_se)
+    } else if difference == 0.0 {
+        1.0
+    } else {
+        0.0
+    };
+    MetricDifference {
+        difference,
+        ci_low: difference - z * interval_se,
+        ci_high: difference + z * interval_se,
+        p_value,
+        significant: p_value < alpha,
+    }
+}
+
+/// Pooled two-proportion z-test; the interval uses the unpooled error.
+fn compare_rates(
+    control: &RateEstimate,
+    variant: &RateEstimate,
+    z: f64,
+    alpha: f64,
+) -> Option<MetricDifference> {
+    if control.total == 0 || variant.total == 0 {
+        return None;
+    }
+    let (n1, n2) = (control.total as f64, variant.total as f64);
+    let pooled = (control.count + variant.count) as f64 / (n1 + n2);
+    let test_se = (pooled * (1.0 - pooled) * (1.0 / n1 + 1.0 / n2)).sqrt();
+    let interval_se = (control.rate * (1.0 - control.rate) / n1
+        + variant.rate * (1.0 - variant.rate) / n2)
+        .sqrt();
+    Some(difference(
+        variant.rate - control.rate,
+        test_se,
+        interval_se,
+        z,
+        alpha,
+    ))
+}
+
+/// Welch z-test on the difference of means
+fn compare_means(
+    control: &MeanEstimate,
+    variant: &MeanEstimate,
+    z: f64,
+    alpha: f64,
+) -> Option<MetricDifference> {
+    if control.observations < 2 || variant.observations < 2 {
+        return None;
+    }
+    let se = (control.std_dev.powi(2) / control.observations as f64
+        + variant.std_dev.powi(2) / variant.observations as f64)
+        .sqrt();
+    Some(difference(variant.mean - control.mean, se, se, z, alpha))
+}
+
+/// Devices should split evenly between two arms of equal traffic
+fn sample_ratio(control_devices: u64, variant_devices: u64) -> Option<SampleRatioCheck> {
+    let total = control_devices + variant_devices;
+    if total == 0 {
+        return None;
+    }
+    let z = (variant_devices as f64 - control_devices as f64) / (total as f64).sqrt();
+    let p_value = p_value(z);
+    Some(SampleRatioCheck {
+        variant_devices,
+        control_devices,
+        p_value,
+        mismatch: p_value < SRM_ALPHA,
+    })
+}
+
+fn summarize(
+    request: &ExperimentRequest,
+    variant_id: &str,
+    counts: &BreakdownCounts,
+    devices: Option<&(BTreeMap<String, u64>, u64)>,
+    z: f64,
+) -> VariantSummary {
+    let variant_devices =
+        devices.map(|(by_variant, _)| by_variant.get(variant_id).copied().unwrap_or_default());
+    let attempts = counts.download_success + counts.apply_success + counts.failures();
+    VariantSummary {
+        variant_id: variant_id.to_string(),
+        control: variant_id == request.control,
+        devices: variant_devices,
+        expected_share: request.traffic_percentage.map(|traffic| traffic / 100.0),
+        observed_share: devices.and_then(|(by_variant, total)| {
+            (*total > 0).then(|| {
+                by_variant.get(variant_id).copied().unwrap_or_default() as f64 / *total as f64
+            })
+        }),
+        apply_success_rate: rate(
+            counts.apply_success,
+            counts.apply_success + counts.apply_failures,
+            z,
+        ),
+        failure_rate: rate(counts.failures(), attempts, z),
+        download_time_ms: mean(
+            counts.download_time_ms_sum,
+            counts.download_time_ms_sq_sum,
+            counts.download_time_count,
+            z,
+        ),
+        apply_time_ms: mean(
+            counts.apply_time_ms_sum,
+            counts.apply_time_ms_sq_sum,
+            counts.apply_time_count,
+            z,
+        ),
+    }
+}
+
+/// Compares every experimental variant with the control. `devices` holds
+/// distinct devices per variant and for the whole app, when the backend
+/// can count them.
+pub fn compare(
+    request: &ExperimentRequest,
+    rows: Vec<BreakdownRow>,
+    devices: Option<(BTreeMap<String, u64>, u64)>,
+) -> ExperimentComparison {
+    let mut by_variant: BTreeMap<String, BreakdownCounts> = BTreeMap::new();
+    for row in rows {
+        if let Some(variant) = row.group.into_iter().next() {
+            by_variant.entry(variant).or_default().add(&row.counts);
+        }
+    }
+
+    let z = critical_value(1.0 - request.confidence);
+    let alpha = (1.0 - request.confidence) / request.variants.len() as f64;
+    let corrected_z = critical_value(alpha);
+
+    let empty = BreakdownCounts::default();
+    let variants: Vec<VariantSummary> = std::iter::once(&request.control)
+        .chain(&request.variants)
+        .map(|variant| {
+            summarize(
+                request,
+                variant,
+                by_variant.get(variant).unwrap_or(&empty),
+                devices.as_ref(),
+                z,
+            )
+        })
+        .collect();
+
+    // Intervals of the differences use the corrected level. For timings the
+    // test and the interval share one standard error, so a difference is
+    // significant exactly when its interval excludes zero. Rates are tested
+    // with the pooled error but bounded with the unpooled one, so near the
+    // boundary the two can disagree.
+    let control = &variants[0];
+    let comparisons = variants[1..]
+        .iter()
+        .map(|variant| VariantComparison {
+            variant_id: variant.variant_id.clone(),
+            sample_ratio: control
+                .devices
+                .zip(variant.devices)
+                .and_then(|(control, variant)| sample_ratio(control, variant)),
+            apply_success_rate: compare_rates(
+                &control.apply_success_rate,
+                &variant.apply_success_rate,
+                corrected_z,
+                alpha,
+            ),
+            failure_rate: compare_rates(
+                &control.failure_rate,
+                &variant.failure_rate,
+                corrected_z,
+                alpha,
+            ),
+            download_time_ms: compare_means(
+                &control.download_time_ms,
+                &variant.download_time_ms,
+                corrected_z,
+                alpha,
+            ),
+            apply_time_ms: compare_means(
+                &control.apply_time_ms,
+                &variant.apply_time_ms,
+                corrected_z,
+                alpha,
+            ),
+        })
+        .collect();
+
+    ExperimentComparison {
+        release_id: request.release_id.clone(),
+        control: request.control.clone(),
+        start_date: DateTime::from_timestamp(request.range.start, 0).unwrap_or_else(Utc::now),
+        end_date: DateTime::from_timestamp(request.range.end, 0).unwrap_or_else(Utc::now),
+        confidence: request.confidence,
+        alpha,
+        observation_unit: OBSERVATION_UNIT.to_string(),
+        variants,
+        comparisons,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    fn timing(mean: f64, std_dev: f64, observations: u64) -> MeanEstimate {
+        MeanEstimate {
+            observations,
+            mean,
+            std_dev,
+            ci_low: 0.0,
+            ci_high: 0.0,
+        }
+    }
+
+    #[test]
+    fn erfc_matches_known_values() {
+        close(erfc(0.0), 1.0, 1e-7);
+        close(erfc(0.5), 0.479_500_122, 1e-7);
+        close(erfc(1.0), 0.157_299_207, 1e-7);
+        close(erfc(-1.0), 1.842_700_793, 1e-7);
+        close(erfc(2.0), 0.004_677_735, 1e-8);
+    }
+
+    #[test]
+    fn critical_values_match_normal_quantiles() {
+        close(critical_value(0.10), 1.644_854, 1e-5);
+        close(critical_value(0.05), 1.959_964, 1e-5);
+        close(critical_value(0.01), 2.575_829, 1e-5);
+    }
+
+    #[test]
+    fn rates_are_compared_with_a_pooled_test() {
+        let z = critical_value(0.05);
+        let control = rate(100, 1000, z);
+        let variant = rate(150, 1000, z);
+        let result = compare_rates(&control, &variant, z, 0.05).unwrap();
+        close(result.difference, 0.05, 1e-12);
+        // z = 3.3806 on the pooled rate of 12.5%
+        close(result.p_value, 0.000_723_2, 1e-6);
+        close(result.ci_low, 0.021_095, 1e-5);
+        close(result.ci_high, 0.078_905, 1e-5);
+        assert!(result.significant);
+
+        assert!(compare_rates(&rate(0, 0, z), &variant, z, 0.05).is_none());
+    }
+
+    #[test]
+    fn means_are_compared_with_a_welch_test() {
+        let z = critical_value(0.05);
+        let result = compare_means(
+            &timing(1000.0, 200.0, 100),
+            &timing(1100.0, 300.0, 100),
+            z,
+            0.05,
+        )
+        .unwrap();
+        close(result.difference, 100.0, 1e-9);
+        // z = 100 / sqrt(200^2/100 + 300^2/100) = 2.7735
+        close(result.p_value, 0.005_545_7, 1e-6);
+        close(result.ci_low, 29.332_49, 1e-3);
+        close(result.ci_high, 170.667_51, 1e-3);
+        assert!(result.significant);
+        // One observation has no spread to test against
+        assert!(compare_means(&timing(1.0, 0.0, 1), &timing(1.0, 0.0, 5), z, 0.05).is_none());
+    }
+
+    #[test]
+    fn sample_ratio_flags_uneven_splits() {
+        let even = sample_ratio(1000, 1000).unwrap();
+        close(even.p_value, 1.0, 1e-7);
+        assert!(!even.mismatch);
+
+        // z = 200 / sqrt(2200) = 4.264
+        let uneven = sample_ratio(1000, 1200).unwrap();
+        close(uneven.p_value, 2.008e-5, 1e-7);
+        assert!(uneven.mismatch);
+
+        assert!(sample_ratio(0, 0).is_none());
+    }
+}
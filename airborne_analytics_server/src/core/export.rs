@@ -0,0 +1,448 @@
+//! Exports of raw events and aggregate reports as CSV, NDJSON or Parquet.
+//! Rows are encoded as they are read and handed on in chunks, to an HTTP
+//! response or an object store upload, so an export never has to fit in
+//! memory. Events are read one UTC day at a time.
+//!
+//! Column names and types are part of the contract with downstream
+//! warehouses: columns are only ever appended, and `SCHEMA_VERSION` is
+//! bumped when they are.
+
+pub mod encoder;
+pub mod object_store;
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use axum::body::Body;
+use bytes::Bytes;
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use futures::{channel::mpsc, SinkExt};
+use tracing::{error, info, warn};
+
+use crate::{
+    common::{
+        config::ExportConfig,
+        models::{Dimension, ExportFormat, OtaEventType},
+    },
+    core::{
+        breakdown::{self, BreakdownRequest, BreakdownRow, TimeRange},
+        clickhouse::{self, models::StoredOtaEventRow},
+        export::{encoder::Encoder, object_store::Upload},
+    },
+};
+
+pub const SCHEMA_VERSION: u32 = 1;
+pub const SCHEMA_VERSION_HEADER: &str = "x-export-schema-version";
+pub const MAX_EXPORT_DAYS: i64 = 93;
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(3600);
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Ndjson => "application/x-ndjson",
+            Self::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Ndjson => "ndjson",
+            Self::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ColumnKind {
+    String,
+    UInt64,
+    Float64,
+    /// Milliseconds since the epoch, UTC
+    Timestamp,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Column {
+    pub name: &'static str,
+    pub kind: ColumnKind,
+}
+
+const fn column(name: &'static str, kind: ColumnKind) -> Column {
+    Column { name, kind }
+}
+
+#[derive(Debug, Clone)]
+pub enum Cell {
+    Null,
+    String(String),
+    UInt(u64),
+    Float(f64),
+    Timestamp(i64),
+}
+
+impl From<Option<String>> for Cell {
+    fn from(value: Option<String>) -> Self {
+        value.map_or(Cell::Null, Cell::String)
+    }
+}
+
+impl From<Option<u64>> for Cell {
+    fn from(value: Option<u64>) -> Self {
+        value.map_or(Cell::Null, Cell::UInt)
+    }
+}
+
+/// Columns of an events export, one per column of `ota_events_raw` that
+/// `StoredOtaEventRow` reads
+pub const EVENT_COLUMNS: [Column; 25] = [
+    column("org_id", ColumnKind::String),
+    column("app_id", ColumnKind::String),
+    column("device_id", ColumnKind::String),
+    column("session_id", ColumnKind::String),
+    column("event_type", ColumnKind::String),
+    column("event_id", ColumnKind::String),
+    column("timestamp", ColumnKind::Timestamp),
+    column("release_id", ColumnKind::String),
+    column("variant_id", ColumnKind::String),
+    column("current_js_version", ColumnKind::String),
+    column("target_js_version", ColumnKind::String),
+    column("rollout_percentage", ColumnKind::UInt64),
+    column("os_version", ColumnKind::String),
+    column("app_version", ColumnKind::String),
+    column("device_type", ColumnKind::String),
+    column("network_type", ColumnKind::String),
+    column("error_code", ColumnKind::String),
+    column("error_message", ColumnKind::String),
+    column("stack_trace", ColumnKind::String),
+    column("download_size_bytes", ColumnKind::UInt64),
+    column("download_time_ms", ColumnKind::UInt64),
+    column("apply_time_ms", ColumnKind::UInt64),
+    column("payload", ColumnKind::String),
+    column("user_agent", ColumnKind::String),
+    column("ip_address", ColumnKind::String),
+];
+
+fn event_cells(row: StoredOtaEventRow) -> Vec<Cell> {
+    vec![
+        Cell::String(row.org_id),
+        Cell::String(row.app_id),
+        Cell::String(row.device_id),
+        row.session_id.into(),
+        Cell::String(row.event_type),
+        Cell::String(row.event_id.to_string()),
+        Cell::Timestamp(row.timestamp),
+        row.release_id.into(),
+        row.variant_id.into(),
+        row.current_js_version.into(),
+        row.target_js_version.into(),
+        row.rollout_percentage.map(u64::from).into(),
+        row.os_version.into(),
+        row.app_version.into(),
+        row.device_type.into(),
+        row.network_type.into(),
+        row.error_code.into(),
+        row.error_message.into(),
+        row.stack_trace.into(),
+        row.download_size_bytes.into(),
+        row.download_time_ms.into(),
+        row.apply_time_ms.into(),
+        Cell::String(row.payload),
+        row.user_agent.into(),
+        row.ip_address.into(),
+    ]
+}
+
+/// Counts of a report row, after its time slot and group
+const REPORT_COUNT_COLUMNS: [Column; 18] = [
+    column("events", ColumnKind::UInt64),
+    column("download_success", ColumnKind::UInt64),
+    column("download_failures", ColumnKind::UInt64),
+    column("apply_success", ColumnKind::UInt64),
+    column("apply_failures", ColumnKind::UInt64),
+    column("rollbacks_initiated", ColumnKind::UInt64),
+    column("rollbacks_completed", ColumnKind::UInt64),
+    column("rollback_failures", ColumnKind::UInt64),
+    column("update_checks", ColumnKind::UInt64),
+    column("update_available", ColumnKind::UInt64),
+    column("download_time_ms_sum", ColumnKind::UInt64),
+    column("download_time_count", ColumnKind::UInt64),
+    column("download_time_ms_sq_sum", ColumnKind::Float64),
+    column("apply_time_ms_sum", ColumnKind::UInt64),
+    column("apply_time_count", ColumnKind::UInt64),
+    column("apply_time_ms_sq_sum", ColumnKind::Float64),
+    column("download_size_bytes_sum", ColumnKind::UInt64),
+    column("download_size_count", ColumnKind::UInt64),
+];
+
+/// `time_slot`, one column per `group_by` dimension, then the counts.
+/// Sums, sums of squares and counts are exported rather than averages so
+/// that rows can be added up.
+pub fn report_columns(group_by: &[Dimension]) -> Arc<[Column]> {
+    std::iter::once(column("time_slot", ColumnKind::Timestamp))
+        .chain(
+            group_by
+                .iter()
+                .map(|dimension| column(dimension.label(), ColumnKind::String)),
+        )
+        .chain(REPORT_COUNT_COLUMNS)
+        .collect()
+}
+
+fn report_cells(row: BreakdownRow) -> Vec<Cell> {
+    let counts = row.counts;
+    let mut cells = vec![Cell::Timestamp(row.time_slot * 1000)];
+    cells.extend(row.group.into_iter().map(Cell::String));
+    cells.extend([
+        Cell::UInt(counts.events),
+        Cell::UInt(counts.download_success),
+        Cell::UInt(counts.download_failures),
+        Cell::UInt(counts.apply_success),
+        Cell::UInt(counts.apply_failures),
+        Cell::UInt(counts.rollbacks_initiated),
+        Cell::UInt(counts.rollbacks_completed),
+        Cell::UInt(counts.rollback_failures),
+        Cell::UInt(counts.update_checks),
+        Cell::UInt(counts.update_available),
+        Cell::UInt(counts.download_time_ms_sum),
+        Cell::UInt(counts.download_time_count),
+        Cell::Float(counts.download_time_ms_sq_sum),
+        Cell::UInt(counts.apply_time_ms_sum),
+        Cell::UInt(counts.apply_time_count),
+        Cell::Float(counts.apply_time_ms_sq_sum),
+        Cell::UInt(counts.download_size_bytes_sum),
+        Cell::UInt(counts.download_size_count),
+    ]);
+    cells
+}
+
+/// Which events an export covers
+#[derive(Debug, Clone)]
+pub struct EventFilter {
+    pub org_id: String,
+    pub app_id: String,
+    /// Millis
+    pub start_ms: i64,
+    /// Millis, exclusive
+    pub end_ms: i64,
+    pub release_id: Option<String>,
+    pub event_type: Option<OtaEventType>,
+}
+
+impl EventFilter {
+    /// The UTC days the range touches
+    fn days(&self) -> Vec<NaiveDate> {
+        let (Some(start), Some(end)) = (
+            DateTime::from_timestamp_millis(self.start_ms),
+            DateTime::from_timestamp_millis(self.end_ms - 1),
+        ) else {
+            return Vec::new();
+        };
+        start
+            .date_naive()
+            .iter_days()
+            .take_while(|day| *day <= end.date_naive())
+            .collect()
+    }
+}
+
+/// Where encoded chunks go
+pub trait ChunkSink {
+    async fn send(&mut self, chunk: Bytes) -> Result<()>;
+}
+
+/// Feeds a streamed response body
+pub struct ChannelSink(mpsc::Sender<Result<Bytes, std::io::Error>>);
+
+impl ChannelSink {
+    pub fn new() -> (Self, Body) {
+        // A couple of chunks in flight; the export waits for a slow client
+        let (sender, receiver) = mpsc::channel(2);
+        (Self(sender), Body::from_stream(receiver))
+    }
+
+    /// Ends the body. A failed export aborts it, so the client sees a broken
+    /// transfer instead of a file that silently stops early.
+    pub async fn finish(mut self, result: Result<u64>, what: &str) {
+        match result {
+            Ok(rows) => info!("Exported {} rows of {}", rows, what),
+            Err(e) => {
+                error!("Export of {} failed: {:?}", what, e);
+                let _ = self.0.send(Err(std::io::Error::other(e.to_string()))).await;
+            }
+        }
+    }
+}
+
+impl ChunkSink for ChannelSink {
+    async fn send(&mut self, chunk: Bytes) -> Result<()> {
+        self.0
+            .send(Ok(chunk))
+            .await
+            .map_err(|_| anyhow!("The client went away"))
+    }
+}
+
+/// Writes the filtered events, oldest first, and returns how many there
+/// were.
+pub async fn write_events(
+    clickhouse: &clickhouse::Client,
+    filter: &EventFilter,
+    format: ExportFormat,
+    sink: &mut impl ChunkSink,
+) -> Result<u64> {
+    let mut encoder = Encoder::new(format, Arc::from(EVENT_COLUMNS))?;
+    let mut rows = 0;
+    for day in filter.days() {
+        let mut cursor = clickhouse.event_cursor(filter, day)?;
+        while let Some(row) = cursor.next().await? {
+            encoder.push(event_cells(row))?;
+            rows += 1;
+            if let Some(chunk) = encoder.take_chunk() {
+                sink.send(chunk).await?;
+            }
+        }
+    }
+    sink.send(encoder.finish()?).await?;
+    Ok(rows)
+}
+
+/// Writes breakdown rows ordered by time slot, then group.
+pub async fn write_report(
+    mut rows: Vec<BreakdownRow>,
+    group_by: &[Dimension],
+    format: ExportFormat,
+    sink: &mut impl ChunkSink,
+) -> Result<u64> {
+    rows.sort_by(|a, b| (a.time_slot, &a.group).cmp(&(b.time_slot, &b.group)));
+    let count = rows.len() as u64;
+    let mut encoder = Encoder::new(format, report_columns(group_by))?;
+    for row in rows {
+        encoder.push(report_cells(row))?;
+        if let Some(chunk) = encoder.take_chunk() {
+            sink.send(chunk).await?;
+        }
+    }
+    sink.send(encoder.finish()?).await?;
+    Ok(count)
+}
+
+/// `<prefix>/org_id=<org>/app_id=<app>/<kind>/date=<day>/<kind>.<ext>`,
+/// partitioned the way warehouses discover tables
+fn object_key(
+    config: &ExportConfig,
+    org_id: &str,
+    app_id: &str,
+    kind: &str,
+    day: NaiveDate,
+) -> String {
+    format!(
+        "{}/org_id={}/app_id={}/{}/date={}/{}.{}",
+        config.s3_prefix.trim_end_matches('/'),
+        org_id,
+        app_id,
+        kind,
+        day.format("%Y-%m-%d"),
+        kind,
+        config.format.extension()
+    )
+}
+
+/// Uploads `write`'s output to `key`, unless the object already exists.
+async fn upload<F>(
+    s3: &aws_sdk_s3::Client,
+    config: &ExportConfig,
+    key: &str,
+    write: F,
+) -> Result<()>
+where
+    F: AsyncFnOnce(&mut Upload) -> Result<u64>,
+{
+    let bucket = config.s3_bucket.as_deref().unwrap_or_default();
+    if object_store::exists(s3, bucket, key).await? {
+        return Ok(());
+    }
+    let mut upload = Upload::start(s3, bucket, key, config.format.content_type()).await?;
+    match write(&mut upload).await {
+        Ok(rows) => {
+            upload.complete().await?;
+            info!("Exported {} rows to s3://{}/{}", rows, bucket, key);
+            Ok(())
+        }
+        Err(e) => {
+            upload.abort().await;
+            Err(e)
+        }
+    }
+}
+
+/// Exports one app's events and hourly report, by release, of one day
+async fn export_app_day(
+    clickhouse: &clickhouse::Client,
+    s3: &aws_sdk_s3::Client,
+    config: &ExportConfig,
+    org_id: &str,
+    app_id: &str,
+    day: NaiveDate,
+) -> Result<()> {
+    let start = day.and_time(chrono::NaiveTime::MIN).and_utc();
+    let filter = EventFilter {
+        org_id: org_id.to_string(),
+        app_id: app_id.to_string(),
+        start_ms: start.timestamp_millis(),
+        end_ms: (start + chrono::Duration::days(1)).timestamp_millis(),
+        release_id: None,
+        event_type: None,
+    };
+    let key = object_key(config, org_id, app_id, "events", day);
+    upload(s3, config, &key, async |sink| {
+        write_events(clickhouse, &filter, config.format, sink).await
+    })
+    .await?;
+
+    let request = BreakdownRequest {
+        group_by: vec![Dimension::ReleaseId],
+        filters: Default::default(),
+        top: breakdown::MAX_TOP,
+        range: TimeRange::hours_of_day(start.timestamp()),
+    };
+    let key = object_key(config, org_id, app_id, "report", day);
+    upload(s3, config, &key, async |sink| {
+        let rows = clickhouse
+            .get_breakdown_rows(org_id, app_id, &request)
+            .await?;
+        write_report(rows, &request.group_by, config.format, sink).await
+    })
+    .await
+}
+
+/// Exports every app's previous UTC day to the object store, checking every
+/// hour. Objects already present are skipped, so a restart picks up where
+/// the last run stopped. Events ingested after their day was exported are
+/// not in the export.
+pub async fn run_scheduled_exports(
+    clickhouse: Arc<clickhouse::Client>,
+    s3: aws_sdk_s3::Client,
+    config: ExportConfig,
+) {
+    loop {
+        let day = Utc::now().date_naive() - Days::new(1);
+        match clickhouse.list_apps_with_events(day).await {
+            Ok(apps) => {
+                for (org_id, app_id) in apps {
+                    if let Err(e) =
+                        export_app_day(&clickhouse, &s3, &config, &org_id, &app_id, day).await
+                    {
+                        warn!(
+                            "Scheduled export of {}/{} for {} failed: {:?}",
+                            org_id, app_id, day, e
+                        );
+                    }
+                }
+            }
+            Err(e) => error!("Could not list apps to export for {}: {:?}", day, e),
+        }
+        tokio::time::sleep(SCHEDULE_INTERVAL).await;
+    }
+}
@@ -0,0 +1,245 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use arrow_array::{
+    builder::{Float64Builder, StringBuilder, TimestampMillisecondBuilder, UInt64Builder},
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use bytes::Bytes;
+use chrono::{DateTime, SecondsFormat};
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{Compression, ZstdLevel},
+    file::properties::WriterProperties,
+};
+use serde_json::{Map, Value};
+
+use crate::core::export::{Cell, Column, ColumnKind, ExportFormat};
+
+// Text is handed out once this much is buffered
+const CHUNK_BYTES: usize = 1 << 20;
+// Parquet buffers a row group in memory until it is complete
+const ROWS_PER_BATCH: usize = 8192;
+const ROWS_PER_ROW_GROUP: usize = 8 * ROWS_PER_BATCH;
+
+enum Output {
+    Text(Vec<u8>),
+    Parquet {
+        writer: Box<ArrowWriter<Vec<u8>>>,
+        schema: Arc<Schema>,
+        rows: Vec<Vec<Cell>>,
+    },
+}
+
+/// Encodes rows of a fixed set of columns, handing out the encoded bytes in
+/// chunks so that an export never holds more than a chunk (or one Parquet
+/// row group) in memory.
+pub struct Encoder {
+    format: ExportFormat,
+    columns: Arc<[Column]>,
+    output: Output,
+}
+
+impl Encoder {
+    pub fn new(format: ExportFormat, columns: Arc<[Column]>) -> Result<Self> {
+        let output = match format {
+            ExportFormat::Csv => {
+                let mut buffer = Vec::new();
+                let header: Vec<String> = columns
+                    .iter()
+                    .map(|column| csv_field(column.name))
+                    .collect();
+                buffer.extend_from_slice(header.join(",").as_bytes());
+                buffer.extend_from_slice(b"\r\n");
+                Output::Text(buffer)
+            }
+            ExportFormat::Ndjson => Output::Text(Vec::new()),
+            ExportFormat::Parquet => {
+                let schema = Arc::new(Schema::new(
+                    columns
+                        .iter()
+                        .map(|column| Field::new(column.name, arrow_type(column.kind), true))
+                        .collect::<Vec<_>>(),
+                ));
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::ZSTD(ZstdLevel::default()))
+                    .set_max_row_group_size(ROWS_PER_ROW_GROUP)
+                    .build();
+                Output::Parquet {
+                    writer: Box::new(ArrowWriter::try_new(
+                        Vec::new(),
+                        Arc::clone(&schema),
+                        Some(properties),
+                    )?),
+                    schema,
+                    rows: Vec::with_capacity(ROWS_PER_BATCH),
+                }
+            }
+        };
+        Ok(Self {
+            format,
+            columns,
+            output,
+        })
+    }
+
+    pub fn push(&mut self, row: Vec<Cell>) -> Result<()> {
+        match &mut self.output {
+            Output::Text(buffer) if self.format == ExportFormat::Csv => {
+                let fields: Vec<String> = row.iter().map(|cell| csv_field(&cell.text())).collect();
+                buffer.extend_from_slice(fields.join(",").as_bytes());
+                buffer.extend_from_slice(b"\r\n");
+            }
+            Output::Text(buffer) => {
+                let object: Map<String, Value> = self
+                    .columns
+                    .iter()
+                    .zip(row)
+                    .map(|(column, cell)| (column.name.to_string(), cell.json()))
+                    .collect();
+                serde_json::to_writer(&mut *buffer, &object)?;
+                buffer.push(b'\n');
+            }
+            Output::Parquet {
+                writer,
+                schema,
+                rows,
+            } => {
+                rows.push(row);
+                if rows.len() >= ROWS_PER_BATCH {
+                    writer.write(&record_batch(schema, &self.columns, rows)?)?;
+                    rows.clear();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Bytes encoded so far, once there are enough to be worth sending
+    pub fn take_chunk(&mut self) -> Option<Bytes> {
+        let buffer = match &mut self.output {
+            Output::Text(buffer) => buffer,
+            // Parquet bytes reach the buffer a whole row group at a time
+            Output::Parquet { writer, .. } => writer.inner_mut(),
+        };
+        (buffer.len() >= CHUNK_BYTES).then(|| Bytes::from(std::mem::take(buffer)))
+    }
+
+    /// The rest of the output, including the Parquet footer
+    pub fn finish(mut self) -> Result<Bytes> {
+        match &mut self.output {
+            Output::Text(buffer) => Ok(Bytes::from(std::mem::take(buffer))),
+            Output::Parquet {
+                writer,
+                schema,
+                rows,
+            } => {
+                if !rows.is_empty() {
+                    writer.write(&record_batch(schema, &self.columns, rows)?)?;
+                }
+                writer.finish()?;
+                Ok(Bytes::from(std::mem::take(writer.inner_mut())))
+            }
+        }
+    }
+}
+
+fn arrow_type(kind: ColumnKind) -> DataType {
+    match kind {
+        ColumnKind::String => DataType::Utf8,
+        ColumnKind::UInt64 => DataType::UInt64,
+        ColumnKind::Float64 => DataType::Float64,
+        ColumnKind::Timestamp => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+    }
+}
+
+fn record_batch(
+    schema: &Arc<Schema>,
+    columns: &[Column],
+    rows: &[Vec<Cell>],
+) -> Result<RecordBatch> {
+    let arrays: Vec<ArrayRef> = columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| {
+            let cells = rows.iter().map(|row| &row[index]);
+            match column.kind {
+                ColumnKind::String => {
+                    let mut builder = StringBuilder::new();
+                    for cell in cells {
+                        match cell {
+                            Cell::Null => builder.append_null(),
+                            cell => builder.append_value(cell.text()),
+                        }
+                    }
+                    Arc::new(builder.finish()) as ArrayRef
+                }
+                ColumnKind::UInt64 => {
+                    let mut builder = UInt64Builder::new();
+                    for cell in cells {
+                        builder.append_option(match cell {
+                            Cell::UInt(value) => Some(*value),
+                            _ => None,
+                        });
+                    }
+                    Arc::new(builder.finish())
+                }
+                ColumnKind::Float64 => {
+                    let mut builder = Float64Builder::new();
+                    for cell in cells {
+                        builder.append_option(match cell {
+                            Cell::Float(value) => Some(*value),
+                            _ => None,
+                        });
+                    }
+                    Arc::new(builder.finish())
+                }
+                ColumnKind::Timestamp => {
+                    let mut builder = TimestampMillisecondBuilder::new().with_timezone("UTC");
+                    for cell in cells {
+                        builder.append_option(match cell {
+                            Cell::Timestamp(millis) => Some(*millis),
+                            _ => None,
+                        });
+                    }
+                    Arc::new(builder.finish())
+                }
+            }
+        })
+        .collect();
+    Ok(RecordBatch::try_new(Arc::clone(schema), arrays)?)
+}
+
+/// Quotes a field per RFC 4180 when it needs it
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl Cell {
+    fn text(&self) -> String {
+        match self {
+            Cell::Null => String::new(),
+            Cell::String(value) => value.clone(),
+            Cell::UInt(value) => value.to_string(),
+            Cell::Float(value) => value.to_string(),
+            Cell::Timestamp(millis) => DateTime::from_timestamp_millis(*millis)
+                .map(|timestamp| timestamp.to_rfc3339_opts(SecondsFormat::Millis, true))
+                .unwrap_or_default(),
+        }
+    }
+
+    fn json(self) -> Value {
+        match self {
+            Cell::Null => Value::Null,
+            Cell::String(value) => Value::String(value),
+            Cell::UInt(value) => Value::from(value),
+            Cell::Float(value) => Value::from(value),
+            cell @ Cell::Timestamp(_) => Value::String(cell.text()),
+        }
+    }
+}
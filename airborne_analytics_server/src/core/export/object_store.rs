@@ -0,0 +1,126 @@
+use anyhow::{Context, Result};
+use aws_sdk_s3::{
+    primitives::ByteStream,
+    types::{CompletedMultipartUpload, CompletedPart},
+    Client as S3Client,
+};
+use bytes::Bytes;
+use tracing::warn;
+
+use crate::core::export::ChunkSink;
+
+// S3 requires every part but the last to be at least 5 MiB
+const PART_BYTES: usize = 8 << 20;
+
+/// Whether `key` already exists in `bucket`
+pub async fn exists(client: &S3Client, bucket: &str, key: &str) -> Result<bool> {
+    match client.head_object().bucket(bucket).key(key).send().await {
+        Ok(_) => Ok(true),
+        Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(false),
+        Err(e) => Err(e).with_context(|| format!("Could not look up s3://{}/{}", bucket, key)),
+    }
+}
+
+/// A multipart upload fed chunk by chunk. The object only appears once
+/// `complete` succeeds; an abandoned upload should be `abort`ed.
+pub struct Upload {
+    client: S3Client,
+    bucket: String,
+    key: String,
+    upload_id: String,
+    parts: Vec<CompletedPart>,
+    buffer: Vec<u8>,
+}
+
+impl Upload {
+    pub async fn start(
+        client: &S3Client,
+        bucket: &str,
+        key: &str,
+        content_type: &str,
+    ) -> Result<Self> {
+        let upload = client
+            .create_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .content_type(content_type)
+            .send()
+            .await
+            .with_context(|| format!("Could not start upload of s3://{}/{}", bucket, key))?;
+        Ok(Self {
+            client: client.clone(),
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            upload_id: upload.upload_id().unwrap_or_default().to_string(),
+            parts: Vec::new(),
+            buffer: Vec::with_capacity(PART_BYTES),
+        })
+    }
+
+    async fn upload_part(&mut self) -> Result<()> {
+        let part_number = self.parts.len() as i32 + 1;
+        let body = std::mem::replace(&mut self.buffer, Vec::with_capacity(PART_BYTES));
+        let part = self
+            .client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .upload_id(&self.upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .with_context(|| format!("Could not upload part {} of {}", part_number, self.key))?;
+        self.parts.push(
+            CompletedPart::builder()
+                .part_number(part_number)
+                .set_e_tag(part.e_tag().map(str::to_string))
+                .build(),
+        );
+        Ok(())
+    }
+
+    pub async fn complete(mut self) -> Result<()> {
+        if !self.buffer.is_empty() || self.parts.is_empty() {
+            self.upload_part().await?;
+        }
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .upload_id(&self.upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(self.parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .with_context(|| format!("Could not complete upload of {}", self.key))?;
+        Ok(())
+    }
+
+    pub async fn abort(self) {
+        if let Err(e) = self
+            .client
+            .abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .upload_id(&self.upload_id)
+            .send()
+            .await
+        {
+            warn!("Could not abort upload of {}: {:?}", self.key, e);
+        }
+    }
+}
+
+impl ChunkSink for Upload {
+    async fn send(&mut self, chunk: Bytes) -> Result<()> {
+        self.buffer.extend_from_slice(&chunk);
+        if self.buffer.len() >= PART_BYTES {
+            self.upload_part().await?;
+        }
+        Ok(())
+    }
+}
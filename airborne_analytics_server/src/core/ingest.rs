@@ -0,0 +1,329 @@
+//! Admission checks for event ingestion: ingest keys issued by the Airborne
+//! server, optional HMAC request signatures, and per-key and per-device rate
+//! limits. Every rejection is counted in `analytics_ingest_rejected_total`,
+//! labelled by reason.
+
+pub mod codec;
+pub mod keys;
+mod rate_limit;
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use prometheus::{IntCounter, IntCounterVec, Opts};
+use sha2::Sha256;
+use tracing::{error, warn};
+
+use crate::{
+    common::{
+        config::IngestConfig,
+        error::{AppError, AppResult},
+        models::OtaEventRequest,
+    },
+    core::ingest::{
+        keys::{IngestKey, KeyVerifier},
+        rate_limit::RateLimiter,
+    },
+};
+
+pub const INGEST_KEY_HEADER: &str = "x-ingest-key";
+/// `t=<unix seconds>,v1=<hex HMAC-SHA256 of "<t>.<body>">`
+pub const SIGNATURE_HEADER: &str = "x-airborne-signature";
+
+// Corrected timestamps may run slightly ahead of the server's clock, since
+// the skew estimate also absorbs the request's transit time.
+const MAX_FUTURE_SECS: i64 = 300;
+
+pub struct Guard {
+    config: IngestConfig,
+    verifier: Option<KeyVerifier>,
+    key_limiter: RateLimiter,
+    device_limiter: RateLimiter,
+    accepted: IntCounter,
+    rejected: IntCounterVec,
+}
+
+impl Guard {
+    pub fn new(config: &IngestConfig) -> Result<Self> {
+        let verifier = match (&config.verify_url, &config.verify_token) {
+            (Some(url), Some(token)) => Some(KeyVerifier::new(
+                url.clone(),
+                token.clone(),
+                Duration::from_secs(config.key_cache_ttl_secs),
+            )?),
+            _ => None,
+        };
+
+        let accepted = IntCounter::new(
+            "analytics_ingest_accepted_total",
+            "Events accepted by the ingest endpoint",
+        )?;
+        let rejected = IntCounterVec::new(
+            Opts::new(
+                "analytics_ingest_rejected_total",
+                "Events rejected by the ingest endpoint",
+            ),
+            &["reason"],
+        )?;
+        prometheus::register(Box::new(accepted.clone()))?;
+        prometheus::register(Box::new(rejected.clone()))?;
+
+        Ok(Self {
+            config: config.clone(),
+            verifier,
+            key_limiter: RateLimiter::new(),
+            device_limiter: RateLimiter::new(),
+            accepted,
+            rejected,
+        })
+    }
+
+    pub fn reject(&self, reason: &str, error: AppError) -> AppError {
+        self.rejected.with_label_values(&[reason]).inc();
+        error
+    }
+
+    /// Authenticates the request, parses its body and applies rate limits.
+    pub async fn admit(&self, headers: &HeaderMap, body: &[u8]) -> AppResult<OtaEventRequest> {
+        let key = self.authenticate(headers, body).await?;
+        let request: OtaEventRequest =
+            codec::decode(headers, body).map_err(|e| self.reject("invalid_body", e))?;
+        self.check_event(key.as_deref(), &request, &mut HashSet::new())?;
+        Ok(request)
+    }
+
+    /// Checks that an event belongs to the key's application and is within
+    /// the key's and the device's rate limits. The key limit counts events.
+    /// The device limit counts requests: a device is charged only if it is
+    /// not yet in `charged`, so a batch it buffered while offline costs one
+    /// request however many events it holds.
+    pub fn check_event(
+        &self,
+        key: Option<&IngestKey>,
+        request: &OtaEventRequest,
+        charged: &mut HashSet<String>,
+    ) -> AppResult<()> {
+        let bucket = match key {
+            Some(key) => {
+                if key.organisation != request.org_id || key.application != request.app_id {
+                    return Err(self.reject(
+                        "app_mismatch",
+                        AppError::Forbidden(
+                            "Ingest key does not belong to this application".to_string(),
+                        ),
+                    ));
+                }
+                key.key_id.to_string()
+            }
+            None => format!("{}:{}", request.org_id, request.app_id),
+        };
+        let key_limit = key
+            .and_then(|key| key.rate_limit_per_minute)
+            .unwrap_or(self.config.key_rate_limit_per_minute);
+        self.key_limiter
+            .check(&bucket, key_limit)
+            .map_err(|retry| self.reject("key_rate_limited", AppError::RateLimited(retry)))?;
+        let device = format!("{}:{}", bucket, request.device_id);
+        if !charged.contains(&device) {
+            self.device_limiter
+                .check(&device, self.config.device_rate_limit_per_minute)
+                .map_err(|retry| {
+                    self.reject("device_rate_limited", AppError::RateLimited(retry))
+                })?;
+            charged.insert(device);
+        }
+
+        self.accepted.inc();
+        Ok(())
+    }
+
+    /// Resolves when an event happened: the client's timestamp shifted by
+    /// the client's clock skew, or `received_at` when the client sent none.
+    pub fn event_time(
+        &self,
+        client_timestamp: Option<DateTime<Utc>>,
+        skew: chrono::Duration,
+        received_at: DateTime<Utc>,
+    ) -> AppResult<DateTime<Utc>> {
+        let Some(client_timestamp) = client_timestamp else {
+            return Ok(received_at);
+        };
+        let timestamp = client_timestamp + skew;
+        if timestamp > received_at + chrono::Duration::seconds(MAX_FUTURE_SECS) {
+            return Err(self.reject(
+                "future_timestamp",
+                AppError::Validation("Event timestamp is in the future".to_string()),
+            ));
+        }
+        if timestamp < received_at - chrono::Duration::seconds(self.config.max_event_age_secs) {
+            return Err(self.reject(
+                "stale_timestamp",
+                AppError::Validation("Event timestamp is too old".to_string()),
+            ));
+        }
+        Ok(timestamp)
+    }
+
+    /// Authenticates the request with its ingest key and, when present or
+    /// required, its signature. Returns `None` when keys are not required and
+    /// none was sent.
+    pub async fn authenticate(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> AppResult<Option<Arc<IngestKey>>> {
+        let presented = headers
+            .get(INGEST_KEY_HEADER)
+            .and_then(|value| value.to_str().ok());
+        let (Some(presented), Some(verifier)) = (presented, &self.verifier) else {
+            if self.config.require_key {
+                return Err(self.reject(
+                    "missing_key",
+                    AppError::Unauthorized(format!("Missing {} header", INGEST_KEY_HEADER)),
+                ));
+            }
+            return Ok(None);
+        };
+
+        let key = verifier
+            .verify(presented)
+            .await
+            .map_err(|e| {
+                error!("Could not verify ingest key: {:?}", e);
+                self.reject(
+                    "verification_unavailable",
+                    AppError::Internal("Could not verify ingest key".to_string()),
+                )
+            })?
+            .ok_or_else(|| {
+                self.reject(
+                    "invalid_key",
+                    AppError::Unauthorized("Invalid ingest key".to_string()),
+                )
+            })?;
+
+        let signature = headers
+            .get(SIGNATURE_HEADER)
+            .and_then(|value| value.to_str().ok());
+        match (&key.signing_secret, signature) {
+            (Some(secret), Some(signature)) => {
+                if let Err(reason) = verify_signature(
+                    secret,
+                    signature,
+                    body,
+                    self.config.signature_tolerance_secs,
+                ) {
+                    warn!(
+                        "Rejected signature for ingest key {}: {}",
+                        key.key_id, reason
+                    );
+                    return Err(self.reject(
+                        "invalid_signature",
+                        AppError::Unauthorized(format!("Invalid signature: {}", reason)),
+                    ));
+                }
+            }
+            (_, None) if key.require_signature => {
+                return Err(self.reject(
+                    "missing_signature",
+                    AppError::Unauthorized(format!("Missing {} header", SIGNATURE_HEADER)),
+                ));
+            }
+            (None, Some(_)) => {
+                return Err(self.reject(
+                    "invalid_signature",
+                    AppError::Unauthorized("Ingest key has no signing secret".to_string()),
+                ));
+            }
+            _ => {}
+        }
+
+        Ok(Some(key))
+    }
+}
+
+fn verify_signature(
+    secret: &str,
+    header: &str,
+    body: &[u8],
+    tolerance_secs: i64,
+) -> Result<(), &'static str> {
+    let mut timestamp = None;
+    let mut digest = None;
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => digest = hex::decode(value).ok(),
+            _ => {}
+        }
+    }
+    let (Some(timestamp), Some(digest)) = (timestamp, digest) else {
+        return Err("malformed header");
+    };
+    if (Utc::now().timestamp() - timestamp).abs() > tolerance_secs {
+        return Err("timestamp outside the allowed window");
+    }
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_err(|_| "invalid secret")?;
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac.verify_slice(&digest).map_err(|_| "digest mismatch")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(device_id: &str) -> OtaEventRequest {
+        serde_json::from_value(serde_json::json!({
+            "org_id": "acme",
+            "app_id": "shop",
+            "device_id": device_id,
+            "event_type": "UPDATE_CHECK",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn a_full_batch_costs_a_device_one_request() {
+        let config = IngestConfig {
+            require_key: false,
+            verify_url: None,
+            verify_token: None,
+            key_cache_ttl_secs: 60,
+            key_rate_limit_per_minute: 6000,
+            device_rate_limit_per_minute: 120,
+            signature_tolerance_secs: 300,
+            max_batch_events: 500,
+            max_event_age_secs: 7 * 24 * 60 * 60,
+            dedup_window_secs: 24 * 60 * 60,
+        };
+        let guard = Guard::new(&config).unwrap();
+        let request = event("device-1");
+
+        let mut charged = HashSet::new();
+        for _ in 0..config.max_batch_events {
+            guard.check_event(None, &request, &mut charged).unwrap();
+        }
+
+        // The batch took one of the device's 120 requests
+        for _ in 1..config.device_rate_limit_per_minute {
+            guard
+                .check_event(None, &request, &mut HashSet::new())
+                .unwrap();
+        }
+        assert!(matches!(
+            guard.check_event(None, &request, &mut HashSet::new()),
+            Err(AppError::RateLimited(_))
+        ));
+        // Other devices have their own budget
+        assert!(guard
+            .check_event(None, &event("device-2"), &mut HashSet::new())
+            .is_ok());
+    }
+}
@@ -0,0 +1,61 @@
+use std::io::Read;
+
+use axum::http::{header, HeaderMap};
+use flate2::read::GzDecoder;
+use serde::de::DeserializeOwned;
+
+use crate::common::error::{AppError, AppResult};
+
+// Caps the decompressed size so a small compressed body cannot expand into
+// an arbitrarily large allocation.
+const MAX_DECODED_BYTES: u64 = 16 * 1024 * 1024;
+
+fn header_value(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+}
+
+fn read_limited(reader: impl Read) -> AppResult<Vec<u8>> {
+    let mut decoded = Vec::new();
+    reader
+        .take(MAX_DECODED_BYTES + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|e| AppError::Validation(format!("Could not decompress body: {}", e)))?;
+    if decoded.len() as u64 > MAX_DECODED_BYTES {
+        return Err(AppError::Validation(format!(
+            "Decompressed body exceeds {} bytes",
+            MAX_DECODED_BYTES
+        )));
+    }
+    Ok(decoded)
+}
+
+/// Undoes `Content-Encoding` (`gzip` or `zstd`) and parses the body as JSON
+/// or, with `Content-Type: application/cbor`, as CBOR.
+pub fn decode<T: DeserializeOwned>(headers: &HeaderMap, body: &[u8]) -> AppResult<T> {
+    let decoded = match header_value(headers, header::CONTENT_ENCODING) {
+        None | Some("") | Some("identity") => body.to_vec(),
+        Some("gzip") => read_limited(GzDecoder::new(body))?,
+        Some("zstd") => read_limited(
+            zstd::stream::read::Decoder::new(body)
+                .map_err(|e| AppError::Validation(format!("Could not decompress body: {}", e)))?,
+        )?,
+        Some(other) => {
+            return Err(AppError::Validation(format!(
+                "Unsupported Content-Encoding '{}'",
+                other
+            )))
+        }
+    };
+
+    let is_cbor = header_value(headers, header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.starts_with("application/cbor"));
+    if is_cbor {
+        ciborium::from_reader(decoded.as_slice())
+            .map_err(|e| AppError::Validation(format!("Invalid CBOR body: {}", e)))
+    } else {
+        Ok(serde_json::from_slice(&decoded)?)
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use reqwest::{Client as HttpClient, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use uuid::Uuid;
+
+// Unknown keys are cached too, so a client retrying with a bad key does not
+// reach the Airborne server on every request; keep that window short so a
+// freshly issued key starts working quickly.
+const NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(10);
+const MAX_CACHED_KEYS: usize = 10_000;
+
+/// An ingest key as resolved by the Airborne server.
+#[derive(Debug, Deserialize)]
+pub struct IngestKey {
+    pub key_id: Uuid,
+    pub organisation: String,
+    pub application: String,
+    pub signing_secret: Option<String>,
+    pub require_signature: bool,
+    pub rate_limit_per_minute: Option<u32>,
+}
+
+struct CachedKey {
+    key: Option<Arc<IngestKey>>,
+    expires_at: Instant,
+}
+
+/// Resolves ingest keys through the Airborne server's verification endpoint,
+/// caching the answers. Revoking a key therefore takes up to the cache TTL to
+/// reach the analytics server.
+pub struct KeyVerifier {
+    http: HttpClient,
+    verify_url: String,
+    verify_token: String,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, CachedKey>>,
+}
+
+impl KeyVerifier {
+    pub fn new(verify_url: String, verify_token: String, ttl: Duration) -> Result<Self> {
+        Ok(Self {
+            http: HttpClient::builder()
+                .timeout(Duration::from_secs(5))
+                .build()?,
+            verify_url,
+            verify_token,
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Returns `None` for unknown or revoked keys.
+    pub async fn verify(&self, ingest_key: &str) -> Result<Option<Arc<IngestKey>>> {
+        let cache_key = hex::encode(Sha256::digest(ingest_key.as_bytes()));
+        let now = Instant::now();
+        if let Some(cached) = self.cache.lock().unwrap().get(&cache_key) {
+            if cached.expires_at > now {
+                return Ok(cached.key.clone());
+            }
+        }
+
+        let response = self
+            .http
+            .post(&self.verify_url)
+            .bearer_auth(&self.verify_token)
+            .json(&json!({ "ingest_key": ingest_key }))
+            .send()
+            .await?;
+        let key = match response.status() {
+            status if status.is_success() => Some(Arc::new(response.json::<IngestKey>().await?)),
+            StatusCode::UNAUTHORIZED => None,
+            status => {
+                warn!("Ingest key verification failed with status {}", status);
+                anyhow::bail!("ingest key verification returned {}", status);
+            }
+        };
+
+        let ttl = if key.is_some() {
+            self.ttl
+        } else {
+            NEGATIVE_CACHE_TTL.min(self.ttl)
+        };
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_KEYS {
+            cache.retain(|_, cached| cached.expires_at > now);
+        }
+        cache.insert(
+            cache_key,
+            CachedKey {
+                key: key.clone(),
+                expires_at: now + ttl,
+            },
+        );
+        Ok(key)
+    }
+}
@@ -0,0 +1,61 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Idle buckets are dropped once this many are tracked.
+const SWEEP_THRESHOLD: usize = 100_000;
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// In-memory token buckets holding a minute's worth of requests each, so
+/// clients can burst up to their per-minute limit. Limits are per process;
+/// with several replicas behind a load balancer the effective limit scales
+/// with the replica count.
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self {
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes one token from `key`'s bucket, or returns how many seconds
+    /// until one is available.
+    pub fn check(&self, key: &str, limit_per_minute: u32) -> Result<(), u64> {
+        let capacity = f64::from(limit_per_minute.max(1));
+        let refill_per_sec = capacity / 60.0;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= SWEEP_THRESHOLD {
+            // A bucket idle for a minute is full again, so forgetting it
+            // changes nothing.
+            buckets.retain(|_, bucket| {
+                now.duration_since(bucket.updated_at) < Duration::from_secs(60)
+            });
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill_per_sec).min(capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / refill_per_sec).ceil() as u64)
+        }
+    }
+}
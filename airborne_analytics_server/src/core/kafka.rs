@@ -1,11 +1,16 @@
-use std::{sync::Arc, time::Duration};
+pub mod dlq;
+mod metrics;
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use anyhow::Result;
+use futures::future::join_all;
 use rdkafka::{
     config::ClientConfig,
     consumer::{CommitMode, StreamConsumer},
+    message::BorrowedMessage,
     producer::{FutureProducer, FutureRecord},
-    Message,
+    Message, Offset, TopicPartitionList,
 };
 use tracing::{error, info, warn};
 
@@ -14,9 +19,51 @@ use crate::{
         config::KafkaConfig,
         models::{EventType, OtaEvent},
     },
-    core::clickhouse,
+    core::{
+        clickhouse,
+        kafka::{
+            dlq::{DeadLetterQueue, SourceMessage},
+            metrics::ConsumerMetrics,
+        },
+    },
 };
 
+/// Client settings shared by every producer and consumer: the brokers and,
+/// if provided, the security configuration.
+fn client_config(config: &KafkaConfig) -> ClientConfig {
+    let mut client_config = ClientConfig::new();
+    client_config.set("bootstrap.servers", &config.brokers);
+
+    if let Some(security_protocol) = &config.security_protocol {
+        client_config.set("security.protocol", security_protocol);
+
+        if let (Some(username), Some(password)) = (&config.sasl_username, &config.sasl_password) {
+            client_config.set("sasl.username", username);
+            client_config.set("sasl.password", password);
+        }
+
+        if let Some(mechanisms) = &config.sasl_mechanisms {
+            client_config.set("sasl.mechanisms", mechanisms);
+        }
+    }
+
+    client_config
+}
+
+fn parse_message(message: &BorrowedMessage<'_>) -> Result<OtaEvent, (&'static str, String)> {
+    let payload = match message.payload_view::<str>() {
+        None => return Err(("empty_payload", "Empty message payload".to_string())),
+        Some(Ok(s)) => s,
+        Some(Err(e)) => {
+            return Err((
+                "invalid_utf8",
+                format!("Message payload is not valid UTF-8: {:?}", e),
+            ))
+        }
+    };
+    serde_json::from_str(payload).map_err(|e| ("parse_error", format!("Invalid OTA event: {}", e)))
+}
+
 #[derive(Clone)]
 pub struct Producer {
     producer: FutureProducer,
@@ -25,28 +72,12 @@ pub struct Producer {
 
 impl Producer {
     pub async fn new(config: &KafkaConfig) -> Result<Self> {
-        let mut client_config = ClientConfig::new();
-        client_config.set("bootstrap.servers", &config.brokers);
+        let mut client_config = client_config(config);
         client_config.set("message.timeout.ms", "5000");
         client_config.set("batch.size", "65536"); // 64KB batches for better throughput
         client_config.set("linger.ms", "10"); // Small delay to allow batching
         client_config.set("compression.type", "snappy"); // Compression for efficiency
 
-        // Add security configuration if provided
-        if let Some(security_protocol) = &config.security_protocol {
-            client_config.set("security.protocol", security_protocol);
-
-            if let (Some(username), Some(password)) = (&config.sasl_username, &config.sasl_password)
-            {
-                client_config.set("sasl.username", username);
-                client_config.set("sasl.password", password);
-            }
-
-            if let Some(mechanisms) = &config.sasl_mechanisms {
-                client_config.set("sasl.mechanisms", mechanisms);
-            }
-        }
-
         let producer: FutureProducer = client_config.create()?;
 
         Ok(Self {
@@ -105,35 +136,37 @@ impl Producer {
         }
     }
 
-    /// Send multiple events in a batch for better performance
-    #[allow(dead_code)]
+    /// Send multiple events in a batch for better performance. The sends run
+    /// concurrently so the producer can batch them; results keep the order of
+    /// `events`.
     pub async fn send_ota_events_batch(
         &self,
         events: &[OtaEvent],
     ) -> Result<Vec<Result<(), anyhow::Error>>> {
-        let mut results = Vec::new();
-
-        for event in events {
-            results.push(self.send_ota_event(event).await);
-        }
-
-        Ok(results)
+        Ok(join_all(events.iter().map(|event| self.send_ota_event(event))).await)
     }
 }
 
+struct BatchEntry {
+    event: OtaEvent,
+    source: SourceMessage,
+}
+
 pub struct Consumer {
     consumer: StreamConsumer,
     topic: String,
     clickhouse: Arc<clickhouse::Client>,
+    dlq: DeadLetterQueue,
+    metrics: ConsumerMetrics,
+    batch_max_attempts: u32,
 }
 
 impl Consumer {
     pub async fn new(config: &KafkaConfig, clickhouse: Arc<clickhouse::Client>) -> Result<Self> {
         use rdkafka::consumer::Consumer as _; // Import trait methods
 
-        let mut client_config = ClientConfig::new();
+        let mut client_config = client_config(config);
         client_config.set("group.id", &config.consumer_group);
-        client_config.set("bootstrap.servers", &config.brokers);
         client_config.set("enable.partition.eof", "false");
         client_config.set("session.timeout.ms", "6000");
         client_config.set("enable.auto.commit", "false"); // Manual commit for better control
@@ -141,34 +174,26 @@ impl Consumer {
         client_config.set("fetch.min.bytes", "1048576"); // 1MB minimum fetch for efficiency
         client_config.set("fetch.wait.max.ms", "500"); // Max 500ms wait
 
-        // Add security configuration if provided
-        if let Some(security_protocol) = &config.security_protocol {
-            client_config.set("security.protocol", security_protocol);
-
-            if let (Some(username), Some(password)) = (&config.sasl_username, &config.sasl_password)
-            {
-                client_config.set("sasl.username", username);
-                client_config.set("sasl.password", password);
-            }
-
-            if let Some(mechanisms) = &config.sasl_mechanisms {
-                client_config.set("sasl.mechanisms", mechanisms);
-            }
-        }
-
         let consumer: StreamConsumer = client_config.create()?;
         consumer.subscribe(&[&config.topic])?;
 
+        let metrics = ConsumerMetrics::new()?;
+        let dlq = DeadLetterQueue::new(config, metrics.dlq_send_failures.clone())?;
+
         Ok(Self {
             consumer,
             topic: config.topic.clone(),
             clickhouse,
+            dlq,
+            metrics,
+            batch_max_attempts: config.batch_max_attempts.max(1),
         })
     }
 
+    /// Consumes events in batches. Messages that cannot be parsed, and
+    /// batches that cannot be stored after `batch_max_attempts`, go to the
+    /// dead-letter topic, so offsets are always committed after a flush.
     pub async fn start_consuming(&self) -> Result<()> {
-        use rdkafka::consumer::Consumer as _; // Import trait methods
-
         info!(
             "Starting Kafka consumer for OTA events topic: {}",
             self.topic
@@ -176,6 +201,8 @@ impl Consumer {
 
         // For batch processing
         let mut batch = Vec::new();
+        // Highest offset received per partition since the last commit
+        let mut offsets = HashMap::new();
         const BATCH_SIZE: usize = 100;
         let mut last_commit = std::time::Instant::now();
         const COMMIT_INTERVAL: Duration = Duration::from_secs(5);
@@ -188,20 +215,10 @@ impl Consumer {
                     continue;
                 }
                 Ok(m) => {
-                    let payload = match m.payload_view::<str>() {
-                        None => {
-                            warn!("Empty message payload");
-                            continue;
-                        }
-                        Some(Ok(s)) => s,
-                        Some(Err(e)) => {
-                            error!("Message payload is not valid UTF-8: {:?}", e);
-                            continue;
-                        }
-                    };
+                    offsets.insert(m.partition(), m.offset());
+                    let source = SourceMessage::new(&m);
 
-                    // Parse the OTA event
-                    match serde_json::from_str::<OtaEvent>(payload) {
+                    match parse_message(&m) {
                         Ok(event) => {
                             info!(
                                 "Received OTA event: {} for {}/{}",
@@ -209,38 +226,133 @@ impl Consumer {
                                 event.org_id,
                                 event.app_id
                             );
-
-                            batch.push(event);
-
-                            // Process batch when it reaches size limit or commit interval
-                            if batch.len() >= BATCH_SIZE || last_commit.elapsed() > COMMIT_INTERVAL
-                            {
-                                if let Err(e) = self.process_event_batch(&batch).await {
-                                    error!("Failed to process event batch: {:?}", e);
-                                    // In production, you might want to send failed events to a dead letter queue
-                                } else {
-                                    // Only commit if batch processing succeeded
-                                    if let Err(e) =
-                                        self.consumer.commit_message(&m, CommitMode::Async)
-                                    {
-                                        error!("Failed to commit message: {:?}", e);
-                                    } else {
-                                        info!("Successfully processed and committed batch of {} events", batch.len());
-                                    }
-                                }
-
-                                batch.clear();
-                                last_commit = std::time::Instant::now();
-                            }
+                            batch.push(BatchEntry { event, source });
+                        }
+                        Err((reason, e)) => {
+                            error!(
+                                "Unparseable message {}:{}: {}",
+                                source.partition, source.offset, e
+                            );
+                            self.metrics
+                                .parse_failures
+                                .with_label_values(&[reason])
+                                .inc();
+                            self.dlq.send(&source, reason, &e).await;
+                            self.metrics
+                                .messages
+                                .with_label_values(&["dead_lettered"])
+                                .inc();
                         }
-                        Err(e) => {
-                            error!("Failed to parse OTA event: {:?}, payload: {}", e, payload);
-                            // Log the failed message for debugging but continue processing
+                    }
+
+                    // Process batch when it reaches size limit or commit interval
+                    if batch.len() >= BATCH_SIZE || last_commit.elapsed() > COMMIT_INTERVAL {
+                        self.flush(&mut batch).await;
+                        self.commit(&mut offsets);
+                        last_commit = std::time::Instant::now();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Stores the batch, retrying with backoff; storage is idempotent by
+    /// event id, so a retry after a partial insert does not double count.
+    /// Dead-letters the batch once the attempts run out.
+    async fn flush(&self, batch: &mut Vec<BatchEntry>) {
+        if batch.is_empty() {
+            return;
+        }
+        let events: Vec<OtaEvent> = batch.iter().map(|entry| entry.event.clone()).collect();
+        let mut attempt = 1;
+        loop {
+            match self.process_event_batch(&events).await {
+                Ok(()) => {
+                    self.metrics
+                        .messages
+                        .with_label_values(&["stored"])
+                        .inc_by(events.len() as i64);
+                    info!("Successfully processed batch of {} events", events.len());
+                    break;
+                }
+                Err(e) => {
+                    self.metrics.store_failures.inc();
+                    if attempt >= self.batch_max_attempts {
+                        error!(
+                            "Giving up on batch of {} events after {} attempts, dead-lettering it: {:?}",
+                            events.len(),
+                            attempt,
+                            e
+                        );
+                        let error = e.to_string();
+                        for entry in batch.iter() {
+                            self.dlq.send(&entry.source, "store_failed", &error).await;
                         }
+                        self.metrics
+                            .messages
+                            .with_label_values(&["dead_lettered"])
+                            .inc_by(events.len() as i64);
+                        break;
                     }
+                    let backoff = Duration::from_secs(1 << attempt.min(5));
+                    warn!(
+                        "Failed to process event batch (attempt {}/{}), retrying in {:?}: {:?}",
+                        attempt, self.batch_max_attempts, backoff, e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
                 }
             }
         }
+        batch.clear();
+    }
+
+    fn commit(&self, offsets: &mut HashMap<i32, i64>) {
+        use rdkafka::consumer::Consumer as _; // Import trait methods
+
+        if offsets.is_empty() {
+            return;
+        }
+        let mut list = TopicPartitionList::new();
+        for (&partition, &offset) in offsets.iter() {
+            // The committed offset is the next message to consume
+            if let Err(e) =
+                list.add_partition_offset(&self.topic, partition, Offset::Offset(offset + 1))
+            {
+                error!(
+                    "Invalid offset {} for partition {}: {:?}",
+                    offset, partition, e
+                );
+            }
+        }
+        match self.consumer.commit(&list, CommitMode::Async) {
+            Ok(()) => self.record_lag(offsets),
+            Err(e) => error!("Failed to commit offsets: {:?}", e),
+        }
+        offsets.clear();
+    }
+
+    fn record_lag(&self, offsets: &HashMap<i32, i64>) {
+        use rdkafka::consumer::Consumer as _; // Import trait methods
+
+        for (&partition, &offset) in offsets {
+            // Fetching watermarks is a blocking broker round trip
+            let watermarks = tokio::task::block_in_place(|| {
+                self.consumer
+                    .fetch_watermarks(&self.topic, partition, Duration::from_secs(1))
+            });
+            match watermarks {
+                Ok((_, high)) => self
+                    .metrics
+                    .lag
+                    .with_label_values(&[&partition.to_string()])
+                    .set(high - offset - 1),
+                Err(e) => warn!(
+                    "Could not fetch watermarks for partition {}: {:?}",
+                    partition, e
+                ),
+            }
+        }
     }
 
     async fn process_event_batch(&self, events: &[OtaEvent]) -> Result<()> {
@@ -0,0 +1,222 @@
+//! Dead-letter topic for messages the consumer could not parse or store.
+//! Each dead letter carries the original key and payload unchanged, plus
+//! headers describing the failure and where the message came from, so it can
+//! be replayed onto the events topic once the cause is fixed.
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::Utc;
+use prometheus::IntCounter;
+use rdkafka::{
+    consumer::{CommitMode, Consumer as _, StreamConsumer},
+    message::{BorrowedMessage, Header, OwnedHeaders},
+    producer::{FutureProducer, FutureRecord},
+    Message,
+};
+use serde::Serialize;
+use tracing::{error, info};
+
+use crate::{common::config::KafkaConfig, core::kafka::client_config};
+
+/// Set on replayed messages to `<dlq partition>:<dlq offset>`.
+pub const REPLAY_HEADER: &str = "dlq_replay_of";
+
+// The replay stops once the topic has been quiet this long.
+const REPLAY_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_SEND_BACKOFF: Duration = Duration::from_secs(30);
+
+static REPLAY_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// The parts of a consumed message needed to dead-letter it after the
+/// borrowed message itself is gone.
+pub struct SourceMessage {
+    pub key: Option<Vec<u8>>,
+    pub payload: Option<Vec<u8>>,
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+}
+
+impl SourceMessage {
+    pub fn new(message: &BorrowedMessage<'_>) -> Self {
+        Self {
+            key: message.key().map(<[u8]>::to_vec),
+            payload: message.payload().map(<[u8]>::to_vec),
+            topic: message.topic().to_string(),
+            partition: message.partition(),
+            offset: message.offset(),
+        }
+    }
+}
+
+fn record<'a>(
+    topic: &'a str,
+    key: Option<&'a [u8]>,
+    payload: Option<&'a [u8]>,
+    headers: OwnedHeaders,
+) -> FutureRecord<'a, [u8], [u8]> {
+    let mut record = FutureRecord::to(topic).headers(headers);
+    if let Some(key) = key {
+        record = record.key(key);
+    }
+    if let Some(payload) = payload {
+        record = record.payload(payload);
+    }
+    record
+}
+
+pub struct DeadLetterQueue {
+    producer: FutureProducer,
+    topic: String,
+    send_failures: IntCounter,
+}
+
+impl DeadLetterQueue {
+    pub fn new(config: &KafkaConfig, send_failures: IntCounter) -> Result<Self> {
+        let producer = client_config(config)
+            .set("message.timeout.ms", "5000")
+            .create()?;
+        Ok(Self {
+            producer,
+            topic: config.dlq_topic.clone(),
+            send_failures,
+        })
+    }
+
+    /// Produces `source` to the dead-letter topic. Retries until the broker
+    /// accepts it, since the caller commits past the message afterwards.
+    pub async fn send(&self, source: &SourceMessage, reason: &str, error: &str) {
+        let partition = source.partition.to_string();
+        let offset = source.offset.to_string();
+        let failed_at = Utc::now().to_rfc3339();
+        let mut backoff = Duration::from_secs(1);
+        loop {
+            let headers = OwnedHeaders::new()
+                .insert(Header {
+                    key: "reason",
+                    value: Some(reason),
+                })
+                .insert(Header {
+                    key: "error",
+                    value: Some(error),
+                })
+                .insert(Header {
+                    key: "source_topic",
+                    value: Some(&source.topic),
+                })
+                .insert(Header {
+                    key: "source_partition",
+                    value: Some(&partition),
+                })
+                .insert(Header {
+                    key: "source_offset",
+                    value: Some(&offset),
+                })
+                .insert(Header {
+                    key: "failed_at",
+                    value: Some(&failed_at),
+                });
+            let record = record(
+                &self.topic,
+                source.key.as_deref(),
+                source.payload.as_deref(),
+                headers,
+            );
+            match self.producer.send(record, Duration::from_secs(5)).await {
+                Ok(_) => {
+                    info!(
+                        "Dead-lettered message {}:{} ({})",
+                        source.partition, source.offset, reason
+                    );
+                    return;
+                }
+                Err((e, _)) => {
+                    self.send_failures.inc();
+                    error!(
+                        "Failed to dead-letter message {}:{}, retrying in {:?}: {:?}",
+                        source.partition, source.offset, backoff, e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_SEND_BACKOFF);
+                }
+            }
+        }
+    }
+}
+
+/// Held while a replay runs, so the admin endpoint starts one at a time.
+pub struct ReplayLock(());
+
+impl ReplayLock {
+    pub fn acquire() -> Option<Self> {
+        REPLAY_RUNNING
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .ok()
+            .map(|_| Self(()))
+    }
+}
+
+impl Drop for ReplayLock {
+    fn drop(&mut self) {
+        REPLAY_RUNNING.store(false, Ordering::Release);
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReplayReport {
+    pub replayed: usize,
+    /// Why the replay stopped before draining the topic, if it did.
+    pub error: Option<String>,
+}
+
+/// Moves up to `max_messages` dead letters back onto the events topic, in
+/// order. Progress is tracked by its own consumer group, so each dead letter
+/// is replayed once; replayed messages that fail again are dead-lettered
+/// anew.
+pub async fn replay(config: &KafkaConfig, max_messages: usize) -> Result<ReplayReport> {
+    let consumer: StreamConsumer = client_config(config)
+        .set("group.id", format!("{}-dlq-replay", config.consumer_group))
+        .set("enable.partition.eof", "false")
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .create()?;
+    consumer.subscribe(&[&config.dlq_topic])?;
+    let producer: FutureProducer = client_config(config)
+        .set("message.timeout.ms", "5000")
+        .create()?;
+
+    let mut report = ReplayReport::default();
+    while report.replayed < max_messages {
+        let message = match tokio::time::timeout(REPLAY_IDLE_TIMEOUT, consumer.recv()).await {
+            Err(_) => break,
+            Ok(Err(e)) => {
+                report.error = Some(format!("Failed to read dead-letter topic: {}", e));
+                break;
+            }
+            Ok(Ok(message)) => message,
+        };
+
+        let replay_of = format!("{}:{}", message.partition(), message.offset());
+        let headers = OwnedHeaders::new().insert(Header {
+            key: REPLAY_HEADER,
+            value: Some(&replay_of),
+        });
+        let record = record(&config.topic, message.key(), message.payload(), headers);
+        if let Err((e, _)) = producer.send(record, Duration::from_secs(5)).await {
+            report.error = Some(format!("Failed to replay {}: {}", replay_of, e));
+            break;
+        }
+        if let Err(e) = consumer.commit_message(&message, CommitMode::Sync) {
+            report.error = Some(format!("Failed to commit {}: {}", replay_of, e));
+            break;
+        }
+        report.replayed += 1;
+    }
+
+    info!("DLQ replay finished: {:?}", report);
+    Ok(report)
+}
@@ -0,0 +1,63 @@
+use anyhow::Result;
+use prometheus::{IntCounter, IntCounterVec, IntGaugeVec, Opts};
+
+/// Consumer health, exposed on `/analytics/metrics`.
+pub struct ConsumerMetrics {
+    /// Messages handled, labelled `stored` or `dead_lettered`.
+    pub messages: IntCounterVec,
+    /// Messages that could not be parsed, labelled by reason.
+    pub parse_failures: IntCounterVec,
+    /// Failed attempts at storing a batch in ClickHouse.
+    pub store_failures: IntCounter,
+    /// Failed attempts at producing to the dead-letter topic.
+    pub dlq_send_failures: IntCounter,
+    /// Messages between the committed offset and the end of each partition.
+    pub lag: IntGaugeVec,
+}
+
+impl ConsumerMetrics {
+    pub fn new() -> Result<Self> {
+        let messages = IntCounterVec::new(
+            Opts::new(
+                "analytics_consumer_messages_total",
+                "Kafka messages handled by the consumer",
+            ),
+            &["outcome"],
+        )?;
+        let parse_failures = IntCounterVec::new(
+            Opts::new(
+                "analytics_consumer_parse_failures_total",
+                "Kafka messages the consumer could not parse",
+            ),
+            &["reason"],
+        )?;
+        let store_failures = IntCounter::new(
+            "analytics_consumer_store_failures_total",
+            "Failed attempts at storing a batch of events",
+        )?;
+        let dlq_send_failures = IntCounter::new(
+            "analytics_consumer_dlq_send_failures_total",
+            "Failed attempts at producing to the dead-letter topic",
+        )?;
+        let lag = IntGaugeVec::new(
+            Opts::new(
+                "analytics_consumer_lag",
+                "Messages not yet consumed, per partition",
+            ),
+            &["partition"],
+        )?;
+        prometheus::register(Box::new(messages.clone()))?;
+        prometheus::register(Box::new(parse_failures.clone()))?;
+        prometheus::register(Box::new(store_failures.clone()))?;
+        prometheus::register(Box::new(dlq_send_failures.clone()))?;
+        prometheus::register(Box::new(lag.clone()))?;
+
+        Ok(Self {
+            messages,
+            parse_failures,
+            store_failures,
+            dlq_send_failures,
+            lag,
+        })
+    }
+}
@@ -0,0 +1,49 @@
+//! Personal data handling: client IP anonymisation at ingest and the
+//! identifiers recorded on erasure receipts.
+
+use std::net::IpAddr;
+
+use sha2::{Digest, Sha256};
+
+use crate::common::config::{IpMode, PrivacyConfig};
+
+/// Applies the configured `IpMode` to a client address. Values that do not
+/// parse as an IP address are dropped rather than stored verbatim.
+pub fn anonymize_ip(config: &PrivacyConfig, ip: &str) -> Option<String> {
+    if config.ip_mode == IpMode::Full {
+        return Some(ip.to_string());
+    }
+    let ip: IpAddr = ip.trim().parse().ok()?;
+    match config.ip_mode {
+        IpMode::Full => Some(ip.to_string()),
+        IpMode::Truncate => Some(match ip {
+            IpAddr::V4(ip) => {
+                let [a, b, c, _] = ip.octets();
+                format!("{}.{}.{}.0", a, b, c)
+            }
+            IpAddr::V6(ip) => {
+                let mut segments = ip.segments();
+                segments[3..].fill(0);
+                std::net::Ipv6Addr::from(segments).to_string()
+            }
+        }),
+        IpMode::Hash => {
+            let mut digest = Sha256::new();
+            digest.update(config.ip_hash_salt.as_deref().unwrap_or_default());
+            digest.update(ip.to_string());
+            Some(hex::encode(digest.finalize()))
+        }
+        IpMode::Drop => None,
+    }
+}
+
+/// Erasure receipts identify the device by this digest, so the audit trail
+/// does not itself retain the identifier that was erased.
+pub fn device_id_digest(org_id: &str, app_id: &str, device_id: &str) -> String {
+    let mut digest = Sha256::new();
+    for part in [org_id, app_id, device_id] {
+        digest.update(part.as_bytes());
+        digest.update([0]);
+    }
+    hex::encode(digest.finalize())
+}
@@ -1,32 +1,51 @@
 pub mod client;
 mod query_builder;
 
-use std::{collections::BTreeMap, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use anyhow::Result;
 use chrono::{DateTime, Timelike, Utc};
 use futures::{stream::FuturesUnordered, StreamExt};
 use prometheus::{
-    CounterVec, Encoder, GaugeVec, HistogramOpts, HistogramVec, Opts, Registry, TextEncoder,
+    core::Collector, CounterVec, Encoder, GaugeVec, HistogramOpts, HistogramVec, Opts, Registry,
+    TextEncoder,
 };
 use reqwest::Client as HttpClient;
 use tokio::time::interval;
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::{
     common::{
+        config::PrivacyConfig,
         models::{
             ActiveDevicesMetrics, AdoptionMetrics, AdoptionTimeSeries, AnalyticsInterval,
-            DailyActiveDevices, ErrorFrequency, FailureAnalytics, OtaEvent, VersionDistribution,
-            VersionMetrics,
+            DailyActiveDevices, ErrorFrequency, FailureAnalytics, OtaEvent, OtaEventType,
+            VersionDistribution, VersionMetrics,
         },
         utils,
     },
-    core::victoria::query_builder::VictoriaQuery,
+    core::{
+        breakdown::{self, BreakdownCounts, BreakdownRequest, BreakdownRow},
+        dedup::DedupCache,
+        victoria::query_builder::VictoriaQuery,
+    },
 };
 
 const SECONDS_IN_DAY: i64 = 86400;
 
+// Device series are the only per-device data kept here; they are keyed by
+// (org_id, app_id, device_id).
+type DeviceKey = (String, String, String);
+
+/// Quotes a value for use in a series selector.
+fn selector_value(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
 /// Victoria Metrics client
 #[derive(Clone)]
 pub struct Client {
@@ -57,12 +76,24 @@ pub struct Client {
     ota_os_version_total: CounterVec,
     ota_device_type_total: CounterVec,
 
+    // Events and measures by device context, for breakdowns
+    ota_context_events_total: CounterVec,
+    ota_context_measure_total: CounterVec,
+    ota_context_measure_observations_total: CounterVec,
+    ota_context_measure_squares_total: CounterVec,
+
     // Query client for reading data
     query_client: client::VictoriaMetricsQueryClient,
+
+    // Counters are not idempotent, so retried events are dropped here
+    dedup: Arc<DedupCache>,
+
+    // When each device last sent an event, for the retention enforcer
+    device_last_seen: Arc<Mutex<HashMap<DeviceKey, DateTime<Utc>>>>,
 }
 
 impl Client {
-    pub async fn new(victoria_metrics_url: String) -> Result<Self> {
+    pub async fn new(victoria_metrics_url: String, dedup_window: Duration) -> Result<Self> {
         let registry = Registry::new();
 
         // Core event counters
@@ -186,6 +217,47 @@ impl Client {
             &["org_id", "app_id", "device_type"],
         )?;
 
+        // Breakdown counters; every context label is present, "unknown" when
+        // the event did not report it
+        let context_labels = [
+            "org_id",
+            "app_id",
+            "release_id",
+            "os_version",
+            "app_version",
+            "device_type",
+            "network_type",
+            "variant_id",
+        ];
+        let ota_context_events_total = CounterVec::new(
+            Opts::new("ota_context_events_total", "Total events by device context"),
+            &[&context_labels[..], &["event_type"]].concat(),
+        )?;
+
+        let ota_context_measure_total = CounterVec::new(
+            Opts::new(
+                "ota_context_measure_total",
+                "Sum of download and apply measures by device context",
+            ),
+            &[&context_labels[..], &["measure"]].concat(),
+        )?;
+
+        let ota_context_measure_observations_total = CounterVec::new(
+            Opts::new(
+                "ota_context_measure_observations_total",
+                "Number of download and apply measures by device context",
+            ),
+            &[&context_labels[..], &["measure"]].concat(),
+        )?;
+
+        let ota_context_measure_squares_total = CounterVec::new(
+            Opts::new(
+                "ota_context_measure_squares_total",
+                "Sum of squared download and apply times by device context",
+            ),
+            &[&context_labels[..], &["measure"]].concat(),
+        )?;
+
         // Register all metrics
         registry.register(Box::new(ota_events_total.clone()))?;
         registry.register(Box::new(ota_downloads_total.clone()))?;
@@ -204,6 +276,10 @@ impl Client {
         registry.register(Box::new(ota_device_version.clone()))?;
         registry.register(Box::new(ota_os_version_total.clone()))?;
         registry.register(Box::new(ota_device_type_total.clone()))?;
+        registry.register(Box::new(ota_context_events_total.clone()))?;
+        registry.register(Box::new(ota_context_measure_total.clone()))?;
+        registry.register(Box::new(ota_context_measure_observations_total.clone()))?;
+        registry.register(Box::new(ota_context_measure_squares_total.clone()))?;
 
         let query_client = client::VictoriaMetricsQueryClient::new(victoria_metrics_url);
 
@@ -231,7 +307,13 @@ impl Client {
             ota_device_version,
             ota_os_version_total,
             ota_device_type_total,
+            ota_context_events_total,
+            ota_context_measure_total,
+            ota_context_measure_observations_total,
+            ota_context_measure_squares_total,
             query_client,
+            dedup: Arc::new(DedupCache::new(dedup_window)),
+            device_last_seen: Arc::new(Mutex::new(HashMap::new())),
         })
     }
 
@@ -246,6 +328,13 @@ impl Client {
 
     /// Insert OTA event into metrics (mirrors ClickHouse insert_ota_event)
     pub async fn insert_ota_event(&self, event: &OtaEvent) -> Result<()> {
+        if let Some(event_id) = event.event_id {
+            if !self.dedup.first_sighting(event_id) {
+                debug!("Skipping duplicate OTA event {}", event_id);
+                return Ok(());
+            }
+        }
+
         let labels = &[
             event.org_id.as_str(),
             event.app_id.as_str(),
@@ -254,6 +343,7 @@ impl Client {
 
         // Core event counter
         self.ota_events_total.with_label_values(labels).inc();
+        self.record_context(event);
 
         // Event-specific counters based on event type
         match event.event_type.to_string().as_str() {
@@ -384,6 +474,14 @@ impl Client {
         self.ota_active_device
             .with_label_values(device_labels)
             .set(1.0);
+        self.device_last_seen.lock().unwrap().insert(
+            (
+                event.org_id.clone(),
+                event.app_id.clone(),
+                event.device_id.clone(),
+            ),
+            event.timestamp,
+        );
 
         // Track version distribution
         if let Some(current_version) = &event.current_js_version {
@@ -422,8 +520,147 @@ impl Client {
         Ok(())
     }
 
+    /// Counts the event, and its download or apply measures, by device
+    /// context.
+    fn record_context(&self, event: &OtaEvent) {
+        let context = [
+            event.org_id.as_str(),
+            event.app_id.as_str(),
+            event.release_id.as_deref().unwrap_or(breakdown::UNKNOWN),
+            event.os_version.as_deref().unwrap_or(breakdown::UNKNOWN),
+            event.app_version.as_deref().unwrap_or(breakdown::UNKNOWN),
+            event.device_type.as_deref().unwrap_or(breakdown::UNKNOWN),
+            event.network_type.as_deref().unwrap_or(breakdown::UNKNOWN),
+            event.variant_id.as_deref().unwrap_or(breakdown::UNKNOWN),
+        ];
+        let event_type = event.event_type.to_string();
+        self.ota_context_events_total
+            .with_label_values(&[&context[..], &[event_type.as_str()]].concat())
+            .inc();
+
+        let measures = match event.event_type {
+            OtaEventType::DownloadCompleted => vec![
+                ("download_time_ms", event.download_time_ms),
+                ("download_size_bytes", event.download_size_bytes),
+            ],
+            OtaEventType::ApplySuccess => vec![("apply_time_ms", event.apply_time_ms)],
+            _ => Vec::new(),
+        };
+        for (measure, value) in measures {
+            let Some(value) = value else {
+                continue;
+            };
+            let labels = [&context[..], &[measure]].concat();
+            self.ota_context_measure_total
+                .with_label_values(&labels)
+                .inc_by(value as f64);
+            self.ota_context_measure_observations_total
+                .with_label_values(&labels)
+                .inc();
+            if measure.ends_with("_ms") {
+                self.ota_context_measure_squares_total
+                    .with_label_values(&labels)
+                    .inc_by((value as f64).powi(2));
+            }
+        }
+    }
+
+    /// Counts per breakdown group and time slot (mirrors ClickHouse
+    /// get_breakdown_rows). Each point of `increase(...[step])` covers the
+    /// step before it, so it is reported at the slot that step starts.
+    pub async fn get_breakdown_rows(
+        &self,
+        org_id: &str,
+        app_id: &str,
+        request: &BreakdownRequest,
+    ) -> Result<Vec<BreakdownRow>> {
+        let mut labels = vec![
+            ("org_id".to_string(), org_id.to_string()),
+            ("app_id".to_string(), app_id.to_string()),
+        ];
+        labels.extend(
+            request
+                .filters
+                .iter()
+                .map(|(dimension, value)| (dimension.label().to_string(), value.clone())),
+        );
+        let group_labels: Vec<&str> = request.group_by.iter().map(|d| d.label()).collect();
+        let range = request.range;
+        let step = format!("{}s", range.step);
+
+        let series_query = |metric: &str, extra_label: &str| {
+            VictoriaQuery::new()
+                .metric_name(metric)
+                .labels(labels.clone())
+                .operation("increase")
+                .time_bucket(step.clone())
+                .group_by("sum")
+                .group_by_labels([&group_labels[..], &[extra_label]].concat())
+                .build()
+        };
+        let queries = [
+            series_query("ota_context_events_total", "event_type"),
+            series_query("ota_context_measure_total", "measure"),
+            series_query("ota_context_measure_observations_total", "measure"),
+            series_query("ota_context_measure_squares_total", "measure"),
+        ];
+
+        let mut responses = Vec::with_capacity(queries.len());
+        for promql in &queries {
+            responses.push(
+                self.query_client
+                    .query_range(promql, range.start + range.step, range.end, &step)
+                    .await?,
+            );
+        }
+
+        // (group, slot) -> counts; measure sums, observation counts and
+        // squares arrive from separate queries
+        let mut counts: BTreeMap<(Vec<String>, i64), BreakdownCounts> = BTreeMap::new();
+        for (index, response) in responses.into_iter().enumerate() {
+            for series in response.data.result {
+                let group: Vec<String> = group_labels
+                    .iter()
+                    .map(|label| {
+                        series
+                            .metric
+                            .get(*label)
+                            .cloned()
+                            .unwrap_or_else(|| breakdown::UNKNOWN.to_string())
+                    })
+                    .collect();
+                let kind = match index {
+                    0 => series.metric.get("event_type"),
+                    _ => series.metric.get("measure"),
+                }
+                .cloned()
+                .unwrap_or_default();
+                for (ts, value) in series.values.unwrap_or_default() {
+                    let value = value.parse::<f64>().unwrap_or(0.0);
+                    let entry = counts
+                        .entry((group.clone(), ts as i64 - range.step))
+                        .or_default();
+                    match index {
+                        0 => entry.add_events(&kind, value.round() as u64),
+                        1 => entry.add_measure(&kind, value.round() as u64, 0),
+                        2 => entry.add_measure(&kind, 0, value.round() as u64),
+                        _ => entry.add_measure_squares(&kind, value),
+                    }
+                }
+            }
+        }
+
+        Ok(counts
+            .into_iter()
+            .map(|((group, time_slot), counts)| BreakdownRow {
+                group,
+                time_slot,
+                counts,
+            })
+            .collect())
+    }
+
     /// Insert batch of OTA events (mirrors ClickHouse insert_ota_events_batch)
-    #[allow(dead_code)]
     pub async fn insert_ota_events_batch(&self, events: Vec<OtaEvent>) -> Result<()> {
         for event in events {
             self.insert_ota_event(&event).await?;
@@ -662,6 +899,7 @@ impl Client {
                     app_id: app_id.to_string(),
                     release_id: release_id.to_string(),
                     time_breakdown: hourly_adoption_metrics.await?,
+                    breakdown: None,
                 })
             }
             AnalyticsInterval::Day => {
@@ -677,6 +915,7 @@ impl Client {
                     app_id: app_id.to_string(),
                     release_id: release_id.to_string(),
                     time_breakdown: daywise_adoption_metrics.await?,
+                    breakdown: None,
                 })
             }
             AnalyticsInterval::Week => todo!(),
@@ -969,4 +1208,111 @@ impl Client {
             }
         }
     }
+
+    /// Removes a device's series, both from the registry pushed to Victoria
+    /// Metrics and from Victoria Metrics itself. Aggregate counters carry no
+    /// device id and are left alone.
+    pub async fn erase_device(&self, org_id: &str, app_id: &str, device_id: &str) -> Result<()> {
+        self.device_last_seen.lock().unwrap().remove(&(
+            org_id.to_string(),
+            app_id.to_string(),
+            device_id.to_string(),
+        ));
+        let _ = self
+            .ota_active_device
+            .remove_label_values(&[org_id, app_id, device_id]);
+
+        let js_versions: Vec<String> = self
+            .ota_device_version
+            .collect()
+            .iter()
+            .flat_map(|family| family.get_metric())
+            .filter_map(|metric| {
+                let label = |name: &str| {
+                    metric
+                        .get_label()
+                        .iter()
+                        .find(|pair| pair.get_name() == name)
+                        .map(|pair| pair.get_value())
+                };
+                let matches = label("org_id") == Some(org_id)
+                    && label("app_id") == Some(app_id)
+                    && label("device_id") == Some(device_id);
+                matches
+                    .then(|| label("js_version").map(str::to_string))
+                    .flatten()
+            })
+            .collect();
+        for js_version in &js_versions {
+            let _ = self.ota_device_version.remove_label_values(&[
+                org_id,
+                app_id,
+                js_version.as_str(),
+                device_id,
+            ]);
+        }
+
+        let selector = format!(
+            "{{org_id={},app_id={},device_id={}}}",
+            selector_value(org_id),
+            selector_value(app_id),
+            selector_value(device_id)
+        );
+        let delete_url = format!(
+            "{}/api/v1/admin/tsdb/delete_series",
+            self.query_client.base_url
+        );
+        let response = self
+            .query_client
+            .client
+            .post(&delete_url)
+            .form(&[("match[]", selector.as_str())])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "delete_series returned {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+
+    /// Hourly, erases devices that have not sent an event within their
+    /// organisation's retention window. Aggregate series are bounded by
+    /// Victoria Metrics' own `-retentionPeriod` instead.
+    pub async fn run_retention_enforcer(&self, privacy: PrivacyConfig) {
+        let mut ticker = interval(Duration::from_secs(3600));
+
+        loop {
+            ticker.tick().await;
+
+            let now = Utc::now();
+            let expired: Vec<DeviceKey> = self
+                .device_last_seen
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|((org_id, _, _), last_seen)| {
+                    let retention =
+                        chrono::Duration::days(privacy.retention_days_for(org_id).into());
+                    **last_seen < now - retention
+                })
+                .map(|(key, _)| key.clone())
+                .collect();
+
+            for (org_id, app_id, device_id) in &expired {
+                if let Err(e) = self.erase_device(org_id, app_id, device_id).await {
+                    warn!(
+                        "Could not expire device series for {}/{}: {:?}",
+                        org_id, app_id, e
+                    );
+                }
+            }
+            if !expired.is_empty() {
+                info!("Expired series of {} devices", expired.len());
+            }
+        }
+    }
 }
@@ -49,7 +49,6 @@ impl VictoriaQuery {
     }
 
     /// Set an aggregator (e.g. "sum", "avg").
-    #[allow(dead_code)]
     pub fn group_by(mut self, agg: impl Into<String>) -> Self {
         self.group_by = Some(agg.into());
         self
@@ -79,7 +78,13 @@ impl VictoriaQuery {
                 let lbl_text = self
                     .labels
                     .iter()
-                    .map(|(k, v)| format!(r#"{}="{}""#, k, v))
+                    .map(|(k, v)| {
+                        format!(
+                            r#"{}="{}""#,
+                            k,
+                            v.replace('\\', r"\\").replace('"', r#"\""#)
+                        )
+                    })
                     .collect::<Vec<_>>()
                     .join(",");
                 sel = format!("{}{{{}}}", sel, lbl_text);
@@ -1,3 +1,8 @@
+pub mod admin;
+pub mod alerts;
 pub mod analytics;
 pub mod events;
+pub mod experiments;
+pub mod export;
 pub mod health;
+pub mod privacy;
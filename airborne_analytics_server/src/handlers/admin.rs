@@ -0,0 +1,77 @@
+use axum::{extract::State, http::HeaderMap, response::Json};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+
+use crate::{
+    common::{
+        error::{AppError, AppResult},
+        models::LoggingInfra,
+    },
+    core::kafka::dlq,
+    AppState,
+};
+
+/// Checks the request's bearer token against `ANALYTICS_ADMIN_TOKEN`. Digests
+/// are compared so the comparison time does not depend on how much of the
+/// token matched.
+fn authorize(state: &AppState, headers: &HeaderMap) -> AppResult<()> {
+    let Some(expected) = state.config.server.admin_token.as_deref() else {
+        return Err(AppError::Forbidden(
+            "Admin endpoints are disabled; set ANALYTICS_ADMIN_TOKEN".to_string(),
+        ));
+    };
+    let presented = headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| AppError::Unauthorized("Missing bearer token".to_string()))?;
+    if Sha256::digest(expected.as_bytes()) != Sha256::digest(presented.as_bytes()) {
+        return Err(AppError::Unauthorized("Invalid admin token".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayDlqRequest {
+    #[serde(default = "default_max_messages")]
+    pub max_messages: usize,
+}
+
+fn default_max_messages() -> usize {
+    1000
+}
+
+/// Starts replaying dead-lettered events onto the events topic. The replay
+/// runs in the background and logs its outcome; only one runs at a time.
+pub async fn replay_dlq(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ReplayDlqRequest>,
+) -> AppResult<Json<serde_json::Value>> {
+    authorize(&state, &headers)?;
+    if state.config.logging_infrastructure != LoggingInfra::KafkaClickhouse {
+        return Err(AppError::Validation(
+            "The dead-letter queue is only used with Kafka-ClickHouse".to_string(),
+        ));
+    }
+    let lock = dlq::ReplayLock::acquire()
+        .ok_or_else(|| AppError::Validation("A DLQ replay is already running".to_string()))?;
+
+    let config = state.config.kafka.clone();
+    let max_messages = request.max_messages;
+    tokio::spawn(async move {
+        let _lock = lock;
+        match dlq::replay(&config, max_messages).await {
+            Ok(report) => info!("DLQ replay requested via admin endpoint: {:?}", report),
+            Err(e) => error!("DLQ replay failed: {:?}", e),
+        }
+    });
+
+    Ok(Json(json!({
+        "status": "started",
+        "dlq_topic": state.config.kafka.dlq_topic,
+        "max_messages": max_messages,
+    })))
+}
@@ -0,0 +1,180 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        error::{AppError, AppResult},
+        models::{Alert, AlertRule, AlertRuleSpec, Silence},
+    },
+    core::{access, alerts::Engine},
+    handlers::analytics::AnalyticsResponse,
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct AlertsQuery {
+    pub org_id: String,
+    pub app_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SilenceRequest {
+    pub org_id: String,
+    pub app_id: String,
+    /// Only this rule's alerts; all of the application's otherwise
+    pub rule_id: Option<Uuid>,
+    /// Only this release's alerts
+    pub release_id: Option<String>,
+    /// Defaults to now
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: DateTime<Utc>,
+    pub comment: Option<String>,
+}
+
+fn engine(state: &AppState) -> AppResult<&Arc<Engine>> {
+    state
+        .alerts
+        .as_ref()
+        .ok_or_else(|| AppError::Unsupported("Alerting is disabled on this instance".to_string()))
+}
+
+pub async fn list_rules(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<AlertsQuery>,
+) -> AppResult<Json<AnalyticsResponse<Vec<AlertRule>>>> {
+    state
+        .access
+        .authorize(&headers, &params.org_id, &params.app_id)
+        .await?;
+    let rules = engine(&state)?
+        .list_rules(&params.org_id, &params.app_id)
+        .await?;
+    Ok(Json(AnalyticsResponse::success(rules)))
+}
+
+pub async fn create_rule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(spec): Json<AlertRuleSpec>,
+) -> AppResult<Json<AnalyticsResponse<AlertRule>>> {
+    let created_by = state
+        .access
+        .authorize_action(&headers, &spec.org_id, &spec.app_id, access::ALERTS)
+        .await?;
+    let rule = engine(&state)?.create_rule(spec, created_by).await?;
+    Ok(Json(AnalyticsResponse::success(rule)))
+}
+
+pub async fn update_rule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(rule_id): Path<Uuid>,
+    Json(spec): Json<AlertRuleSpec>,
+) -> AppResult<Json<AnalyticsResponse<AlertRule>>> {
+    state
+        .access
+        .authorize_action(&headers, &spec.org_id, &spec.app_id, access::ALERTS)
+        .await?;
+    let rule = engine(&state)?.update_rule(rule_id, spec).await?;
+    Ok(Json(AnalyticsResponse::success(rule)))
+}
+
+pub async fn delete_rule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(rule_id): Path<Uuid>,
+    Query(params): Query<AlertsQuery>,
+) -> AppResult<Json<AnalyticsResponse<Uuid>>> {
+    state
+        .access
+        .authorize_action(&headers, &params.org_id, &params.app_id, access::ALERTS)
+        .await?;
+    engine(&state)?
+        .delete_rule(&params.org_id, &params.app_id, rule_id)
+        .await?;
+    Ok(Json(AnalyticsResponse::success(rule_id)))
+}
+
+/// Pending and firing alerts of an application, and those resolved in the
+/// last day
+pub async fn list_alerts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<AlertsQuery>,
+) -> AppResult<Json<AnalyticsResponse<Vec<Alert>>>> {
+    state
+        .access
+        .authorize(&headers, &params.org_id, &params.app_id)
+        .await?;
+    let alerts = engine(&state)?
+        .list_alerts(&params.org_id, &params.app_id)
+        .await?;
+    Ok(Json(AnalyticsResponse::success(alerts)))
+}
+
+pub async fn list_silences(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<AlertsQuery>,
+) -> AppResult<Json<AnalyticsResponse<Vec<Silence>>>> {
+    state
+        .access
+        .authorize(&headers, &params.org_id, &params.app_id)
+        .await?;
+    let silences = engine(&state)?
+        .list_silences(&params.org_id, &params.app_id)
+        .await?;
+    Ok(Json(AnalyticsResponse::success(silences)))
+}
+
+pub async fn create_silence(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SilenceRequest>,
+) -> AppResult<Json<AnalyticsResponse<Silence>>> {
+    let created_by = state
+        .access
+        .authorize_action(&headers, &request.org_id, &request.app_id, access::ALERTS)
+        .await?;
+    let now = Utc::now();
+    let silence = engine(&state)?
+        .create_silence(Silence {
+            silence_id: Uuid::nil(),
+            org_id: request.org_id,
+            app_id: request.app_id,
+            rule_id: request.rule_id,
+            release_id: request.release_id,
+            starts_at: request.starts_at.unwrap_or(now),
+            ends_at: request.ends_at,
+            comment: request.comment,
+            created_by,
+            created_at: now,
+        })
+        .await?;
+    Ok(Json(AnalyticsResponse::success(silence)))
+}
+
+pub async fn delete_silence(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(silence_id): Path<Uuid>,
+    Query(params): Query<AlertsQuery>,
+) -> AppResult<Json<AnalyticsResponse<Uuid>>> {
+    state
+        .access
+        .authorize_action(&headers, &params.org_id, &params.app_id, access::ALERTS)
+        .await?;
+    engine(&state)?
+        .delete_silence(&params.org_id, &params.app_id, silence_id)
+        .await?;
+    Ok(Json(AnalyticsResponse::success(silence_id)))
+}
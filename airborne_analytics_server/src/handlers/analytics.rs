@@ -1,5 +1,6 @@
 use axum::{
     extract::{Query, State},
+    http::HeaderMap,
     response::Json,
 };
 use serde::{Deserialize, Serialize};
@@ -9,10 +10,12 @@ use crate::{
     common::{
         error::{AppError, AppResult},
         models::{
-            ActiveDevicesMetrics, AdoptionMetrics, AnalyticsInterval, FailureMetrics, LoggingInfra,
-            PerformanceMetrics, VersionDistribution,
+            ActiveDevicesMetrics, AdoptionMetrics, AnalyticsInterval, DeviceTimeline,
+            FailureMetrics, LoggingInfra, PerformanceMetrics, VersionDistribution,
         },
+        utils,
     },
+    core::breakdown::{self, BreakdownRequest, BreakdownRow, TimeRange},
     AppState,
 };
 
@@ -26,8 +29,32 @@ pub struct AnalyticsQuery {
     pub start_date: Option<i64>,
     pub end_date: Option<i64>,
     pub interval: Option<AnalyticsInterval>,
+    /// Comma-separated dimensions to split adoption, failure and
+    /// performance metrics by, e.g. `os_version,network_type`
+    pub group_by: Option<String>,
+    /// Comma-separated `dimension:value` pairs, e.g. `os_version:9`
+    pub filter: Option<String>,
+    /// Groups kept before the rest are folded into "other"
+    pub top: Option<usize>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct DeviceTimelineQuery {
+    pub org_id: String,
+    pub app_id: String,
+    pub device_id: String,
+    pub session_id: Option<String>,
+    /// Millis; defaults to `DEFAULT_TIMELINE_DAYS` before `end_date`
+    pub start_date: Option<i64>,
+    /// Millis, exclusive; defaults to now
+    pub end_date: Option<i64>,
+    pub limit: Option<usize>,
+}
+
+const DEFAULT_TIMELINE_DAYS: i64 = 30;
+const DEFAULT_TIMELINE_LIMIT: usize = 500;
+const MAX_TIMELINE_LIMIT: usize = 5000;
+
 #[derive(Debug, Serialize)]
 pub struct AnalyticsResponse<T> {
     pub success: bool,
@@ -53,10 +80,57 @@ impl<T> AnalyticsResponse<T> {
     }
 }
 
+/// Parses the query's breakdown parameters over `range`.
+fn breakdown_request(
+    params: &AnalyticsQuery,
+    range: TimeRange,
+) -> AppResult<Option<BreakdownRequest>> {
+    BreakdownRequest::parse(
+        params.group_by.as_deref(),
+        params.filter.as_deref(),
+        params.release_id.as_deref(),
+        params.top,
+        range,
+    )
+}
+
+/// Counts for a breakdown from the configured backend
+pub(crate) async fn breakdown_rows(
+    state: &AppState,
+    org_id: &str,
+    app_id: &str,
+    request: &BreakdownRequest,
+) -> AppResult<Vec<BreakdownRow>> {
+    let rows = if state.config.logging_infrastructure == LoggingInfra::KafkaClickhouse {
+        let clickhouse = state.clickhouse.as_ref().ok_or_else(|| {
+            AppError::DatabaseError("Clickhouse client not initialized".to_string())
+        })?;
+        clickhouse.get_breakdown_rows(org_id, app_id, request).await
+    } else if state.config.logging_infrastructure == LoggingInfra::VictoriaMetrics {
+        let victoria = state.victoria.as_ref().ok_or_else(|| {
+            AppError::DatabaseError("Victoria Metrics client not initialized".to_string())
+        })?;
+        victoria.get_breakdown_rows(org_id, app_id, request).await
+    } else {
+        return Err(AppError::Validation(
+            "Unsupported logging infrastructure for analytics".to_string(),
+        ));
+    };
+    rows.map_err(|e| {
+        error!("Failed to fetch breakdown: {:?}", e);
+        AppError::DatabaseError(e.to_string())
+    })
+}
+
 pub async fn get_adoption_metrics(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Query(params): Query<AnalyticsQuery>,
 ) -> AppResult<Json<AnalyticsResponse<AdoptionMetrics>>> {
+    state
+        .access
+        .authorize(&headers, &params.org_id, &params.app_id)
+        .await?;
     let date = params.date.unwrap_or(chrono::Utc::now().timestamp());
 
     match params.interval {
@@ -81,9 +155,17 @@ pub async fn get_adoption_metrics(
             ));
         }
     }
+    let range = match params.interval {
+        Some(AnalyticsInterval::Hour) => TimeRange::hours_of_day(utils::normalize_to_secs(date)),
+        _ => TimeRange::days_between(
+            utils::normalize_to_secs(params.start_date.unwrap_or(0)),
+            utils::normalize_to_secs(params.end_date.unwrap_or(0)),
+        ),
+    };
+    let breakdown_request = breakdown_request(&params, range)?;
 
     let metrics = if state.config.logging_infrastructure == LoggingInfra::KafkaClickhouse {
-        match state.clickhouse {
+        match &state.clickhouse {
             Some(clickhouse) => {
                 clickhouse
                     .get_adoption_metrics(
@@ -91,7 +173,7 @@ pub async fn get_adoption_metrics(
                         &params.app_id,
                         params.release_id.as_deref().unwrap_or("default"),
                         date,
-                        params.interval.unwrap_or(AnalyticsInterval::Day),
+                        params.interval.clone().unwrap_or(AnalyticsInterval::Day),
                         params.start_date.unwrap_or(0),
                         params.end_date.unwrap_or(0),
                     )
@@ -102,7 +184,7 @@ pub async fn get_adoption_metrics(
             }
         }
     } else if state.config.logging_infrastructure == LoggingInfra::VictoriaMetrics {
-        match state.victoria {
+        match &state.victoria {
             Some(victoria) => {
                 victoria
                     .get_adoption_metrics(
@@ -110,7 +192,7 @@ pub async fn get_adoption_metrics(
                         &params.app_id,
                         params.release_id.as_deref().unwrap_or("default"),
                         date,
-                        params.interval.unwrap_or(AnalyticsInterval::Day),
+                        params.interval.clone().unwrap_or(AnalyticsInterval::Day),
                         params.start_date.unwrap_or(0),
                         params.end_date.unwrap_or(0),
                     )
@@ -127,19 +209,26 @@ pub async fn get_adoption_metrics(
         ));
     };
 
-    match metrics {
-        Ok(metrics) => Ok(Json(AnalyticsResponse::success(metrics))),
-        Err(e) => {
-            error!("Failed to fetch adoption metrics: {:?}", e);
-            Err(AppError::DatabaseError(e.to_string()))
-        }
+    let mut metrics = metrics.map_err(|e| {
+        error!("Failed to fetch adoption metrics: {:?}", e);
+        AppError::DatabaseError(e.to_string())
+    })?;
+    if let Some(request) = breakdown_request {
+        let rows = breakdown_rows(&state, &params.org_id, &params.app_id, &request).await?;
+        metrics.breakdown = Some(breakdown::assemble(&request, rows, |counts| counts.events));
     }
+    Ok(Json(AnalyticsResponse::success(metrics)))
 }
 
 pub async fn get_version_distribution(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Query(params): Query<AnalyticsQuery>,
 ) -> AppResult<Json<AnalyticsResponse<Vec<VersionDistribution>>>> {
+    state
+        .access
+        .authorize(&headers, &params.org_id, &params.app_id)
+        .await?;
     info!(
         "Fetching version distribution for app_id: {} and org_id: {}",
         params.app_id, params.org_id
@@ -193,8 +282,13 @@ pub async fn get_version_distribution(
 
 pub async fn get_active_devices(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Query(params): Query<AnalyticsQuery>,
 ) -> AppResult<Json<AnalyticsResponse<ActiveDevicesMetrics>>> {
+    state
+        .access
+        .authorize(&headers, &params.org_id, &params.app_id)
+        .await?;
     info!(
         "Fetching active devices for org_id: {} and app_id: {}",
         params.org_id, params.app_id
@@ -242,18 +336,24 @@ pub async fn get_active_devices(
 
 pub async fn get_failure_metrics(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Query(params): Query<AnalyticsQuery>,
 ) -> AppResult<Json<AnalyticsResponse<FailureMetrics>>> {
+    state
+        .access
+        .authorize(&headers, &params.org_id, &params.app_id)
+        .await?;
     info!(
         "Fetching failure metrics for org_id: {} and app_id: {}",
         params.org_id, params.app_id
     );
 
     let days = params.days.unwrap_or(30);
+    let breakdown_request = breakdown_request(&params, TimeRange::last_days(days))?;
 
     let metrics: Result<FailureMetrics, Box<dyn std::error::Error + Send + Sync>> =
         if state.config.logging_infrastructure == LoggingInfra::KafkaClickhouse {
-            match state.clickhouse {
+            match &state.clickhouse {
                 Some(clickhouse) => {
                     let failure_analytics = clickhouse
                         .get_failure_analytics(
@@ -274,6 +374,7 @@ pub async fn get_failure_metrics(
                             0.0
                         },
                         common_errors: failure_analytics.common_errors,
+                        breakdown: None,
                     };
                     Ok(failure_metrics)
                 }
@@ -283,7 +384,7 @@ pub async fn get_failure_metrics(
                 .into()),
             }
         } else if state.config.logging_infrastructure == LoggingInfra::VictoriaMetrics {
-            match state.victoria {
+            match &state.victoria {
                 Some(victoria) => {
                     let failure_analytics = victoria
                         .get_failure_analytics(
@@ -304,6 +405,7 @@ pub async fn get_failure_metrics(
                             0.0
                         },
                         common_errors: failure_analytics.common_errors,
+                        breakdown: None,
                     };
                     Ok(failure_metrics)
                 }
@@ -319,7 +421,13 @@ pub async fn get_failure_metrics(
         };
 
     match metrics {
-        Ok(failure_metrics) => {
+        Ok(mut failure_metrics) => {
+            if let Some(request) = breakdown_request {
+                let rows = breakdown_rows(&state, &params.org_id, &params.app_id, &request).await?;
+                failure_metrics.breakdown = Some(breakdown::assemble(&request, rows, |counts| {
+                    counts.failures()
+                }));
+            }
             info!(
                 "Successfully fetched failure metrics for org_id: {} and app_id: {}",
                 params.org_id, params.app_id
@@ -334,15 +442,29 @@ pub async fn get_failure_metrics(
 }
 
 pub async fn get_performance_metrics(
-    State(_): State<AppState>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
     Query(params): Query<AnalyticsQuery>,
 ) -> AppResult<Json<AnalyticsResponse<PerformanceMetrics>>> {
+    state
+        .access
+        .authorize(&headers, &params.org_id, &params.app_id)
+        .await?;
     info!(
         "Fetching performance metrics for org_id: {} and app_id: {}",
         params.org_id, params.app_id
     );
 
-    let _days = params.days.unwrap_or(30);
+    let days = params.days.unwrap_or(30);
+    let breakdown = match breakdown_request(&params, TimeRange::last_days(days))? {
+        Some(request) => {
+            let rows = breakdown_rows(&state, &params.org_id, &params.app_id, &request).await?;
+            Some(breakdown::assemble(&request, rows, |counts| {
+                counts.download_success + counts.apply_success
+            }))
+        }
+        None => None,
+    };
 
     let metrics = PerformanceMetrics {
         org_id: params.org_id.clone(),
@@ -356,7 +478,88 @@ pub async fn get_performance_metrics(
         avg_download_time_ms: 0.0,
         avg_apply_time_ms: 0.0,
         avg_download_size_bytes: 0.0,
+        breakdown,
     };
 
     Ok(Json(AnalyticsResponse::success(metrics)))
 }
+
+/// A device's event history, oldest first, for support investigations:
+/// update checks, downloads, applies, errors and rollbacks.
+pub async fn get_device_timeline(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<DeviceTimelineQuery>,
+) -> AppResult<Json<AnalyticsResponse<DeviceTimeline>>> {
+    state
+        .access
+        .authorize(&headers, &params.org_id, &params.app_id)
+        .await?;
+
+    if params.device_id.is_empty() {
+        return Err(AppError::Validation(
+            "device_id cannot be empty".to_string(),
+        ));
+    }
+    let end_ms = params
+        .end_date
+        .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+    let start_ms = params
+        .start_date
+        .unwrap_or(end_ms - DEFAULT_TIMELINE_DAYS * 24 * 60 * 60 * 1000);
+    if start_ms >= end_ms {
+        return Err(AppError::Validation(
+            "start_date must be before end_date".to_string(),
+        ));
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_TIMELINE_LIMIT);
+    if limit == 0 || limit > MAX_TIMELINE_LIMIT {
+        return Err(AppError::Validation(format!(
+            "limit must be between 1 and {}",
+            MAX_TIMELINE_LIMIT
+        )));
+    }
+
+    // VictoriaMetrics only keeps aggregated counters, never single events
+    if state.config.logging_infrastructure != LoggingInfra::KafkaClickhouse {
+        return Err(AppError::Unsupported(
+            "Device timelines need the kafka-clickhouse backend; victoria-metrics stores only aggregated counters".to_string(),
+        ));
+    }
+    let clickhouse = state
+        .clickhouse
+        .as_ref()
+        .ok_or_else(|| AppError::DatabaseError("Clickhouse client not initialized".to_string()))?;
+
+    info!(
+        "Fetching timeline of device {} for org_id: {} and app_id: {}",
+        params.device_id, params.org_id, params.app_id
+    );
+    // One extra row tells whether the timeline was cut off
+    let mut events = clickhouse
+        .get_device_timeline(
+            &params.org_id,
+            &params.app_id,
+            &params.device_id,
+            params.session_id.as_deref(),
+            start_ms,
+            end_ms,
+            limit + 1,
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch device timeline: {:?}", e);
+            AppError::DatabaseError(e.to_string())
+        })?;
+    let truncated = events.len() > limit;
+    events.truncate(limit);
+
+    Ok(Json(AnalyticsResponse::success(DeviceTimeline {
+        org_id: params.org_id,
+        app_id: params.app_id,
+        device_id: params.device_id,
+        session_id: params.session_id,
+        events,
+        truncated,
+    })))
+}
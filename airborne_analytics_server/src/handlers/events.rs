@@ -1,37 +1,33 @@
-use std::net::SocketAddr;
+use std::{collections::HashSet, net::SocketAddr};
 
 use axum::{
+    body::Bytes,
     extract::{ConnectInfo, State},
     http::HeaderMap,
     response::Json,
 };
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 use serde_json::json;
 use tracing::{error, info};
 use uuid::Uuid;
 
 use crate::{
     common::{
+        config::PrivacyConfig,
         error::{AppError, AppResult},
-        models::{LoggingInfra, OtaEvent, OtaEventRequest},
+        models::{BatchEventResult, LoggingInfra, OtaEvent, OtaEventBatchRequest, OtaEventRequest},
     },
+    core::{ingest::codec, privacy},
     AppState,
 };
 
-pub async fn ingest_event(
-    State(state): State<AppState>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-    headers: HeaderMap,
-    Json(request): Json<OtaEventRequest>,
-) -> AppResult<Json<serde_json::Value>> {
-    info!("Ingesting OTA event: {:?}", request.event_type);
-
-    if request.device_id.is_empty() {
-        return Err(AppError::Validation(
-            "Device ID cannot be empty".to_string(),
-        ));
-    }
-
+/// The client's user agent and IP address, the latter anonymised as
+/// configured by `INGEST_IP_MODE`.
+fn client_metadata(
+    headers: &HeaderMap,
+    addr: SocketAddr,
+    privacy: &PrivacyConfig,
+) -> (Option<String>, Option<String>) {
     let user_agent = headers
         .get("user-agent")
         .and_then(|v| v.to_str().ok())
@@ -41,17 +37,28 @@ pub async fn ingest_event(
         .get("x-forwarded-for")
         .and_then(|v| v.to_str().ok())
         .map(|s| s.split(',').next().unwrap_or("").trim().to_string())
-        .or_else(|| Some(addr.ip().to_string()));
+        .or_else(|| Some(addr.ip().to_string()))
+        .and_then(|ip| privacy::anonymize_ip(privacy, &ip));
+
+    (user_agent, ip_address)
+}
 
-    let event = OtaEvent {
+fn build_event(
+    request: OtaEventRequest,
+    timestamp: DateTime<Utc>,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
+) -> OtaEvent {
+    OtaEvent {
         org_id: request.org_id,
         app_id: request.app_id,
         device_id: request.device_id,
         session_id: request.session_id,
         event_type: request.event_type,
-        event_id: Some(Uuid::new_v4()),
-        timestamp: Utc::now(),
+        event_id: Some(request.event_id.unwrap_or_else(Uuid::new_v4)),
+        timestamp,
         release_id: request.release_id,
+        variant_id: request.variant_id,
         current_js_version: request.current_js_version,
         target_js_version: request.target_js_version,
         rollout_percentage: request.rollout_percentage,
@@ -68,7 +75,30 @@ pub async fn ingest_event(
         payload: request.payload,
         user_agent,
         ip_address,
-    };
+    }
+}
+
+pub async fn ingest_event(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AppResult<Json<serde_json::Value>> {
+    let request = state.ingest.admit(&headers, &body).await?;
+    info!("Ingesting OTA event: {:?}", request.event_type);
+
+    if request.device_id.is_empty() {
+        return Err(AppError::Validation(
+            "Device ID cannot be empty".to_string(),
+        ));
+    }
+
+    let received_at = Utc::now();
+    let timestamp = state
+        .ingest
+        .event_time(request.timestamp, Duration::zero(), received_at)?;
+    let (user_agent, ip_address) = client_metadata(&headers, addr, &state.config.privacy);
+    let event = build_event(request, timestamp, user_agent, ip_address);
 
     if state.config.logging_infrastructure == LoggingInfra::KafkaClickhouse {
         match state.kafka {
@@ -117,3 +147,136 @@ pub async fn ingest_event(
         "timestamp": Utc::now()
     })))
 }
+
+/// Stores accepted batch events, returning an error message for each event
+/// that could not be stored.
+async fn store_events(state: &AppState, events: Vec<OtaEvent>) -> AppResult<Vec<Option<String>>> {
+    if state.config.logging_infrastructure == LoggingInfra::KafkaClickhouse {
+        let kafka = state
+            .kafka
+            .as_ref()
+            .ok_or_else(|| AppError::Internal("Kafka client not initialized".to_string()))?;
+        let results = kafka.send_ota_events_batch(&events).await?;
+        Ok(results
+            .into_iter()
+            .map(|result| {
+                result.err().map(|e| {
+                    error!("Failed to send event to Kafka: {:?}", e);
+                    "Failed to send event to Kafka".to_string()
+                })
+            })
+            .collect())
+    } else if state.config.logging_infrastructure == LoggingInfra::VictoriaMetrics {
+        let victoria = state.victoria.as_ref().ok_or_else(|| {
+            AppError::Internal("Victoria Metrics client not initialized".to_string())
+        })?;
+        let count = events.len();
+        if let Err(e) = victoria.insert_ota_events_batch(events).await {
+            error!("Failed to send events to Victoria Metrics: {:?}", e);
+            return Err(AppError::Internal(
+                "Failed to send events to Victoria Metrics".to_string(),
+            ));
+        }
+        Ok(vec![None; count])
+    } else {
+        Err(AppError::Internal(
+            "Unsupported logging infrastructure".to_string(),
+        ))
+    }
+}
+
+/// Ingests a batch of events, e.g. ones an SDK buffered while offline. The
+/// body may be gzip- or zstd-compressed JSON or CBOR. Each event is accepted
+/// or rejected on its own; the response lists the outcome per event, in
+/// request order.
+pub async fn ingest_event_batch(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AppResult<Json<serde_json::Value>> {
+    let received_at = Utc::now();
+    let key = state.ingest.authenticate(&headers, &body).await?;
+    let batch: OtaEventBatchRequest =
+        codec::decode(&headers, &body).map_err(|e| state.ingest.reject("invalid_body", e))?;
+    if batch.events.len() > state.config.ingest.max_batch_events {
+        return Err(state.ingest.reject(
+            "batch_too_large",
+            AppError::Validation(format!(
+                "A batch may hold at most {} events",
+                state.config.ingest.max_batch_events
+            )),
+        ));
+    }
+    let skew = batch
+        .sent_at
+        .map_or_else(Duration::zero, |sent_at| received_at - sent_at);
+    let (user_agent, ip_address) = client_metadata(&headers, addr, &state.config.privacy);
+
+    let mut results = Vec::with_capacity(batch.events.len());
+    let mut events = Vec::new();
+    let mut charged = HashSet::new();
+    for (index, raw) in batch.events.into_iter().enumerate() {
+        let mut result = BatchEventResult {
+            index,
+            event_id: None,
+            accepted: false,
+            error: None,
+        };
+        let outcome = serde_json::from_value::<OtaEventRequest>(raw)
+            .map_err(|e| {
+                state
+                    .ingest
+                    .reject("invalid_event", AppError::Serialization(e))
+            })
+            .and_then(|request| {
+                if request.device_id.is_empty() {
+                    return Err(state.ingest.reject(
+                        "invalid_event",
+                        AppError::Validation("Device ID cannot be empty".to_string()),
+                    ));
+                }
+                let timestamp = state
+                    .ingest
+                    .event_time(request.timestamp, skew, received_at)?;
+                state
+                    .ingest
+                    .check_event(key.as_deref(), &request, &mut charged)?;
+                Ok(build_event(
+                    request,
+                    timestamp,
+                    user_agent.clone(),
+                    ip_address.clone(),
+                ))
+            });
+        match outcome {
+            Ok(event) => {
+                result.event_id = event.event_id;
+                events.push((index, event));
+            }
+            Err(e) => result.error = Some(e.to_string()),
+        }
+        results.push(result);
+    }
+
+    let (indices, events): (Vec<usize>, Vec<OtaEvent>) = events.into_iter().unzip();
+    let stored = store_events(&state, events).await?;
+    for (index, error) in indices.into_iter().zip(stored) {
+        results[index].accepted = error.is_none();
+        results[index].error = error;
+    }
+
+    let accepted = results.iter().filter(|result| result.accepted).count();
+    info!(
+        "Ingested batch: {} of {} events accepted",
+        accepted,
+        results.len()
+    );
+    Ok(Json(json!({
+        "status": "success",
+        "accepted": accepted,
+        "rejected": results.len() - accepted,
+        "results": results,
+        "timestamp": received_at
+    })))
+}
@@ -0,0 +1,85 @@
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::Json,
+};
+use serde::Deserialize;
+use tracing::error;
+
+use crate::{
+    common::{
+        error::{AppError, AppResult},
+        models::{ExperimentComparison, LoggingInfra},
+    },
+    core::experiment::{self, ExperimentRequest},
+    handlers::analytics::{self, AnalyticsResponse},
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ExperimentCompareQuery {
+    pub org_id: String,
+    pub app_id: String,
+    /// The release's experiment, whose id is the release id
+    pub release_id: String,
+    /// Variant id of the control
+    pub control: String,
+    /// Comma-separated variant ids of the experimental variants
+    pub variants: String,
+    /// The experiment's traffic percentage, served to each variant
+    pub traffic_percentage: Option<f64>,
+    /// The last `days` UTC days are compared, today included
+    pub days: Option<u32>,
+    pub confidence: Option<f64>,
+}
+
+/// Compares the apply success rate, failure rate and download and apply
+/// times of each experimental variant with the control.
+pub async fn compare_variants(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<ExperimentCompareQuery>,
+) -> AppResult<Json<AnalyticsResponse<ExperimentComparison>>> {
+    state
+        .access
+        .authorize(&headers, &params.org_id, &params.app_id)
+        .await?;
+
+    let request = ExperimentRequest::parse(
+        params.release_id,
+        params.control,
+        &params.variants,
+        params.traffic_percentage,
+        params.confidence,
+        params.days,
+    )?;
+    let rows =
+        analytics::breakdown_rows(&state, &params.org_id, &params.app_id, &request.breakdown())
+            .await?;
+
+    // Only the raw events can count distinct devices per variant
+    let devices = match (
+        &state.config.logging_infrastructure,
+        state.clickhouse.as_ref(),
+    ) {
+        (LoggingInfra::KafkaClickhouse, Some(clickhouse)) => Some(
+            clickhouse
+                .get_variant_devices(
+                    &params.org_id,
+                    &params.app_id,
+                    &request.release_id,
+                    request.range,
+                )
+                .await
+                .map_err(|e| {
+                    error!("Failed to count devices per variant: {:?}", e);
+                    AppError::DatabaseError(e.to_string())
+                })?,
+        ),
+        _ => None,
+    };
+
+    Ok(Json(AnalyticsResponse::success(experiment::compare(
+        &request, rows, devices,
+    ))))
+}
@@ -0,0 +1,179 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use crate::{
+    common::{
+        error::{AppError, AppResult},
+        models::{AnalyticsInterval, ExportFormat, LoggingInfra, OtaEventType},
+    },
+    core::{
+        access,
+        breakdown::{self, BreakdownRequest, TimeRange, HOUR_SECS},
+        export::{self, ChannelSink, EventFilter},
+    },
+    handlers::analytics,
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct EventExportQuery {
+    pub org_id: String,
+    pub app_id: String,
+    /// Millis
+    pub start_date: i64,
+    /// Millis, exclusive
+    pub end_date: i64,
+    pub format: Option<ExportFormat>,
+    pub release_id: Option<String>,
+    pub event_type: Option<OtaEventType>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportExportQuery {
+    pub org_id: String,
+    pub app_id: String,
+    /// Millis
+    pub start_date: i64,
+    /// Millis, exclusive
+    pub end_date: i64,
+    pub format: Option<ExportFormat>,
+    /// `HOUR` or `DAY` slots; defaults to `DAY`
+    pub interval: Option<AnalyticsInterval>,
+    pub release_id: Option<String>,
+    pub group_by: Option<String>,
+    pub filter: Option<String>,
+}
+
+fn check_range(start_ms: i64, end_ms: i64) -> AppResult<()> {
+    if end_ms <= start_ms {
+        return Err(AppError::Validation(
+            "end_date must be after start_date".to_string(),
+        ));
+    }
+    if end_ms - start_ms > export::MAX_EXPORT_DAYS * breakdown::DAY_SECS * 1000 {
+        return Err(AppError::Validation(format!(
+            "An export covers at most {} days",
+            export::MAX_EXPORT_DAYS
+        )));
+    }
+    Ok(())
+}
+
+fn attachment(body: axum::body::Body, format: ExportFormat, name: &str) -> Response {
+    let headers = [
+        (
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(format.content_type()),
+        ),
+        (
+            header::CONTENT_DISPOSITION,
+            HeaderValue::from_str(&format!(
+                "attachment; filename=\"{}.{}\"",
+                name,
+                format.extension()
+            ))
+            .unwrap_or_else(|_| HeaderValue::from_static("attachment")),
+        ),
+        (
+            header::HeaderName::from_static(export::SCHEMA_VERSION_HEADER),
+            HeaderValue::from(export::SCHEMA_VERSION),
+        ),
+    ];
+    (headers, body).into_response()
+}
+
+/// Streams raw events, oldest first. Only ClickHouse keeps them.
+pub async fn export_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<EventExportQuery>,
+) -> AppResult<Response> {
+    state
+        .access
+        .authorize_action(&headers, &params.org_id, &params.app_id, access::EXPORT)
+        .await?;
+    check_range(params.start_date, params.end_date)?;
+    if state.config.logging_infrastructure != LoggingInfra::KafkaClickhouse {
+        return Err(AppError::Unsupported(
+            "Raw events are stored only with the kafka-clickhouse backend".to_string(),
+        ));
+    }
+    let clickhouse = state
+        .clickhouse
+        .clone()
+        .ok_or_else(|| AppError::DatabaseError("Clickhouse client not initialized".to_string()))?;
+
+    let format = params.format.unwrap_or(ExportFormat::Csv);
+    let name = format!("events-{}-{}", params.org_id, params.app_id);
+    let filter = EventFilter {
+        org_id: params.org_id,
+        app_id: params.app_id,
+        start_ms: params.start_date,
+        end_ms: params.end_date,
+        release_id: params.release_id,
+        event_type: params.event_type,
+    };
+
+    // The body outlives the request timeout, so it is written in the
+    // background
+    let (mut sink, body) = ChannelSink::new();
+    let response = attachment(body, format, &name);
+    tokio::spawn(async move {
+        let result = export::write_events(&clickhouse, &filter, format, &mut sink).await;
+        sink.finish(result, &name).await;
+    });
+    Ok(response)
+}
+
+/// Streams the breakdown counts behind the adoption, failure and
+/// performance metrics, one row per time slot and group.
+pub async fn export_report(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<ReportExportQuery>,
+) -> AppResult<Response> {
+    state
+        .access
+        .authorize(&headers, &params.org_id, &params.app_id)
+        .await?;
+    check_range(params.start_date, params.end_date)?;
+
+    let (start, end) = (params.start_date / 1000, (params.end_date - 1) / 1000);
+    let range = match params.interval {
+        None | Some(AnalyticsInterval::Day) => TimeRange::days_between(start, end),
+        Some(AnalyticsInterval::Hour) => TimeRange {
+            start: start - start.rem_euclid(HOUR_SECS),
+            end: end - end.rem_euclid(HOUR_SECS) + HOUR_SECS,
+            step: HOUR_SECS,
+        },
+        Some(_) => {
+            return Err(AppError::Validation(
+                "interval must be HOUR or DAY".to_string(),
+            ))
+        }
+    };
+    // An empty group_by still gets the request validated
+    let request = BreakdownRequest::parse(
+        Some(params.group_by.as_deref().unwrap_or_default()),
+        params.filter.as_deref(),
+        params.release_id.as_deref(),
+        Some(breakdown::MAX_TOP),
+        range,
+    )?
+    .ok_or_else(|| AppError::Internal("Empty report request".to_string()))?;
+    let rows = analytics::breakdown_rows(&state, &params.org_id, &params.app_id, &request).await?;
+
+    let format = params.format.unwrap_or(ExportFormat::Csv);
+    let name = format!("report-{}-{}", params.org_id, params.app_id);
+    let (mut sink, body) = ChannelSink::new();
+    let response = attachment(body, format, &name);
+    tokio::spawn(async move {
+        let result = export::write_report(rows, &request.group_by, format, &mut sink).await;
+        sink.finish(result, &name).await;
+    });
+    Ok(response)
+}
@@ -1,9 +1,10 @@
 use axum::{extract::State, response::Json};
 use chrono::Utc;
+use prometheus::{Encoder, TextEncoder};
 
 use crate::{
     common::{
-        error::AppResult,
+        error::{AppError, AppResult},
         models::{HealthResponse, LoggingInfra, ServiceHealthCheck, SystemMetrics},
     },
     AppState,
@@ -78,3 +79,13 @@ pub async fn health_check(State(state): State<AppState>) -> AppResult<Json<Healt
 
     Ok(Json(health_response))
 }
+
+/// Prometheus exposition of the server's own metrics, such as ingest
+/// rejections.
+pub async fn metrics() -> AppResult<String> {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    String::from_utf8(buffer).map_err(|e| AppError::Internal(e.to_string()))
+}
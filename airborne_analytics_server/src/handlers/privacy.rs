@@ -0,0 +1,162 @@
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::Json,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        error::{AppError, AppResult},
+        models::{ErasureReceipt, ErasureStatus, LoggingInfra},
+    },
+    core::{access, privacy},
+    handlers::analytics::AnalyticsResponse,
+    AppState,
+};
+
+const MAX_REASON_LENGTH: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct ErasureRequest {
+    pub org_id: String,
+    pub app_id: String,
+    pub device_id: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ErasureReceiptsQuery {
+    pub org_id: String,
+    pub app_id: String,
+}
+
+/// Erases every stored event of one device and returns a receipt for the
+/// request. On ClickHouse the erasure worker deletes the events later, so the
+/// receipt starts out pending; its final state is listed by
+/// `list_erasure_receipts`.
+pub async fn erase_device(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ErasureRequest>,
+) -> AppResult<Json<AnalyticsResponse<ErasureReceipt>>> {
+    let requested_by = state
+        .access
+        .authorize_action(&headers, &request.org_id, &request.app_id, access::ERASE)
+        .await?;
+
+    if request.device_id.is_empty() {
+        return Err(AppError::Validation(
+            "device_id cannot be empty".to_string(),
+        ));
+    }
+    if request
+        .reason
+        .as_ref()
+        .is_some_and(|reason| reason.len() > MAX_REASON_LENGTH)
+    {
+        return Err(AppError::Validation(format!(
+            "reason may be at most {} bytes",
+            MAX_REASON_LENGTH
+        )));
+    }
+
+    let requested_at = Utc::now();
+    let mut receipt = ErasureReceipt {
+        receipt_id: Uuid::new_v4(),
+        device_id_sha256: privacy::device_id_digest(
+            &request.org_id,
+            &request.app_id,
+            &request.device_id,
+        ),
+        org_id: request.org_id,
+        app_id: request.app_id,
+        device_id: Some(request.device_id),
+        requested_by,
+        reason: request.reason,
+        status: ErasureStatus::Pending,
+        events_deleted: None,
+        error: None,
+        requested_at,
+        updated_at: requested_at,
+    };
+    let device_id = receipt.device_id.clone().unwrap_or_default();
+
+    if state.config.logging_infrastructure == LoggingInfra::KafkaClickhouse {
+        let clickhouse = state.clickhouse.clone().ok_or_else(|| {
+            AppError::DatabaseError("Clickhouse client not initialized".to_string())
+        })?;
+        // The erasure worker deletes the events and completes the receipt
+        clickhouse
+            .insert_erasure_receipt(&receipt)
+            .await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    } else if state.config.logging_infrastructure == LoggingInfra::VictoriaMetrics {
+        let victoria = state.victoria.as_ref().ok_or_else(|| {
+            AppError::Internal("Victoria Metrics client not initialized".to_string())
+        })?;
+        let result = victoria
+            .erase_device(&receipt.org_id, &receipt.app_id, &device_id)
+            .await;
+        receipt.updated_at = Utc::now();
+        match result {
+            Ok(()) => receipt.status = ErasureStatus::Completed,
+            Err(e) => {
+                error!("Erasure {} failed: {:?}", receipt.receipt_id, e);
+                receipt.status = ErasureStatus::Failed;
+                receipt.error = Some(e.to_string());
+            }
+        }
+    } else {
+        return Err(AppError::Internal(
+            "Unsupported logging infrastructure".to_string(),
+        ));
+    }
+
+    // Victoria Metrics has nowhere to keep receipts, so the log is the record
+    info!(
+        "Erasure {} of device {} in {}/{} requested by {}: {}",
+        receipt.receipt_id,
+        receipt.device_id_sha256,
+        receipt.org_id,
+        receipt.app_id,
+        receipt.requested_by.as_deref().unwrap_or("anonymous"),
+        receipt.status
+    );
+    Ok(Json(AnalyticsResponse::success(receipt)))
+}
+
+/// Erasure receipts of an application, newest first. Devices appear only by
+/// digest.
+pub async fn list_erasure_receipts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<ErasureReceiptsQuery>,
+) -> AppResult<Json<AnalyticsResponse<Vec<ErasureReceipt>>>> {
+    state
+        .access
+        .authorize_action(&headers, &params.org_id, &params.app_id, access::ERASE)
+        .await?;
+
+    if state.config.logging_infrastructure != LoggingInfra::KafkaClickhouse {
+        return Err(AppError::Unsupported(
+            "Erasure receipts are stored only with the kafka-clickhouse backend".to_string(),
+        ));
+    }
+    let clickhouse = state
+        .clickhouse
+        .as_ref()
+        .ok_or_else(|| AppError::DatabaseError("Clickhouse client not initialized".to_string()))?;
+
+    let receipts = clickhouse
+        .list_erasure_receipts(&params.org_id, &params.app_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to list erasure receipts: {:?}", e);
+            AppError::DatabaseError(e.to_string())
+        })?;
+    Ok(Json(AnalyticsResponse::success(receipts)))
+}
@@ -9,12 +9,12 @@ use anyhow::Result;
 use axum::{
     error_handling::HandleErrorLayer,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post, put},
     Router,
 };
 use tower::ServiceBuilder;
 use tower_http::{cors::CorsLayer, timeout::TimeoutLayer, trace::TraceLayer};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use crate::{
@@ -23,8 +23,11 @@ use crate::{
         models::{AppState, ErrorResponse, LoggingInfra},
     },
     core::kafka,
-    core::{bootstrap_clickhouse, victoria},
-    handlers::{analytics, events, health},
+    core::{access, alerts, bootstrap_clickhouse, export, ingest, victoria},
+    handlers::{
+        admin, alerts as alert_handlers, analytics, events, experiments, export as export_handlers,
+        health, privacy,
+    },
 };
 
 #[tokio::main]
@@ -40,11 +43,32 @@ async fn main() -> Result<()> {
     let config = Config::load()?;
     info!("Loaded configuration: {:?}", config);
 
+    // `replay-dlq [max_messages]` replays dead-lettered events and exits
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("replay-dlq") {
+        let max_messages = match args.next() {
+            Some(max) => max.parse()?,
+            None => usize::MAX,
+        };
+        let report = kafka::dlq::replay(&config.kafka, max_messages).await?;
+        info!(
+            "Replayed {} dead-lettered events from {}",
+            report.replayed, config.kafka.dlq_topic
+        );
+        return match report.error {
+            Some(e) => Err(anyhow::anyhow!(e)),
+            None => Ok(()),
+        };
+    }
+
     let mut consumer_handle: Option<tokio::task::JoinHandle<()>> = None;
     let mut app_state = AppState {
         clickhouse: None,
         victoria: None,
         kafka: None,
+        ingest: Arc::new(ingest::Guard::new(&config.ingest)?),
+        access: Arc::new(access::Authorizer::new(&config.query_auth)?),
+        alerts: None,
         config: Arc::new(config.clone()),
     };
 
@@ -68,6 +92,11 @@ async fn main() -> Result<()> {
                     }
                 }));
 
+                let erasure_worker = Arc::clone(&clickhouse_client);
+                tokio::spawn(async move {
+                    erasure_worker.run_erasure_worker().await;
+                });
+
                 app_state.clickhouse = Some(Arc::clone(&clickhouse_client));
                 app_state.kafka = Some(Arc::clone(&kafka_producer));
             }
@@ -82,13 +111,19 @@ async fn main() -> Result<()> {
         // For now, use a default Victoria Metrics URL (this should be configurable in the future)
         let victoria_url = std::env::var("VICTORIA_METRICS_URL")
             .unwrap_or_else(|_| "http://localhost:8428".to_string());
-        match victoria::Client::new(victoria_url).await {
+        let dedup_window = Duration::from_secs(config.ingest.dedup_window_secs);
+        match victoria::Client::new(victoria_url, dedup_window).await {
             Ok(victoria_client) => {
                 let victoria_client_arc = Arc::new(victoria_client);
                 let vm_pusher = victoria_client_arc.clone();
                 tokio::spawn(async move {
                     let _ = vm_pusher.run_metrics_pusher().await;
                 });
+                let vm_retention = victoria_client_arc.clone();
+                let privacy = config.privacy.clone();
+                tokio::spawn(async move {
+                    vm_retention.run_retention_enforcer(privacy).await;
+                });
                 app_state.victoria = Some(victoria_client_arc);
                 info!("Connected to Victoria Metrics");
             }
@@ -107,6 +142,36 @@ async fn main() -> Result<()> {
         ));
     }
 
+    if config.alerts.enabled {
+        let engine = Arc::new(alerts::Engine::new(&config.alerts).await?);
+        app_state.alerts = Some(Arc::clone(&engine));
+        let state = app_state.clone();
+        tokio::spawn(async move {
+            engine.run_evaluator(state).await;
+        });
+        info!("Alert evaluation enabled");
+    }
+
+    if let Some(bucket) = &config.export.s3_bucket {
+        match &app_state.clickhouse {
+            Some(clickhouse) => {
+                let shared = aws_config::from_env().load().await;
+                let s3 = aws_sdk_s3::Client::from_conf(
+                    aws_sdk_s3::config::Builder::from(&shared)
+                        .force_path_style(config.export.s3_force_path_style)
+                        .build(),
+                );
+                let clickhouse = Arc::clone(clickhouse);
+                let export_config = config.export.clone();
+                tokio::spawn(async move {
+                    export::run_scheduled_exports(clickhouse, s3, export_config).await;
+                });
+                info!("Scheduled exports to s3://{} enabled", bucket);
+            }
+            None => warn!("EXPORT_S3_BUCKET is set, but scheduled exports need ClickHouse"),
+        }
+    }
+
     let server_port = config.server.port;
 
     let safe_layer = ServiceBuilder::new()
@@ -117,7 +182,10 @@ async fn main() -> Result<()> {
 
     let app = Router::new()
         .route("/analytics/health", get(health::health_check))
+        .route("/analytics/metrics", get(health::metrics))
         .route("/analytics/events", post(events::ingest_event))
+        .route("/analytics/events/batch", post(events::ingest_event_batch))
+        .route("/analytics/admin/dlq/replay", post(admin::replay_dlq))
         .route("/analytics/adoption", get(analytics::get_adoption_metrics))
         .route(
             "/analytics/versions",
@@ -128,10 +196,47 @@ async fn main() -> Result<()> {
             get(analytics::get_active_devices),
         )
         .route("/analytics/failures", get(analytics::get_failure_metrics))
+        .route(
+            "/analytics/device-timeline",
+            get(analytics::get_device_timeline),
+        )
         .route(
             "/analytics/performance",
             get(analytics::get_performance_metrics),
         )
+        .route(
+            "/analytics/experiments/compare",
+            get(experiments::compare_variants),
+        )
+        .route("/analytics/alerts", get(alert_handlers::list_alerts))
+        .route(
+            "/analytics/alerts/rules",
+            get(alert_handlers::list_rules).post(alert_handlers::create_rule),
+        )
+        .route(
+            "/analytics/alerts/rules/:rule_id",
+            put(alert_handlers::update_rule).delete(alert_handlers::delete_rule),
+        )
+        .route(
+            "/analytics/alerts/silences",
+            get(alert_handlers::list_silences).post(alert_handlers::create_silence),
+        )
+        .route(
+            "/analytics/alerts/silences/:silence_id",
+            delete(alert_handlers::delete_silence),
+        )
+        .route(
+            "/analytics/export/events",
+            get(export_handlers::export_events),
+        )
+        .route(
+            "/analytics/export/report",
+            get(export_handlers::export_report),
+        )
+        .route(
+            "/analytics/erasure",
+            post(privacy::erase_device).get(privacy::list_erasure_receipts),
+        )
         .layer(CorsLayer::permissive())
         .layer(TraceLayer::new_for_http())
         .layer(safe_layer)
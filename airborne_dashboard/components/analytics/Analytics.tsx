@@ -116,7 +116,7 @@ export interface MetricCard {
 }
 
 const Analytics: React.FC<AnalyticsProps> = () => {
-  const { org, app } = useAppContext();
+  const { org, app, token } = useAppContext();
   const today = new Date();
   const sevenDaysAgo = new Date();
   sevenDaysAgo.setDate(today.getDate() - 7);
@@ -137,16 +137,16 @@ const Analytics: React.FC<AnalyticsProps> = () => {
       const orgId = org || "";
       const appId = app || "";
       const [adoption, performance, active] = await Promise.all([
-        fetchAdoptionMetrics(orgId, appId, interval, range),
-        fetchPerformanceMetrics(orgId, appId, interval, range),
-        fetchActiveDevices(orgId, appId, interval, range),
+        fetchAdoptionMetrics(orgId, appId, interval, range, token),
+        fetchPerformanceMetrics(orgId, appId, interval, range, token),
+        fetchActiveDevices(orgId, appId, interval, range, token),
       ]);
       setAdoptionData(adoption);
       setPerformanceData(performance);
       setActiveDevicesData(active);
     };
     fetchData();
-  }, [org, app, interval, range]);
+  }, [org, app, token, interval, range]);
 
   useEffect(() => {
     const diff = range.endDate.getTime() - range.startDate.getTime();
@@ -1,10 +1,16 @@
 import { AdoptionMetrics, PerformanceMetrics, ActiveDevicesMetrics, AnalyticsResponse } from "./Analytics";
 
+// The analytics server checks the same bearer token as the Airborne API.
+function analyticsFetch(url: string, token: string | null) {
+  return fetch(url, { headers: token ? { Authorization: `Bearer ${token}` } : {} });
+}
+
 export async function fetchAdoptionMetrics(
   org: string,
   app: string,
   interval: "HOUR" | "DAY",
-  range: { startDate: Date; endDate: Date }
+  range: { startDate: Date; endDate: Date },
+  token: string | null
 ) {
   try {
     const params = new URLSearchParams({
@@ -16,7 +22,7 @@ export async function fetchAdoptionMetrics(
       date: new Date().getTime().toString(),
     });
     const url = `/analytics/adoption?${params.toString()}`;
-    const res = await fetch(url);
+    const res = await analyticsFetch(url, token);
     const data: AnalyticsResponse<AdoptionMetrics> = await res.json();
     return data.data;
   } catch (error) {
@@ -29,7 +35,8 @@ export async function fetchPerformanceMetrics(
   org: string,
   app: string,
   interval: "HOUR" | "DAY",
-  range: { startDate: Date; endDate: Date }
+  range: { startDate: Date; endDate: Date },
+  token: string | null
 ) {
   try {
     const diffMs = range.endDate.getTime() - range.startDate.getTime();
@@ -41,7 +48,7 @@ export async function fetchPerformanceMetrics(
       days: diffDays < 1 ? "1" : diffDays.toString(),
     });
     const url = `/analytics/performance?${params.toString()}`;
-    const res = await fetch(url);
+    const res = await analyticsFetch(url, token);
     const data: AnalyticsResponse<PerformanceMetrics> = await res.json();
     return data.data;
   } catch (error) {
@@ -54,7 +61,8 @@ export async function fetchActiveDevices(
   org: string,
   app: string,
   interval: "HOUR" | "DAY",
-  range: { startDate: Date; endDate: Date }
+  range: { startDate: Date; endDate: Date },
+  token: string | null
 ) {
   try {
     const diffMs = range.endDate.getTime() - range.startDate.getTime();
@@ -66,7 +74,7 @@ export async function fetchActiveDevices(
       days: diffDays < 1 ? "1" : diffDays.toString(),
     });
     const url = `/analytics/active-devices?${params.toString()}`;
-    const res = await fetch(url);
+    const res = await analyticsFetch(url, token);
     const data: AnalyticsResponse<ActiveDevicesMetrics> = await res.json();
     return data.data;
   } catch (error) {
@@ -0,0 +1,158 @@
+//! Requests to URLs chosen by users, such as alert webhooks and mirrored
+//! files. They are made from inside the deployment, so an internal address
+//! would reach services such as admin APIs or cloud metadata endpoints. Only
+//! public addresses are accepted, both when a URL is checked and on every
+//! connection, where the addresses actually connected to are checked too so
+//! a DNS answer cannot change in between.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect, ClientBuilder, Url,
+};
+
+/// Whether `ip` is routable on the public internet
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // "This network" and carrier-grade NAT
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        || ip.is_unique_local()
+        || ip.is_unicast_link_local())
+}
+
+/// Checks a URL and every address its host resolves to. Returns why it is
+/// refused.
+pub async fn check(url: &str) -> Result<(), String> {
+    let url = Url::parse(url).map_err(|_| "not a valid URL".to_string())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("only http and https are supported".to_string());
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| "the URL has no host".to_string())?;
+    if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse() {
+        return check_ip(ip);
+    }
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|_| format!("{} does not resolve", host))?;
+    let mut resolved = false;
+    for addr in addrs {
+        check_ip(addr.ip())?;
+        resolved = true;
+    }
+    if !resolved {
+        return Err(format!("{} does not resolve", host));
+    }
+    Ok(())
+}
+
+fn check_ip(ip: IpAddr) -> Result<(), String> {
+    if is_public(ip) {
+        Ok(())
+    } else {
+        Err(format!("{} is not a public address", ip))
+    }
+}
+
+/// Resolver that only hands out public addresses
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// An HTTP client builder that only connects to public addresses. A redirect
+/// or proxy would reach a host the checks never saw, so neither is followed.
+/// Hosts given as IP literals bypass the resolver; pass URLs through
+/// [`check`] first.
+pub fn client_builder() -> ClientBuilder {
+    reqwest::Client::builder()
+        .redirect(redirect::Policy::none())
+        .no_proxy()
+        .dns_resolver(Arc::new(PublicResolver))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "100.64.0.1",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["1.1.1.1", "93.184.216.34", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn internal_urls_are_refused() {
+        for url in [
+            "http://127.0.0.1:8428/api/v1/admin/tsdb/delete_series",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[::1]/",
+            "http://localhost:8428/",
+            "ftp://example.com/",
+            "not a url",
+        ] {
+            assert!(check(url).await.is_err(), "{}", url);
+        }
+        assert!(check("https://1.1.1.1/hook").await.is_ok());
+    }
+}
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS hyperotaserver.package_size_budgets (
+    org_id TEXT NOT NULL,
+    app_id TEXT NOT NULL,
+    max_important_bytes BIGINT CHECK (max_important_bytes >= 0),
+    max_lazy_bytes BIGINT CHECK (max_lazy_bytes >= 0),
+    max_file_bytes BIGINT CHECK (max_file_bytes >= 0),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (org_id, app_id)
+);
@@ -0,0 +1 @@
+ALTER TABLE hyperotaserver.files DROP COLUMN IF EXISTS variants;
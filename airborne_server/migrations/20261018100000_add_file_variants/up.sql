@@ -0,0 +1 @@
+ALTER TABLE hyperotaserver.files ADD COLUMN IF NOT EXISTS variants JSONB NOT NULL DEFAULT '[]'::jsonb;
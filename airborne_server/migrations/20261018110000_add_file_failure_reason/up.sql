@@ -0,0 +1 @@
+ALTER TABLE hyperotaserver.files ADD COLUMN IF NOT EXISTS failure_reason TEXT;
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS hyperotaserver.upload_validation_configs;
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS hyperotaserver.upload_validation_configs (
+    org_id TEXT NOT NULL,
+    app_id TEXT NOT NULL,
+    config JSONB NOT NULL DEFAULT '{}'::jsonb,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (org_id, app_id)
+);
@@ -0,0 +1,8 @@
+DROP TABLE IF EXISTS hyperotaserver.issued_access_tokens;
+
+ALTER TABLE hyperotaserver.user_credentials
+    DROP COLUMN IF EXISTS permissions,
+    DROP COLUMN IF EXISTS expires_at,
+    DROP COLUMN IF EXISTS allowed_ips,
+    DROP COLUMN IF EXISTS last_used_at,
+    DROP COLUMN IF EXISTS last_used_ip;
@@ -0,0 +1,16 @@
+ALTER TABLE hyperotaserver.user_credentials
+    ADD COLUMN IF NOT EXISTS permissions TEXT[],
+    ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS allowed_ips TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN IF NOT EXISTS last_used_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS last_used_ip TEXT;
+
+CREATE TABLE IF NOT EXISTS hyperotaserver.issued_access_tokens (
+    token_hash TEXT PRIMARY KEY,
+    client_id UUID NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_issued_access_tokens_expires_at
+    ON hyperotaserver.issued_access_tokens (expires_at);
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS hyperotaserver.service_account_tokens;
+DROP TABLE IF EXISTS hyperotaserver.service_account_keys;
+DROP TABLE IF EXISTS hyperotaserver.service_accounts;
@@ -0,0 +1,32 @@
+CREATE TABLE IF NOT EXISTS hyperotaserver.service_accounts (
+    id UUID PRIMARY KEY,
+    organisation TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (organisation, name)
+);
+
+CREATE TABLE IF NOT EXISTS hyperotaserver.service_account_keys (
+    id UUID PRIMARY KEY,
+    service_account_id UUID NOT NULL REFERENCES hyperotaserver.service_accounts (id) ON DELETE CASCADE,
+    secret_hash TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_service_account_keys_account
+    ON hyperotaserver.service_account_keys (service_account_id);
+
+CREATE TABLE IF NOT EXISTS hyperotaserver.service_account_tokens (
+    token_hash TEXT PRIMARY KEY,
+    service_account_id UUID NOT NULL REFERENCES hyperotaserver.service_accounts (id) ON DELETE CASCADE,
+    key_id UUID NOT NULL REFERENCES hyperotaserver.service_account_keys (id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_service_account_tokens_expires_at
+    ON hyperotaserver.service_account_tokens (expires_at);
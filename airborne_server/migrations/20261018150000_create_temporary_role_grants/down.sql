@@ -0,0 +1 @@
+DROP TABLE IF EXISTS hyperotaserver.temporary_role_grants;
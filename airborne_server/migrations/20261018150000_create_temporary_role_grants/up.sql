@@ -0,0 +1,24 @@
+CREATE TABLE IF NOT EXISTS hyperotaserver.temporary_role_grants (
+    id UUID PRIMARY KEY,
+    subject TEXT NOT NULL,
+    organisation TEXT NOT NULL,
+    application TEXT,
+    role TEXT NOT NULL,
+    previous_role TEXT,
+    reason TEXT NOT NULL,
+    duration_secs BIGINT NOT NULL,
+    status TEXT NOT NULL,
+    requested_by TEXT NOT NULL,
+    requested_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    decided_by TEXT,
+    decided_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ,
+    ended_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_temporary_role_grants_org_status
+    ON hyperotaserver.temporary_role_grants (organisation, status);
+
+CREATE INDEX IF NOT EXISTS idx_temporary_role_grants_active_expiry
+    ON hyperotaserver.temporary_role_grants (expires_at)
+    WHERE status = 'active';
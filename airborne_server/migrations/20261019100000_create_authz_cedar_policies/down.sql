@@ -0,0 +1 @@
+DROP TABLE IF EXISTS hyperotaserver.authz_cedar_policies;
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS hyperotaserver.authz_cedar_policies (
+    id TEXT PRIMARY KEY,
+    policy TEXT NOT NULL,
+    description TEXT,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    updated_by TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
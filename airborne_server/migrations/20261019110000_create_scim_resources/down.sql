@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS hyperotaserver.scim_group_roles;
+DROP TABLE IF EXISTS hyperotaserver.scim_group_members;
+DROP TABLE IF EXISTS hyperotaserver.scim_groups;
+DROP TABLE IF EXISTS hyperotaserver.scim_users;
+DROP TABLE IF EXISTS hyperotaserver.scim_tokens;
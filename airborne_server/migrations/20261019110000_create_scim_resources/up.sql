@@ -0,0 +1,54 @@
+CREATE TABLE IF NOT EXISTS hyperotaserver.scim_tokens (
+    id UUID PRIMARY KEY,
+    organisation TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    description TEXT,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS hyperotaserver.scim_users (
+    id UUID PRIMARY KEY,
+    organisation TEXT NOT NULL,
+    user_name TEXT NOT NULL,
+    external_id TEXT,
+    subject TEXT NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    resource JSONB NOT NULL DEFAULT '{}'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (organisation, user_name)
+);
+
+CREATE TABLE IF NOT EXISTS hyperotaserver.scim_groups (
+    id UUID PRIMARY KEY,
+    organisation TEXT NOT NULL,
+    display_name TEXT NOT NULL,
+    external_id TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (organisation, display_name)
+);
+
+CREATE TABLE IF NOT EXISTS hyperotaserver.scim_group_members (
+    group_id UUID NOT NULL REFERENCES hyperotaserver.scim_groups (id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES hyperotaserver.scim_users (id) ON DELETE CASCADE,
+    PRIMARY KEY (group_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_scim_group_members_user
+    ON hyperotaserver.scim_group_members (user_id);
+
+CREATE TABLE IF NOT EXISTS hyperotaserver.scim_group_roles (
+    id UUID PRIMARY KEY,
+    organisation TEXT NOT NULL,
+    group_name TEXT NOT NULL,
+    application TEXT,
+    role TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_scim_group_roles_organisation
+    ON hyperotaserver.scim_group_roles (organisation);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS hyperotaserver.auth_session_tokens;
+DROP TABLE IF EXISTS hyperotaserver.auth_sessions;
@@ -0,0 +1,30 @@
+CREATE TABLE IF NOT EXISTS hyperotaserver.auth_sessions (
+    id UUID PRIMARY KEY,
+    subject TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_refreshed_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    revoked_by TEXT,
+    revoke_reason TEXT,
+    client_ip TEXT,
+    user_agent TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_auth_sessions_subject
+    ON hyperotaserver.auth_sessions (subject);
+
+CREATE INDEX IF NOT EXISTS idx_auth_sessions_expires_at
+    ON hyperotaserver.auth_sessions (expires_at);
+
+CREATE TABLE IF NOT EXISTS hyperotaserver.auth_session_tokens (
+    token_hash TEXT PRIMARY KEY,
+    session_id UUID NOT NULL REFERENCES hyperotaserver.auth_sessions (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('access', 'refresh')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    rotated_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_auth_session_tokens_session
+    ON hyperotaserver.auth_session_tokens (session_id);
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS hyperotaserver.analytics_ingest_keys;
@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS hyperotaserver.analytics_ingest_keys (
+    id UUID PRIMARY KEY,
+    organisation TEXT NOT NULL,
+    application TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    -- Signing secret, encrypted with the ingest key itself so it can only be
+    -- recovered by someone presenting the key.
+    encrypted_signing_secret TEXT,
+    require_signature BOOLEAN NOT NULL DEFAULT FALSE,
+    rate_limit_per_minute INTEGER,
+    description TEXT,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_analytics_ingest_keys_org_app
+    ON hyperotaserver.analytics_ingest_keys (organisation, application);
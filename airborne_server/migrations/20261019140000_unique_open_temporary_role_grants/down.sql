@@ -0,0 +1 @@
+DROP INDEX IF EXISTS hyperotaserver.idx_temporary_role_grants_open;
@@ -0,0 +1,21 @@
+-- Requests that lost a race before this index existed are rejected, keeping
+-- the active grant or else the newest request of each subject and scope
+UPDATE hyperotaserver.temporary_role_grants
+SET status = 'rejected', ended_at = NOW()
+WHERE status = 'pending'
+  AND id IN (
+    SELECT id FROM (
+        SELECT id, ROW_NUMBER() OVER (
+            PARTITION BY subject, organisation, COALESCE(application, '')
+            ORDER BY status = 'active' DESC, requested_at DESC
+        ) AS position
+        FROM hyperotaserver.temporary_role_grants
+        WHERE status IN ('pending', 'active')
+    ) ranked
+    WHERE position > 1
+  );
+
+-- At most one pending or active grant per subject and scope
+CREATE UNIQUE INDEX IF NOT EXISTS idx_temporary_role_grants_open
+    ON hyperotaserver.temporary_role_grants (subject, organisation, COALESCE(application, ''))
+    WHERE status IN ('pending', 'active');
@@ -6,6 +6,8 @@ use actix_web::{
     Scope,
 };
 
+use airborne_authz_macros::authz;
+
 use crate::{
     middleware::auth::AuthResponse,
     provider::authz::{permission::EndpointPermissionBinding, AuthzPermissionCheck},
@@ -14,10 +16,13 @@ use crate::{
 };
 
 use self::types::{
-    EnforceBatchRequest, EnforceBatchResponse, PermissionBatchCheckResult, PermissionCatalogItem,
-    PermissionCatalogQuery, PermissionCatalogResponse,
+    EnforceBatchRequest, EnforceBatchResponse, ExplainCheck, ExplainPolicy, ExplainRequest,
+    ExplainResponse, PermissionBatchCheckResult, PermissionCatalogItem, PermissionCatalogQuery,
+    PermissionCatalogResponse,
 };
 
+pub mod grant;
+pub mod policy;
 pub mod types;
 
 const SCOPE_ORG: &str = "org";
@@ -25,6 +30,64 @@ const SCOPE_APP: &str = "app";
 const SCOPE_AUTO: &str = "auto";
 const MAX_BATCH_CHECKS: usize = 200;
 
+// `analytics.read` guards the analytics server's query endpoints,
+// `analytics.erase` its device erasure endpoint, `analytics.alerts`
+// changes to alert rules and silences and `analytics.export` raw event
+// exports; all are checked through
+// `/me/enforce-batch`. No endpoint here declares them, so they are
+// registered directly to seed the default role bindings.
+inventory::submit! {
+    EndpointPermissionBinding::new(
+        "GET",
+        "/analytics",
+        "analytics",
+        "read",
+        &["owner", "admin", "write", "read"],
+        &["admin", "write", "read"],
+        true,
+        true,
+    )
+}
+
+inventory::submit! {
+    EndpointPermissionBinding::new(
+        "POST",
+        "/analytics/erasure",
+        "analytics",
+        "erase",
+        &["owner", "admin"],
+        &["admin"],
+        true,
+        true,
+    )
+}
+
+inventory::submit! {
+    EndpointPermissionBinding::new(
+        "POST",
+        "/analytics/alerts",
+        "analytics",
+        "alerts",
+        &["owner", "admin", "write"],
+        &["admin", "write"],
+        true,
+        true,
+    )
+}
+
+inventory::submit! {
+    EndpointPermissionBinding::new(
+        "GET",
+        "/analytics/export",
+        "analytics",
+        "export",
+        &["owner", "admin"],
+        &["admin"],
+        true,
+        true,
+    )
+}
+
 #[derive(Copy, Clone, Debug)]
 enum PermissionScope {
     Organisation,
@@ -54,6 +117,9 @@ pub fn add_routes() -> Scope {
     Scope::new("")
         .service(permission_catalog)
         .service(enforce_my_permissions_batch)
+        .service(explain_permission)
+        .service(grant::add_routes())
+        .service(policy::add_routes())
 }
 
 #[get("/catalog")]
@@ -204,6 +270,13 @@ async fn enforce_my_permissions_batch(
     let results = result_context
         .into_iter()
         .map(|entry| {
+            // A scoped personal access token narrows what the caller may do,
+            // as it does for endpoint enforcement.
+            let token_permits = crate::token::scope::permits(
+                auth.token_permissions.as_deref(),
+                &entry.resource,
+                &entry.action,
+            );
             let mut allowed = false;
             for index in entry.decision_indexes {
                 let Some(value) = decisions.get(index) else {
@@ -221,12 +294,111 @@ async fn enforce_my_permissions_batch(
                 resource: entry.resource,
                 action: entry.action,
                 scope: entry.scope,
-                allowed,
+                allowed: allowed && token_permits,
             })
         })
         .collect::<airborne_types::Result<Vec<_>>>()?;
 
-    Ok(Json(EnforceBatchResponse { results }))
+    Ok(Json(EnforceBatchResponse {
+        subject: auth.sub,
+        results,
+    }))
+}
+
+/// Explains why a subject is or is not allowed `resource.action` in the
+/// request's organisation/application, using the same org/app fallback as
+/// endpoint enforcement for the `auto` scope.
+#[authz(
+    resource = "authz_explain",
+    action = "read",
+    org_roles = ["owner", "admin"],
+    app_roles = ["admin"]
+)]
+#[post("/explain")]
+async fn explain_permission(
+    auth_response: ReqData<AuthResponse>,
+    body: Json<ExplainRequest>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<ExplainResponse>> {
+    let auth = auth_response.into_inner();
+    let payload = body.into_inner();
+    let subject = payload.subject.trim().to_ascii_lowercase();
+    if subject.is_empty() {
+        return Err(ABError::BadRequest("subject cannot be empty".to_string()));
+    }
+    let resource = normalize_permission_part(&payload.resource, "resource")?;
+    let action = normalize_permission_part(&payload.action, "action")?;
+    let scope = parse_requested_scope(payload.scope.as_deref())?;
+
+    let organisation = required_context_value(
+        auth.organisation.as_ref().map(|value| value.name.as_str()),
+        "organisation",
+        "x-organisation",
+    )?;
+    let app_name = auth.application.as_ref().map(|value| value.name.as_str());
+    let targets = match scope {
+        PermissionScope::Organisation => vec![None],
+        PermissionScope::Application => vec![Some(required_context_value(
+            app_name,
+            "application",
+            "x-application",
+        )?)],
+        PermissionScope::Auto => match app_name {
+            Some(application) => vec![Some(application), None],
+            None => vec![None],
+        },
+    };
+
+    let mut checks = Vec::with_capacity(targets.len());
+    let mut notes = BTreeSet::new();
+    let mut is_super_admin = false;
+    for application in targets {
+        let explanation = state
+            .authz_provider
+            .explain_permission(
+                state.get_ref(),
+                &subject,
+                organisation,
+                application,
+                &resource,
+                &action,
+            )
+            .await?;
+        is_super_admin |= explanation.is_super_admin;
+        notes.extend(explanation.note);
+        checks.push(ExplainCheck {
+            scope: if application.is_some() {
+                SCOPE_APP
+            } else {
+                SCOPE_ORG
+            }
+            .to_string(),
+            organisation: organisation.to_string(),
+            application: application.map(str::to_string),
+            permission: explanation.permission,
+            allowed: explanation.allowed,
+            policies: explanation
+                .policies
+                .into_iter()
+                .map(|matched| ExplainPolicy {
+                    grants: !matched.role_chain.is_empty(),
+                    policy: matched.policy,
+                    role: matched.role,
+                    role_chain: matched.role_chain,
+                    custom_roles: matched.custom_roles,
+                })
+                .collect(),
+        });
+    }
+
+    Ok(Json(ExplainResponse {
+        subject,
+        key: format!("{}.{}", resource, action),
+        allowed: is_super_admin || checks.iter().any(|check| check.allowed),
+        is_super_admin,
+        notes: notes.into_iter().collect(),
+        checks,
+    }))
 }
 
 fn parse_requested_scope(raw_scope: Option<&str>) -> airborne_types::Result<PermissionScope> {
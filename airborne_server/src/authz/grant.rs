@@ -0,0 +1,806 @@
+//! Time-bound role grants.
+//!
+//! A grant gives a subject an organisation or application role for a limited
+//! time. Admins can grant directly, or a member requests elevation and another
+//! admin approves it. While active, the subject holds the granted role in the
+//! authorization provider; when it expires or is revoked, the role the subject
+//! had before is put back, unless someone changed it in the meantime.
+
+use std::collections::HashMap;
+
+use actix_web::{
+    get, post,
+    web::{self, Json, Path, Query, ReqData},
+    Scope,
+};
+use airborne_authz_macros::authz;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use log::{error, info, warn};
+
+use crate::{
+    authz::types::{RoleGrant, RoleGrantListQuery, RoleGrantRequest, TemporaryGrantInfo},
+    middleware::auth::{require_scope_name, AuthResponse, ADMIN, OWNER, READ, WRITE},
+    provider::authz::permission::enforce_endpoint_permission,
+    run_blocking, types as airborne_types,
+    types::{ABError, AppState, ListResponse},
+    utils::db::{
+        models::TemporaryRoleGrantEntry,
+        schema::hyperotaserver::{temporary_role_grants::dsl, workspace_names},
+        DbPool,
+    },
+};
+
+const STATUS_PENDING: &str = "pending";
+pub(crate) const STATUS_ACTIVE: &str = "active";
+const STATUS_EXPIRED: &str = "expired";
+const STATUS_REVOKED: &str = "revoked";
+const STATUS_REJECTED: &str = "rejected";
+const STATUSES: [&str; 5] = [
+    STATUS_PENDING,
+    STATUS_ACTIVE,
+    STATUS_EXPIRED,
+    STATUS_REVOKED,
+    STATUS_REJECTED,
+];
+const EXPIRY_CHECK_INTERVAL_SECS: u64 = 60;
+
+pub fn add_routes() -> Scope {
+    Scope::new("/grants")
+        .service(list_grants)
+        .service(request_grant)
+        .service(create_grant)
+        .service(approve_grant)
+        .service(reject_grant)
+        .service(revoke_grant)
+}
+
+pub(crate) fn system_role_level(role: &str) -> Option<u8> {
+    match role {
+        "owner" => Some(OWNER.access),
+        "admin" => Some(ADMIN.access),
+        "write" => Some(WRITE.access),
+        "read" => Some(READ.access),
+        _ => None,
+    }
+}
+
+fn validate_request(
+    state: &AppState,
+    request: &RoleGrantRequest,
+) -> airborne_types::Result<(String, String)> {
+    let role = request.role.trim().to_ascii_lowercase();
+    if role.is_empty() {
+        return Err(ABError::BadRequest("role cannot be empty".to_string()));
+    }
+    if role == "owner" {
+        return Err(ABError::BadRequest(
+            "Ownership cannot be granted temporarily".to_string(),
+        ));
+    }
+    let max_secs = state.env.temporary_grant_max_secs;
+    if request.duration_secs <= 0 || request.duration_secs > max_secs {
+        return Err(ABError::BadRequest(format!(
+            "duration_secs must be between 1 and {}",
+            max_secs
+        )));
+    }
+    let reason = request.reason.trim().to_string();
+    if reason.is_empty() {
+        return Err(ABError::BadRequest("reason cannot be empty".to_string()));
+    }
+    Ok((role, reason))
+}
+
+/// Checks that `auth` may hand out `grant`'s role: non-super-admins need admin
+/// access at the grant's scope and cannot grant above their own level.
+fn ensure_can_grant(
+    auth: &AuthResponse,
+    grant: &TemporaryRoleGrantEntry,
+) -> airborne_types::Result<()> {
+    if auth.is_super_admin {
+        return Ok(());
+    }
+    let org_level = auth.organisation.as_ref().map_or(0, |org| org.level);
+    let app_level = match (&grant.application, &auth.application) {
+        (Some(grant_app), Some(app)) if *grant_app == app.name => app.level,
+        _ => 0,
+    };
+    let actor_level = if grant.application.is_some() {
+        org_level.max(app_level)
+    } else {
+        org_level
+    };
+    let required = system_role_level(&grant.role)
+        .unwrap_or(ADMIN.access)
+        .max(ADMIN.access);
+    if actor_level < required {
+        return Err(ABError::Forbidden(format!(
+            "Cannot grant '{}' above your own access level",
+            grant.role
+        )));
+    }
+    Ok(())
+}
+
+async fn load_grant(
+    pool: DbPool,
+    organisation: String,
+    grant_id: uuid::Uuid,
+) -> airborne_types::Result<TemporaryRoleGrantEntry> {
+    run_blocking!({
+        let mut conn = pool.get()?;
+        dsl::temporary_role_grants
+            .filter(dsl::id.eq(grant_id))
+            .filter(dsl::organisation.eq(&organisation))
+            .select(TemporaryRoleGrantEntry::as_select())
+            .first::<TemporaryRoleGrantEntry>(&mut conn)
+            .optional()?
+            .ok_or_else(|| ABError::NotFound(format!("Grant {} not found", grant_id)))
+    })
+}
+
+/// Fails with `NotFound` when `grant` names an application the organisation
+/// does not have.
+async fn ensure_application_exists(
+    pool: DbPool,
+    grant: &TemporaryRoleGrantEntry,
+) -> airborne_types::Result<()> {
+    let Some(application) = grant.application.clone() else {
+        return Ok(());
+    };
+    let organisation = grant.organisation.clone();
+    run_blocking!({
+        let mut conn = pool.get()?;
+        let exists = diesel::select(diesel::dsl::exists(
+            workspace_names::table
+                .filter(workspace_names::organization_id.eq(&organisation))
+                .filter(workspace_names::application_id.eq(&application)),
+        ))
+        .get_result::<bool>(&mut conn)?;
+        if exists {
+            Ok(())
+        } else {
+            Err(ABError::NotFound(format!(
+                "Application not found: {}",
+                application
+            )))
+        }
+    })
+}
+
+/// Stores a new grant. The check for an open grant gives a readable error;
+/// the unique index on open grants settles concurrent inserts.
+async fn insert_grant(
+    pool: DbPool,
+    grant: TemporaryRoleGrantEntry,
+) -> airborne_types::Result<TemporaryRoleGrantEntry> {
+    run_blocking!({
+        let mut conn = pool.get()?;
+        let conflict = || {
+            ABError::Conflict(format!(
+                "{} already has a pending or active grant in this scope",
+                grant.subject
+            ))
+        };
+        let mut open = dsl::temporary_role_grants
+            .filter(dsl::subject.eq(&grant.subject))
+            .filter(dsl::organisation.eq(&grant.organisation))
+            .filter(dsl::status.eq_any([STATUS_PENDING, STATUS_ACTIVE]))
+            .into_boxed();
+        open = match &grant.application {
+            Some(app) => open.filter(dsl::application.eq(app)),
+            None => open.filter(dsl::application.is_null()),
+        };
+        if open
+            .select(dsl::id)
+            .first::<uuid::Uuid>(&mut conn)
+            .optional()?
+            .is_some()
+        {
+            return Err(conflict());
+        }
+        match diesel::insert_into(dsl::temporary_role_grants)
+            .values(&grant)
+            .returning(TemporaryRoleGrantEntry::as_returning())
+            .get_result::<TemporaryRoleGrantEntry>(&mut conn)
+        {
+            Ok(saved) => Ok(saved),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => Err(conflict()),
+            Err(e) => Err(e.into()),
+        }
+    })
+}
+
+/// Moves the grant from `from` to `to`, returning false when another caller
+/// (or another server instance) changed it first.
+async fn claim_transition(
+    pool: DbPool,
+    grant_id: uuid::Uuid,
+    from: &'static str,
+    to: &'static str,
+) -> airborne_types::Result<bool> {
+    run_blocking!({
+        let mut conn = pool.get()?;
+        let updated = diesel::update(
+            dsl::temporary_role_grants
+                .filter(dsl::id.eq(grant_id))
+                .filter(dsl::status.eq(from)),
+        )
+        .set(dsl::status.eq(to))
+        .execute(&mut conn)?;
+        Ok(updated == 1)
+    })
+}
+
+async fn activate(
+    state: &AppState,
+    grant: TemporaryRoleGrantEntry,
+    actor: &str,
+) -> airborne_types::Result<TemporaryRoleGrantEntry> {
+    let previous_role = state
+        .authz_provider
+        .subject_role(
+            state,
+            &grant.organisation,
+            grant.application.as_deref(),
+            &grant.subject,
+        )
+        .await?;
+    if let (Some(current), Some(granted)) = (
+        previous_role.as_deref().and_then(system_role_level),
+        system_role_level(&grant.role),
+    ) {
+        if current >= granted {
+            return Err(ABError::BadRequest(format!(
+                "{} already has '{}' or higher in this scope",
+                grant.subject, grant.role
+            )));
+        }
+    }
+
+    // Status, expiry and previous role are written together, so an active
+    // grant always has what the expiry task needs to end it
+    let pool = state.db_pool.clone();
+    let now = Utc::now();
+    let actor = actor.to_string();
+    let grant_id = grant.id;
+    let expires_at = now + Duration::seconds(grant.duration_secs);
+    let saved = run_blocking!({
+        let mut conn = pool.get()?;
+        let saved = diesel::update(
+            dsl::temporary_role_grants
+                .filter(dsl::id.eq(grant_id))
+                .filter(dsl::status.eq(STATUS_PENDING)),
+        )
+        .set((
+            dsl::status.eq(STATUS_ACTIVE),
+            dsl::previous_role.eq(previous_role),
+            dsl::decided_by.eq(actor),
+            dsl::decided_at.eq(now),
+            dsl::expires_at.eq(expires_at),
+        ))
+        .returning(TemporaryRoleGrantEntry::as_returning())
+        .get_result::<TemporaryRoleGrantEntry>(&mut conn)
+        .optional()?;
+        Ok(saved)
+    })?
+    .ok_or_else(|| ABError::Conflict(format!("Grant {} is no longer pending", grant.id)))?;
+
+    if let Err(e) = state
+        .authz_provider
+        .assign_role(
+            state,
+            &grant.organisation,
+            grant.application.as_deref(),
+            &grant.subject,
+            Some(&grant.role),
+        )
+        .await
+    {
+        let pool = state.db_pool.clone();
+        run_blocking!({
+            let mut conn = pool.get()?;
+            diesel::update(
+                dsl::temporary_role_grants
+                    .filter(dsl::id.eq(grant_id))
+                    .filter(dsl::status.eq(STATUS_ACTIVE)),
+            )
+            .set((
+                dsl::status.eq(STATUS_PENDING),
+                dsl::previous_role.eq(None::<String>),
+                dsl::decided_by.eq(None::<String>),
+                dsl::decided_at.eq(None::<chrono::DateTime<Utc>>),
+                dsl::expires_at.eq(None::<chrono::DateTime<Utc>>),
+            ))
+            .execute(&mut conn)?;
+            Ok(())
+        })?;
+        return Err(e);
+    }
+
+    info!(
+        "Activated grant {}: {} has '{}' in {}{} until {}",
+        saved.id,
+        saved.subject,
+        saved.role,
+        saved.organisation,
+        saved
+            .application
+            .as_deref()
+            .map(|app| format!("/{}", app))
+            .unwrap_or_default(),
+        expires_at
+    );
+    Ok(saved)
+}
+
+/// Ends an active grant and restores the subject's previous role. The role
+/// is restored before the grant is marked ended, so if that fails the grant
+/// stays active and the expiry task tries again.
+async fn end_grant(
+    state: &AppState,
+    grant: &TemporaryRoleGrantEntry,
+    status: &'static str,
+) -> airborne_types::Result<bool> {
+    if grant.status != STATUS_ACTIVE {
+        return Ok(false);
+    }
+
+    let current_role = state
+        .authz_provider
+        .subject_role(
+            state,
+            &grant.organisation,
+            grant.application.as_deref(),
+            &grant.subject,
+        )
+        .await?;
+    if current_role.as_deref() == Some(grant.role.as_str()) {
+        state
+            .authz_provider
+            .assign_role(
+                state,
+                &grant.organisation,
+                grant.application.as_deref(),
+                &grant.subject,
+                grant.previous_role.as_deref(),
+            )
+            .await?;
+    } else if current_role != grant.previous_role {
+        warn!(
+            "Role of {} changed while grant {} was active; leaving '{}' in place",
+            grant.subject,
+            grant.id,
+            current_role.unwrap_or_default()
+        );
+    }
+
+    let pool = state.db_pool.clone();
+    let grant_id = grant.id;
+    let ended = run_blocking!({
+        let mut conn = pool.get()?;
+        let updated = diesel::update(
+            dsl::temporary_role_grants
+                .filter(dsl::id.eq(grant_id))
+                .filter(dsl::status.eq(STATUS_ACTIVE)),
+        )
+        .set((dsl::status.eq(status), dsl::ended_at.eq(Utc::now())))
+        .execute(&mut conn)?;
+        Ok(updated == 1)
+    })?;
+
+    if ended {
+        info!(
+            "Grant {} of '{}' to {} {}",
+            grant.id, grant.role, grant.subject, status
+        );
+    }
+    Ok(ended)
+}
+
+async fn expire_grants(state: &AppState) -> airborne_types::Result<()> {
+    let pool = state.db_pool.clone();
+    let due = run_blocking!({
+        let mut conn = pool.get()?;
+        let due = dsl::temporary_role_grants
+            .filter(dsl::status.eq(STATUS_ACTIVE))
+            .filter(dsl::expires_at.le(Utc::now()))
+            .select(TemporaryRoleGrantEntry::as_select())
+            .load::<TemporaryRoleGrantEntry>(&mut conn)?;
+        Ok(due)
+    })?;
+
+    for grant in due {
+        if let Err(e) = end_grant(state, &grant, STATUS_EXPIRED).await {
+            error!("Failed to expire grant {}: {}", grant.id, e);
+        }
+    }
+    Ok(())
+}
+
+/// Periodically revokes grants whose time is up.
+pub fn spawn_expiry_task(state: web::Data<AppState>) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(EXPIRY_CHECK_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = expire_grants(&state).await {
+                error!("Temporary grant expiry run failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Active grants in the organisation (`application` = `None`) or application,
+/// keyed by subject, for the user list endpoints.
+pub async fn active_grants_by_subject(
+    pool: DbPool,
+    organisation: String,
+    application: Option<String>,
+) -> airborne_types::Result<HashMap<String, Vec<TemporaryGrantInfo>>> {
+    let grants = run_blocking!({
+        let mut conn = pool.get()?;
+        let mut query = dsl::temporary_role_grants
+            .filter(dsl::organisation.eq(&organisation))
+            .filter(dsl::status.eq(STATUS_ACTIVE))
+            .into_boxed();
+        query = match &application {
+            Some(app) => query.filter(dsl::application.eq(app)),
+            None => query.filter(dsl::application.is_null()),
+        };
+        let grants = query
+            .select(TemporaryRoleGrantEntry::as_select())
+            .load::<TemporaryRoleGrantEntry>(&mut conn)?;
+        Ok(grants)
+    })?;
+
+    let mut by_subject: HashMap<String, Vec<TemporaryGrantInfo>> = HashMap::new();
+    for grant in grants {
+        by_subject
+            .entry(grant.subject)
+            .or_default()
+            .push(TemporaryGrantInfo {
+                id: grant.id,
+                role: grant.role,
+                expires_at: grant.expires_at,
+                reason: grant.reason,
+            });
+    }
+    Ok(by_subject)
+}
+
+fn new_grant(
+    subject: String,
+    organisation: String,
+    request: &RoleGrantRequest,
+    role: String,
+    reason: String,
+    requested_by: String,
+) -> TemporaryRoleGrantEntry {
+    TemporaryRoleGrantEntry {
+        id: uuid::Uuid::new_v4(),
+        subject,
+        organisation,
+        application: request
+            .application
+            .as_deref()
+            .map(str::trim)
+            .filter(|app| !app.is_empty())
+            .map(str::to_string),
+        role,
+        previous_role: None,
+        reason,
+        duration_secs: request.duration_secs,
+        status: STATUS_PENDING.to_string(),
+        requested_by,
+        requested_at: Utc::now(),
+        decided_by: None,
+        decided_at: None,
+        expires_at: None,
+        ended_at: None,
+    }
+}
+
+/// Lists grants in the organisation. Callers without `role_grant.read` only
+/// see their own.
+#[get("/list")]
+async fn list_grants(
+    query: Query<RoleGrantListQuery>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<ListResponse<Vec<RoleGrant>>>> {
+    let can_read_all =
+        enforce_endpoint_permission(&state, &auth_response, "role_grant", "read", true, true)
+            .await
+            .is_ok();
+    let auth = auth_response.into_inner();
+    let organisation = require_scope_name(auth.organisation.clone(), "organisation")?;
+    let status = query
+        .into_inner()
+        .status
+        .map(|value| value.trim().to_ascii_lowercase());
+    if let Some(value) = &status {
+        if !STATUSES.contains(&value.as_str()) {
+            return Err(ABError::BadRequest(format!(
+                "Invalid status '{}'. Expected one of: {}",
+                value,
+                STATUSES.join(", ")
+            )));
+        }
+    }
+
+    let pool = state.db_pool.clone();
+    let subject = auth.sub;
+    let grants = run_blocking!({
+        let mut conn = pool.get()?;
+        let mut query = dsl::temporary_role_grants
+            .filter(dsl::organisation.eq(&organisation))
+            .into_boxed();
+        if !can_read_all {
+            query = query.filter(dsl::subject.eq(&subject));
+        }
+        if let Some(status) = &status {
+            query = query.filter(dsl::status.eq(status));
+        }
+        let grants = query
+            .order(dsl::requested_at.desc())
+            .select(TemporaryRoleGrantEntry::as_select())
+            .load::<TemporaryRoleGrantEntry>(&mut conn)?;
+        Ok(grants)
+    })?;
+
+    Ok(Json(ListResponse {
+        data: grants.into_iter().map(RoleGrant::from).collect(),
+    }))
+}
+
+/// Asks for a temporary role for the caller; another admin has to approve it.
+#[post("/request")]
+async fn request_grant(
+    req: Json<RoleGrantRequest>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<RoleGrant>> {
+    let req = req.into_inner();
+    let auth = auth_response.into_inner();
+    crate::token::scope::require_unrestricted(&auth)?;
+    let organisation = require_scope_name(auth.organisation.clone(), "organisation")?;
+    if req
+        .subject
+        .as_deref()
+        .is_some_and(|subject| !subject.trim().eq_ignore_ascii_case(&auth.sub))
+    {
+        return Err(ABError::BadRequest(
+            "Elevation can only be requested for yourself".to_string(),
+        ));
+    }
+    let (role, reason) = validate_request(&state, &req)?;
+
+    let grant = new_grant(
+        auth.sub.clone(),
+        organisation,
+        &req,
+        role,
+        reason,
+        auth.sub.clone(),
+    );
+    ensure_application_exists(state.db_pool.clone(), &grant).await?;
+    let saved = insert_grant(state.db_pool.clone(), grant).await?;
+    info!(
+        "{} requested '{}' for {}s: {}",
+        saved.subject, saved.role, saved.duration_secs, saved.reason
+    );
+    Ok(Json(saved.into()))
+}
+
+#[authz(
+    resource = "role_grant",
+    action = "create",
+    org_roles = ["owner", "admin"],
+    app_roles = ["admin"]
+)]
+#[post("")]
+async fn create_grant(
+    req: Json<RoleGrantRequest>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<RoleGrant>> {
+    let req = req.into_inner();
+    let auth = auth_response.into_inner();
+    let organisation = require_scope_name(auth.organisation.clone(), "organisation")?;
+    let subject = req
+        .subject
+        .as_deref()
+        .map(|subject| subject.trim().to_ascii_lowercase())
+        .filter(|subject| !subject.is_empty())
+        .ok_or_else(|| ABError::BadRequest("subject is required".to_string()))?;
+    let (role, reason) = validate_request(&state, &req)?;
+
+    let grant = new_grant(subject, organisation, &req, role, reason, auth.sub.clone());
+    ensure_can_grant(&auth, &grant)?;
+    ensure_application_exists(state.db_pool.clone(), &grant).await?;
+    let saved = insert_grant(state.db_pool.clone(), grant).await?;
+    match activate(&state, saved.clone(), &auth.sub).await {
+        Ok(active) => Ok(Json(active.into())),
+        Err(e) => {
+            let pool = state.db_pool.clone();
+            let grant_id = saved.id;
+            run_blocking!({
+                let mut conn = pool.get()?;
+                diesel::delete(dsl::temporary_role_grants.find(grant_id)).execute(&mut conn)?;
+                Ok(())
+            })?;
+            Err(e)
+        }
+    }
+}
+
+#[authz(
+    resource = "role_grant",
+    action = "approve",
+    org_roles = ["owner", "admin"],
+    app_roles = ["admin"]
+)]
+#[post("/{grant_id}/approve")]
+async fn approve_grant(
+    grant_id: Path<uuid::Uuid>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<RoleGrant>> {
+    let auth = auth_response.into_inner();
+    let organisation = require_scope_name(auth.organisation.clone(), "organisation")?;
+    let grant = load_grant(state.db_pool.clone(), organisation, grant_id.into_inner()).await?;
+    if grant.status != STATUS_PENDING {
+        return Err(ABError::BadRequest(format!(
+            "Grant is {}, not pending",
+            grant.status
+        )));
+    }
+    if grant.subject == auth.sub {
+        return Err(ABError::Forbidden(
+            "You cannot approve your own elevation request".to_string(),
+        ));
+    }
+    ensure_can_grant(&auth, &grant)?;
+
+    let active = activate(&state, grant, &auth.sub).await?;
+    Ok(Json(active.into()))
+}
+
+#[authz(
+    resource = "role_grant",
+    action = "approve",
+    org_roles = ["owner", "admin"],
+    app_roles = ["admin"]
+)]
+#[post("/{grant_id}/reject")]
+async fn reject_grant(
+    grant_id: Path<uuid::Uuid>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<RoleGrant>> {
+    let auth = auth_response.into_inner();
+    let organisation = require_scope_name(auth.organisation.clone(), "organisation")?;
+    let grant = load_grant(state.db_pool.clone(), organisation, grant_id.into_inner()).await?;
+    if !claim_transition(
+        state.db_pool.clone(),
+        grant.id,
+        STATUS_PENDING,
+        STATUS_REJECTED,
+    )
+    .await?
+    {
+        return Err(ABError::BadRequest(format!(
+            "Grant is {}, not pending",
+            grant.status
+        )));
+    }
+
+    let pool = state.db_pool.clone();
+    let actor = auth.sub.clone();
+    let grant_id = grant.id;
+    let saved = run_blocking!({
+        let mut conn = pool.get()?;
+        let now = Utc::now();
+        let saved = diesel::update(dsl::temporary_role_grants.find(grant_id))
+            .set((
+                dsl::decided_by.eq(actor),
+                dsl::decided_at.eq(now),
+                dsl::ended_at.eq(now),
+            ))
+            .returning(TemporaryRoleGrantEntry::as_returning())
+            .get_result::<TemporaryRoleGrantEntry>(&mut conn)?;
+        Ok(saved)
+    })?;
+    info!("{} rejected grant {}", auth.sub, grant_id);
+    Ok(Json(saved.into()))
+}
+
+/// Ends an active grant early. The grantee can always give a role back;
+/// anyone else needs `role_grant.approve`.
+#[post("/{grant_id}/revoke")]
+async fn revoke_grant(
+    grant_id: Path<uuid::Uuid>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<RoleGrant>> {
+    let organisation = require_scope_name(auth_response.organisation.clone(), "organisation")?;
+    let grant = load_grant(state.db_pool.clone(), organisation, grant_id.into_inner()).await?;
+    if grant.subject != auth_response.sub {
+        enforce_endpoint_permission(&state, &auth_response, "role_grant", "approve", true, true)
+            .await?;
+    } else {
+        crate::token::scope::require_unrestricted(&auth_response)?;
+    }
+    if !end_grant(&state, &grant, STATUS_REVOKED).await? {
+        return Err(ABError::BadRequest(format!(
+            "Grant is {}, not active",
+            grant.status
+        )));
+    }
+
+    let ended = load_grant(state.db_pool.clone(), grant.organisation, grant.id).await?;
+    info!("{} revoked grant {}", auth_response.sub, ended.id);
+    Ok(Json(ended.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::auth::AccessLevel;
+
+    fn auth(org_level: u8, app: Option<(&str, u8)>) -> AuthResponse {
+        AuthResponse {
+            sub: "admin@example.com".to_string(),
+            authn_sub: String::new(),
+            authn_iss: None,
+            authn_email: None,
+            organisation: Some(AccessLevel {
+                name: "org".to_string(),
+                level: org_level,
+            }),
+            application: app.map(|(name, level)| AccessLevel {
+                name: name.to_string(),
+                level,
+            }),
+            is_super_admin: false,
+            username: String::new(),
+            token_permissions: None,
+        }
+    }
+
+    fn grant(application: Option<&str>, role: &str) -> TemporaryRoleGrantEntry {
+        TemporaryRoleGrantEntry {
+            id: uuid::Uuid::new_v4(),
+            subject: "dev@example.com".to_string(),
+            organisation: "org".to_string(),
+            application: application.map(str::to_string),
+            role: role.to_string(),
+            previous_role: None,
+            reason: "incident".to_string(),
+            duration_secs: 3600,
+            status: STATUS_PENDING.to_string(),
+            requested_by: "dev@example.com".to_string(),
+            requested_at: Utc::now(),
+            decided_by: None,
+            decided_at: None,
+            expires_at: None,
+            ended_at: None,
+        }
+    }
+
+    #[test]
+    fn grant_needs_admin_at_scope() {
+        assert!(ensure_can_grant(&auth(ADMIN.access, None), &grant(None, "admin")).is_ok());
+        assert!(ensure_can_grant(&auth(WRITE.access, None), &grant(None, "write")).is_err());
+        let app_admin = auth(READ.access, Some(("app", ADMIN.access)));
+        assert!(ensure_can_grant(&app_admin, &grant(Some("app"), "admin")).is_ok());
+        assert!(ensure_can_grant(&app_admin, &grant(Some("other"), "admin")).is_err());
+        assert!(ensure_can_grant(&app_admin, &grant(None, "read")).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Export and import of an organisation's role bindings and custom roles as a
+//! YAML or JSON document, so they can be reviewed and versioned like code.
+
+use actix_web::{
+    get,
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    post,
+    web::{self, Json, Query, ReqData},
+    Scope,
+};
+use airborne_authz_macros::authz;
+use bytes::Bytes;
+use http::HeaderValue;
+use log::info;
+
+use crate::{
+    authz::types::{PolicyExportQuery, PolicyImportQuery},
+    middleware::auth::{require_scope_name, AuthResponse},
+    provider::authz::{AuthzPolicyDiff, AuthzPolicyDocument},
+    types as airborne_types,
+    types::{ABError, AppState, WithHeaders},
+};
+
+pub fn add_routes() -> Scope {
+    Scope::new("/policy")
+        .service(export_policy)
+        .service(import_policy)
+}
+
+#[authz(
+    resource = "authz_policy",
+    action = "export",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[get("/export")]
+async fn export_policy(
+    query: Query<PolicyExportQuery>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Bytes>> {
+    let auth = auth_response.into_inner();
+    let organisation = require_scope_name(auth.organisation.clone(), "organisation")?;
+    let document = state
+        .authz_provider
+        .export_policy_document(state.get_ref(), &organisation)
+        .await?;
+
+    let format = query
+        .into_inner()
+        .format
+        .unwrap_or_else(|| "yaml".to_string())
+        .to_ascii_lowercase();
+    let (body, content_type, extension) = match format.as_str() {
+        "yaml" | "yml" => (
+            serde_yaml::to_string(&document)
+                .map_err(|e| ABError::InternalServerError(e.to_string()))?,
+            "application/yaml",
+            "yaml",
+        ),
+        "json" => (
+            serde_json::to_string_pretty(&document)
+                .map_err(|e| ABError::InternalServerError(e.to_string()))?,
+            "application/json",
+            "json",
+        ),
+        other => {
+            return Err(ABError::BadRequest(format!(
+                "Unsupported format '{}'. Expected yaml or json",
+                other
+            )))
+        }
+    };
+
+    let disposition = HeaderValue::from_str(&format!(
+        "attachment; filename=\"{}-authz.{}\"",
+        organisation, extension
+    ))
+    .map_err(|e| {
+        ABError::InternalServerError(format!(
+            "Failed to create content disposition header: {}",
+            e
+        ))
+    })?;
+    Ok(WithHeaders::new(Bytes::from(body))
+        .header(CONTENT_TYPE, HeaderValue::from_static(content_type))
+        .header(CONTENT_DISPOSITION, disposition))
+}
+
+/// Accepts the document as YAML or JSON (JSON is valid YAML).
+#[authz(
+    resource = "authz_policy",
+    action = "import",
+    org_roles = ["owner"],
+    app_roles = []
+)]
+#[post("/import")]
+async fn import_policy(
+    body: Bytes,
+    query: Query<PolicyImportQuery>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<AuthzPolicyDiff>> {
+    let auth = auth_response.into_inner();
+    let organisation = require_scope_name(auth.organisation.clone(), "organisation")?;
+    let document: AuthzPolicyDocument = serde_yaml::from_slice(&body)
+        .map_err(|e| ABError::BadRequest(format!("Invalid policy document: {}", e)))?;
+    if document.organisation != organisation {
+        return Err(ABError::BadRequest(format!(
+            "Document is for organisation '{}', not '{}'",
+            document.organisation, organisation
+        )));
+    }
+
+    let dry_run = query.dry_run;
+    let diff = state
+        .authz_provider
+        .import_policy_document(state.get_ref(), &document, dry_run)
+        .await?;
+    if diff.applied {
+        info!(
+            "{} imported the authorization policy of {}",
+            auth.sub, organisation
+        );
+    }
+    Ok(Json(diff))
+}
@@ -1,5 +1,8 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::utils::db::models::TemporaryRoleGrantEntry;
+
 #[derive(Debug, Deserialize)]
 pub struct PermissionCatalogQuery {
     pub scope: Option<String>,
@@ -41,5 +44,123 @@ pub struct PermissionBatchCheckResult {
 
 #[derive(Debug, Serialize)]
 pub struct EnforceBatchResponse {
+    /// The caller the checks were evaluated for.
+    pub subject: String,
     pub results: Vec<PermissionBatchCheckResult>,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct ExplainRequest {
+    pub subject: String,
+    pub resource: String,
+    pub action: String,
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExplainPolicy {
+    /// The Casbin `p` line: subject, scope, organisation, application, role.
+    pub policy: Vec<String>,
+    pub role: String,
+    pub role_chain: Vec<String>,
+    pub custom_roles: Vec<String>,
+    pub grants: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExplainCheck {
+    pub scope: String,
+    pub organisation: String,
+    pub application: Option<String>,
+    pub permission: String,
+    pub allowed: bool,
+    pub policies: Vec<ExplainPolicy>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExplainResponse {
+    pub subject: String,
+    pub key: String,
+    pub allowed: bool,
+    pub is_super_admin: bool,
+    pub notes: Vec<String>,
+    pub checks: Vec<ExplainCheck>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoleGrantRequest {
+    /// Who receives the role; defaults to the caller for elevation requests.
+    pub subject: Option<String>,
+    /// Grant an application role instead of an organisation role.
+    pub application: Option<String>,
+    pub role: String,
+    pub duration_secs: i64,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoleGrantListQuery {
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoleGrant {
+    pub id: uuid::Uuid,
+    pub subject: String,
+    pub organisation: String,
+    pub application: Option<String>,
+    pub role: String,
+    pub previous_role: Option<String>,
+    pub reason: String,
+    pub duration_secs: i64,
+    pub status: String,
+    pub requested_by: String,
+    pub requested_at: DateTime<Utc>,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+impl From<TemporaryRoleGrantEntry> for RoleGrant {
+    fn from(entry: TemporaryRoleGrantEntry) -> Self {
+        RoleGrant {
+            id: entry.id,
+            subject: entry.subject,
+            organisation: entry.organisation,
+            application: entry.application,
+            role: entry.role,
+            previous_role: entry.previous_role,
+            reason: entry.reason,
+            duration_secs: entry.duration_secs,
+            status: entry.status,
+            requested_by: entry.requested_by,
+            requested_at: entry.requested_at,
+            decided_by: entry.decided_by,
+            decided_at: entry.decided_at,
+            expires_at: entry.expires_at,
+            ended_at: entry.ended_at,
+        }
+    }
+}
+
+/// An active temporary grant, as shown next to a user in the user lists.
+#[derive(Debug, Serialize, Clone)]
+pub struct TemporaryGrantInfo {
+    pub id: uuid::Uuid,
+    pub role: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PolicyExportQuery {
+    /// `yaml` (default) or `json`.
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PolicyImportQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
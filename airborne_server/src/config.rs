@@ -53,6 +53,9 @@ pub struct AppConfig {
     pub oidc_clock_skew_secs: u64,
     pub authz_bootstrap_super_admins: Option<String>,
     pub authz_casbin_auto_load_secs: Option<u64>,
+    pub authz_cedar_policy_path: Option<String>,
+    pub authz_cedar_reload_secs: u64,
+    pub authz_cedar_role_baseline: bool,
     pub auth_admin_client_id: Option<String>,
     pub auth_admin_client_secret: Option<String>,
     pub auth_admin_token_url: Option<String>,
@@ -60,6 +63,18 @@ pub struct AppConfig {
     pub auth_admin_scopes: Option<String>,
     pub auth_admin_issuer: Option<String>,
 
+    // SAML settings
+    pub saml_idp_entity_id: Option<String>,
+    pub saml_idp_sso_url: Option<String>,
+    pub saml_idp_certificate: Option<String>,
+    pub saml_sp_entity_id: Option<String>,
+    pub saml_sp_acs_url: Option<String>,
+    pub saml_session_secret: Option<String>,
+    pub saml_session_ttl_secs: i64,
+    pub saml_refresh_ttl_secs: i64,
+    pub saml_email_attribute: String,
+    pub saml_username_attribute: String,
+
     // Superposition settings
     pub superposition_url: String,
     pub superposition_org_id: String,
@@ -96,6 +111,33 @@ pub struct AppConfig {
 
     // Victoria Metrics
     pub victoria_metrics_url: String,
+
+    // Bundle export/import
+    pub bundle_signing_key: Option<String>,
+    pub bundle_max_unpacked_bytes: u64,
+
+    // Upload validation
+    pub upload_validation_hook: Option<String>,
+    pub upload_validation_hook_timeout_secs: u64,
+    pub upload_validation_hook_uid: u32,
+    pub upload_validation_hook_gid: u32,
+    pub upload_validation_hook_max_memory_mb: u64,
+
+    // File mirroring
+    pub file_mirror_max_bytes: u64,
+
+    // Service accounts
+    pub service_account_token_ttl_secs: i64,
+
+    // Temporary role grants
+    pub temporary_grant_max_secs: i64,
+
+    // Sessions
+    pub session_refresh_ttl_secs: i64,
+    pub trusted_proxies: Vec<ipnet::IpNet>,
+
+    // Analytics ingest keys
+    pub analytics_ingest_verify_token: Option<String>,
 }
 
 impl AppConfig {
@@ -202,6 +244,9 @@ impl AppConfig {
             authz_casbin_auto_load_secs: env::var("AUTHZ_CASBIN_AUTOLOAD_SECS")
                 .ok()
                 .and_then(|value| value.parse::<u64>().ok()),
+            authz_cedar_policy_path: get_optional("AUTHZ_CEDAR_POLICY_PATH"),
+            authz_cedar_reload_secs: parse_env("AUTHZ_CEDAR_RELOAD_SECS", 60),
+            authz_cedar_role_baseline: parse_env("AUTHZ_CEDAR_ROLE_BASELINE", true),
             auth_admin_client_id: get_optional("AUTH_ADMIN_CLIENT_ID"),
             auth_admin_client_secret: get_optional_secret("AUTH_ADMIN_CLIENT_SECRET")?,
             auth_admin_token_url: get_optional("AUTH_ADMIN_TOKEN_URL"),
@@ -209,6 +254,18 @@ impl AppConfig {
             auth_admin_scopes: get_optional("AUTH_ADMIN_SCOPES"),
             auth_admin_issuer: get_optional("AUTH_ADMIN_ISSUER"),
 
+            // SAML settings
+            saml_idp_entity_id: get_optional("SAML_IDP_ENTITY_ID"),
+            saml_idp_sso_url: get_optional("SAML_IDP_SSO_URL"),
+            saml_idp_certificate: get_optional("SAML_IDP_CERTIFICATE"),
+            saml_sp_entity_id: get_optional("SAML_SP_ENTITY_ID"),
+            saml_sp_acs_url: get_optional("SAML_SP_ACS_URL"),
+            saml_session_secret: get_optional_secret("SAML_SESSION_SECRET")?,
+            saml_session_ttl_secs: parse_env("SAML_SESSION_TTL_SECS", 3600),
+            saml_refresh_ttl_secs: parse_env("SAML_REFRESH_TTL_SECS", 7 * 24 * 60 * 60),
+            saml_email_attribute: get_env("SAML_EMAIL_ATTRIBUTE", Some("email"))?,
+            saml_username_attribute: get_env("SAML_USERNAME_ATTRIBUTE", Some("username"))?,
+
             // Superposition settings
             superposition_url: get_env("SUPERPOSITION_URL", None)?,
             superposition_org_id: get_env("SUPERPOSITION_ORG_ID", None)?,
@@ -260,6 +317,60 @@ impl AppConfig {
 
             // Victoria Metrics
             victoria_metrics_url: get_env("VICTORIA_METRICS_INSERT_URL", Some(""))?,
+
+            // Bundle export/import
+            bundle_signing_key: get_optional_secret("BUNDLE_SIGNING_KEY")?,
+            bundle_max_unpacked_bytes: parse_env("BUNDLE_MAX_UNPACKED_BYTES", 1024 * 1024 * 1024),
+
+            // Upload validation
+            upload_validation_hook: get_optional("UPLOAD_VALIDATION_HOOK"),
+            upload_validation_hook_timeout_secs: parse_env(
+                "UPLOAD_VALIDATION_HOOK_TIMEOUT_SECS",
+                30,
+            ),
+            upload_validation_hook_uid: parse_env("UPLOAD_VALIDATION_HOOK_UID", 65534),
+            upload_validation_hook_gid: parse_env("UPLOAD_VALIDATION_HOOK_GID", 65534),
+            upload_validation_hook_max_memory_mb: parse_env(
+                "UPLOAD_VALIDATION_HOOK_MAX_MEMORY_MB",
+                512,
+            ),
+
+            // File mirroring
+            file_mirror_max_bytes: parse_env("FILE_MIRROR_MAX_BYTES", 256 * 1024 * 1024),
+
+            // Service accounts
+            service_account_token_ttl_secs: parse_env("SERVICE_ACCOUNT_TOKEN_TTL_SECS", 3600),
+
+            // Temporary role grants
+            temporary_grant_max_secs: parse_env("TEMPORARY_GRANT_MAX_SECS", 8 * 60 * 60),
+
+            // Sessions
+            session_refresh_ttl_secs: parse_env("SESSION_REFRESH_TTL_SECS", 30 * 24 * 60 * 60),
+
+            // Proxies whose X-Forwarded-For is believed
+            trusted_proxies: get_optional("TRUSTED_PROXIES")
+                .map(|raw| {
+                    raw.split(',')
+                        .map(str::trim)
+                        .filter(|value| !value.is_empty())
+                        .map(|value| {
+                            value
+                                .parse::<ipnet::IpNet>()
+                                .or_else(|_| value.parse::<std::net::IpAddr>().map(Into::into))
+                                .map_err(|_| {
+                                    format!(
+                                        "TRUSTED_PROXIES: '{}' is not an IP address or CIDR range",
+                                        value
+                                    )
+                                })
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()?
+                .unwrap_or_default(),
+
+            // Analytics ingest keys
+            analytics_ingest_verify_token: get_optional_secret("ANALYTICS_INGEST_VERIFY_TOKEN")?,
         })
     }
 }
@@ -1,13 +1,15 @@
 pub mod groups;
+mod mirror;
 pub mod types;
 pub mod utils;
+pub mod validation;
 
 use std::{fs::File, io::Read};
 
 use actix_multipart::form::MultipartForm;
 use actix_web::{
     error::PayloadError,
-    get, patch, post,
+    get, patch, post, put,
     web::{self, Json, Path, Payload, Query, ReqData},
     Scope,
 };
@@ -50,6 +52,8 @@ pub fn add_routes() -> Scope {
         .service(list_file_tags)
         .service(get_file)
         .service(update_file)
+        .service(get_upload_validation)
+        .service(update_upload_validation)
         .service(groups::add_routes())
 }
 
@@ -63,12 +67,16 @@ fn db_file_to_response(file: &DbFile) -> FileResponse {
         size: file.size,
         checksum: file.checksum.clone(),
         metadata: file.metadata.clone(),
-        status: if file.size > 0 {
+        status: if file.failure_reason.is_some() {
+            FileStatus::Failed
+        } else if file.size > 0 {
             FileStatus::Ready
         } else {
             FileStatus::Pending
         },
+        failure_reason: file.failure_reason.clone(),
         created_at: file.created_at.to_rfc3339(),
+        variants: utils::file_variants(file),
     }
 }
 
@@ -91,6 +99,7 @@ async fn create_file(
         auth_response.application.clone(),
     )?;
 
+    let mirror = req.mirror.unwrap_or(false);
     let (file_size, file_checksum) = match (&req.size, &req.checksum) {
         (Some(provided_size), Some(provided_checksum)) => {
             info!("Using provided size and checksum");
@@ -117,6 +126,9 @@ async fn create_file(
 
             (*provided_size, provided_checksum.to_lowercase())
         }
+        // Mirrored files get their size and checksum once the background
+        // download lands.
+        (None, None) if mirror => (0, String::new()),
         (None, None) => {
             info!("Downloading file to calculate size and checksum");
             utils::download_and_checksum(&req.url.clone())
@@ -140,8 +152,15 @@ async fn create_file(
     let db_application = application.clone();
     let db_file_path = request.file_path.clone();
     let db_tag = request.tag.clone();
+    let mirror_source = mirror.then(|| mirror::MirrorSource {
+        url: request.url.clone(),
+        size: request.size,
+        checksum: request.checksum.clone().map(|c| c.to_lowercase()),
+    });
 
-    let created_file = run_blocking!({
+    // Whether this request has to start a mirror download: it inserted the
+    // row or took over a failed one, as opposed to finding it
+    let (created_file, start_mirror) = run_blocking!({
         let mut conn = pool.get()?;
 
         let existing_file = files
@@ -153,19 +172,42 @@ async fn create_file(
             .first::<DbFile>(&mut conn)
             .optional()?;
 
+        // A failed mirror is downloaded again rather than returned. Clearing
+        // its failure claims it, so concurrent requests retry it once.
+        if let Some(existing) = existing_file.as_ref().filter(|existing| {
+            mirror && existing.url.is_empty() && existing.failure_reason.is_some()
+        }) {
+            info!("Retrying failed mirror of file {}", existing.id);
+            let claimed = diesel::update(files.find(existing.id))
+                .filter(failure_reason.is_not_null())
+                .set(failure_reason.eq(None::<String>))
+                .returning(DbFile::as_returning())
+                .get_result::<DbFile>(&mut conn)
+                .optional()?;
+            let retried = claimed.is_some();
+            let file = claimed.unwrap_or_else(|| DbFile {
+                failure_reason: None,
+                ..existing.clone()
+            });
+            return Ok((file, retried));
+        }
+
+        // A mirrored file is served from our own bucket, so only its
+        // content can be compared against the request.
+        let url_matches = |existing: &DbFile| mirror || existing.url == request.url;
         if let Some(existing) = &existing_file {
             if db_tag.is_some() {
-                if existing.checksum != file_checksum || existing.url != request.url {
+                if existing.checksum != file_checksum || !url_matches(existing) {
                     return Err(ABError::BadRequest(format!(
                         "File with file_path '{}' and tag '{}' already exists with different checksum or URL",
                         db_file_path, db_tag.as_ref().unwrap()
                     )));
                 }
                 info!("Existing file matches request, returning existing file");
-                return Ok(existing.clone());
-            } else if existing.checksum == file_checksum && existing.url == request.url {
+                return Ok((existing.clone(), false));
+            } else if existing.checksum == file_checksum && url_matches(existing) {
                 info!("Existing file matches request (no tag), returning existing file");
-                return Ok(existing.clone());
+                return Ok((existing.clone(), false));
             }
         } else {
             info!("No existing file found, creating new entry");
@@ -187,12 +229,16 @@ async fn create_file(
             let new_file = NewFileEntry {
                 app_id: db_application.clone(),
                 org_id: db_organisation.clone(),
-                url: request.url.clone(),
+                url: if mirror {
+                    String::new()
+                } else {
+                    request.url.clone()
+                },
                 file_path: db_file_path.clone(),
                 version: latest_version + 1,
                 tag: db_tag.clone(),
-                size: file_size as i64,
-                checksum: file_checksum,
+                size: if mirror { 0 } else { file_size as i64 },
+                checksum: if mirror { String::new() } else { file_checksum },
                 metadata: request.metadata.clone().unwrap_or_else(|| json!({})),
                 created_at: Utc::now(),
             };
@@ -202,7 +248,7 @@ async fn create_file(
                 .returning(DbFile::as_returning())
                 .get_result::<DbFile>(conn)
         })?;
-        Ok(result)
+        Ok((result, true))
     })?;
 
     // remove this tag file_entry from redis cache if exists
@@ -220,6 +266,11 @@ async fn create_file(
         }
     }
 
+    // A pending row that was found already has a download running.
+    if let Some(source) = mirror_source.filter(|_| start_mirror) {
+        mirror::spawn_mirror(state.clone(), created_file.clone(), source);
+    }
+
     Ok(Json(db_file_to_response(&created_file)))
 }
 
@@ -243,15 +294,30 @@ async fn bulk_create_files(
 
     let pool = state.db_pool.clone();
     let request = req.into_inner();
-
-    let (inserted_files, skipped_files) = run_blocking!({
+    // By position in the request, as several entries may share a path
+    let mut mirror_sources = request
+        .files
+        .iter()
+        .map(|f| {
+            f.mirror.unwrap_or(false).then(|| mirror::MirrorSource {
+                url: f.url.clone(),
+                size: f.size,
+                checksum: f.checksum.clone().map(|c| c.to_lowercase()),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let (inserted_files, inserted_indices, skipped_files) = run_blocking!({
         let mut conn = pool.get()?;
 
         let mut new_files = Vec::new();
+        let mut inserted_indices = Vec::new();
         let mut skipped_files = Vec::new();
+        // Versions already taken by earlier entries of this request
+        let mut batch_versions = std::collections::HashMap::<String, i32>::new();
 
         let result = conn.transaction::<Vec<DbFile>, diesel::result::Error, _>(|conn| {
-            for file_req in &request.files {
+            for (index, file_req) in request.files.iter().enumerate() {
                 let existing_file = files
                     .filter(org_id.eq(&organisation))
                     .filter(app_id.eq(&application))
@@ -285,12 +351,22 @@ async fn bulk_create_files(
                     .first::<DbFile>(conn)
                     .optional()?;
 
-                let latest_version = latest_file.map_or(0, |f| f.version);
+                let latest_version = latest_file.map_or(0, |f| f.version).max(
+                    batch_versions
+                        .get(&file_req.file_path)
+                        .copied()
+                        .unwrap_or_default(),
+                );
+                batch_versions.insert(file_req.file_path.clone(), latest_version + 1);
 
                 let new_file = NewFileEntry {
                     app_id: application.clone(),
                     org_id: organisation.clone(),
-                    url: file_req.url.clone(),
+                    url: if file_req.mirror.unwrap_or(false) {
+                        String::new()
+                    } else {
+                        file_req.url.clone()
+                    },
                     file_path: file_req.file_path.clone(),
                     version: latest_version + 1,
                     tag: file_req.tag.clone(),
@@ -301,6 +377,7 @@ async fn bulk_create_files(
                 };
 
                 new_files.push(new_file);
+                inserted_indices.push(index);
             }
 
             diesel::insert_into(files)
@@ -309,7 +386,7 @@ async fn bulk_create_files(
                 .get_results::<DbFile>(conn)
         })?;
 
-        Ok((result, skipped_files))
+        Ok((result, inserted_indices, skipped_files))
     })?;
 
     let created_files: Vec<FileResponse> = inserted_files
@@ -318,7 +395,13 @@ async fn bulk_create_files(
         .map(|f| db_file_to_response(&f))
         .collect();
 
-    for res in inserted_files.iter() {
+    // Rows are returned in the order they were inserted
+    for (res, index) in inserted_files.iter().zip(inserted_indices) {
+        if let Some(source) = mirror_sources[index].take() {
+            mirror::spawn_mirror(state.clone(), res.clone(), source);
+            continue;
+        }
+
         let pool = state.db_pool.clone();
         let file_url = res.url.clone();
         let res_id = res.id;
@@ -750,6 +833,8 @@ async fn upload_file(
 
     let file_path_str = query.file_path.clone();
     let tag_str = query.tag.clone();
+    let precompress =
+        query.precompress.unwrap_or(true) && utils::is_precompressible(&file_path_str);
 
     let file_size = req
         .headers()
@@ -845,6 +930,32 @@ async fn upload_file(
         return Ok(Json(db_file_to_response(&existing_file)));
     }
 
+    // Validators need the whole body, so buffer it up front when any are
+    // configured and only create the file once it has passed.
+    let validation_config =
+        validation::get_validation_config(pool.clone(), organisation.clone(), application.clone())
+            .await?;
+    let validated_body = if validation_config.is_active() {
+        let mut body = bytes::BytesMut::new();
+        while let Some(chunk) = payload.next().await {
+            let chunk =
+                chunk.map_err(|e| ABError::BadRequest(format!("Failed to read upload: {}", e)))?;
+            body.extend_from_slice(&chunk);
+        }
+        let errors =
+            validation::validate_upload(&state, &validation_config, &file_path_str, &body).await;
+        if !errors.is_empty() {
+            return Err(ABError::BadRequest(format!(
+                "File '{}' failed validation: {}",
+                file_path_str,
+                errors.join("; ")
+            )));
+        }
+        Some(body.freeze())
+    } else {
+        None
+    };
+
     let created_file = {
         let pool_clone = pool.clone();
         let org = organisation.clone();
@@ -924,9 +1035,20 @@ async fn upload_file(
         .await
     });
 
+    // Variants are compressed alongside the upload, chunk by chunk.
+    let mut compressor = precompress.then(utils::VariantCompressor::start);
+    if let Some(body) = validated_body {
+        if let Some(compressor) = compressor.as_mut() {
+            compressor.push(body.clone()).await;
+        }
+        let _ = tx.send(Ok(body));
+    }
     while let Some(result) = payload.next().await {
         match result {
             Ok(chunk) => {
+                if let Some(compressor) = compressor.as_mut() {
+                    compressor.push(chunk.clone()).await;
+                }
                 let _ = tx.send(Ok(chunk));
             }
             Err(PayloadError::Overflow) => {
@@ -948,6 +1070,15 @@ async fn upload_file(
                 info!("✅ Upload to S3 completed successfully");
                 let checksum_hex = utils::base64_to_hex(&b64_file_checksum);
 
+                let file_variants = match compressor {
+                    Some(compressor) => {
+                        utils::store_precompressed_variants(&state, &s3_path, compressor).await
+                    }
+                    None => Vec::new(),
+                };
+                let variants_json = serde_json::to_value(&file_variants)
+                    .unwrap_or_else(|_| serde_json::Value::Array(Vec::new()));
+
                 let updated_file = run_blocking!({
                     let mut conn = pool.get()?;
                     let updated_file = diesel::update(files.filter(id.eq(created_file.id)))
@@ -955,6 +1086,7 @@ async fn upload_file(
                             url.eq(full_url),
                             size.eq(file_size),
                             checksum.eq(checksum_hex),
+                            variants.eq(variants_json),
                         ))
                         .get_result::<DbFile>(&mut conn)
                         .map_err(|_| ABError::InternalServerError("DB Error".to_string()))?;
@@ -1096,6 +1228,13 @@ async fn upload_bulk_files(
         Ok((inserted_files, to_be_uploaded, skipped))
     })?;
     let mut uploaded = Vec::new();
+    let mut rejected = Vec::new();
+    let validation_config = validation::get_validation_config(
+        state.db_pool.clone(),
+        organisation.clone(),
+        application.clone(),
+    )
+    .await?;
 
     if !to_be_uploaded.is_empty() {
         for mapping in to_be_uploaded.iter().map(|(m, _)| m) {
@@ -1115,8 +1254,34 @@ async fn upload_bulk_files(
                 .read_to_end(&mut buf)
                 .map_err(|e| ABError::InternalServerError(e.to_string()))?;
 
+            if validation_config.is_active() {
+                let errors = validation::validate_upload(
+                    &state,
+                    &validation_config,
+                    &created_file.file_path,
+                    &buf,
+                )
+                .await;
+                if !errors.is_empty() {
+                    let pool = state.db_pool.clone();
+                    let file_id = created_file.id;
+                    run_blocking!({
+                        let mut conn = pool.get()?;
+                        diesel::delete(files.filter(id.eq(file_id))).execute(&mut conn)?;
+                        Ok(())
+                    })?;
+                    rejected.push(FileValidationFailure {
+                        file_path: created_file.file_path.clone(),
+                        version: created_file.version,
+                        errors,
+                    });
+                    continue;
+                }
+            }
+
             let file_checksum = utils::calculate_checksum(buf.clone()).await;
             let file_size = buf.len() as i64;
+            let buf = bytes::Bytes::from(buf);
 
             let file_name = std::path::Path::new(&created_file.file_path)
                 .file_name()
@@ -1134,13 +1299,18 @@ async fn upload_bulk_files(
             match push_file_byte_arr(
                 &state.s3_client,
                 state.env.bucket_name.clone(),
-                buf,
+                buf.clone(),
                 s3_path.clone(),
             )
             .await
             {
                 Ok(_) => {
                     let file_url = format!("{}/{}", &state.env.public_url, &s3_path,);
+                    let file_variants =
+                        utils::precompress_content(&state, &s3_path, &created_file.file_path, buf)
+                            .await;
+                    let variants_json = serde_json::to_value(&file_variants)
+                        .unwrap_or_else(|_| serde_json::Value::Array(Vec::new()));
                     let pool = state.db_pool.clone();
                     let file_id = created_file.id;
 
@@ -1151,6 +1321,7 @@ async fn upload_bulk_files(
                                 url.eq(file_url),
                                 size.eq(file_size),
                                 checksum.eq(file_checksum),
+                                variants.eq(variants_json),
                             ))
                             .execute(&mut conn)?;
                         Ok(())
@@ -1177,5 +1348,55 @@ async fn upload_bulk_files(
         }
     }
 
-    Ok(Json(BulkFileUploadResponse { uploaded, skipped }))
+    Ok(Json(BulkFileUploadResponse {
+        uploaded,
+        skipped,
+        rejected,
+    }))
+}
+
+#[authz(
+    resource = "upload_validation",
+    action = "read",
+    org_roles = ["owner", "admin", "write", "read"],
+    app_roles = ["admin", "write", "read"]
+)]
+#[get("/validation")]
+async fn get_upload_validation(
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<UploadValidationConfig>> {
+    let auth_response = auth_response.into_inner();
+    let (organisation, application) = require_org_and_app(
+        auth_response.organisation.clone(),
+        auth_response.application.clone(),
+    )?;
+
+    let config =
+        validation::get_validation_config(state.db_pool.clone(), organisation, application).await?;
+    Ok(Json(config))
+}
+
+#[authz(
+    resource = "upload_validation",
+    action = "update",
+    org_roles = ["owner", "admin"],
+    app_roles = ["admin"]
+)]
+#[put("/validation")]
+async fn update_upload_validation(
+    req: Json<UploadValidationConfig>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<UploadValidationConfig>> {
+    let auth_response = auth_response.into_inner();
+    let (organisation, application) = require_org_and_app(
+        auth_response.organisation.clone(),
+        auth_response.application.clone(),
+    )?;
+
+    let config =
+        validation::put_validation_config(&state, organisation, application, req.into_inner())
+            .await?;
+    Ok(Json(config))
 }
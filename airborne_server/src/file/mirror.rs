@@ -0,0 +1,215 @@
+//! Server-side ingest of externally hosted files.
+//!
+//! A mirrored file is inserted with an empty URL and zero size, which reads as
+//! `Pending`. A background task downloads the source, verifies it against the
+//! caller's size/checksum (when given), stores it under the application's
+//! bucket path and fills in URL, size, checksum and precompressed variants,
+//! turning it `Ready`. When any step fails the file keeps its empty URL and
+//! gets a `failure_reason`, so it reads as `Failed` and cannot be added to
+//! packages.
+//!
+//! The source URL is chosen by the caller and fetched from inside the
+//! deployment, so only public addresses are connected to, redirects are not
+//! followed and the body is capped at `FILE_MIRROR_MAX_BYTES`.
+
+use actix_web::web;
+use diesel::prelude::*;
+use log::{error, info};
+
+use crate::{
+    file::utils::{
+        calculate_checksum, create_s3_file_path, is_precompressible, store_precompressed_variants,
+        VariantCompressor,
+    },
+    run_blocking, types as airborne_types,
+    types::{ABError, AppState},
+    utils::{
+        db::{models::FileEntry, schema::hyperotaserver::files::dsl},
+        s3::push_file_byte_arr,
+    },
+};
+
+/// Where to fetch a mirrored file from and what it is expected to contain.
+pub struct MirrorSource {
+    pub url: String,
+    pub size: Option<u64>,
+    pub checksum: Option<String>,
+}
+
+pub fn spawn_mirror(state: web::Data<AppState>, file: FileEntry, source: MirrorSource) {
+    tokio::spawn(async move {
+        let file_id = file.id;
+        let result = mirror_file(&state, &file, &source).await;
+        let failure_reason = match &result {
+            Ok(()) => {
+                info!("Mirrored {} into file {}", source.url, file_id);
+                None
+            }
+            Err(e) => {
+                error!(
+                    "Failed to mirror {} into file {}: {}",
+                    source.url, file_id, e
+                );
+                Some(e.to_string())
+            }
+        };
+
+        if let Some(reason) = failure_reason {
+            let pool = state.db_pool.clone();
+            let _ = run_blocking!({
+                let mut conn = pool.get()?;
+                diesel::update(dsl::files.find(file_id))
+                    .set(dsl::failure_reason.eq(reason))
+                    .execute(&mut conn)?;
+                Ok(())
+            });
+        }
+
+        invalidate_cached_entry(&state, &file).await;
+    });
+}
+
+async fn mirror_file(
+    state: &AppState,
+    file: &FileEntry,
+    source: &MirrorSource,
+) -> airborne_types::Result<()> {
+    let mut compressor = is_precompressible(&file.file_path).then(VariantCompressor::start);
+    let content = download_source(
+        &source.url,
+        state.env.file_mirror_max_bytes,
+        compressor.as_mut(),
+    )
+    .await?;
+    let content_size = content.len() as u64;
+    if let Some(expected) = source.size {
+        if expected != content_size {
+            return Err(ABError::BadRequest(format!(
+                "Size mismatch: expected {} bytes, downloaded {}",
+                expected, content_size
+            )));
+        }
+    }
+
+    let content_checksum = calculate_checksum(content.clone()).await;
+    if let Some(expected) = &source.checksum {
+        if !expected.eq_ignore_ascii_case(&content_checksum) {
+            return Err(ABError::BadRequest(format!(
+                "Checksum mismatch: expected {}, downloaded {}",
+                expected, content_checksum
+            )));
+        }
+    }
+
+    let s3_path = create_s3_file_path(
+        &file.org_id,
+        &file.app_id,
+        &file.id.to_string(),
+        &file.version.to_string(),
+        &file.file_path,
+    );
+    push_file_byte_arr(
+        &state.s3_client,
+        state.env.bucket_name.clone(),
+        content,
+        s3_path.clone(),
+    )
+    .await?;
+
+    let file_variants = match compressor {
+        Some(compressor) => store_precompressed_variants(state, &s3_path, compressor).await,
+        None => Vec::new(),
+    };
+    let variants_json = serde_json::to_value(&file_variants)
+        .unwrap_or_else(|_| serde_json::Value::Array(Vec::new()));
+
+    let pool = state.db_pool.clone();
+    let file_id = file.id;
+    let file_url = format!("{}/{}", state.env.public_url, s3_path);
+    run_blocking!({
+        let mut conn = pool.get()?;
+        diesel::update(dsl::files.find(file_id))
+            .set((
+                dsl::url.eq(file_url),
+                dsl::size.eq(content_size as i64),
+                dsl::checksum.eq(content_checksum),
+                dsl::variants.eq(variants_json),
+                dsl::failure_reason.eq(None::<String>),
+            ))
+            .execute(&mut conn)?;
+        Ok(())
+    })
+}
+
+/// Downloads `url`, feeding each chunk to `compressor` as it arrives.
+async fn download_source(
+    url: &str,
+    max_bytes: u64,
+    mut compressor: Option<&mut VariantCompressor>,
+) -> airborne_types::Result<Vec<u8>> {
+    airborne_egress::check(url)
+        .await
+        .map_err(|reason| ABError::BadRequest(format!("Cannot mirror {}: {}", url, reason)))?;
+    let client = airborne_egress::client_builder().build().map_err(|e| {
+        ABError::InternalServerError(format!("Failed to build download client: {}", e))
+    })?;
+    let response = client
+        .get(url)
+        .header("User-Agent", "Airborne-Rust/1.0")
+        .send()
+        .await
+        .map_err(|e| {
+            ABError::InternalServerError(format!("Failed to download file from {}: {}", url, e))
+        })?;
+    if response.status().is_redirection() {
+        return Err(ABError::BadRequest(format!(
+            "{} answered with a redirect, which is not followed",
+            url
+        )));
+    }
+    let mut response = response.error_for_status().map_err(|e| {
+        ABError::InternalServerError(format!(
+            "Received error status while downloading {}: {}",
+            url, e
+        ))
+    })?;
+
+    let too_large = || {
+        ABError::BadRequest(format!(
+            "{} is larger than the {} byte limit for mirrored files",
+            url, max_bytes
+        ))
+    };
+    if response.content_length().is_some_and(|len| len > max_bytes) {
+        return Err(too_large());
+    }
+    let mut content = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| {
+        ABError::InternalServerError(format!("Failed to download file from {}: {}", url, e))
+    })? {
+        if (content.len() + chunk.len()) as u64 > max_bytes {
+            return Err(too_large());
+        }
+        content.extend_from_slice(&chunk);
+        if let Some(compressor) = compressor.as_deref_mut() {
+            compressor.push(chunk).await;
+        }
+    }
+    Ok(content)
+}
+
+/// Drops cached copies of the pending entry so readers pick up the outcome.
+async fn invalidate_cached_entry(state: &AppState, file: &FileEntry) {
+    let Some(cache) = &state.redis_cache else {
+        return;
+    };
+
+    let mut keys = vec![format!("{}@version:{}", file.file_path, file.version)];
+    if let Some(file_tag) = &file.tag {
+        keys.push(format!("{}@tag:{}", file.file_path, file_tag));
+    }
+    for key in keys {
+        let cache_key = cache.key(&file.org_id, &file.app_id, &["file_entry", &key]);
+        let _ = cache.del(&cache_key).await;
+    }
+}
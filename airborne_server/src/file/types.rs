@@ -17,6 +17,10 @@ pub struct FileRequest {
     pub metadata: Option<Value>,
     pub size: Option<u64>,
     pub checksum: Option<String>,
+    /// Fetch `url` server-side and serve the file from managed storage
+    /// instead of the caller's host. `size`/`checksum`, when given, are
+    /// verified against the downloaded content.
+    pub mirror: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -30,10 +34,20 @@ pub struct UpdateFileRequest {
     pub tag: String,
 }
 
+/// A precompressed copy of a file, stored next to the original object.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileVariant {
+    pub encoding: String,
+    pub url: String,
+    pub size: i64,
+    pub checksum: String,
+}
+
 #[derive(Serialize, Deserialize)]
 pub enum FileStatus {
     Pending,
     Ready,
+    Failed,
 }
 
 impl Display for FileStatus {
@@ -41,6 +55,7 @@ impl Display for FileStatus {
         match self {
             FileStatus::Pending => write!(f, "pending"),
             FileStatus::Ready => write!(f, "ready"),
+            FileStatus::Failed => write!(f, "failed"),
         }
     }
 }
@@ -56,7 +71,11 @@ pub struct FileResponse {
     pub checksum: String,
     pub metadata: Value,
     pub status: FileStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure_reason: Option<String>,
     pub created_at: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<FileVariant>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -97,6 +116,7 @@ pub struct GetFileQuery {
 pub struct UploadFileQuery {
     pub file_path: String,
     pub tag: Option<String>,
+    pub precompress: Option<bool>,
 }
 
 #[derive(MultipartForm)]
@@ -117,6 +137,38 @@ pub struct UploadBulkMapping {
 pub struct BulkFileUploadResponse {
     pub uploaded: Vec<FileResponse>,
     pub skipped: Vec<String>,
+    pub rejected: Vec<FileValidationFailure>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FileValidationFailure {
+    pub file_path: String,
+    pub version: i32,
+    pub errors: Vec<String>,
+}
+
+/// Validators run against every upload of an application before the file is
+/// stored. Everything is off by default.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct UploadValidationConfig {
+    /// Lower-case extensions without the dot, e.g. `["js", "json", "png"]`.
+    pub allowed_extensions: Option<Vec<String>>,
+    pub max_file_bytes: Option<i64>,
+    /// A ban on source maps. JS bundles also get a structural check whose
+    /// findings are logged as warnings only.
+    pub js_sanity: bool,
+    pub json_schemas: Vec<JsonSchemaRule>,
+    /// Run the server's `UPLOAD_VALIDATION_HOOK` against each file, in a
+    /// sandbox.
+    pub external_hook: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JsonSchemaRule {
+    /// File path pattern; `*` matches any run of characters.
+    pub path_pattern: String,
+    pub schema: Value,
 }
 
 pub type ReadResult = Result<Bytes, PayloadError>;
@@ -1,9 +1,15 @@
+use std::io::Write;
+
 use base64::{engine::general_purpose, Engine as _};
+use bytes::Bytes;
 use log::info;
 use sha2::{Digest, Sha256 as checksum_algorithm};
+use tokio::{sync::mpsc, task::JoinHandle};
 
+use crate::file::types::FileVariant;
 use crate::types as airborne_types;
-use crate::types::ABError;
+use crate::types::{ABError, AppState};
+use crate::utils::{db::models::FileEntry, s3::push_file_byte_arr};
 
 pub async fn download_and_checksum(file_url: &str) -> airborne_types::Result<(u64, String)> {
     let bytes = download_and_calculate_filesize(file_url, &None).await?;
@@ -88,3 +94,204 @@ pub fn base64_to_hex(value: &str) -> String {
         Err(_) => String::new(), // return empty string on invalid base64
     }
 }
+
+const PRECOMPRESSIBLE_EXTENSIONS: &[&str] = &[
+    "js", "mjs", "cjs", "jsbundle", "bundle", "json", "map", "css", "html", "htm", "txt", "svg",
+    "xml",
+];
+
+/// Whether a file is a text asset worth storing precompressed variants for.
+pub fn is_precompressible(file_path: &str) -> bool {
+    std::path::Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            PRECOMPRESSIBLE_EXTENSIONS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(ext))
+        })
+}
+
+pub fn file_variants(entry: &FileEntry) -> Vec<FileVariant> {
+    serde_json::from_value(entry.variants.clone()).unwrap_or_default()
+}
+
+// Chunks waiting for the compressor; the producer waits when it falls behind
+const COMPRESSOR_QUEUE: usize = 16;
+
+/// Compressed copies of one file, with the encoding and object suffix of each.
+type CompressedVariants = Vec<(&'static str, &'static str, Vec<u8>)>;
+
+/// Compresses a file with brotli and zstd as its chunks arrive, on a blocking
+/// thread, so only the compressed output is held in memory.
+pub struct VariantCompressor {
+    chunks: mpsc::Sender<Bytes>,
+    worker: JoinHandle<std::io::Result<CompressedVariants>>,
+    original_size: usize,
+}
+
+impl VariantCompressor {
+    pub fn start() -> Self {
+        let (chunks, mut receiver) = mpsc::channel::<Bytes>(COMPRESSOR_QUEUE);
+        let worker = tokio::task::spawn_blocking(move || {
+            let params = brotli::enc::BrotliEncoderParams {
+                quality: 11,
+                lgwin: 22,
+                ..Default::default()
+            };
+            let mut br = brotli::CompressorWriter::with_params(Vec::new(), 64 * 1024, &params);
+            let mut zst = zstd::stream::write::Encoder::new(Vec::new(), 19)?;
+            while let Some(chunk) = receiver.blocking_recv() {
+                br.write_all(&chunk)?;
+                zst.write_all(&chunk)?;
+            }
+            br.flush()?;
+            Ok(vec![
+                ("br", "br", br.into_inner()),
+                ("zstd", "zst", zst.finish()?),
+            ])
+        });
+        Self {
+            chunks,
+            worker,
+            original_size: 0,
+        }
+    }
+
+    pub async fn push(&mut self, chunk: Bytes) {
+        self.original_size += chunk.len();
+        // A failed worker drops its receiver; `finish` reports why.
+        let _ = self.chunks.send(chunk).await;
+    }
+
+    async fn finish(self) -> airborne_types::Result<(usize, CompressedVariants)> {
+        drop(self.chunks);
+        let compressed = self
+            .worker
+            .await
+            .map_err(|e| ABError::InternalServerError(format!("Compression task failed: {}", e)))?
+            .map_err(|e| ABError::InternalServerError(format!("Failed to compress file: {}", e)))?;
+        Ok((self.original_size, compressed))
+    }
+}
+
+/// Uploads what `compressor` produced next to the original object as
+/// `<s3_path>.br` / `<s3_path>.zst`. Variants that are not smaller than the
+/// original are dropped. Variants are an optimisation, so failures are logged
+/// and leave the file without them.
+pub async fn store_precompressed_variants(
+    state: &AppState,
+    s3_path: &str,
+    compressor: VariantCompressor,
+) -> Vec<FileVariant> {
+    upload_variants(state, s3_path, compressor)
+        .await
+        .unwrap_or_else(|e| {
+            info!("Skipping precompressed variants for {}: {:?}", s3_path, e);
+            Vec::new()
+        })
+}
+
+/// Precompressed variants for content that is already in memory, when
+/// `file_path` is a text asset.
+pub async fn precompress_content(
+    state: &AppState,
+    s3_path: &str,
+    file_path: &str,
+    content: Bytes,
+) -> Vec<FileVariant> {
+    if !is_precompressible(file_path) {
+        return Vec::new();
+    }
+    let mut compressor = VariantCompressor::start();
+    compressor.push(content).await;
+    store_precompressed_variants(state, s3_path, compressor).await
+}
+
+async fn upload_variants(
+    state: &AppState,
+    s3_path: &str,
+    compressor: VariantCompressor,
+) -> airborne_types::Result<Vec<FileVariant>> {
+    let (original_size, compressed) = compressor.finish().await?;
+    let mut variants = Vec::new();
+    for (encoding, suffix, bytes) in compressed {
+        if bytes.len() >= original_size {
+            continue;
+        }
+        let variant_path = format!("{}.{}", s3_path, suffix);
+        let size = bytes.len() as i64;
+        let checksum = calculate_checksum(bytes.clone()).await;
+        push_file_byte_arr(
+            &state.s3_client,
+            state.env.bucket_name.clone(),
+            bytes,
+            variant_path.clone(),
+        )
+        .await?;
+        variants.push(FileVariant {
+            encoding: encoding.to_string(),
+            url: format!("{}/{}", state.env.public_url, variant_path),
+            size,
+            checksum,
+        });
+    }
+
+    Ok(variants)
+}
+
+/// Rejects files that cannot be served: ones whose ingest failed and ones
+/// that are still waiting for their content to land in storage.
+pub fn ensure_files_usable<'a>(
+    entries: impl IntoIterator<Item = &'a FileEntry>,
+) -> airborne_types::Result<()> {
+    let unusable = entries
+        .into_iter()
+        .filter_map(|entry| {
+            let key = format!("{}@version:{}", entry.file_path, entry.version);
+            match (&entry.failure_reason, entry.url.is_empty()) {
+                (Some(reason), _) => Some(format!("'{}' failed ingest: {}", key, reason)),
+                (None, true) => Some(format!("'{}' is still pending", key)),
+                (None, false) => None,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if unusable.is_empty() {
+        Ok(())
+    } else {
+        Err(ABError::BadRequest(format!(
+            "Files are not ready to be used: {}",
+            unusable.join("; ")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn compresses_chunks_as_they_arrive() {
+        let line = "console.log('airborne');\n".repeat(1000);
+        let mut compressor = VariantCompressor::start();
+        for chunk in line.as_bytes().chunks(1000) {
+            compressor.push(Bytes::copy_from_slice(chunk)).await;
+        }
+
+        let (original_size, compressed) = compressor.finish().await.unwrap();
+        assert_eq!(original_size, line.len());
+        for (encoding, _, bytes) in compressed {
+            assert!(bytes.len() < original_size, "{} did not shrink", encoding);
+            let decoded = match encoding {
+                "br" => {
+                    let mut out = Vec::new();
+                    brotli::BrotliDecompress(&mut bytes.as_slice(), &mut out).unwrap();
+                    out
+                }
+                _ => zstd::decode_all(bytes.as_slice()).unwrap(),
+            };
+            assert_eq!(decoded, line.as_bytes());
+        }
+    }
+}
@@ -0,0 +1,598 @@
+//! Per-application upload validation.
+//!
+//! Validators are configured per application and run against the full body of
+//! an upload before it is written to storage, so a rejected file never becomes
+//! visible. Every validator reports its own errors; the upload is rejected when
+//! any of them complains.
+
+#[cfg(target_os = "linux")]
+mod sandbox;
+
+use std::{path::Path, process::Stdio, time::Duration};
+
+use diesel::prelude::*;
+use log::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    file::types::UploadValidationConfig,
+    run_blocking, types as airborne_types,
+    types::{ABError, AppState},
+    utils::db::{
+        models::UploadValidationConfigEntry,
+        schema::hyperotaserver::upload_validation_configs::dsl, DbPool,
+    },
+};
+
+const JS_EXTENSIONS: &[&str] = &["js", "mjs", "cjs", "jsbundle", "bundle"];
+const HERMES_BYTECODE_MAGIC: [u8; 8] = [0xc6, 0x1f, 0xbc, 0x03, 0xc1, 0x03, 0x19, 0x1f];
+const MAX_SCHEMA_ERRORS: usize = 10;
+const MAX_HOOK_OUTPUT: usize = 2000;
+// Keywords after which a '/' starts a regular expression, not a division
+const EXPRESSION_KEYWORDS: &[&str] = &[
+    "return",
+    "typeof",
+    "case",
+    "do",
+    "else",
+    "in",
+    "instanceof",
+    "new",
+    "delete",
+    "void",
+    "throw",
+    "yield",
+    "await",
+    "of",
+];
+
+impl UploadValidationConfig {
+    pub fn is_active(&self) -> bool {
+        self.allowed_extensions.is_some()
+            || self.max_file_bytes.is_some()
+            || self.js_sanity
+            || !self.json_schemas.is_empty()
+            || self.external_hook
+    }
+}
+
+pub async fn get_validation_config(
+    pool: DbPool,
+    organisation: String,
+    application: String,
+) -> airborne_types::Result<UploadValidationConfig> {
+    let entry = run_blocking!({
+        let mut conn = pool.get()?;
+        let entry = dsl::upload_validation_configs
+            .filter(dsl::org_id.eq(&organisation))
+            .filter(dsl::app_id.eq(&application))
+            .select(UploadValidationConfigEntry::as_select())
+            .first::<UploadValidationConfigEntry>(&mut conn)
+            .optional()?;
+        Ok(entry)
+    })?;
+
+    match entry {
+        Some(entry) => serde_json::from_value(entry.config).map_err(|e| {
+            ABError::InternalServerError(format!("Invalid upload validation config: {}", e))
+        }),
+        None => Ok(UploadValidationConfig::default()),
+    }
+}
+
+pub async fn put_validation_config(
+    state: &AppState,
+    organisation: String,
+    application: String,
+    config: UploadValidationConfig,
+) -> airborne_types::Result<UploadValidationConfig> {
+    if config.max_file_bytes.is_some_and(|limit| limit < 0) {
+        return Err(ABError::BadRequest(
+            "max_file_bytes cannot be negative".to_string(),
+        ));
+    }
+    if config.external_hook && state.env.upload_validation_hook.is_none() {
+        return Err(ABError::BadRequest(
+            "No upload validation hook is configured on this server".to_string(),
+        ));
+    }
+    for rule in &config.json_schemas {
+        jsonschema::JSONSchema::compile(&rule.schema).map_err(|e| {
+            ABError::BadRequest(format!(
+                "Invalid JSON schema for '{}': {}",
+                rule.path_pattern, e
+            ))
+        })?;
+    }
+
+    let config_json = serde_json::to_value(&config)
+        .map_err(|e| ABError::InternalServerError(format!("Failed to encode config: {}", e)))?;
+    let pool = state.db_pool.clone();
+    run_blocking!({
+        let mut conn = pool.get()?;
+        let entry = UploadValidationConfigEntry {
+            org_id: organisation,
+            app_id: application,
+            config: config_json,
+            updated_at: chrono::Utc::now(),
+        };
+        diesel::insert_into(dsl::upload_validation_configs)
+            .values(&entry)
+            .on_conflict((dsl::org_id, dsl::app_id))
+            .do_update()
+            .set(&entry)
+            .execute(&mut conn)?;
+        Ok(())
+    })?;
+
+    Ok(config)
+}
+
+/// Runs every configured validator against `content` and returns the
+/// collected errors. An empty list means the file is accepted.
+pub async fn validate_upload(
+    state: &AppState,
+    config: &UploadValidationConfig,
+    file_path: &str,
+    content: &[u8],
+) -> Vec<String> {
+    let mut errors = Vec::new();
+    let extension = Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    if let Some(allowed) = &config.allowed_extensions {
+        if !allowed.iter().any(|a| a.eq_ignore_ascii_case(&extension)) {
+            errors.push(format!(
+                "extension '{}' is not allowed (allowed: {})",
+                extension,
+                allowed.join(", ")
+            ));
+        }
+    }
+
+    if let Some(limit) = config.max_file_bytes {
+        if content.len() as i64 > limit {
+            errors.push(format!("file is {} bytes (limit {})", content.len(), limit));
+        }
+    }
+
+    if config.js_sanity {
+        if extension == "map" {
+            errors.push("source maps must not be uploaded".to_string());
+        } else if JS_EXTENSIONS.contains(&extension.as_str()) {
+            errors.extend(check_js_bundle(file_path, content));
+        }
+    }
+
+    for rule in &config.json_schemas {
+        if matches_pattern(&rule.path_pattern, file_path) {
+            errors.extend(check_json_schema(&rule.schema, content));
+        }
+    }
+
+    if config.external_hook {
+        if let Err(e) = run_external_hook(state, file_path, content).await {
+            errors.push(e);
+        }
+    }
+
+    errors
+}
+
+/// Matches `value` against a pattern where `*` stands for any run of
+/// characters, including `/`.
+pub fn matches_pattern(pattern: &str, value: &str) -> bool {
+    let parts = pattern.split('*').collect::<Vec<_>>();
+    if parts.len() == 1 {
+        return pattern == value;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !value.starts_with(first) || !value.ends_with(last) || value.len() < first.len() + last.len()
+    {
+        return false;
+    }
+    let mut rest = &value[first.len()..value.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+fn check_json_schema(schema: &serde_json::Value, content: &[u8]) -> Vec<String> {
+    let instance: serde_json::Value = match serde_json::from_slice(content) {
+        Ok(instance) => instance,
+        Err(e) => return vec![format!("invalid JSON: {}", e)],
+    };
+    let compiled = match jsonschema::JSONSchema::compile(schema) {
+        Ok(compiled) => compiled,
+        Err(e) => return vec![format!("configured JSON schema is invalid: {}", e)],
+    };
+
+    let violations = match compiled.validate(&instance) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors
+            .take(MAX_SCHEMA_ERRORS)
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                if path.is_empty() {
+                    format!("schema violation: {}", e)
+                } else {
+                    format!("schema violation at {}: {}", path, e)
+                }
+            })
+            .collect(),
+    };
+    violations
+}
+
+fn check_js_bundle(file_path: &str, content: &[u8]) -> Vec<String> {
+    if content.starts_with(&HERMES_BYTECODE_MAGIC) {
+        // Precompiled Hermes bytecode has no source to inspect.
+        return Vec::new();
+    }
+    let Ok(source) = std::str::from_utf8(content) else {
+        return vec!["JS bundle is not valid UTF-8".to_string()];
+    };
+
+    let mut errors = Vec::new();
+    // The structural check is a heuristic that can misread valid code, so
+    // its findings are only logged and never reject the upload
+    if let Err(e) = check_js_structure(source) {
+        warn!("JS bundle {} may not be parseable: {}", file_path, e);
+    }
+    if source.contains("sourceMappingURL=") {
+        errors.push("JS bundle references a source map (sourceMappingURL)".to_string());
+    }
+    errors
+}
+
+fn scan_quoted(chars: &[char], i: &mut usize, quote: char, line: usize) -> Result<(), String> {
+    *i += 1;
+    while *i < chars.len() {
+        match chars[*i] {
+            '\\' => *i += 1,
+            '\n' => return Err(format!("unterminated string on line {}", line)),
+            c if c == quote => return Ok(()),
+            _ => {}
+        }
+        *i += 1;
+    }
+    Err(format!("unterminated string on line {}", line))
+}
+
+/// Scans template literal text starting right after a backtick or a closing
+/// `}` of a substitution. Returns `true` when it stopped at a `${`.
+fn scan_template(chars: &[char], i: &mut usize, line: &mut usize) -> Result<bool, String> {
+    let start_line = *line;
+    while *i < chars.len() {
+        match chars[*i] {
+            '\\' => *i += 1,
+            '\n' => *line += 1,
+            '`' => return Ok(false),
+            '$' if chars.get(*i + 1) == Some(&'{') => {
+                *i += 1;
+                return Ok(true);
+            }
+            _ => {}
+        }
+        *i += 1;
+    }
+    Err(format!(
+        "unterminated template literal on line {}",
+        start_line
+    ))
+}
+
+/// Whether `end` follows a postfix `++` or `--`, after which a `/` divides.
+fn follows_postfix_operator(chars: &[char], end: usize) -> bool {
+    let mut end = end;
+    while end > 0 && chars[end - 1].is_whitespace() {
+        end -= 1;
+    }
+    end >= 2 && "+-".contains(chars[end - 1]) && chars[end - 2] == chars[end - 1]
+}
+
+/// Whether the identifier ending just before `end` is a keyword that
+/// expects an expression, and not a property such as `x.return`.
+fn follows_expression_keyword(chars: &[char], end: usize) -> bool {
+    let mut end = end;
+    while end > 0 && chars[end - 1].is_whitespace() {
+        end -= 1;
+    }
+    let mut start = end;
+    while start > 0 && (chars[start - 1].is_alphanumeric() || "_$".contains(chars[start - 1])) {
+        start -= 1;
+    }
+    let word = chars[start..end].iter().collect::<String>();
+    EXPRESSION_KEYWORDS.contains(&word.as_str()) && (start == 0 || chars[start - 1] != '.')
+}
+
+/// A lexical sanity check for JavaScript: strings, comments, template
+/// literals and regular expressions must terminate and brackets must balance.
+/// It does not build a syntax tree, so it can misjudge where a regular
+/// expression starts; callers treat its result as a hint, not a verdict.
+pub fn check_js_structure(source: &str) -> Result<(), String> {
+    let chars = source.chars().collect::<Vec<_>>();
+    // '$' marks an open template substitution.
+    let mut stack: Vec<(char, usize)> = Vec::new();
+    let mut last_significant: Option<char> = None;
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\n' => line += 1,
+            '/' if chars.get(i + 1) == Some(&'/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                let start_line = line;
+                i += 2;
+                loop {
+                    match chars.get(i) {
+                        None => {
+                            return Err(format!(
+                                "unterminated block comment on line {}",
+                                start_line
+                            ))
+                        }
+                        Some('*') if chars.get(i + 1) == Some(&'/') => break,
+                        Some('\n') => line += 1,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                i += 2;
+                continue;
+            }
+            '/' if (last_significant.is_none_or(|p| "(,=:[!&|?{};+-*%<>~^".contains(p))
+                && !follows_postfix_operator(&chars, i))
+                || follows_expression_keyword(&chars, i) =>
+            {
+                let mut in_class = false;
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None | Some('\n') => {
+                            return Err(format!("unterminated regular expression on line {}", line))
+                        }
+                        Some('\\') => i += 1,
+                        Some('[') => in_class = true,
+                        Some(']') => in_class = false,
+                        Some('/') if !in_class => break,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+            }
+            '"' | '\'' => scan_quoted(&chars, &mut i, c, line)?,
+            '`' => {
+                i += 1;
+                if scan_template(&chars, &mut i, &mut line)? {
+                    stack.push(('$', line));
+                }
+            }
+            '(' | '[' | '{' => stack.push((c, line)),
+            ')' | ']' | '}' => {
+                let expected = match c {
+                    ')' => '(',
+                    ']' => '[',
+                    _ => '{',
+                };
+                match stack.pop() {
+                    Some(('$', _)) if c == '}' => {
+                        i += 1;
+                        if scan_template(&chars, &mut i, &mut line)? {
+                            stack.push(('$', line));
+                        }
+                    }
+                    Some((open, _)) if open == expected => {}
+                    Some((open, open_line)) => {
+                        return Err(format!(
+                            "'{}' on line {} does not match '{}' opened on line {}",
+                            c, line, open, open_line
+                        ))
+                    }
+                    None => return Err(format!("unexpected '{}' on line {}", c, line)),
+                }
+            }
+            _ => {}
+        }
+        if !c.is_whitespace() {
+            last_significant = Some(chars[i.min(chars.len() - 1)]);
+        }
+        i += 1;
+    }
+
+    match stack.pop() {
+        Some(('$', open_line)) => Err(format!(
+            "unterminated template literal on line {}",
+            open_line
+        )),
+        Some((open, open_line)) => Err(format!(
+            "'{}' opened on line {} is never closed",
+            open, open_line
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Runs `UPLOAD_VALIDATION_HOOK` on a copy of the file, with an empty
+/// environment and the copy's directory as working directory, inside the
+/// sandbox described in [`sandbox`]. The hook is never run unconfined: on
+/// other platforms than Linux it is refused.
+async fn run_external_hook(
+    state: &AppState,
+    file_path: &str,
+    content: &[u8],
+) -> Result<(), String> {
+    let Some(hook) = &state.env.upload_validation_hook else {
+        return Err("no upload validation hook is configured on this server".to_string());
+    };
+
+    let scratch = std::env::temp_dir().join(format!("upload-validation-{}", Uuid::new_v4()));
+    let file_name = Path::new(file_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("upload");
+    let target = scratch.join(file_name);
+
+    let result = async {
+        tokio::fs::create_dir_all(&scratch)
+            .await
+            .map_err(|e| format!("failed to prepare validation hook: {}", e))?;
+        tokio::fs::write(&target, content)
+            .await
+            .map_err(|e| format!("failed to prepare validation hook: {}", e))?;
+
+        let timeout = Duration::from_secs(state.env.upload_validation_hook_timeout_secs);
+        let mut command = tokio::process::Command::new(hook);
+        command
+            .arg(&target)
+            .env_clear()
+            .current_dir(&scratch)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        confine_hook(state, &mut command, &scratch, &target, timeout)
+            .map_err(|e| format!("failed to sandbox validation hook: {}", e))?;
+        let child = command
+            .spawn()
+            .map_err(|e| format!("failed to start validation hook: {}", e))?;
+
+        let output = tokio::time::timeout(timeout, child.wait_with_output())
+            .await
+            .map_err(|_| format!("validation hook timed out after {}s", timeout.as_secs()))?
+            .map_err(|e| format!("validation hook failed: {}", e))?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stderr = stderr.trim();
+            let stderr = match stderr.char_indices().nth(MAX_HOOK_OUTPUT) {
+                Some((idx, _)) => &stderr[..idx],
+                None => stderr,
+            };
+            info!("Validation hook rejected {}: {}", file_path, stderr);
+            Err(format!(
+                "validation hook rejected the file ({}): {}",
+                output.status, stderr
+            ))
+        }
+    }
+    .await;
+
+    let _ = tokio::fs::remove_dir_all(&scratch).await;
+    result
+}
+
+#[cfg(target_os = "linux")]
+fn confine_hook(
+    state: &AppState,
+    command: &mut tokio::process::Command,
+    scratch: &Path,
+    target: &Path,
+    timeout: Duration,
+) -> std::io::Result<()> {
+    let sandbox = sandbox::HookSandbox {
+        uid: state.env.upload_validation_hook_uid,
+        gid: state.env.upload_validation_hook_gid,
+        cpu_secs: timeout.as_secs().max(1),
+        max_memory_bytes: state.env.upload_validation_hook_max_memory_mb * 1024 * 1024,
+    };
+    sandbox.prepare_scratch(scratch, target)?;
+    sandbox.apply(command)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn confine_hook(
+    _state: &AppState,
+    _command: &mut tokio::process::Command,
+    _scratch: &Path,
+    _target: &Path,
+    _timeout: Duration,
+) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "the hook sandbox is only available on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_well_formed_javascript() {
+        let source = r#"
+            // a comment with an unmatched ( paren
+            const re = /[)}\]]+/g;
+            const s = "a string with } inside";
+            const t = `template ${ { a: [1, 2] }.a } with ${`nested ${s}`} parts`;
+            /* block ] comment */
+            function f(x) { return x / 2 + (t.length / 4); }
+        "#;
+        assert_eq!(check_js_structure(source), Ok(()));
+    }
+
+    #[test]
+    fn regex_after_keyword_is_not_a_division() {
+        assert_eq!(
+            check_js_structure(r#"function q(s) { return /"/.test(s); }"#),
+            Ok(())
+        );
+        assert_eq!(
+            check_js_structure("if (typeof /'/ === 'object') {}"),
+            Ok(())
+        );
+        assert_eq!(
+            check_js_structure("switch (x) { case /[}]/.source: break; }"),
+            Ok(())
+        );
+        assert_eq!(check_js_structure("const r = a.return / 2 / (b);"), Ok(()));
+        assert_eq!(check_js_structure("const returned = n / 2; f('/')"), Ok(()));
+        assert_eq!(check_js_structure("x = i++ / 2; y = '/';"), Ok(()));
+        assert_eq!(check_js_structure("m = a-- / n; s = \"/\";"), Ok(()));
+    }
+
+    #[test]
+    fn structure_findings_do_not_reject_bundles() {
+        assert!(check_js_bundle("index.js", b"function f() { return [1, 2").is_empty());
+        assert_eq!(
+            check_js_bundle("index.js", b"f();\n//# sourceMappingURL=index.js.map").len(),
+            1
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_javascript() {
+        assert!(check_js_structure("function f() { return [1, 2").is_err());
+        assert!(check_js_structure("const s = 'unterminated;\n").is_err());
+        assert!(check_js_structure("const t = `open ${x").is_err());
+        assert!(check_js_structure("/* never closed").is_err());
+        assert!(check_js_structure("f(a]").is_err());
+    }
+
+    #[test]
+    fn matches_wildcard_patterns() {
+        assert!(matches_pattern("config/*.json", "config/app.json"));
+        assert!(matches_pattern("*", "anything/at/all"));
+        assert!(matches_pattern("*/settings.json", "a/b/settings.json"));
+        assert!(matches_pattern("theme.json", "theme.json"));
+        assert!(!matches_pattern("config/*.json", "config/app.js"));
+        assert!(!matches_pattern("a*a", "a"));
+    }
+}
@@ -0,0 +1,179 @@
+//! Confinement for `UPLOAD_VALIDATION_HOOK`.
+//!
+//! The hook runs in its own mount and network namespaces, with every mount
+//! remounted read-only and no network interface it could use, under resource
+//! limits, as an unprivileged user that cannot regain privileges. Setting this
+//! up needs root (or `CAP_SYS_ADMIN` and `CAP_SETUID`); without it the hook
+//! fails to start and files that require it are rejected.
+
+use std::{
+    ffi::CString,
+    fs::Permissions,
+    io,
+    os::unix::fs::{chown, PermissionsExt},
+    path::Path,
+    ptr::null,
+};
+
+const MAX_OPEN_FILES: libc::rlim_t = 64;
+const MAX_PROCESSES: libc::rlim_t = 32;
+
+pub struct HookSandbox {
+    pub uid: u32,
+    pub gid: u32,
+    pub cpu_secs: u64,
+    pub max_memory_bytes: u64,
+}
+
+impl HookSandbox {
+    /// Hands the scratch directory and the file in it to the hook's user,
+    /// and hides them from everyone else.
+    pub fn prepare_scratch(&self, scratch: &Path, target: &Path) -> io::Result<()> {
+        chown(scratch, Some(self.uid), Some(self.gid))?;
+        chown(target, Some(self.uid), Some(self.gid))?;
+        std::fs::set_permissions(target, Permissions::from_mode(0o400))?;
+        std::fs::set_permissions(scratch, Permissions::from_mode(0o500))
+    }
+
+    /// Makes `command` enter the sandbox before it executes. Mount points are
+    /// looked up here, as the forked child may only make plain system calls.
+    pub fn apply(&self, command: &mut tokio::process::Command) -> io::Result<()> {
+        let mount_points = mount_points()?;
+        let limits = [
+            (libc::RLIMIT_CPU, self.cpu_secs),
+            (libc::RLIMIT_AS, self.max_memory_bytes),
+            (libc::RLIMIT_FSIZE, 0),
+            (libc::RLIMIT_NOFILE, MAX_OPEN_FILES),
+            (libc::RLIMIT_NPROC, MAX_PROCESSES),
+            (libc::RLIMIT_CORE, 0),
+        ];
+        let (uid, gid) = (self.uid, self.gid);
+
+        // SAFETY: `enter` only makes system calls and does not allocate.
+        unsafe {
+            command.pre_exec(move || enter(&mount_points, &limits, uid, gid));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_env = "gnu")]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(target_env = "gnu"))]
+type Resource = libc::c_int;
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn enter(
+    mount_points: &[CString],
+    limits: &[(Resource, libc::rlim_t)],
+    uid: u32,
+    gid: u32,
+) -> io::Result<()> {
+    // SAFETY: plain system calls on valid, NUL-terminated paths.
+    unsafe {
+        check(libc::unshare(libc::CLONE_NEWNS | libc::CLONE_NEWNET))?;
+        // Keeps the remounts below from reaching the server's namespace
+        check(libc::mount(
+            null(),
+            c"/".as_ptr(),
+            null(),
+            libc::MS_REC | libc::MS_PRIVATE,
+            null(),
+        ))?;
+        for point in mount_points {
+            let mut stat: libc::statvfs = std::mem::zeroed();
+            let mut flags = libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY;
+            if libc::statvfs(point.as_ptr(), &mut stat) == 0 {
+                // A bind remount drops the flags it is not given
+                for (st, ms) in [
+                    (libc::ST_NOSUID, libc::MS_NOSUID),
+                    (libc::ST_NODEV, libc::MS_NODEV),
+                    (libc::ST_NOEXEC, libc::MS_NOEXEC),
+                ] {
+                    if stat.f_flag & st != 0 {
+                        flags |= ms;
+                    }
+                }
+            }
+            let ret = libc::mount(null(), point.as_ptr(), null(), flags, null());
+            // Mounts hidden under another one cannot be reached by path, and
+            // writes to any file are refused by RLIMIT_FSIZE regardless
+            if ret == -1 && point.as_bytes() == b"/" {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        for &(resource, limit) in limits {
+            let limit = libc::rlimit {
+                rlim_cur: limit,
+                rlim_max: limit,
+            };
+            check(libc::setrlimit(resource, &limit))?;
+        }
+
+        check(libc::setgroups(0, null()))?;
+        check(libc::setgid(gid))?;
+        check(libc::setuid(uid))?;
+        check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+    }
+    Ok(())
+}
+
+/// Mount points of the server's namespace, from `/proc/self/mountinfo`.
+fn mount_points() -> io::Result<Vec<CString>> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+    mountinfo
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .map(|field| {
+            CString::new(unescape_mount_path(field))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+        .collect()
+}
+
+/// Undoes the octal escapes (`\040` for a space) mountinfo uses in paths.
+fn unescape_mount_path(field: &str) -> Vec<u8> {
+    let bytes = field.as_bytes();
+    let mut path = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 4)
+            .filter(|digits| bytes[i] == b'\\' && digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+            .map(|digits| digits.iter().fold(0u8, |acc, d| (acc << 3) | (d - b'0')));
+        match escaped {
+            Some(byte) => {
+                path.push(byte);
+                i += 4;
+            }
+            None => {
+                path.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescapes_mountinfo_paths() {
+        assert_eq!(unescape_mount_path("/"), b"/".to_vec());
+        assert_eq!(
+            unescape_mount_path(r"/mnt/my\040disk"),
+            b"/mnt/my disk".to_vec()
+        );
+        assert_eq!(unescape_mount_path(r"/a\\b"), br"/a\\b".to_vec());
+    }
+}
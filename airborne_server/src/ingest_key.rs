@@ -0,0 +1,283 @@
+//! Per-application keys for the analytics ingest endpoint.
+//!
+//! SDKs send the key with every event; the analytics server resolves it to
+//! the organisation and application it belongs to through
+//! `POST /ingest-keys/verify`, which only accepts the shared
+//! `ANALYTICS_INGEST_VERIFY_TOKEN`. Keys are stored hashed. A key may come
+//! with a signing secret for HMAC-signed requests; the secret is stored
+//! encrypted with the key, so it can only be recovered by whoever presents
+//! the key.
+
+pub mod types;
+
+use actix_web::{
+    delete, get,
+    http::header,
+    post,
+    web::{self, Json, Path, ReqData},
+    HttpRequest, Scope,
+};
+use airborne_authz_macros::authz;
+use chrono::Utc;
+use diesel::prelude::*;
+use log::info;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    ingest_key::types::*,
+    middleware::auth::{require_org_and_app, Auth, AuthResponse},
+    run_blocking,
+    token::scope::token_hash,
+    types as airborne_types,
+    types::{ABError, AppState, ListResponse},
+    utils::{
+        db::{models::AnalyticsIngestKeyEntry, schema::hyperotaserver::analytics_ingest_keys},
+        encryption::{decrypt_string, encrypt_string, generate_random_key},
+    },
+};
+
+pub const KEY_PREFIX: &str = "abik_";
+const MAX_RATE_LIMIT_PER_MINUTE: u32 = 1_000_000;
+
+pub fn add_scopes(path: &str) -> Scope {
+    Scope::new(path).service(verify_ingest_key).service(
+        Scope::new("")
+            .wrap(Auth)
+            .service(create_ingest_key)
+            .service(list_ingest_keys)
+            .service(revoke_ingest_key),
+    )
+}
+
+fn key_info(entry: &AnalyticsIngestKeyEntry) -> IngestKeyInfo {
+    IngestKeyInfo {
+        id: entry.id,
+        description: entry.description.clone(),
+        signed: entry.encrypted_signing_secret.is_some(),
+        require_signature: entry.require_signature,
+        rate_limit_per_minute: entry.rate_limit_per_minute.map(|limit| limit as u32),
+        created_by: entry.created_by.clone(),
+        created_at: entry.created_at,
+        last_used_at: entry.last_used_at,
+        revoked_at: entry.revoked_at,
+    }
+}
+
+/// Compares digests rather than the raw strings so the comparison time does
+/// not depend on how much of the token matched.
+fn is_verify_token(state: &AppState, presented: &str) -> bool {
+    state
+        .env
+        .analytics_ingest_verify_token
+        .as_deref()
+        .is_some_and(|expected| {
+            Sha256::digest(expected.as_bytes()) == Sha256::digest(presented.as_bytes())
+        })
+}
+
+#[post("/verify")]
+async fn verify_ingest_key(
+    http_req: HttpRequest,
+    req: Json<VerifyIngestKeyRequest>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<VerifyIngestKeyResponse>> {
+    let presented = http_req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !is_verify_token(state.get_ref(), presented) {
+        return Err(ABError::Unauthorized(
+            "Invalid ingest verification token".to_string(),
+        ));
+    }
+
+    let ingest_key = req.into_inner().ingest_key;
+    let secret = ingest_key
+        .strip_prefix(KEY_PREFIX)
+        .ok_or_else(|| ABError::Unauthorized("Invalid ingest key".to_string()))?
+        .to_string();
+    let key_hash = token_hash(&ingest_key);
+    let pool = state.db_pool.clone();
+    let entry = run_blocking!({
+        let mut conn = pool.get()?;
+        let entry = diesel::update(
+            analytics_ingest_keys::table
+                .filter(analytics_ingest_keys::key_hash.eq(&key_hash))
+                .filter(analytics_ingest_keys::revoked_at.is_null()),
+        )
+        .set(analytics_ingest_keys::last_used_at.eq(Utc::now()))
+        .returning(AnalyticsIngestKeyEntry::as_returning())
+        .get_result::<AnalyticsIngestKeyEntry>(&mut conn)
+        .optional()?
+        .ok_or_else(|| ABError::Unauthorized("Invalid ingest key".to_string()))?;
+        Ok(entry)
+    })?;
+
+    let signing_secret = match &entry.encrypted_signing_secret {
+        Some(encrypted) => Some(decrypt_string(encrypted, &secret).await?),
+        None => None,
+    };
+    Ok(Json(VerifyIngestKeyResponse {
+        key_id: entry.id,
+        organisation: entry.organisation,
+        application: entry.application,
+        signing_secret,
+        require_signature: entry.require_signature,
+        rate_limit_per_minute: entry.rate_limit_per_minute.map(|limit| limit as u32),
+    }))
+}
+
+#[authz(
+    resource = "ingest_key",
+    action = "create",
+    org_roles = ["owner", "admin"],
+    app_roles = ["admin"]
+)]
+#[post("")]
+async fn create_ingest_key(
+    req: Json<CreateIngestKeyRequest>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<CreateIngestKeyResponse>> {
+    let req = req.into_inner();
+    let auth_response = auth_response.into_inner();
+    let (organisation, application) = require_org_and_app(
+        auth_response.organisation.clone(),
+        auth_response.application.clone(),
+    )?;
+    if req
+        .rate_limit_per_minute
+        .is_some_and(|limit| limit == 0 || limit > MAX_RATE_LIMIT_PER_MINUTE)
+    {
+        return Err(ABError::BadRequest(format!(
+            "rate_limit_per_minute must be between 1 and {}",
+            MAX_RATE_LIMIT_PER_MINUTE
+        )));
+    }
+
+    let secret = generate_random_key().await?;
+    let ingest_key = format!("{}{}", KEY_PREFIX, secret);
+    let signing_secret = if req.signed || req.require_signature {
+        Some(generate_random_key().await?)
+    } else {
+        None
+    };
+    let encrypted_signing_secret = match &signing_secret {
+        Some(signing_secret) => Some(encrypt_string(signing_secret, &secret).await?),
+        None => None,
+    };
+
+    let entry = AnalyticsIngestKeyEntry {
+        id: uuid::Uuid::new_v4(),
+        organisation,
+        application,
+        key_hash: token_hash(&ingest_key),
+        encrypted_signing_secret,
+        require_signature: req.require_signature,
+        rate_limit_per_minute: req.rate_limit_per_minute.map(|limit| limit as i32),
+        description: req.description,
+        created_by: auth_response.sub.clone(),
+        created_at: Utc::now(),
+        last_used_at: None,
+        revoked_at: None,
+    };
+    let pool = state.db_pool.clone();
+    let new_entry = entry.clone();
+    run_blocking!({
+        let mut conn = pool.get()?;
+        diesel::insert_into(analytics_ingest_keys::table)
+            .values(&new_entry)
+            .execute(&mut conn)?;
+        Ok(())
+    })?;
+
+    info!(
+        "{} created ingest key {} for {}/{}",
+        auth_response.sub, entry.id, entry.organisation, entry.application
+    );
+    Ok(Json(CreateIngestKeyResponse {
+        info: key_info(&entry),
+        ingest_key,
+        signing_secret,
+    }))
+}
+
+#[authz(
+    resource = "ingest_key",
+    action = "read",
+    org_roles = ["owner", "admin"],
+    app_roles = ["admin"]
+)]
+#[get("/list")]
+async fn list_ingest_keys(
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<ListResponse<Vec<IngestKeyInfo>>>> {
+    let auth_response = auth_response.into_inner();
+    let (organisation, application) = require_org_and_app(
+        auth_response.organisation.clone(),
+        auth_response.application.clone(),
+    )?;
+
+    let pool = state.db_pool.clone();
+    let entries = run_blocking!({
+        let mut conn = pool.get()?;
+        let entries = analytics_ingest_keys::table
+            .filter(analytics_ingest_keys::organisation.eq(&organisation))
+            .filter(analytics_ingest_keys::application.eq(&application))
+            .order(analytics_ingest_keys::created_at.desc())
+            .select(AnalyticsIngestKeyEntry::as_select())
+            .load::<AnalyticsIngestKeyEntry>(&mut conn)?;
+        Ok(entries)
+    })?;
+
+    Ok(Json(ListResponse {
+        data: entries.iter().map(key_info).collect(),
+    }))
+}
+
+#[authz(
+    resource = "ingest_key",
+    action = "delete",
+    org_roles = ["owner", "admin"],
+    app_roles = ["admin"]
+)]
+#[delete("/{id}")]
+async fn revoke_ingest_key(
+    id: Path<uuid::Uuid>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<RevokeIngestKeyResponse>> {
+    let auth_response = auth_response.into_inner();
+    let (organisation, application) = require_org_and_app(
+        auth_response.organisation.clone(),
+        auth_response.application.clone(),
+    )?;
+    let key_id = id.into_inner();
+
+    let pool = state.db_pool.clone();
+    let revoked = run_blocking!({
+        let mut conn = pool.get()?;
+        let revoked = diesel::update(
+            analytics_ingest_keys::table
+                .filter(analytics_ingest_keys::id.eq(key_id))
+                .filter(analytics_ingest_keys::organisation.eq(&organisation))
+                .filter(analytics_ingest_keys::application.eq(&application))
+                .filter(analytics_ingest_keys::revoked_at.is_null()),
+        )
+        .set(analytics_ingest_keys::revoked_at.eq(Utc::now()))
+        .execute(&mut conn)?;
+        Ok(revoked)
+    })?;
+    if revoked == 0 {
+        return Err(ABError::NotFound(format!(
+            "Ingest key {} not found",
+            key_id
+        )));
+    }
+
+    info!("{} revoked ingest key {}", auth_response.sub, key_id);
+    Ok(Json(RevokeIngestKeyResponse { success: true }))
+}
@@ -0,0 +1,58 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct CreateIngestKeyRequest {
+    pub description: Option<String>,
+    /// Issue a signing secret for HMAC-signed requests.
+    pub signed: bool,
+    /// Reject unsigned requests; implies `signed`.
+    pub require_signature: bool,
+    /// Overrides the analytics server's default per-key rate limit.
+    pub rate_limit_per_minute: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct IngestKeyInfo {
+    pub id: uuid::Uuid,
+    pub description: Option<String>,
+    pub signed: bool,
+    pub require_signature: bool,
+    pub rate_limit_per_minute: Option<u32>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct CreateIngestKeyResponse {
+    #[serde(flatten)]
+    pub info: IngestKeyInfo,
+    /// Shown only once.
+    pub ingest_key: String,
+    /// Shown only once.
+    pub signing_secret: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyIngestKeyRequest {
+    pub ingest_key: String,
+}
+
+/// What the analytics server needs to accept events sent with a key.
+#[derive(Serialize)]
+pub struct VerifyIngestKeyResponse {
+    pub key_id: uuid::Uuid,
+    pub organisation: String,
+    pub application: String,
+    pub signing_secret: Option<String>,
+    pub require_signature: bool,
+    pub rate_limit_per_minute: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct RevokeIngestKeyResponse {
+    pub success: bool,
+}
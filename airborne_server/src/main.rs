@@ -20,11 +20,15 @@ mod build;
 mod config;
 mod dashboard;
 mod file;
+mod ingest_key;
 mod middleware;
 mod organisation;
 mod package;
 mod provider;
 mod release;
+mod scim;
+mod service_account;
+mod session;
 mod token;
 mod types;
 mod user;
@@ -62,9 +66,10 @@ use crate::{
         request::{req_id_header_mw, WithRequestId},
     },
     provider::{
-        authn::build_authn_provider,
+        authn::{build_authn_provider, saml::SamlSettings},
         authz::{
             build_authz_provider,
+            cedar::CedarSettings,
             migration::{import_keycloak_authz_to_casbin, parse_keycloak_admin_issuer},
         },
     },
@@ -252,12 +257,15 @@ async fn main() -> std::io::Result<()> {
     let superposition_org_id_env = app_config.superposition_org_id.clone();
 
     let authn_provider_kind = types::AuthnProviderKind::from_str(&app_config.authn_provider)
-        .expect("AUTHN_PROVIDER must be one of: keycloak, oidc, okta, auth0");
+        .expect("AUTHN_PROVIDER must be one of: keycloak, oidc, okta, auth0, saml");
     let authz_provider_kind = types::AuthzProviderKind::from_str(&app_config.authz_provider)
-        .expect("AUTHZ_PROVIDER must be one of: casbin");
+        .expect("AUTHZ_PROVIDER must be one of: casbin, cedar");
+    // SAML deployments have no OIDC issuer or client to configure.
+    let saml_login = authn_provider_kind == types::AuthnProviderKind::Saml;
     let issuer = app_config
         .oidc_issuer_url
         .clone()
+        .or_else(|| saml_login.then(String::new))
         .expect("OIDC_ISSUER_URL must be set");
     let external_issuer = app_config
         .oidc_external_issuer_url
@@ -268,10 +276,12 @@ async fn main() -> std::io::Result<()> {
     let authn_client_id = app_config
         .oidc_client_id
         .clone()
+        .or_else(|| saml_login.then(String::new))
         .expect("OIDC_CLIENT_ID must be set");
     let authn_client_secret = app_config
         .oidc_client_secret
         .clone()
+        .or_else(|| saml_login.then(String::new))
         .expect("OIDC_CLIENT_SECRET must be set");
     let authn_clock_skew_secs = app_config.oidc_clock_skew_secs;
     let authz_bootstrap_super_admins = app_config
@@ -315,6 +325,46 @@ async fn main() -> std::io::Result<()> {
         }
     }
 
+    let saml_settings = saml_login.then(|| {
+        if app_config.redis_url.is_none() {
+            panic!("REDIS_URL must be set when AUTHN_PROVIDER=saml");
+        }
+        let public_endpoint = trim_trailing_slash(&app_config.public_endpoint);
+        SamlSettings {
+            idp_entity_id: app_config
+                .saml_idp_entity_id
+                .clone()
+                .expect("SAML_IDP_ENTITY_ID must be set when AUTHN_PROVIDER=saml"),
+            idp_sso_url: app_config
+                .saml_idp_sso_url
+                .clone()
+                .expect("SAML_IDP_SSO_URL must be set when AUTHN_PROVIDER=saml"),
+            idp_certificate: app_config
+                .saml_idp_certificate
+                .clone()
+                .expect("SAML_IDP_CERTIFICATE must be set when AUTHN_PROVIDER=saml"),
+            sp_entity_id: app_config
+                .saml_sp_entity_id
+                .clone()
+                .unwrap_or_else(|| public_endpoint.clone()),
+            acs_url: app_config.saml_sp_acs_url.clone().unwrap_or_else(|| {
+                format!(
+                    "{}/{}/users/saml/acs",
+                    public_endpoint,
+                    server_path_prefix.trim_matches('/')
+                )
+            }),
+            session_secret: app_config
+                .saml_session_secret
+                .clone()
+                .expect("SAML_SESSION_SECRET must be set when AUTHN_PROVIDER=saml"),
+            session_ttl_secs: app_config.saml_session_ttl_secs,
+            refresh_ttl_secs: app_config.saml_refresh_ttl_secs,
+            email_attribute: app_config.saml_email_attribute.clone(),
+            username_attribute: app_config.saml_username_attribute.clone(),
+        }
+    });
+
     let env = types::Environment {
         public_url: app_config.public_endpoint.clone(),
         authn_issuer_url,
@@ -339,6 +389,19 @@ async fn main() -> std::io::Result<()> {
         default_configs: get_default_configs_from_file()
             .await
             .expect("Failed to load superposition default configs from file"),
+        bundle_signing_key: app_config.bundle_signing_key.clone(),
+        bundle_max_unpacked_bytes: app_config.bundle_max_unpacked_bytes,
+        upload_validation_hook: app_config.upload_validation_hook.clone(),
+        upload_validation_hook_timeout_secs: app_config.upload_validation_hook_timeout_secs,
+        upload_validation_hook_uid: app_config.upload_validation_hook_uid,
+        upload_validation_hook_gid: app_config.upload_validation_hook_gid,
+        upload_validation_hook_max_memory_mb: app_config.upload_validation_hook_max_memory_mb,
+        file_mirror_max_bytes: app_config.file_mirror_max_bytes,
+        service_account_token_ttl_secs: app_config.service_account_token_ttl_secs,
+        temporary_grant_max_secs: app_config.temporary_grant_max_secs,
+        session_refresh_ttl_secs: app_config.session_refresh_ttl_secs,
+        trusted_proxies: app_config.trusted_proxies.clone(),
+        analytics_ingest_verify_token: app_config.analytics_ingest_verify_token.clone(),
     };
 
     // Create an S3 client with path-style enforced (for localstack)
@@ -410,6 +473,11 @@ async fn main() -> std::io::Result<()> {
         authz_bootstrap_super_admins.clone(),
         pool.clone(),
         authz_casbin_auto_load_secs,
+        CedarSettings {
+            policy_path: app_config.authz_cedar_policy_path.clone(),
+            reload_secs: app_config.authz_cedar_reload_secs,
+            role_baseline: app_config.authz_cedar_role_baseline,
+        },
     )
     .await
     .expect("Failed to initialize AuthZ provider");
@@ -455,7 +523,8 @@ async fn main() -> std::io::Result<()> {
 
     let app_state = Arc::new(types::AppState {
         env: env.clone(),
-        authn_provider: build_authn_provider(authn_provider_kind),
+        authn_provider: build_authn_provider(authn_provider_kind, saml_settings)
+            .expect("Failed to initialize AuthN provider"),
         authz_provider,
         db_pool: pool,
         redis_cache,
@@ -490,6 +559,8 @@ async fn main() -> std::io::Result<()> {
         }
     }
 
+    authz::grant::spawn_expiry_task(app_state_data.clone());
+
     let num_workers = app_config.num_workers;
     let keep_alive = app_config.keep_alive;
     let backlog = app_config.backlog;
@@ -532,6 +603,10 @@ async fn main() -> std::io::Result<()> {
                     )
                     .service(user::add_routes("users"))
                     .service(token::add_scopes("token"))
+                    .service(service_account::add_scopes("service-accounts"))
+                    .service(scim::add_scopes("scim"))
+                    .service(session::add_scopes("sessions"))
+                    .service(ingest_key::add_scopes("ingest-keys"))
                     .service(web::scope("/file").wrap(Auth).service(file::add_routes()))
                     .service(
                         web::scope("/packages")
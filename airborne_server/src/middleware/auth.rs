@@ -17,13 +17,14 @@ use std::{
     rc::Rc,
 };
 
-use crate::types::AppState;
+use crate::{service_account, session, types::AppState, utils::client_ip::client_ip};
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
     web::Data,
     Error, HttpMessage,
 };
 use futures::future::LocalBoxFuture;
+use tracing::Span;
 
 use crate::types::{ABError, Result as ABResult};
 
@@ -78,6 +79,9 @@ pub struct AuthResponse {
     pub application: Option<AccessLevel>,
     pub is_super_admin: bool,
     pub username: String,
+    /// Permission subset of the personal access token the request was made
+    /// with; `None` when the caller has their full access.
+    pub token_permissions: Option<Vec<String>>,
 }
 
 #[derive(Copy, Clone)]
@@ -142,17 +146,76 @@ where
             let app = app_header.and_then(|app_header| app_header.to_str().ok());
             match auth {
                 Some(access_token) => {
-                    let token_data = app_state
-                        .authn_provider
-                        .verify_access_token(app_state.get_ref(), access_token)
-                        .await?;
+                    let mut auth_response = if access_token
+                        .starts_with(service_account::TOKEN_PREFIX)
+                    {
+                        let account =
+                            service_account::authenticate(app_state.db_pool.clone(), access_token)
+                                .await?;
+                        let subject =
+                            service_account::subject(&account.organisation, &account.name);
+                        log::info!(
+                            "Service account {} calling {} {}",
+                            subject,
+                            req.method(),
+                            req.path()
+                        );
+                        AuthResponse {
+                            sub: subject.clone(),
+                            authn_sub: subject.clone(),
+                            authn_iss: None,
+                            authn_email: None,
+                            organisation: None,
+                            application: None,
+                            is_super_admin: false,
+                            username: subject,
+                            token_permissions: None,
+                        }
+                    } else {
+                        let token_data = app_state
+                            .authn_provider
+                            .verify_access_token(app_state.get_ref(), access_token)
+                            .await?;
+                        session::ensure_not_revoked(app_state.get_ref(), access_token).await?;
+
+                        let client_ip = client_ip(req.request(), &app_state.env.trusted_proxies);
+                        let token_permissions = crate::token::scope::permissions_for_access_token(
+                            app_state.db_pool.clone(),
+                            access_token,
+                            client_ip.as_deref(),
+                        )
+                        .await?
+                        .flatten();
+
+                        let authz_subject = app_state
+                            .authz_provider
+                            .subject_from_claims(&token_data.claims)?;
+                        // Service-account subjects are only reachable with
+                        // their own tokens, never through an IdP identity.
+                        if service_account::is_service_account_subject(&authz_subject) {
+                            return Err(
+                                ABError::Unauthorized("Invalid token subject".to_string()).into()
+                            );
+                        }
+                        AuthResponse {
+                            sub: authz_subject,
+                            authn_sub: token_data.claims.sub.clone(),
+                            authn_iss: token_data.claims.iss.clone(),
+                            authn_email: token_data.claims.email.clone(),
+                            organisation: None,
+                            application: None,
+                            is_super_admin: false,
+                            username: app_state
+                                .authz_provider
+                                .display_name_from_claims(&token_data.claims),
+                            token_permissions,
+                        }
+                    };
+                    Span::current().record("actor", auth_response.sub.as_str());
 
-                    let authz_subject = app_state
-                        .authz_provider
-                        .subject_from_claims(&token_data.claims)?;
                     let access_context = app_state
                         .authz_provider
-                        .access_for_request(app_state.get_ref(), &authz_subject, org, app)
+                        .access_for_request(app_state.get_ref(), &auth_response.sub, org, app)
                         .await?;
 
                     if org.is_some() && access_context.organisation.is_none() {
@@ -166,18 +229,10 @@ where
                         );
                     }
 
-                    req.extensions_mut().insert(AuthResponse {
-                        sub: authz_subject,
-                        authn_sub: token_data.claims.sub.clone(),
-                        authn_iss: token_data.claims.iss.clone(),
-                        authn_email: token_data.claims.email.clone(),
-                        organisation: access_context.organisation,
-                        application: access_context.application,
-                        is_super_admin: access_context.is_super_admin,
-                        username: app_state
-                            .authz_provider
-                            .display_name_from_claims(&token_data.claims),
-                    });
+                    auth_response.organisation = access_context.organisation;
+                    auth_response.application = access_context.application;
+                    auth_response.is_super_admin = access_context.is_super_admin;
+                    req.extensions_mut().insert(auth_response);
                     service.call(req).await
                 }
                 None => Err(ABError::Unauthorized("No Authorization token".to_string()).into()),
@@ -57,6 +57,7 @@ impl RootSpanBuilder for WithRequestId {
             org_id = %org,
             app_id = %app,
             superposition_workspace = tracing::field::Empty,
+            actor = tracing::field::Empty,
             route  = %req.match_pattern().unwrap_or("<unmatched>".to_string()),
         )
     }
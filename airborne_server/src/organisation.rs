@@ -156,6 +156,7 @@ async fn create_organisation(
         .get::<AuthResponse>()
         .cloned()
         .ok_or(ABError::Unauthorized("Token Parse Failed".to_string()))?;
+    crate::token::scope::require_unrestricted(&auth_response)?;
     let sub = &auth_response.sub;
 
     if state.env.organisation_creation_disabled && !auth_response.is_super_admin {
@@ -208,6 +209,7 @@ async fn delete_organisation(
         .get::<AuthResponse>()
         .cloned()
         .ok_or(ABError::Unauthorized("Token Parse Failed".to_string()))?;
+    crate::token::scope::require_unrestricted(&auth_response)?;
 
     // Check if organization exists
     if !state
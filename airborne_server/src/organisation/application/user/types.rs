@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::authz::types::TemporaryGrantInfo;
+
 // Request and Response Types
 #[derive(Deserialize)]
 pub struct UserRequest {
@@ -29,6 +31,9 @@ pub struct UserInfo {
     pub username: String,
     pub email: Option<String>,
     pub roles: Vec<String>,
+    /// Time-bound grants currently held in this scope.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub temporary_grants: Vec<TemporaryGrantInfo>,
 }
 
 #[derive(Serialize, Clone)]
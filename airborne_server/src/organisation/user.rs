@@ -23,6 +23,7 @@ use airborne_authz_macros::authz;
 use log::info;
 
 use crate::{
+    authz::grant::active_grants_by_subject,
     middleware::auth::{require_scope_name, AuthResponse},
     organisation::user::types::*,
     types as airborne_types,
@@ -176,11 +177,14 @@ async fn organisation_list_users(
         .authz_provider
         .list_organisation_users(state.get_ref(), &org_name)
         .await?;
+    let mut grants =
+        active_grants_by_subject(state.db_pool.clone(), org_name.clone(), None).await?;
 
     Ok(Json(ListUsersResponse {
         users: users
             .into_iter()
             .map(|user| UserInfo {
+                temporary_grants: grants.remove(&user.username).unwrap_or_default(),
                 username: user.username,
                 email: user.email,
                 roles: user.roles,
@@ -1,7 +1,12 @@
+pub mod budget;
+pub mod bundle;
+mod report;
 pub mod utils;
 use crate::{
+    file::utils::ensure_files_usable,
     package::{types::*, utils::parse_package_key},
-    release::utils::get_files_by_file_keys_async,
+    provider::authz::permission::enforce_resource_permission,
+    release::{self, utils::get_files_by_file_keys_async},
     run_blocking,
     types::{ABError, PaginatedQuery, PaginatedResponse, WithHeaders},
     utils::db::{
@@ -13,17 +18,20 @@ use crate::{
         DbPool,
     },
 };
+use actix_multipart::form::MultipartForm;
 use actix_web::{
-    get, post,
-    web::{self, Json, Query},
+    get, post, put,
+    web::{self, Json, Path, Query},
     Scope,
 };
 use airborne_authz_macros::authz;
+use bytes::Bytes;
 
 use crate::{
     middleware::auth::{require_org_and_app, AuthResponse},
     types as airborne_types,
     types::AppState,
+    utils::workspace::get_workspace_name_for_application,
 };
 use diesel::RunQueryDsl;
 use diesel::{dsl::count_star, prelude::*};
@@ -35,6 +43,11 @@ pub fn add_routes() -> Scope {
         .service(create_package)
         .service(get_package)
         .service(list_packages)
+        .service(export_package)
+        .service(import_bundle)
+        .service(get_size_budget)
+        .service(update_size_budget)
+        .service(get_package_report)
 }
 
 #[authz(
@@ -70,6 +83,17 @@ async fn create_package(
     if files.len() != request.files.len() {
         return Err(ABError::BadRequest("Some files not found".to_string()));
     }
+    ensure_files_usable(&files)?;
+
+    budget::enforce_size_budget(
+        state.db_pool.clone(),
+        organisation.clone(),
+        application.clone(),
+        &[],
+        &[],
+        &files.iter().collect::<Vec<_>>(),
+    )
+    .await?;
 
     let opt_pkg_tag = request.tag.clone();
     let db_organisation = organisation.clone();
@@ -311,3 +335,241 @@ async fn list_packages(
 
     Ok(Json(response))
 }
+
+#[authz(
+    resource = "package",
+    action = "export",
+    org_roles = ["owner", "admin", "write"],
+    app_roles = ["admin", "write"]
+)]
+#[get("/export")]
+async fn export_package(
+    query: Query<ExportPackageQuery>,
+    auth_response: web::ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Bytes>> {
+    let auth_response = auth_response.into_inner();
+    let (organisation, application) = require_org_and_app(
+        auth_response.organisation.clone(),
+        auth_response.application.clone(),
+    )?;
+    bundle::signing_key(&state)?;
+
+    let (opt_pkg_version, opt_pkg_tag) = parse_package_key(&query.package_key);
+    let package = get_package_from_db(
+        organisation.clone(),
+        application.clone(),
+        opt_pkg_version,
+        opt_pkg_tag,
+        state.db_pool.clone(),
+    )
+    .await?;
+
+    // A bare package has no release config of its own, so ship the
+    // application's current default config alongside it.
+    let workspace_name = get_workspace_name_for_application(
+        state.db_pool.clone(),
+        &state.redis_cache,
+        application.clone(),
+        organisation.clone(),
+    )
+    .await
+    .map_err(|e| ABError::InternalServerError(format!("Failed to get workspace name: {}", e)))?;
+    let workspace_handle = state.provider_registry.get_or_init(&workspace_name).await;
+    let default_config = crate::release::get_release_config_from_provider(
+        &workspace_handle.provider,
+        &open_feature::EvaluationContext::default(),
+    )
+    .await?
+    .config;
+
+    let version = package.version;
+    let archive = bundle::export_bundle(
+        &state,
+        &organisation,
+        &application,
+        package,
+        default_config,
+        None,
+    )
+    .await?;
+
+    bundle::archive_response(
+        archive,
+        &format!(
+            "{}-{}-package-{}.airborne.zip",
+            organisation, application, version
+        ),
+    )
+}
+
+#[authz(
+    resource = "package",
+    action = "import",
+    org_roles = ["owner", "admin", "write"],
+    app_roles = ["admin", "write"]
+)]
+#[post("/import")]
+async fn import_bundle(
+    MultipartForm(req): MultipartForm<ImportBundleRequest>,
+    auth_response: web::ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Json<BundleImportResponse>>> {
+    let auth_response = auth_response.into_inner();
+    let (organisation, application) = require_org_and_app(
+        auth_response.organisation.clone(),
+        auth_response.application.clone(),
+    )?;
+    let key = bundle::signing_key(&state)?.to_string();
+    let max_unpacked_bytes = state.env.bundle_max_unpacked_bytes;
+
+    let bytes = tokio::fs::read(req.file.file.path())
+        .await
+        .map_err(|e| ABError::InternalServerError(format!("Failed to read upload: {}", e)))?;
+    let verified = web::block(move || bundle::read_archive(&key, bytes, max_unpacked_bytes))
+        .await
+        .map_err(|e| ABError::InternalServerError(e.to_string()))??;
+
+    let create_release = req.create_release.map(|v| *v).unwrap_or(false);
+    if let (true, Some(bundle_release)) = (create_release, &verified.descriptor.release) {
+        enforce_resource_permission(
+            &state,
+            &auth_response,
+            "release",
+            "create",
+            release::dimension_attributes(Some(&bundle_release.dimensions)),
+        )
+        .await?;
+    }
+    let response =
+        bundle::import_bundle(state, organisation, application, verified, create_release).await?;
+
+    Ok(WithHeaders::new(Json(response)).status(actix_web::http::StatusCode::CREATED))
+}
+
+#[authz(
+    resource = "size_budget",
+    action = "read",
+    org_roles = ["owner", "admin", "write", "read"],
+    app_roles = ["admin", "write", "read"]
+)]
+#[get("/budget")]
+async fn get_size_budget(
+    auth_response: web::ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<SizeBudget>> {
+    let auth_response = auth_response.into_inner();
+    let (organisation, application) = require_org_and_app(
+        auth_response.organisation.clone(),
+        auth_response.application.clone(),
+    )?;
+
+    let size_budget =
+        budget::get_size_budget(state.db_pool.clone(), organisation, application).await?;
+    Ok(Json(size_budget.unwrap_or_default()))
+}
+
+#[authz(
+    resource = "size_budget",
+    action = "update",
+    org_roles = ["owner", "admin"],
+    app_roles = ["admin"]
+)]
+#[put("/budget")]
+async fn update_size_budget(
+    req: Json<SizeBudget>,
+    auth_response: web::ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<SizeBudget>> {
+    let auth_response = auth_response.into_inner();
+    let (organisation, application) = require_org_and_app(
+        auth_response.organisation.clone(),
+        auth_response.application.clone(),
+    )?;
+
+    let size_budget = budget::put_size_budget(
+        state.db_pool.clone(),
+        organisation,
+        application,
+        req.into_inner(),
+    )
+    .await?;
+    Ok(Json(size_budget))
+}
+
+#[authz(
+    resource = "package",
+    action = "read",
+    org_roles = ["owner", "admin", "write", "read"],
+    app_roles = ["admin", "write", "read"]
+)]
+#[get("/{version}/report")]
+async fn get_package_report(
+    version: Path<i32>,
+    auth_response: web::ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<PackageReport>> {
+    let version = version.into_inner();
+    let auth_response = auth_response.into_inner();
+    let (organisation, application) = require_org_and_app(
+        auth_response.organisation.clone(),
+        auth_response.application.clone(),
+    )?;
+
+    let pool = state.db_pool.clone();
+    let db_organisation = organisation.clone();
+    let db_application = application.clone();
+    let (package, previous) = run_blocking!({
+        let mut conn = pool.get()?;
+        let package = packages_table
+            .filter(package_org_id.eq(&db_organisation))
+            .filter(package_app_id.eq(&db_application))
+            .filter(package_version.eq(version))
+            .select(PackageV2Entry::as_select())
+            .first::<PackageV2Entry>(&mut conn)
+            .optional()?
+            .ok_or_else(|| ABError::NotFound(format!("Package version {} not found", version)))?;
+        let previous = packages_table
+            .filter(package_org_id.eq(&db_organisation))
+            .filter(package_app_id.eq(&db_application))
+            .filter(package_version.lt(version))
+            .order(package_version.desc())
+            .select(PackageV2Entry::as_select())
+            .first::<PackageV2Entry>(&mut conn)
+            .optional()?;
+        Ok((package, previous))
+    })?;
+
+    let entries = get_files_by_file_keys_async(
+        state.db_pool.clone(),
+        &state.redis_cache,
+        organisation.clone(),
+        application.clone(),
+        report::package_file_keys(&package),
+    )
+    .await?;
+    let previous_entries = match &previous {
+        Some(previous) => {
+            get_files_by_file_keys_async(
+                state.db_pool.clone(),
+                &state.redis_cache,
+                organisation.clone(),
+                application.clone(),
+                report::package_file_keys(previous),
+            )
+            .await?
+        }
+        None => Vec::new(),
+    };
+    let size_budget =
+        budget::get_size_budget(state.db_pool.clone(), organisation, application).await?;
+
+    Ok(Json(report::build_report(
+        &package,
+        &entries,
+        previous
+            .as_ref()
+            .map(|previous| (previous, previous_entries.as_slice())),
+        size_budget,
+    )))
+}
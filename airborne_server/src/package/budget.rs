@@ -0,0 +1,209 @@
+//! Per-application package size budgets.
+//!
+//! Budgets cap the total size of the `important` set, the total size of the
+//! `lazy` set and the size of any single file. The important/lazy split is only
+//! known once a release picks it, so package creation enforces the per-file
+//! limit and release creation/update enforces all three.
+
+use diesel::prelude::*;
+
+use crate::{
+    run_blocking, types as airborne_types,
+    types::ABError,
+    utils::db::{
+        models::{FileEntry, PackageSizeBudgetEntry},
+        schema::hyperotaserver::package_size_budgets::dsl,
+        DbPool,
+    },
+};
+
+use super::types::SizeBudget;
+
+impl From<PackageSizeBudgetEntry> for SizeBudget {
+    fn from(entry: PackageSizeBudgetEntry) -> Self {
+        SizeBudget {
+            max_important_bytes: entry.max_important_bytes,
+            max_lazy_bytes: entry.max_lazy_bytes,
+            max_file_bytes: entry.max_file_bytes,
+        }
+    }
+}
+
+pub fn file_key(entry: &FileEntry) -> String {
+    format!("{}@version:{}", entry.file_path, entry.version)
+}
+
+pub async fn get_size_budget(
+    pool: DbPool,
+    organisation: String,
+    application: String,
+) -> airborne_types::Result<Option<SizeBudget>> {
+    run_blocking!({
+        let mut conn = pool.get()?;
+        let entry = dsl::package_size_budgets
+            .filter(dsl::org_id.eq(&organisation))
+            .filter(dsl::app_id.eq(&application))
+            .select(PackageSizeBudgetEntry::as_select())
+            .first::<PackageSizeBudgetEntry>(&mut conn)
+            .optional()?;
+        Ok(entry.map(SizeBudget::from))
+    })
+}
+
+pub async fn put_size_budget(
+    pool: DbPool,
+    organisation: String,
+    application: String,
+    budget: SizeBudget,
+) -> airborne_types::Result<SizeBudget> {
+    for (name, limit) in [
+        ("max_important_bytes", budget.max_important_bytes),
+        ("max_lazy_bytes", budget.max_lazy_bytes),
+        ("max_file_bytes", budget.max_file_bytes),
+    ] {
+        if limit.is_some_and(|limit| limit < 0) {
+            return Err(ABError::BadRequest(format!("{} cannot be negative", name)));
+        }
+    }
+
+    run_blocking!({
+        let mut conn = pool.get()?;
+        let entry = PackageSizeBudgetEntry {
+            org_id: organisation,
+            app_id: application,
+            max_important_bytes: budget.max_important_bytes,
+            max_lazy_bytes: budget.max_lazy_bytes,
+            max_file_bytes: budget.max_file_bytes,
+            updated_at: chrono::Utc::now(),
+        };
+        let saved = diesel::insert_into(dsl::package_size_budgets)
+            .values(&entry)
+            .on_conflict((dsl::org_id, dsl::app_id))
+            .do_update()
+            .set(&entry)
+            .returning(PackageSizeBudgetEntry::as_returning())
+            .get_result::<PackageSizeBudgetEntry>(&mut conn)?;
+        Ok(saved.into())
+    })
+}
+
+/// Lists every way the given file sets exceed the budget. `others` only count
+/// towards the per-file limit.
+pub fn find_violations(
+    budget: &SizeBudget,
+    important: &[&FileEntry],
+    lazy: &[&FileEntry],
+    others: &[&FileEntry],
+) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    let total = |files: &[&FileEntry]| files.iter().map(|f| f.size).sum::<i64>();
+    if let Some(limit) = budget.max_important_bytes {
+        let size = total(important);
+        if size > limit {
+            violations.push(format!(
+                "important files total {} bytes (limit {})",
+                size, limit
+            ));
+        }
+    }
+    if let Some(limit) = budget.max_lazy_bytes {
+        let size = total(lazy);
+        if size > limit {
+            violations.push(format!("lazy files total {} bytes (limit {})", size, limit));
+        }
+    }
+    if let Some(limit) = budget.max_file_bytes {
+        let mut seen = std::collections::HashSet::new();
+        for file in important.iter().chain(lazy).chain(others) {
+            if file.size > limit && seen.insert(file.id) {
+                violations.push(format!(
+                    "'{}' is {} bytes (limit {})",
+                    file_key(file),
+                    file.size,
+                    limit
+                ));
+            }
+        }
+    }
+
+    violations
+}
+
+/// Fails with a `BadRequest` listing every offender when the application has a
+/// budget and the files exceed it.
+pub async fn enforce_size_budget(
+    pool: DbPool,
+    organisation: String,
+    application: String,
+    important: &[&FileEntry],
+    lazy: &[&FileEntry],
+    others: &[&FileEntry],
+) -> airborne_types::Result<()> {
+    let Some(budget) = get_size_budget(pool, organisation, application).await? else {
+        return Ok(());
+    };
+
+    let violations = find_violations(&budget, important, lazy, others);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(ABError::BadRequest(format!(
+            "Package exceeds the application's size budget: {}",
+            violations.join("; ")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::json;
+
+    fn file(path: &str, size: i64) -> FileEntry {
+        FileEntry {
+            id: uuid::Uuid::new_v4(),
+            app_id: "app".to_string(),
+            org_id: "org".to_string(),
+            version: 1,
+            tag: None,
+            url: String::new(),
+            file_path: path.to_string(),
+            size,
+            checksum: String::new(),
+            metadata: json!({}),
+            created_at: Utc::now(),
+            variants: json!([]),
+            failure_reason: None,
+        }
+    }
+
+    #[test]
+    fn reports_every_offender() {
+        let budget = SizeBudget {
+            max_important_bytes: Some(100),
+            max_lazy_bytes: Some(1000),
+            max_file_bytes: Some(60),
+        };
+        let index = file("index.js", 70);
+        let vendor = file("vendor.js", 50);
+        let image = file("img/logo.png", 80);
+
+        let violations = find_violations(&budget, &[&index, &vendor], &[&image], &[]);
+        assert_eq!(
+            violations,
+            vec![
+                "important files total 120 bytes (limit 100)".to_string(),
+                "'index.js@version:1' is 70 bytes (limit 60)".to_string(),
+                "'img/logo.png@version:1' is 80 bytes (limit 60)".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn unlimited_budget_has_no_violations() {
+        let big = file("index.js", i64::MAX / 2);
+        assert!(find_violations(&SizeBudget::default(), &[&big], &[&big], &[&big]).is_empty());
+    }
+}
@@ -0,0 +1,922 @@
+//! Self-contained package / release bundles.
+//!
+//! A bundle is a zip archive with the following layout:
+//!
+//! ```text
+//! airborne-bundle.json   BundleDescriptor (signed)
+//! manifest.json          ServeReleaseResponse for the exported package/release
+//! signature              hex HMAC-SHA256 of airborne-bundle.json
+//! files/<file_path>      raw contents of every referenced file
+//! ```
+//!
+//! Only the descriptor is signed. It carries the SHA-256 of the manifest and of
+//! every file, so verifying the signature and then the checksums covers the
+//! whole archive.
+
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read, Write},
+};
+
+use actix_web::{
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    web::{self, Json},
+};
+use bytes::Bytes;
+use chrono::Utc;
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use http::HeaderValue;
+use log::{error, info};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use zip::{write::FileOptions, ZipArchive, ZipWriter};
+
+use crate::{
+    file::utils::{
+        calculate_checksum, create_s3_file_path, download_file_content, parse_file_key,
+        precompress_content,
+    },
+    package::{budget, types::*, utils::db_response_to_package},
+    release::{
+        self, serve_file,
+        types::{
+            Config, ConfigRequest, CreateReleaseRequest, PackageRequest, ServeFile, ServePackage,
+            ServeReleaseResponse,
+        },
+        utils::get_files_by_file_keys_async,
+    },
+    run_blocking, types as airborne_types,
+    types::{ABError, AppState, WithHeaders},
+    utils::{
+        db::{
+            models::{FileEntry, NewFileEntry, NewPackageV2Entry, PackageV2Entry},
+            schema::hyperotaserver::{files, packages_v2},
+        },
+        s3::push_file_byte_arr,
+    },
+};
+
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+const DESCRIPTOR_ENTRY: &str = "airborne-bundle.json";
+const MANIFEST_ENTRY: &str = "manifest.json";
+const SIGNATURE_ENTRY: &str = "signature";
+const FILES_PREFIX: &str = "files/";
+// Limit for the descriptor, manifest and signature entries
+const MAX_METADATA_ENTRY_BYTES: u64 = 16 * 1024 * 1024;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A bundle whose signature and checksums have been verified.
+pub struct VerifiedBundle {
+    pub descriptor: BundleDescriptor,
+    pub contents: HashMap<String, Vec<u8>>,
+}
+
+pub fn signing_key(state: &AppState) -> airborne_types::Result<&str> {
+    state
+        .env
+        .bundle_signing_key
+        .as_deref()
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| {
+            ABError::BadRequest("Bundle export/import is not enabled on this server".to_string())
+        })
+}
+
+fn sign(key: &str, payload: &[u8]) -> airborne_types::Result<String> {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes())
+        .map_err(|e| ABError::InternalServerError(format!("Invalid signing key: {}", e)))?;
+    mac.update(payload);
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+fn verify_signature(key: &str, payload: &[u8], signature: &str) -> airborne_types::Result<()> {
+    let signature = hex::decode(signature.trim())
+        .map_err(|_| ABError::BadRequest("Bundle signature is malformed".to_string()))?;
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes())
+        .map_err(|e| ABError::InternalServerError(format!("Invalid signing key: {}", e)))?;
+    mac.update(payload);
+    mac.verify_slice(&signature)
+        .map_err(|_| ABError::BadRequest("Bundle signature verification failed".to_string()))
+}
+
+/// Finds the file entry a file key (`path@version:N` or `path@tag:T`) refers to.
+pub fn find_file_for_key<'a>(entries: &'a [FileEntry], key: &str) -> Option<&'a FileEntry> {
+    let (path, opt_version, opt_tag) = parse_file_key(key);
+    entries.iter().find(|entry| {
+        entry.file_path == path
+            && match (opt_version, &opt_tag) {
+                (Some(v), _) => entry.version == v,
+                (None, Some(t)) => entry.tag.as_deref() == Some(t.as_str()),
+                (None, None) => false,
+            }
+    })
+}
+
+/// Resolves the given file keys and downloads their contents, verifying each
+/// download against the checksum recorded for the file.
+pub async fn collect_files(
+    state: &web::Data<AppState>,
+    organisation: &str,
+    application: &str,
+    keys: Vec<String>,
+) -> airborne_types::Result<(Vec<FileEntry>, Vec<(BundleFile, Vec<u8>)>)> {
+    let mut unique_keys = keys;
+    unique_keys.sort();
+    unique_keys.dedup();
+
+    let entries = get_files_by_file_keys_async(
+        state.db_pool.clone(),
+        &state.redis_cache,
+        organisation.to_string(),
+        application.to_string(),
+        unique_keys.clone(),
+    )
+    .await?;
+
+    let mut collected: Vec<(BundleFile, Vec<u8>)> = Vec::with_capacity(unique_keys.len());
+    for key in &unique_keys {
+        let entry = find_file_for_key(&entries, key)
+            .ok_or_else(|| ABError::NotFound(format!("File '{}' not found", key)))?;
+        if entry.url.is_empty() {
+            return Err(ABError::BadRequest(format!(
+                "File '{}' has not finished uploading",
+                key
+            )));
+        }
+
+        let content = download_file_content(&entry.url, &None).await?;
+        let checksum = calculate_checksum(content.clone()).await;
+        if !entry.checksum.is_empty() && !entry.checksum.eq_ignore_ascii_case(&checksum) {
+            return Err(ABError::InternalServerError(format!(
+                "Checksum mismatch for '{}': expected {}, got {}",
+                key, entry.checksum, checksum
+            )));
+        }
+
+        collected.push((
+            BundleFile {
+                key: key.clone(),
+                file_path: entry.file_path.clone(),
+                version: entry.version,
+                tag: entry.tag.clone(),
+                size: content.len() as i64,
+                checksum,
+                metadata: entry.metadata.clone(),
+                entry: format!("{}{}@{}", FILES_PREFIX, entry.file_path, entry.version),
+            },
+            content,
+        ));
+    }
+
+    Ok((entries, collected))
+}
+
+/// Looks up a package version in the given application.
+pub async fn get_package_version(
+    state: &web::Data<AppState>,
+    organisation: &str,
+    application: &str,
+    version: i32,
+) -> airborne_types::Result<Package> {
+    super::get_package_from_db(
+        organisation.to_string(),
+        application.to_string(),
+        Some(version),
+        None,
+        state.db_pool.clone(),
+    )
+    .await
+}
+
+/// Downloads everything referenced by the package (and release, if given)
+/// and packs it into a signed bundle.
+pub async fn export_bundle(
+    state: &web::Data<AppState>,
+    organisation: &str,
+    application: &str,
+    package: Package,
+    config: Config,
+    release: Option<(String, BundleRelease)>,
+) -> airborne_types::Result<Vec<u8>> {
+    let key = signing_key(state)?;
+
+    let (important, lazy, resources, properties) = match &release {
+        Some((_, r)) => (
+            r.important.clone(),
+            r.lazy.clone(),
+            r.resources.clone(),
+            r.package_properties.clone(),
+        ),
+        None => (
+            package.files.clone(),
+            Vec::new(),
+            Vec::new(),
+            serde_json::Value::Object(Default::default()),
+        ),
+    };
+
+    let all_keys = package
+        .files
+        .iter()
+        .chain(important.iter())
+        .chain(lazy.iter())
+        .chain(resources.iter())
+        .chain(std::iter::once(&package.index))
+        .cloned()
+        .collect::<Vec<String>>();
+    let (entries, files) = collect_files(state, organisation, application, all_keys).await?;
+
+    let serve_files = |keys: &[String]| -> airborne_types::Result<Vec<ServeFile>> {
+        keys.iter()
+            .map(|key| {
+                find_file_for_key(&entries, key)
+                    .map(serve_file)
+                    .ok_or_else(|| ABError::NotFound(format!("File '{}' not found", key)))
+            })
+            .collect()
+    };
+
+    let manifest = ServeReleaseResponse {
+        version: package.version.to_string(),
+        config,
+        package: ServePackage {
+            name: application.to_string(),
+            version: package.version.to_string(),
+            index: serve_files(std::slice::from_ref(&package.index))?
+                .pop()
+                .ok_or_else(|| ABError::NotFound("Package index not found".to_string()))?,
+            properties,
+            important: serve_files(&important)?,
+            lazy: serve_files(&lazy)?,
+        },
+        resources: serve_files(&resources)?,
+    };
+
+    let (kind, release_id, release) = match release {
+        Some((id, r)) => (BundleKind::Release, Some(id), Some(r)),
+        None => (BundleKind::Package, None, None),
+    };
+    let descriptor = BundleDescriptor {
+        format_version: BUNDLE_FORMAT_VERSION,
+        kind,
+        source: BundleSource {
+            organisation: organisation.to_string(),
+            application: application.to_string(),
+            release_id,
+        },
+        created_at: Utc::now(),
+        manifest_checksum: String::new(),
+        package: BundlePackage {
+            version: package.version,
+            tag: package.tag,
+            index: package.index,
+            files: package.files,
+        },
+        files: Vec::new(),
+        release,
+    };
+
+    write_archive(key, descriptor, &manifest, files)
+}
+
+pub fn archive_response(
+    archive: Vec<u8>,
+    file_name: &str,
+) -> airborne_types::Result<WithHeaders<Bytes>> {
+    Ok(WithHeaders::new(Bytes::from(archive))
+        .header(CONTENT_TYPE, HeaderValue::from_static("application/zip"))
+        .header(
+            CONTENT_DISPOSITION,
+            HeaderValue::from_str(&format!("attachment; filename=\"{}\"", file_name)).map_err(
+                |e| {
+                    ABError::InternalServerError(format!(
+                        "Failed to create content disposition header: {}",
+                        e
+                    ))
+                },
+            )?,
+        ))
+}
+
+/// Signs the descriptor and writes the archive.
+pub fn write_archive(
+    key: &str,
+    mut descriptor: BundleDescriptor,
+    manifest: &ServeReleaseResponse,
+    files: Vec<(BundleFile, Vec<u8>)>,
+) -> airborne_types::Result<Vec<u8>> {
+    let manifest_bytes = serde_json::to_vec_pretty(manifest)
+        .map_err(|e| ABError::InternalServerError(format!("Failed to encode manifest: {}", e)))?;
+    descriptor.manifest_checksum = sha256_hex(&manifest_bytes);
+    descriptor.files = files.iter().map(|(file, _)| file.clone()).collect();
+
+    let descriptor_bytes = serde_json::to_vec_pretty(&descriptor).map_err(|e| {
+        ABError::InternalServerError(format!("Failed to encode bundle descriptor: {}", e))
+    })?;
+    let signature = sign(key, &descriptor_bytes)?;
+
+    let mut archive: Vec<u8> = Vec::new();
+    let mut writer = ZipWriter::new(Cursor::new(&mut archive));
+    let mut add_entry = |name: &str, bytes: &[u8]| -> airborne_types::Result<()> {
+        writer
+            .start_file::<_, ()>(name, FileOptions::default())
+            .map_err(|e| {
+                ABError::InternalServerError(format!("Failed to add {} to bundle: {}", name, e))
+            })?;
+        writer.write_all(bytes).map_err(|e| {
+            ABError::InternalServerError(format!("Failed to write {} to bundle: {}", name, e))
+        })
+    };
+
+    add_entry(DESCRIPTOR_ENTRY, &descriptor_bytes)?;
+    add_entry(SIGNATURE_ENTRY, signature.as_bytes())?;
+    add_entry(MANIFEST_ENTRY, &manifest_bytes)?;
+    for (file, content) in &files {
+        add_entry(&file.entry, content)?;
+    }
+
+    writer
+        .finish()
+        .map_err(|e| ABError::InternalServerError(format!("Failed to finish bundle: {}", e)))?;
+
+    Ok(archive)
+}
+
+/// Reads an entry, refusing it when it unpacks to more than `limit` bytes.
+/// The size declared in the archive is checked first, and reading stops past
+/// the limit in case that size is wrong.
+fn read_entry<R: Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+    limit: u64,
+) -> airborne_types::Result<Vec<u8>> {
+    let entry = archive
+        .by_name(name)
+        .map_err(|_| ABError::BadRequest(format!("Bundle is missing '{}'", name)))?;
+    let too_large = || {
+        ABError::BadRequest(format!(
+            "Bundle entry '{}' is larger than {} bytes",
+            name, limit
+        ))
+    };
+    if entry.size() > limit {
+        return Err(too_large());
+    }
+
+    let mut buf = Vec::with_capacity(entry.size() as usize);
+    entry
+        .take(limit + 1)
+        .read_to_end(&mut buf)
+        .map_err(|e| ABError::BadRequest(format!("Failed to read '{}': {}", name, e)))?;
+    if buf.len() as u64 > limit {
+        return Err(too_large());
+    }
+    Ok(buf)
+}
+
+/// Parses an archive, verifying its signature and every checksum it lists.
+/// The listed files may add up to at most `max_unpacked_bytes`.
+pub fn read_archive(
+    key: &str,
+    bytes: Vec<u8>,
+    max_unpacked_bytes: u64,
+) -> airborne_types::Result<VerifiedBundle> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))
+        .map_err(|e| ABError::BadRequest(format!("Bundle is not a valid archive: {}", e)))?;
+
+    let descriptor_bytes = read_entry(&mut archive, DESCRIPTOR_ENTRY, MAX_METADATA_ENTRY_BYTES)?;
+    let signature = read_entry(&mut archive, SIGNATURE_ENTRY, MAX_METADATA_ENTRY_BYTES)?;
+    let signature = String::from_utf8(signature)
+        .map_err(|_| ABError::BadRequest("Bundle signature is malformed".to_string()))?;
+    verify_signature(key, &descriptor_bytes, &signature)?;
+
+    let descriptor: BundleDescriptor = serde_json::from_slice(&descriptor_bytes)
+        .map_err(|e| ABError::BadRequest(format!("Invalid bundle descriptor: {}", e)))?;
+    if descriptor.format_version != BUNDLE_FORMAT_VERSION {
+        return Err(ABError::BadRequest(format!(
+            "Unsupported bundle format version {}",
+            descriptor.format_version
+        )));
+    }
+
+    let manifest_bytes = read_entry(&mut archive, MANIFEST_ENTRY, MAX_METADATA_ENTRY_BYTES)?;
+    let manifest_checksum = sha256_hex(&manifest_bytes);
+    if manifest_checksum != descriptor.manifest_checksum {
+        return Err(ABError::BadRequest(
+            "Bundle manifest does not match its checksum".to_string(),
+        ));
+    }
+
+    let mut unpacked_bytes: u64 = 0;
+    for file in &descriptor.files {
+        let size = u64::try_from(file.size).map_err(|_| {
+            ABError::BadRequest(format!("Bundle file '{}' has a negative size", file.key))
+        })?;
+        unpacked_bytes = unpacked_bytes.saturating_add(size);
+    }
+    if unpacked_bytes > max_unpacked_bytes {
+        return Err(ABError::BadRequest(format!(
+            "Bundle unpacks to {} bytes (limit {})",
+            unpacked_bytes, max_unpacked_bytes
+        )));
+    }
+
+    let mut contents = HashMap::with_capacity(descriptor.files.len());
+    for file in &descriptor.files {
+        // Sizes were checked to be non-negative above
+        let content = read_entry(&mut archive, &file.entry, file.size as u64)?;
+        let checksum = sha256_hex(&content);
+        if checksum != file.checksum || content.len() as i64 != file.size {
+            return Err(ABError::BadRequest(format!(
+                "Bundle file '{}' does not match its checksum",
+                file.key
+            )));
+        }
+        contents.insert(file.key.clone(), content);
+    }
+
+    Ok(VerifiedBundle {
+        descriptor,
+        contents,
+    })
+}
+
+/// Rows an import has created so far. They are removed again when a later
+/// step fails, so a failed import leaves no files or package behind.
+#[derive(Default)]
+struct ImportedRows {
+    files: Vec<Uuid>,
+    package: Option<Uuid>,
+}
+
+impl ImportedRows {
+    async fn remove(self, state: &web::Data<AppState>) {
+        if self.files.is_empty() && self.package.is_none() {
+            return;
+        }
+        let pool = state.db_pool.clone();
+        let result = run_blocking!({
+            let mut conn = pool.get()?;
+            conn.transaction::<(), diesel::result::Error, _>(|conn| {
+                if let Some(package_id) = self.package {
+                    diesel::delete(
+                        packages_v2::dsl::packages_v2.filter(packages_v2::dsl::id.eq(package_id)),
+                    )
+                    .execute(conn)?;
+                }
+                diesel::delete(files::dsl::files.filter(files::dsl::id.eq_any(&self.files)))
+                    .execute(conn)?;
+                Ok(())
+            })?;
+            Ok(())
+        });
+        if let Err(e) = result {
+            error!("Failed to remove the rows of a failed bundle import: {}", e);
+        }
+    }
+}
+
+/// Recreates a single bundled file in the target application, reusing an
+/// existing version with the same checksum when there is one. Returns the
+/// entry and whether it was newly created; a new row is recorded in
+/// `imported` as soon as it exists.
+async fn import_file(
+    state: &web::Data<AppState>,
+    organisation: &str,
+    application: &str,
+    file: &BundleFile,
+    content: Vec<u8>,
+    imported: &mut ImportedRows,
+) -> airborne_types::Result<(FileEntry, bool)> {
+    use files::dsl;
+
+    let pool = state.db_pool.clone();
+    let org = organisation.to_string();
+    let app = application.to_string();
+    let bundle_file = file.clone();
+    let (entry, created) = run_blocking!({
+        let mut conn = pool.get()?;
+        let existing = dsl::files
+            .filter(dsl::org_id.eq(&org))
+            .filter(dsl::app_id.eq(&app))
+            .filter(dsl::file_path.eq(&bundle_file.file_path))
+            .filter(dsl::checksum.eq(&bundle_file.checksum))
+            .order(dsl::version.desc())
+            .select(FileEntry::as_select())
+            .first::<FileEntry>(&mut conn)
+            .optional()?;
+        if let Some(existing) = existing {
+            return Ok((existing, false));
+        }
+
+        let created = conn.transaction::<FileEntry, diesel::result::Error, _>(|conn| {
+            let latest_version = dsl::files
+                .filter(dsl::org_id.eq(&org))
+                .filter(dsl::app_id.eq(&app))
+                .filter(dsl::file_path.eq(&bundle_file.file_path))
+                .order(dsl::version.desc())
+                .select(dsl::version)
+                .for_update()
+                .first::<i32>(conn)
+                .optional()?
+                .unwrap_or(0);
+
+            // Tags are unique per path, so only carry the tag over when it is free.
+            let tag_taken = match &bundle_file.tag {
+                Some(file_tag) => diesel::select(diesel::dsl::exists(
+                    dsl::files
+                        .filter(dsl::org_id.eq(&org))
+                        .filter(dsl::app_id.eq(&app))
+                        .filter(dsl::file_path.eq(&bundle_file.file_path))
+                        .filter(dsl::tag.eq(file_tag)),
+                ))
+                .get_result::<bool>(conn)?,
+                None => false,
+            };
+
+            diesel::insert_into(dsl::files)
+                .values(&NewFileEntry {
+                    app_id: app.clone(),
+                    org_id: org.clone(),
+                    version: latest_version + 1,
+                    tag: if tag_taken {
+                        None
+                    } else {
+                        bundle_file.tag.clone()
+                    },
+                    url: "".to_string(),
+                    file_path: bundle_file.file_path.clone(),
+                    size: 0,
+                    checksum: "".to_string(),
+                    metadata: bundle_file.metadata.clone(),
+                    created_at: Utc::now(),
+                })
+                .returning(FileEntry::as_returning())
+                .get_result::<FileEntry>(conn)
+        })?;
+        Ok((created, true))
+    })?;
+
+    if !created {
+        return Ok((entry, false));
+    }
+    imported.files.push(entry.id);
+
+    let file_name = std::path::Path::new(&entry.file_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(&entry.file_path);
+    let s3_path = create_s3_file_path(
+        organisation,
+        application,
+        &entry.id.to_string(),
+        &entry.version.to_string(),
+        file_name,
+    );
+
+    let content = bytes::Bytes::from(content);
+    push_file_byte_arr(
+        &state.s3_client,
+        state.env.bucket_name.clone(),
+        content.clone(),
+        s3_path.clone(),
+    )
+    .await?;
+    let file_variants = precompress_content(state, &s3_path, &entry.file_path, content).await;
+    let variants_json = serde_json::to_value(&file_variants)
+        .unwrap_or_else(|_| serde_json::Value::Array(Vec::new()));
+
+    let pool = state.db_pool.clone();
+    let file_id = entry.id;
+
+    let file_url = format!("{}/{}", &state.env.public_url, &s3_path);
+    let file_size = file.size;
+    let file_checksum = file.checksum.clone();
+    let updated = run_blocking!({
+        let mut conn = pool.get()?;
+        let updated = diesel::update(files::dsl::files.filter(files::dsl::id.eq(file_id)))
+            .set((
+                files::dsl::url.eq(file_url),
+                files::dsl::size.eq(file_size),
+                files::dsl::checksum.eq(file_checksum),
+                files::dsl::variants.eq(variants_json),
+            ))
+            .returning(FileEntry::as_returning())
+            .get_result::<FileEntry>(&mut conn)?;
+        Ok(updated)
+    })?;
+
+    Ok((updated, true))
+}
+
+/// Recreates the files, the package and, when asked for and present, the
+/// release described by a verified bundle inside the target application.
+/// When any step fails, the files and package created so far are removed.
+pub async fn import_bundle(
+    state: web::Data<AppState>,
+    organisation: String,
+    application: String,
+    bundle: VerifiedBundle,
+    create_release: bool,
+) -> airborne_types::Result<BundleImportResponse> {
+    if create_release && bundle.descriptor.release.is_none() {
+        return Err(ABError::BadRequest(
+            "Bundle does not contain a release".to_string(),
+        ));
+    }
+
+    info!(
+        "Importing {:?} bundle from {}/{} into {}/{}",
+        bundle.descriptor.kind,
+        bundle.descriptor.source.organisation,
+        bundle.descriptor.source.application,
+        organisation,
+        application
+    );
+
+    let mut imported = ImportedRows::default();
+    let result = import_rows(
+        &state,
+        organisation,
+        application,
+        bundle,
+        create_release,
+        &mut imported,
+    )
+    .await;
+    if result.is_err() {
+        imported.remove(&state).await;
+    }
+    result
+}
+
+async fn import_rows(
+    state: &web::Data<AppState>,
+    organisation: String,
+    application: String,
+    bundle: VerifiedBundle,
+    create_release: bool,
+    imported: &mut ImportedRows,
+) -> airborne_types::Result<BundleImportResponse> {
+    let VerifiedBundle {
+        descriptor,
+        mut contents,
+    } = bundle;
+
+    let mut key_mapping: HashMap<String, String> = HashMap::new();
+    let mut created_files = Vec::new();
+    let mut reused_files = Vec::new();
+    let mut entries = Vec::with_capacity(descriptor.files.len());
+    for file in &descriptor.files {
+        let content = contents.remove(&file.key).ok_or_else(|| {
+            ABError::BadRequest(format!("Bundle is missing contents for '{}'", file.key))
+        })?;
+        let (entry, created) =
+            import_file(state, &organisation, &application, file, content, imported).await?;
+        let new_key = format!("{}@version:{}", entry.file_path, entry.version);
+        if created {
+            created_files.push(new_key.clone());
+        } else {
+            reused_files.push(new_key.clone());
+        }
+        key_mapping.insert(file.key.clone(), new_key);
+        entries.push(entry);
+    }
+
+    let map_key = |key: &String| -> airborne_types::Result<String> {
+        key_mapping
+            .get(key)
+            .cloned()
+            .ok_or_else(|| ABError::BadRequest(format!("Bundle does not contain file '{}'", key)))
+    };
+    let map_keys = |keys: &[String]| -> airborne_types::Result<Vec<String>> {
+        keys.iter().map(map_key).collect()
+    };
+
+    let package_index = map_key(&descriptor.package.index)?;
+    let package_files = map_keys(&descriptor.package.files)?;
+
+    // Same check as creating the package by hand; a release created below
+    // checks its own split of the files
+    budget::enforce_size_budget(
+        state.db_pool.clone(),
+        organisation.clone(),
+        application.clone(),
+        &[],
+        &[],
+        &package_files
+            .iter()
+            .filter_map(|key| find_file_for_key(&entries, key))
+            .collect::<Vec<_>>(),
+    )
+    .await?;
+    let package_tag = descriptor.package.tag.clone();
+
+    let pool = state.db_pool.clone();
+    let org = organisation.clone();
+    let app = application.clone();
+    let package = run_blocking!({
+        use packages_v2::dsl;
+        let mut conn = pool.get()?;
+        let package = conn.transaction::<PackageV2Entry, diesel::result::Error, _>(|conn| {
+            let latest_version = dsl::packages_v2
+                .filter(dsl::org_id.eq(&org))
+                .filter(dsl::app_id.eq(&app))
+                .order(dsl::version.desc())
+                .select(dsl::version)
+                .for_update()
+                .first::<i32>(conn)
+                .optional()?
+                .unwrap_or(0);
+
+            let tag_taken = match &package_tag {
+                Some(pkg_tag) => diesel::select(diesel::dsl::exists(
+                    dsl::packages_v2
+                        .filter(dsl::org_id.eq(&org))
+                        .filter(dsl::app_id.eq(&app))
+                        .filter(dsl::tag.eq(pkg_tag)),
+                ))
+                .get_result::<bool>(conn)?,
+                None => false,
+            };
+
+            diesel::insert_into(dsl::packages_v2)
+                .values(&NewPackageV2Entry {
+                    index: package_index.clone(),
+                    org_id: org.clone(),
+                    app_id: app.clone(),
+                    tag: if tag_taken { None } else { package_tag.clone() },
+                    version: latest_version + 1,
+                    files: package_files.iter().cloned().map(Some).collect(),
+                })
+                .returning(PackageV2Entry::as_returning())
+                .get_result::<PackageV2Entry>(conn)
+        })?;
+        Ok(package)
+    })?;
+    imported.package = Some(package.id);
+
+    if let (Some(pkg_tag), Some(cache)) = (&package.tag, &state.redis_cache) {
+        let cache_key = cache.key(
+            &organisation,
+            &application,
+            &["package", &format!("tag:{}", pkg_tag)],
+        );
+        let _ = cache.del(&cache_key).await;
+    }
+
+    let release = match (&descriptor.release, create_release) {
+        (Some(bundle_release), true) => {
+            let request = CreateReleaseRequest {
+                config: ConfigRequest {
+                    boot_timeout: bundle_release.boot_timeout,
+                    release_config_timeout: bundle_release.release_config_timeout,
+                    properties: Some(bundle_release.config_properties.clone()),
+                },
+                package_id: Some(format!("version:{}", package.version)),
+                package: Some(PackageRequest {
+                    properties: Some(bundle_release.package_properties.clone()),
+                    important: Some(map_keys(&bundle_release.important)?),
+                    lazy: Some(map_keys(&bundle_release.lazy)?),
+                }),
+                dimensions: Some(bundle_release.dimensions.clone()),
+                resources: Some(map_keys(&bundle_release.resources)?),
+            };
+            Some(
+                release::create_release_for_application(
+                    Json(request),
+                    organisation.clone(),
+                    application.clone(),
+                    state.clone(),
+                )
+                .await?,
+            )
+        }
+        _ => None,
+    };
+
+    Ok(BundleImportResponse {
+        package: db_response_to_package(package),
+        created_files,
+        reused_files,
+        release,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const KEY: &str = "test-signing-key";
+
+    fn sample_manifest() -> ServeReleaseResponse {
+        ServeReleaseResponse {
+            version: "2".to_string(),
+            config: Config {
+                boot_timeout: 1000,
+                release_config_timeout: 1000,
+                version: "v1".to_string(),
+                properties: None,
+            },
+            package: ServePackage {
+                name: "checkout".to_string(),
+                version: "1".to_string(),
+                index: ServeFile {
+                    file_path: "index.js".to_string(),
+                    url: "https://cdn.example.com/index.js".to_string(),
+                    checksum: String::new(),
+                    size: 0,
+                    variants: Vec::new(),
+                },
+                properties: json!({}),
+                important: Vec::new(),
+                lazy: Vec::new(),
+            },
+            resources: Vec::new(),
+        }
+    }
+
+    fn sample_bundle() -> Vec<u8> {
+        let content = b"console.log('hello');".to_vec();
+        let checksum = sha256_hex(&content);
+        let file = BundleFile {
+            key: "index.js@version:1".to_string(),
+            file_path: "index.js".to_string(),
+            version: 1,
+            tag: None,
+            size: content.len() as i64,
+            checksum,
+            metadata: json!({}),
+            entry: "files/index.js@1".to_string(),
+        };
+        let descriptor = BundleDescriptor {
+            format_version: BUNDLE_FORMAT_VERSION,
+            kind: BundleKind::Package,
+            source: BundleSource {
+                organisation: "acme".to_string(),
+                application: "checkout".to_string(),
+                release_id: None,
+            },
+            created_at: Utc::now(),
+            manifest_checksum: String::new(),
+            package: BundlePackage {
+                version: 1,
+                tag: None,
+                index: file.key.clone(),
+                files: vec![file.key.clone()],
+            },
+            files: Vec::new(),
+            release: None,
+        };
+        write_archive(KEY, descriptor, &sample_manifest(), vec![(file, content)])
+            .expect("bundle should be written")
+    }
+
+    #[test]
+    fn round_trips_a_signed_bundle() {
+        let bundle = read_archive(KEY, sample_bundle(), 1024).expect("bundle should verify");
+        assert_eq!(bundle.descriptor.files.len(), 1);
+        assert_eq!(
+            bundle.contents.get("index.js@version:1").map(Vec::as_slice),
+            Some(b"console.log('hello');".as_slice())
+        );
+    }
+
+    #[test]
+    fn rejects_a_bundle_signed_with_another_key() {
+        let result = read_archive("another-key", sample_bundle(), 1024);
+        assert!(matches!(result, Err(ABError::BadRequest(_))));
+    }
+
+    #[test]
+    fn rejects_a_bundle_that_unpacks_past_the_limit() {
+        let result = read_archive(KEY, sample_bundle(), 8);
+        assert!(matches!(result, Err(ABError::BadRequest(_))));
+    }
+
+    #[test]
+    fn stops_reading_an_entry_past_its_limit() {
+        let mut archive = Vec::new();
+        let mut writer = ZipWriter::new(Cursor::new(&mut archive));
+        writer
+            .start_file::<_, ()>("files/big", FileOptions::default())
+            .unwrap();
+        writer.write_all(&[0u8; 4096]).unwrap();
+        writer.finish().unwrap();
+
+        let mut archive = ZipArchive::new(Cursor::new(archive)).unwrap();
+        assert!(read_entry(&mut archive, "files/big", 4096).is_ok());
+        assert!(matches!(
+            read_entry(&mut archive, "files/big", 1024),
+            Err(ABError::BadRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,140 @@
+//! Size breakdown of a package, used by `GET /packages/{version}/report`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::utils::db::models::{FileEntry, PackageV2Entry};
+
+use super::{budget, types::*};
+
+/// Keys of every file in the package, index first.
+pub fn package_file_keys(package: &PackageV2Entry) -> Vec<String> {
+    let mut keys = vec![package.index.clone()];
+    for key in package.files.iter().flatten() {
+        if !keys.contains(key) {
+            keys.push(key.clone());
+        }
+    }
+    keys
+}
+
+fn report_files(keys: &[String], entries: &[FileEntry]) -> Vec<PackageReportFile> {
+    keys.iter()
+        .filter_map(|key| crate::package::bundle::find_file_for_key(entries, key))
+        .map(|entry| PackageReportFile {
+            key: budget::file_key(entry),
+            file_path: entry.file_path.clone(),
+            size: entry.size,
+        })
+        .collect()
+}
+
+fn directory_of(file_path: &str) -> String {
+    match file_path.rsplit_once('/') {
+        Some((dir, _)) if !dir.is_empty() => dir.to_string(),
+        _ => ".".to_string(),
+    }
+}
+
+pub fn build_report(
+    package: &PackageV2Entry,
+    entries: &[FileEntry],
+    previous: Option<(&PackageV2Entry, &[FileEntry])>,
+    size_budget: Option<SizeBudget>,
+) -> PackageReport {
+    let mut files = report_files(&package_file_keys(package), entries);
+    files.sort_by(|a, b| b.size.cmp(&a.size).then(a.file_path.cmp(&b.file_path)));
+    let total_size = files.iter().map(|f| f.size).sum::<i64>();
+
+    let mut by_directory: BTreeMap<String, (i64, usize)> = BTreeMap::new();
+    for file in &files {
+        let slot = by_directory
+            .entry(directory_of(&file.file_path))
+            .or_default();
+        slot.0 += file.size;
+        slot.1 += 1;
+    }
+    let mut directories = by_directory
+        .into_iter()
+        .map(|(directory, (size, file_count))| PackageReportDirectory {
+            directory,
+            size,
+            file_count,
+        })
+        .collect::<Vec<_>>();
+    directories.sort_by(|a, b| b.size.cmp(&a.size));
+
+    let changes = previous.map(|(previous_package, previous_entries)| {
+        let previous_files = report_files(&package_file_keys(previous_package), previous_entries);
+        let previous_total_size = previous_files.iter().map(|f| f.size).sum::<i64>();
+        let previous_by_path = previous_files
+            .iter()
+            .map(|f| (f.file_path.as_str(), f))
+            .collect::<HashMap<_, _>>();
+        let current_by_path = files
+            .iter()
+            .map(|f| (f.file_path.as_str(), f))
+            .collect::<HashMap<_, _>>();
+
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        for file in &files {
+            match previous_by_path.get(file.file_path.as_str()) {
+                None => added.push(file.clone()),
+                Some(prev) if prev.key != file.key => changed.push(PackageReportFileChange {
+                    file_path: file.file_path.clone(),
+                    previous_key: prev.key.clone(),
+                    key: file.key.clone(),
+                    previous_size: prev.size,
+                    size: file.size,
+                    size_delta: file.size - prev.size,
+                }),
+                Some(_) => {}
+            }
+        }
+        changed.sort_by_key(|c| std::cmp::Reverse(c.size_delta.abs()));
+        let removed = previous_files
+            .iter()
+            .filter(|f| !current_by_path.contains_key(f.file_path.as_str()))
+            .cloned()
+            .collect();
+
+        PackageReportChanges {
+            previous_version: previous_package.version,
+            previous_total_size,
+            size_delta: total_size - previous_total_size,
+            added,
+            removed,
+            changed,
+        }
+    });
+
+    // The important/lazy split belongs to a release, so only the per-file
+    // limit can be checked against a bare package.
+    let budget_violations = size_budget
+        .as_ref()
+        .map(|b| {
+            let package_entries = files
+                .iter()
+                .filter_map(|f| crate::package::bundle::find_file_for_key(entries, &f.key))
+                .collect::<Vec<_>>();
+            let per_file = SizeBudget {
+                max_file_bytes: b.max_file_bytes,
+                ..Default::default()
+            };
+            budget::find_violations(&per_file, &[], &[], &package_entries)
+        })
+        .unwrap_or_default();
+
+    PackageReport {
+        version: package.version,
+        tag: package.tag.clone(),
+        index: package.index.clone(),
+        total_size,
+        file_count: files.len(),
+        files,
+        directories,
+        changes,
+        budget: size_budget,
+        budget_violations,
+    }
+}
@@ -1,4 +1,11 @@
+use std::collections::{BTreeMap, HashMap};
+
+use actix_multipart::form::{tempfile::TempFile, text::Text, MultipartForm};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::release::types::CreateReleaseResponse;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Package {
@@ -24,3 +31,138 @@ pub struct GetPackageQuery {
 pub struct ListPackageQuery {
     pub search: Option<String>,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct ExportPackageQuery {
+    pub package_key: String,
+}
+
+#[derive(MultipartForm)]
+pub struct ImportBundleRequest {
+    pub file: TempFile,
+    pub create_release: Option<Text<bool>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BundleKind {
+    Package,
+    Release,
+}
+
+/// Describes the contents of an exported bundle. This document is what gets
+/// signed; the manifest and every file are covered through their checksums.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleDescriptor {
+    pub format_version: u32,
+    pub kind: BundleKind,
+    pub source: BundleSource,
+    pub created_at: DateTime<Utc>,
+    pub manifest_checksum: String,
+    pub package: BundlePackage,
+    pub files: Vec<BundleFile>,
+    pub release: Option<BundleRelease>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleSource {
+    pub organisation: String,
+    pub application: String,
+    pub release_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundlePackage {
+    pub version: i32,
+    pub tag: Option<String>,
+    pub index: String,
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleFile {
+    pub key: String,
+    pub file_path: String,
+    pub version: i32,
+    pub tag: Option<String>,
+    pub size: i64,
+    pub checksum: String,
+    pub metadata: Value,
+    pub entry: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleRelease {
+    pub boot_timeout: u64,
+    pub release_config_timeout: u64,
+    pub config_properties: BTreeMap<String, Value>,
+    pub package_properties: Value,
+    pub important: Vec<String>,
+    pub lazy: Vec<String>,
+    pub resources: Vec<String>,
+    pub dimensions: HashMap<String, Value>,
+}
+
+#[derive(Serialize)]
+pub struct BundleImportResponse {
+    pub package: Package,
+    pub created_files: Vec<String>,
+    pub reused_files: Vec<String>,
+    pub release: Option<CreateReleaseResponse>,
+}
+
+/// Per-application size limits, in bytes. `None` means unlimited.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SizeBudget {
+    pub max_important_bytes: Option<i64>,
+    pub max_lazy_bytes: Option<i64>,
+    pub max_file_bytes: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PackageReportFile {
+    pub key: String,
+    pub file_path: String,
+    pub size: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageReportDirectory {
+    pub directory: String,
+    pub size: i64,
+    pub file_count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageReportFileChange {
+    pub file_path: String,
+    pub previous_key: String,
+    pub key: String,
+    pub previous_size: i64,
+    pub size: i64,
+    pub size_delta: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageReportChanges {
+    pub previous_version: i32,
+    pub previous_total_size: i64,
+    pub size_delta: i64,
+    pub added: Vec<PackageReportFile>,
+    pub removed: Vec<PackageReportFile>,
+    pub changed: Vec<PackageReportFileChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageReport {
+    pub version: i32,
+    pub tag: Option<String>,
+    pub index: String,
+    pub total_size: i64,
+    pub file_count: usize,
+    pub files: Vec<PackageReportFile>,
+    pub directories: Vec<PackageReportDirectory>,
+    pub changes: Option<PackageReportChanges>,
+    pub budget: Option<SizeBudget>,
+    pub budget_violations: Vec<String>,
+}
@@ -19,7 +19,7 @@ use sha2::{Digest, Sha256};
 use tokio::sync::RwLock;
 
 use crate::{
-    types as airborne_types,
+    session, types as airborne_types,
     types::{ABError, AppState, AuthnProviderKind, Environment},
     user::types::{LoginFailure, TokenResponse, UserCredentials, UserToken},
     utils::redis::{RedisCache, RedisKey},
@@ -29,6 +29,7 @@ pub mod auth0;
 pub mod keycloak;
 pub mod oidc;
 pub mod okta;
+pub mod saml;
 
 const OIDC_CACHE_TTL: Duration = Duration::from_secs(300);
 const OAUTH_PKCE_STATE_TTL: Duration = Duration::from_secs(600);
@@ -470,15 +471,43 @@ pub trait AuthNProvider: Send + Sync {
     ) -> airborne_types::Result<TokenData<AuthnTokenClaims>> {
         verify_authn_token(access_token, &state.env).await
     }
+
+    fn saml_metadata(&self) -> airborne_types::Result<String> {
+        Err(ABError::BadRequest(
+            "SAML is not supported for configured AuthN provider".to_string(),
+        ))
+    }
+
+    /// Validates a response posted to the assertion consumer service and
+    /// returns the URL the browser should continue to.
+    async fn complete_saml_login(
+        &self,
+        _state: &AppState,
+        _saml_response: &str,
+        _relay_state: Option<&str>,
+    ) -> airborne_types::Result<String> {
+        Err(ABError::BadRequest(
+            "SAML is not supported for configured AuthN provider".to_string(),
+        ))
+    }
 }
 
-pub fn build_authn_provider(kind: AuthnProviderKind) -> Arc<dyn AuthNProvider> {
-    match kind {
+pub fn build_authn_provider(
+    kind: AuthnProviderKind,
+    saml_settings: Option<saml::SamlSettings>,
+) -> airborne_types::Result<Arc<dyn AuthNProvider>> {
+    Ok(match kind {
         AuthnProviderKind::Keycloak => Arc::new(keycloak::KeycloakAuthNProvider),
         AuthnProviderKind::Oidc => Arc::new(oidc::OidcAuthNProvider),
         AuthnProviderKind::Okta => Arc::new(okta::OktaAuthNProvider),
         AuthnProviderKind::Auth0 => Arc::new(auth0::Auth0AuthNProvider),
-    }
+        AuthnProviderKind::Saml => {
+            let settings = saml_settings.ok_or_else(|| {
+                ABError::InternalServerError("SAML settings are missing".to_string())
+            })?;
+            Arc::new(saml::SamlAuthNProvider::new(settings)?)
+        }
+    })
 }
 
 fn redirect_uri(state: &AppState) -> String {
@@ -693,39 +722,44 @@ pub async fn password_login_common(
     to_user_token(&token_response)
 }
 
+/// Refreshes through the IdP. The session registry makes the presented
+/// refresh token single-use and records the new tokens.
 pub async fn refresh_access_token_common(
     state: &AppState,
     refresh_token: &str,
 ) -> airborne_types::Result<UserToken> {
-    let oidc_data = fetch_oidc_data(&state.env, false).await?;
-    let client = CoreClient::from_provider_metadata(
-        oidc_data.provider_metadata,
-        ClientId::new(state.env.authn_client_id.clone()),
-        Some(ClientSecret::new(state.env.authn_client_secret.clone())),
-    )
-    .set_redirect_uri(oidc_redirect_url(state)?);
-    let refresh = RefreshToken::new(refresh_token.to_string());
+    session::rotate_refresh_token(state, refresh_token, async {
+        let oidc_data = fetch_oidc_data(&state.env, false).await?;
+        let client = CoreClient::from_provider_metadata(
+            oidc_data.provider_metadata,
+            ClientId::new(state.env.authn_client_id.clone()),
+            Some(ClientSecret::new(state.env.authn_client_secret.clone())),
+        )
+        .set_redirect_uri(oidc_redirect_url(state)?);
+        let refresh = RefreshToken::new(refresh_token.to_string());
 
-    let token_response = client
-        .exchange_refresh_token(&refresh)
-        .map_err(|error| {
-            map_configuration_error(
-                "OIDC client is missing token endpoint for refresh token flow",
-                error,
-            )
-        })?
-        .request_async(oidc_http_client())
-        .await
-        .map_err(|error| {
-            let error_text = error.to_string();
-            let login_err = LoginFailure {
-                error: "Unknown error".to_string(),
-                error_description: error_text,
-            };
-            ABError::Unauthorized(login_err.error_description)
-        })?;
+        let token_response = client
+            .exchange_refresh_token(&refresh)
+            .map_err(|error| {
+                map_configuration_error(
+                    "OIDC client is missing token endpoint for refresh token flow",
+                    error,
+                )
+            })?
+            .request_async(oidc_http_client())
+            .await
+            .map_err(|error| {
+                let error_text = error.to_string();
+                let login_err = LoginFailure {
+                    error: "Unknown error".to_string(),
+                    error_description: error_text,
+                };
+                ABError::Unauthorized(login_err.error_description)
+            })?;
 
-    to_user_token(&token_response)
+        to_user_token(&token_response)
+    })
+    .await
 }
 
 #[cfg(test)]
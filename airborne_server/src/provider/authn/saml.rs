@@ -0,0 +1,826 @@
+//! SAML 2.0 service provider for tenants whose IdP does not speak OIDC.
+//!
+//! Login reuses the dashboard's OAuth round trip: `GET /users/oauth/url`
+//! returns an HTTP-Redirect AuthnRequest, the IdP posts its response to
+//! `POST /users/saml/acs`, and once the assertion checks out the browser is
+//! sent to `/oauth/callback` with a one-time code that
+//! `POST /users/oauth/login` exchanges for tokens. Since the IdP issues no
+//! bearer tokens, Airborne signs its own HS256 session tokens, which
+//! `verify_access_token` checks for the auth middleware.
+
+mod signature;
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Duration, Utc};
+use flate2::{write::DeflateEncoder, Compression};
+use jsonwebtoken::{
+    decode, encode, Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
+};
+use log::info;
+use openidconnect::CsrfToken;
+use openssl::{
+    pkey::{PKey, Public},
+    x509::X509,
+};
+use roxmltree::Node;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    provider::authn::{
+        redirect_uri, AuthNProvider, AuthnTokenClaims, OAuthUrlResponse, OAUTH_PKCE_STATE_TTL,
+    },
+    session, types as airborne_types,
+    types::{ABError, AppState, AuthnProviderKind},
+    user::types::{TokenResponse, UserToken},
+    utils::redis::{RedisCache, RedisKey},
+};
+
+const PROTOCOL_NS: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+const ASSERTION_NS: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+const STATUS_SUCCESS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+const BEARER_CONFIRMATION: &str = "urn:oasis:names:tc:SAML:2.0:cm:bearer";
+const HTTP_POST_BINDING: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST";
+const LOGIN_CODE_TTL_SECS: usize = 60;
+
+#[derive(Clone, Debug)]
+pub struct SamlSettings {
+    pub idp_entity_id: String,
+    pub idp_sso_url: String,
+    /// PEM, or the bare base64 DER found in IdP metadata.
+    pub idp_certificate: String,
+    pub sp_entity_id: String,
+    pub acs_url: String,
+    pub session_secret: String,
+    pub session_ttl_secs: i64,
+    pub refresh_ttl_secs: i64,
+    pub email_attribute: String,
+    pub username_attribute: String,
+}
+
+pub struct SamlAuthNProvider {
+    settings: SamlSettings,
+    idp_key: PKey<Public>,
+}
+
+/// Identity asserted by the IdP once the response has been validated.
+#[derive(Debug)]
+struct SamlIdentity {
+    name_id: String,
+    attributes: HashMap<String, Vec<String>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SessionTokenUse {
+    Access,
+    Refresh,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionClaims {
+    sub: String,
+    preferred_username: Option<String>,
+    email: Option<String>,
+    iss: String,
+    aud: String,
+    iat: i64,
+    exp: i64,
+    // Keeps tokens issued within the same second distinct, which the session
+    // registry relies on to tell a rotated refresh token from its successor.
+    #[serde(default)]
+    jti: String,
+    token_use: SessionTokenUse,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingRequest {
+    request_id: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+    state: String,
+    token: TokenResponse,
+}
+
+fn saml_key(cache: &RedisCache, kind: &str, secret: &str) -> RedisKey {
+    let secret_hash = hex::encode(Sha256::digest(secret.as_bytes()));
+    cache.key_unlabeled("global", "saml", &[kind, &secret_hash])
+}
+
+fn require_cache(state: &AppState) -> airborne_types::Result<&RedisCache> {
+    state.redis_cache.as_ref().ok_or_else(|| {
+        ABError::InternalServerError("SAML login requires Redis to be configured".to_string())
+    })
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn rejected(reason: &str) -> ABError {
+    ABError::Unauthorized(format!("SAML response rejected: {reason}"))
+}
+
+fn element<'a, 'input>(
+    node: Node<'a, 'input>,
+    namespace: &str,
+    name: &str,
+) -> airborne_types::Result<Node<'a, 'input>> {
+    signature::child(node, namespace, name).ok_or_else(|| rejected(&format!("missing {name}")))
+}
+
+/// All text below `node`. Comments are not part of the signed content, so
+/// text split around one must be read as a whole.
+fn text_content(node: Node) -> String {
+    node.descendants()
+        .filter(|child| child.is_text())
+        .filter_map(|child| child.text())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+fn parse_instant(node: Node, attribute: &str) -> airborne_types::Result<Option<DateTime<Utc>>> {
+    node.attribute(attribute)
+        .map(|value| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|instant| instant.with_timezone(&Utc))
+                .map_err(|_| rejected(&format!("malformed {attribute}")))
+        })
+        .transpose()
+}
+
+impl SamlAuthNProvider {
+    pub fn new(settings: SamlSettings) -> airborne_types::Result<Self> {
+        let certificate = if settings.idp_certificate.contains("-----BEGIN") {
+            X509::from_pem(settings.idp_certificate.as_bytes())
+        } else {
+            let der = general_purpose::STANDARD
+                .decode(
+                    settings
+                        .idp_certificate
+                        .split_whitespace()
+                        .collect::<String>(),
+                )
+                .map_err(|error| {
+                    ABError::InternalServerError(format!(
+                        "SAML_IDP_CERTIFICATE is neither PEM nor base64 DER: {error}"
+                    ))
+                })?;
+            X509::from_der(&der)
+        };
+        let idp_key = certificate
+            .and_then(|certificate| certificate.public_key())
+            .map_err(|error| {
+                ABError::InternalServerError(format!("Invalid SAML IdP certificate: {error}"))
+            })?;
+        Ok(Self { settings, idp_key })
+    }
+
+    /// SP metadata to register Airborne with the IdP.
+    fn metadata(&self) -> String {
+        format!(
+            concat!(
+                r#"<md:EntityDescriptor xmlns:md="urn:oasis:names:tc:SAML:2.0:metadata" entityID="{entity_id}">"#,
+                r#"<md:SPSSODescriptor AuthnRequestsSigned="false" WantAssertionsSigned="true" protocolSupportEnumeration="{protocol}">"#,
+                r#"<md:NameIDFormat>urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress</md:NameIDFormat>"#,
+                r#"<md:AssertionConsumerService Binding="{binding}" Location="{acs}" index="0" isDefault="true"/>"#,
+                r#"</md:SPSSODescriptor>"#,
+                r#"</md:EntityDescriptor>"#
+            ),
+            entity_id = escape_xml(&self.settings.sp_entity_id),
+            protocol = PROTOCOL_NS,
+            binding = HTTP_POST_BINDING,
+            acs = escape_xml(&self.settings.acs_url),
+        )
+    }
+
+    fn authn_request(&self, request_id: &str, issued_at: DateTime<Utc>) -> String {
+        format!(
+            concat!(
+                r#"<samlp:AuthnRequest xmlns:samlp="{protocol}" xmlns:saml="{assertion}" ID="{id}" Version="2.0" IssueInstant="{instant}" "#,
+                r#"Destination="{destination}" AssertionConsumerServiceURL="{acs}" ProtocolBinding="{binding}">"#,
+                r#"<saml:Issuer>{issuer}</saml:Issuer>"#,
+                r#"<samlp:NameIDPolicy AllowCreate="true"/>"#,
+                r#"</samlp:AuthnRequest>"#
+            ),
+            protocol = PROTOCOL_NS,
+            assertion = ASSERTION_NS,
+            id = request_id,
+            instant = issued_at.format("%Y-%m-%dT%H:%M:%SZ"),
+            destination = escape_xml(&self.settings.idp_sso_url),
+            acs = escape_xml(&self.settings.acs_url),
+            binding = HTTP_POST_BINDING,
+            issuer = escape_xml(&self.settings.sp_entity_id),
+        )
+    }
+
+    /// HTTP-Redirect binding: deflated, base64-encoded request in the query.
+    fn redirect_url(&self, request: &str, relay_state: &str) -> airborne_types::Result<String> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(request.as_bytes())?;
+        let deflated = encoder.finish()?;
+
+        let mut url = url::Url::parse(&self.settings.idp_sso_url).map_err(|error| {
+            ABError::InternalServerError(format!("Invalid SAML_IDP_SSO_URL: {error}"))
+        })?;
+        url.query_pairs_mut()
+            .append_pair("SAMLRequest", &general_purpose::STANDARD.encode(deflated))
+            .append_pair("RelayState", relay_state);
+        Ok(url.to_string())
+    }
+
+    /// Checks a decoded `<samlp:Response>` against the request it answers and
+    /// returns the asserted identity.
+    fn validate_response(
+        &self,
+        xml: &str,
+        request_id: &str,
+        now: DateTime<Utc>,
+        skew: Duration,
+    ) -> airborne_types::Result<SamlIdentity> {
+        let document = roxmltree::Document::parse(xml)
+            .map_err(|error| ABError::BadRequest(format!("Malformed SAML response: {error}")))?;
+        let response = document.root_element();
+        if !response.has_tag_name((PROTOCOL_NS, "Response")) {
+            return Err(ABError::BadRequest(
+                "Expected a SAML Response document".to_string(),
+            ));
+        }
+
+        // Signature wrapping relies on duplicated IDs or extra assertions, so
+        // neither is accepted and the assertion read below is the one whose
+        // signature (or whose parent's) was checked.
+        let mut ids = HashSet::new();
+        for id in document
+            .descendants()
+            .filter_map(|node| node.attribute("ID"))
+        {
+            if !ids.insert(id) {
+                return Err(rejected("duplicate ID"));
+            }
+        }
+        if document
+            .descendants()
+            .any(|node| node.has_tag_name((ASSERTION_NS, "EncryptedAssertion")))
+        {
+            return Err(rejected("encrypted assertions are not supported"));
+        }
+        let assertions: Vec<Node> = document
+            .descendants()
+            .filter(|node| node.has_tag_name((ASSERTION_NS, "Assertion")))
+            .collect();
+        let [assertion] = assertions.as_slice() else {
+            return Err(rejected("expected exactly one assertion"));
+        };
+        let assertion = *assertion;
+        if assertion.parent_element().map(|parent| parent.id()) != Some(response.id()) {
+            return Err(rejected("assertion is not a child of the response"));
+        }
+
+        let response_signed = signature::enveloped_signature(response).is_some();
+        if response_signed {
+            signature::verify_enveloped(response, &self.idp_key)?;
+        }
+        if signature::enveloped_signature(assertion).is_some() {
+            signature::verify_enveloped(assertion, &self.idp_key)?;
+        } else if !response_signed {
+            return Err(rejected("neither the response nor the assertion is signed"));
+        }
+
+        let status = element(response, PROTOCOL_NS, "Status")
+            .and_then(|status| element(status, PROTOCOL_NS, "StatusCode"))?;
+        let status_code = status.attribute("Value").unwrap_or_default();
+        if status_code != STATUS_SUCCESS {
+            return Err(ABError::Unauthorized(format!(
+                "SAML login failed at the IdP: {status_code}"
+            )));
+        }
+        if response
+            .attribute("Destination")
+            .is_some_and(|destination| destination != self.settings.acs_url)
+        {
+            return Err(rejected("unexpected Destination"));
+        }
+        if response.attribute("InResponseTo") != Some(request_id) {
+            return Err(rejected("response does not answer this login request"));
+        }
+
+        if text_content(element(assertion, ASSERTION_NS, "Issuer")?) != self.settings.idp_entity_id
+        {
+            return Err(rejected("unexpected Issuer"));
+        }
+
+        let subject = element(assertion, ASSERTION_NS, "Subject")?;
+        let name_id = text_content(element(subject, ASSERTION_NS, "NameID")?);
+        if name_id.is_empty() {
+            return Err(rejected("empty NameID"));
+        }
+        let mut confirmed = false;
+        for confirmation in subject
+            .children()
+            .filter(|node| node.has_tag_name((ASSERTION_NS, "SubjectConfirmation")))
+            .filter(|node| node.attribute("Method") == Some(BEARER_CONFIRMATION))
+        {
+            let Some(data) =
+                signature::child(confirmation, ASSERTION_NS, "SubjectConfirmationData")
+            else {
+                continue;
+            };
+            let fresh = parse_instant(data, "NotOnOrAfter")?
+                .is_some_and(|not_on_or_after| now - skew < not_on_or_after);
+            if fresh
+                && data.attribute("Recipient") == Some(self.settings.acs_url.as_str())
+                && data
+                    .attribute("InResponseTo")
+                    .is_none_or(|in_response_to| in_response_to == request_id)
+            {
+                confirmed = true;
+                break;
+            }
+        }
+        if !confirmed {
+            return Err(rejected("no valid bearer subject confirmation"));
+        }
+
+        let conditions = element(assertion, ASSERTION_NS, "Conditions")?;
+        if parse_instant(conditions, "NotBefore")?.is_some_and(|not_before| now + skew < not_before)
+        {
+            return Err(rejected("assertion is not yet valid"));
+        }
+        if parse_instant(conditions, "NotOnOrAfter")?
+            .is_some_and(|not_on_or_after| now - skew >= not_on_or_after)
+        {
+            return Err(rejected("assertion has expired"));
+        }
+        let restrictions: Vec<Node> = conditions
+            .children()
+            .filter(|node| node.has_tag_name((ASSERTION_NS, "AudienceRestriction")))
+            .collect();
+        let audience_matches = |restriction: &Node| {
+            restriction
+                .children()
+                .filter(|node| node.has_tag_name((ASSERTION_NS, "Audience")))
+                .any(|audience| text_content(audience) == self.settings.sp_entity_id)
+        };
+        if restrictions.is_empty() || !restrictions.iter().all(audience_matches) {
+            return Err(rejected(
+                "assertion is not addressed to this service provider",
+            ));
+        }
+
+        let mut attributes: HashMap<String, Vec<String>> = HashMap::new();
+        for statement in assertion
+            .children()
+            .filter(|node| node.has_tag_name((ASSERTION_NS, "AttributeStatement")))
+        {
+            for attribute in statement
+                .children()
+                .filter(|node| node.has_tag_name((ASSERTION_NS, "Attribute")))
+            {
+                let Some(name) = attribute.attribute("Name") else {
+                    continue;
+                };
+                attributes.entry(name.to_string()).or_default().extend(
+                    attribute
+                        .children()
+                        .filter(|node| node.has_tag_name((ASSERTION_NS, "AttributeValue")))
+                        .map(text_content)
+                        .filter(|value| !value.is_empty()),
+                );
+            }
+        }
+
+        Ok(SamlIdentity {
+            name_id,
+            attributes,
+        })
+    }
+
+    /// Maps the asserted identity onto the claims Airborne authorizes with.
+    /// The email attribute wins; an email-shaped NameID is the fallback.
+    fn claims(&self, identity: &SamlIdentity) -> AuthnTokenClaims {
+        let attribute = |name: &str| {
+            identity
+                .attributes
+                .get(name)
+                .and_then(|values| values.first())
+                .cloned()
+        };
+        let email = attribute(&self.settings.email_attribute).or_else(|| {
+            identity
+                .name_id
+                .contains('@')
+                .then(|| identity.name_id.clone())
+        });
+        AuthnTokenClaims {
+            sub: identity.name_id.clone(),
+            preferred_username: attribute(&self.settings.username_attribute)
+                .or_else(|| email.clone()),
+            email,
+            iss: Some(self.settings.sp_entity_id.clone()),
+        }
+    }
+
+    fn session_token(
+        &self,
+        claims: &AuthnTokenClaims,
+        token_use: SessionTokenUse,
+        ttl_secs: i64,
+    ) -> airborne_types::Result<String> {
+        let now = Utc::now().timestamp();
+        let session = SessionClaims {
+            sub: claims.sub.clone(),
+            preferred_username: claims.preferred_username.clone(),
+            email: claims.email.clone(),
+            iss: self.settings.sp_entity_id.clone(),
+            aud: self.settings.sp_entity_id.clone(),
+            iat: now,
+            exp: now + ttl_secs,
+            jti: uuid::Uuid::new_v4().to_string(),
+            token_use,
+        };
+        Ok(encode(
+            &Header::new(Algorithm::HS256),
+            &session,
+            &EncodingKey::from_secret(self.settings.session_secret.as_bytes()),
+        )?)
+    }
+
+    fn issue_tokens(&self, claims: &AuthnTokenClaims) -> airborne_types::Result<UserToken> {
+        Ok(UserToken {
+            access_token: self.session_token(
+                claims,
+                SessionTokenUse::Access,
+                self.settings.session_ttl_secs,
+            )?,
+            token_type: "Bearer".to_string(),
+            expires_in: self.settings.session_ttl_secs,
+            refresh_token: self.session_token(
+                claims,
+                SessionTokenUse::Refresh,
+                self.settings.refresh_ttl_secs,
+            )?,
+            refresh_expires_in: self.settings.refresh_ttl_secs,
+        })
+    }
+
+    fn decode_session(
+        &self,
+        token: &str,
+        token_use: SessionTokenUse,
+        leeway: u64,
+    ) -> airborne_types::Result<TokenData<AuthnTokenClaims>> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_audience(&[self.settings.sp_entity_id.as_str()]);
+        validation.set_issuer(&[self.settings.sp_entity_id.as_str()]);
+        validation.leeway = leeway;
+        let token_data = decode::<SessionClaims>(
+            token,
+            &DecodingKey::from_secret(self.settings.session_secret.as_bytes()),
+            &validation,
+        )
+        .map_err(|error| ABError::Unauthorized(format!("Invalid session token: {error}")))?;
+        if token_data.claims.token_use != token_use {
+            return Err(ABError::Unauthorized(
+                "Invalid session token: wrong token type".to_string(),
+            ));
+        }
+        Ok(TokenData {
+            header: token_data.header,
+            claims: AuthnTokenClaims {
+                sub: token_data.claims.sub,
+                preferred_username: token_data.claims.preferred_username,
+                email: token_data.claims.email,
+                iss: Some(token_data.claims.iss),
+            },
+        })
+    }
+}
+
+#[async_trait]
+impl AuthNProvider for SamlAuthNProvider {
+    fn kind(&self) -> AuthnProviderKind {
+        AuthnProviderKind::Saml
+    }
+
+    fn supports_password_login(&self) -> bool {
+        false
+    }
+
+    fn supports_signup(&self) -> bool {
+        false
+    }
+
+    fn saml_metadata(&self) -> airborne_types::Result<String> {
+        Ok(self.metadata())
+    }
+
+    async fn get_oauth_url(
+        &self,
+        state: &AppState,
+        _offline: bool,
+        _idp_hint: Option<&str>,
+    ) -> airborne_types::Result<OAuthUrlResponse> {
+        let cache = require_cache(state)?;
+        let request_id = format!("_{}", uuid::Uuid::new_v4().simple());
+        let relay_state = CsrfToken::new_random().secret().to_string();
+        let auth_url =
+            self.redirect_url(&self.authn_request(&request_id, Utc::now()), &relay_state)?;
+
+        cache
+            .set_ex(
+                &saml_key(cache, "request", &relay_state),
+                &PendingRequest { request_id },
+                OAUTH_PKCE_STATE_TTL.as_secs() as usize,
+            )
+            .await?;
+
+        Ok(OAuthUrlResponse {
+            auth_url,
+            state: relay_state,
+        })
+    }
+
+    async fn complete_saml_login(
+        &self,
+        state: &AppState,
+        saml_response: &str,
+        relay_state: Option<&str>,
+    ) -> airborne_types::Result<String> {
+        let cache = require_cache(state)?;
+        let relay_state = relay_state
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| {
+                ABError::BadRequest(
+                    "Missing RelayState; IdP-initiated login is not supported".to_string(),
+                )
+            })?;
+        // One-time use: a replayed response finds no pending request.
+        let pending = cache
+            .get_del::<PendingRequest>(&saml_key(cache, "request", relay_state))
+            .await?
+            .ok_or_else(|| ABError::Unauthorized("Invalid or expired SAML login".to_string()))?;
+
+        let decoded = general_purpose::STANDARD
+            .decode(saml_response.split_whitespace().collect::<String>())
+            .map_err(|_| ABError::BadRequest("SAMLResponse is not valid base64".to_string()))?;
+        let xml = String::from_utf8(decoded)
+            .map_err(|_| ABError::BadRequest("SAMLResponse is not valid UTF-8".to_string()))?;
+        let identity = self.validate_response(
+            &xml,
+            &pending.request_id,
+            Utc::now(),
+            Duration::seconds(state.env.authn_clock_skew_secs as i64),
+        )?;
+        info!("[SAML] Accepted assertion for {}", identity.name_id);
+
+        let token = self.issue_tokens(&self.claims(&identity))?;
+        let code = CsrfToken::new_random().secret().to_string();
+        cache
+            .set_ex(
+                &saml_key(cache, "code", &code),
+                &PendingLogin {
+                    state: relay_state.to_string(),
+                    token: TokenResponse {
+                        access_token: token.access_token,
+                        token_type: token.token_type,
+                        expires_in: token.expires_in,
+                        refresh_token: Some(token.refresh_token),
+                        refresh_expires_in: Some(token.refresh_expires_in),
+                        id_token: None,
+                    },
+                },
+                LOGIN_CODE_TTL_SECS,
+            )
+            .await?;
+
+        Ok(format!(
+            "{}?code={}&state={}",
+            redirect_uri(state),
+            urlencoding::encode(&code),
+            urlencoding::encode(relay_state)
+        ))
+    }
+
+    async fn exchange_code_for_token(
+        &self,
+        state: &AppState,
+        code: &str,
+        oauth_state: Option<&str>,
+    ) -> airborne_types::Result<TokenResponse> {
+        let cache = require_cache(state)?;
+        let pending = cache
+            .get_del::<PendingLogin>(&saml_key(cache, "code", code))
+            .await?
+            .ok_or_else(|| ABError::Unauthorized("Invalid or expired login code".to_string()))?;
+        if oauth_state != Some(pending.state.as_str()) {
+            return Err(ABError::Unauthorized(
+                "Login code does not match the OAuth state".to_string(),
+            ));
+        }
+        Ok(pending.token)
+    }
+
+    async fn refresh_access_token(
+        &self,
+        state: &AppState,
+        refresh_token: &str,
+    ) -> airborne_types::Result<UserToken> {
+        session::rotate_refresh_token(state, refresh_token, async {
+            let token_data = self.decode_session(
+                refresh_token,
+                SessionTokenUse::Refresh,
+                state.env.authn_clock_skew_secs,
+            )?;
+            self.issue_tokens(&token_data.claims)
+        })
+        .await
+    }
+
+    async fn verify_access_token(
+        &self,
+        state: &AppState,
+        access_token: &str,
+    ) -> airborne_types::Result<TokenData<AuthnTokenClaims>> {
+        self.decode_session(
+            access_token,
+            SessionTokenUse::Access,
+            state.env.authn_clock_skew_secs,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::{
+        asn1::Asn1Time, bn::BigNum, hash::MessageDigest, pkey::Private, rsa::Rsa, sign::Signer,
+        x509::X509Builder,
+    };
+
+    const ACS_URL: &str = "https://airborne.example.com/api/users/saml/acs";
+    const SP_ENTITY_ID: &str = "https://airborne.example.com";
+    const IDP_ENTITY_ID: &str = "https://idp.example.com/saml";
+
+    fn idp_certificate() -> (PKey<Private>, String) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder
+            .set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap())
+            .unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        let pem = String::from_utf8(builder.build().to_pem().unwrap()).unwrap();
+        (key, pem)
+    }
+
+    /// Signs the assertion the way an IdP would: digest the canonical
+    /// assertion, then sign the canonical SignedInfo placed inside it.
+    fn signed_response(key: &PKey<Private>, name_id: &str) -> String {
+        let assertion = format!(
+            concat!(
+                r#"<saml:Assertion xmlns:saml="{ns}" ID="_a1" Version="2.0" IssueInstant="2026-10-19T10:00:00Z">"#,
+                r#"<saml:Issuer>{idp}</saml:Issuer>"#,
+                r#"<saml:Subject><saml:NameID>{name_id}</saml:NameID>"#,
+                r#"<saml:SubjectConfirmation Method="urn:oasis:names:tc:SAML:2.0:cm:bearer">"#,
+                r#"<saml:SubjectConfirmationData InResponseTo="_req" NotOnOrAfter="2026-10-19T10:05:00Z" Recipient="{acs}"/>"#,
+                r#"</saml:SubjectConfirmation></saml:Subject>"#,
+                r#"<saml:Conditions NotBefore="2026-10-19T09:59:00Z" NotOnOrAfter="2026-10-19T10:05:00Z">"#,
+                r#"<saml:AudienceRestriction><saml:Audience>{sp}</saml:Audience></saml:AudienceRestriction>"#,
+                r#"</saml:Conditions>"#,
+                r#"<saml:AttributeStatement><saml:Attribute Name="email">"#,
+                r#"<saml:AttributeValue>Jane@Example.com</saml:AttributeValue>"#,
+                r#"</saml:Attribute></saml:AttributeStatement>"#,
+                r#"</saml:Assertion>"#
+            ),
+            ns = ASSERTION_NS,
+            idp = IDP_ENTITY_ID,
+            name_id = name_id,
+            acs = ACS_URL,
+            sp = SP_ENTITY_ID,
+        );
+        let document = roxmltree::Document::parse(&assertion).unwrap();
+        let digest = openssl::hash::hash(
+            MessageDigest::sha256(),
+            signature::canonicalize(document.root_element(), None, &[]).as_bytes(),
+        )
+        .unwrap();
+        let signed_info = format!(
+            concat!(
+                r#"<ds:SignedInfo xmlns:ds="{ds}">"#,
+                r#"<ds:CanonicalizationMethod Algorithm="http://www.w3.org/2001/10/xml-exc-c14n#"/>"#,
+                r#"<ds:SignatureMethod Algorithm="http://www.w3.org/2001/04/xmldsig-more#rsa-sha256"/>"#,
+                r##"<ds:Reference URI="#_a1"><ds:Transforms>"##,
+                r#"<ds:Transform Algorithm="http://www.w3.org/2000/09/xmldsig#enveloped-signature"/>"#,
+                r#"<ds:Transform Algorithm="http://www.w3.org/2001/10/xml-exc-c14n#"/>"#,
+                r#"</ds:Transforms>"#,
+                r#"<ds:DigestMethod Algorithm="http://www.w3.org/2001/04/xmlenc#sha256"/>"#,
+                r#"<ds:DigestValue>{digest}</ds:DigestValue>"#,
+                r#"</ds:Reference></ds:SignedInfo>"#
+            ),
+            ds = signature::DSIG_NS,
+            digest = general_purpose::STANDARD.encode(digest),
+        );
+        let document = roxmltree::Document::parse(&signed_info).unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+        signer
+            .update(signature::canonicalize(document.root_element(), None, &[]).as_bytes())
+            .unwrap();
+        let signature_xml = format!(
+            r#"<ds:Signature xmlns:ds="{}">{}<ds:SignatureValue>{}</ds:SignatureValue></ds:Signature>"#,
+            signature::DSIG_NS,
+            signed_info.replacen(&format!(r#" xmlns:ds="{}""#, signature::DSIG_NS), "", 1),
+            general_purpose::STANDARD.encode(signer.sign_to_vec().unwrap()),
+        );
+        let assertion = assertion.replacen(
+            "</saml:Issuer>",
+            &format!("</saml:Issuer>{signature_xml}"),
+            1,
+        );
+        format!(
+            concat!(
+                r#"<samlp:Response xmlns:samlp="{protocol}" ID="_r1" Version="2.0" IssueInstant="2026-10-19T10:00:00Z" "#,
+                r#"Destination="{acs}" InResponseTo="_req">"#,
+                r#"<samlp:Status><samlp:StatusCode Value="{success}"/></samlp:Status>"#,
+                "\n  {assertion}\n",
+                r#"</samlp:Response>"#
+            ),
+            protocol = PROTOCOL_NS,
+            acs = ACS_URL,
+            success = STATUS_SUCCESS,
+            assertion = assertion,
+        )
+    }
+
+    #[test]
+    fn validates_signed_assertion_and_rejects_tampering() {
+        let (key, pem) = idp_certificate();
+        let provider = SamlAuthNProvider::new(SamlSettings {
+            idp_entity_id: IDP_ENTITY_ID.to_string(),
+            idp_sso_url: "https://idp.example.com/sso".to_string(),
+            idp_certificate: pem,
+            sp_entity_id: SP_ENTITY_ID.to_string(),
+            acs_url: ACS_URL.to_string(),
+            session_secret: "secret".to_string(),
+            session_ttl_secs: 3600,
+            refresh_ttl_secs: 86400,
+            email_attribute: "email".to_string(),
+            username_attribute: "username".to_string(),
+        })
+        .unwrap();
+        let now = DateTime::parse_from_rfc3339("2026-10-19T10:01:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let skew = Duration::seconds(60);
+        let response = signed_response(&key, "jane");
+
+        let identity = provider
+            .validate_response(&response, "_req", now, skew)
+            .unwrap();
+        let claims = provider.claims(&identity);
+        assert_eq!(claims.sub, "jane");
+        assert_eq!(claims.email.as_deref(), Some("Jane@Example.com"));
+
+        let token = provider.issue_tokens(&claims).unwrap();
+        let verified = provider
+            .decode_session(&token.access_token, SessionTokenUse::Access, 0)
+            .unwrap();
+        assert_eq!(verified.claims.email.as_deref(), Some("Jane@Example.com"));
+        assert!(provider
+            .decode_session(&token.refresh_token, SessionTokenUse::Access, 0)
+            .is_err());
+
+        let tampered = response.replacen(">jane<", ">admin<", 1);
+        assert!(provider
+            .validate_response(&tampered, "_req", now, skew)
+            .is_err());
+        assert!(provider
+            .validate_response(&response, "_other", now, skew)
+            .is_err());
+        let later = now + Duration::minutes(10);
+        assert!(provider
+            .validate_response(&response, "_req", later, skew)
+            .is_err());
+    }
+}
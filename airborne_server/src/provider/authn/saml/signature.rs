@@ -0,0 +1,319 @@
+//! Enveloped XML signature verification for SAML responses.
+//!
+//! Covers the profile IdPs use in practice: a single same-document reference
+//! with the enveloped-signature and exclusive canonicalization transforms,
+//! an RSA-SHA256/512 signature and a SHA-256/512 digest. The key always comes
+//! from the configured IdP certificate; `KeyInfo` in the document is ignored.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use base64::{engine::general_purpose, Engine as _};
+use openssl::{
+    hash::{hash, MessageDigest},
+    memcmp,
+    pkey::{PKey, Public},
+    sign::Verifier,
+};
+use roxmltree::{Node, NodeType};
+
+use crate::{types as airborne_types, types::ABError};
+
+pub(super) const DSIG_NS: &str = "http://www.w3.org/2000/09/xmldsig#";
+const EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+const ENVELOPED_SIGNATURE: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+
+fn invalid(reason: &str) -> ABError {
+    ABError::Unauthorized(format!("Invalid SAML signature: {reason}"))
+}
+
+fn signature_digest(algorithm: &str) -> Option<MessageDigest> {
+    match algorithm {
+        "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256" => Some(MessageDigest::sha256()),
+        "http://www.w3.org/2001/04/xmldsig-more#rsa-sha512" => Some(MessageDigest::sha512()),
+        _ => None,
+    }
+}
+
+fn reference_digest(algorithm: &str) -> Option<MessageDigest> {
+    match algorithm {
+        "http://www.w3.org/2001/04/xmlenc#sha256" => Some(MessageDigest::sha256()),
+        "http://www.w3.org/2001/04/xmlenc#sha512" => Some(MessageDigest::sha512()),
+        _ => None,
+    }
+}
+
+pub(super) fn child<'a, 'input>(
+    node: Node<'a, 'input>,
+    namespace: &str,
+    name: &str,
+) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|candidate| candidate.has_tag_name((namespace, name)))
+}
+
+/// The signature enveloped directly in `element`, if any.
+pub(super) fn enveloped_signature<'a, 'input>(
+    element: Node<'a, 'input>,
+) -> Option<Node<'a, 'input>> {
+    child(element, DSIG_NS, "Signature")
+}
+
+fn decode_base64(node: Option<Node>) -> airborne_types::Result<Vec<u8>> {
+    let text: String = node
+        .and_then(|node| node.text())
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect();
+    general_purpose::STANDARD
+        .decode(text)
+        .map_err(|_| invalid("malformed base64 value"))
+}
+
+/// Prefixes listed in an `InclusiveNamespaces` child of a canonicalization
+/// method or transform; `#default` stands for the default namespace.
+fn inclusive_prefixes(method: Node) -> Vec<String> {
+    child(method, EXC_C14N, "InclusiveNamespaces")
+        .and_then(|node| node.attribute("PrefixList"))
+        .map(|list| {
+            list.split_whitespace()
+                .map(|prefix| match prefix {
+                    "#default" => String::new(),
+                    prefix => prefix.to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Verifies the signature enveloped in `element` against `key`.
+pub(super) fn verify_enveloped(element: Node, key: &PKey<Public>) -> airborne_types::Result<()> {
+    let signature = enveloped_signature(element).ok_or_else(|| invalid("element is not signed"))?;
+    let signed_info =
+        child(signature, DSIG_NS, "SignedInfo").ok_or_else(|| invalid("missing SignedInfo"))?;
+
+    let c14n_method = child(signed_info, DSIG_NS, "CanonicalizationMethod")
+        .filter(|method| method.attribute("Algorithm") == Some(EXC_C14N))
+        .ok_or_else(|| invalid("unsupported canonicalization method"))?;
+    let signature_digest = child(signed_info, DSIG_NS, "SignatureMethod")
+        .and_then(|method| method.attribute("Algorithm"))
+        .and_then(signature_digest)
+        .ok_or_else(|| invalid("unsupported signature method"))?;
+
+    let references: Vec<Node> = signed_info
+        .children()
+        .filter(|node| node.has_tag_name((DSIG_NS, "Reference")))
+        .collect();
+    let [reference] = references.as_slice() else {
+        return Err(invalid("expected exactly one reference"));
+    };
+    let id = element
+        .attribute("ID")
+        .ok_or_else(|| invalid("signed element has no ID"))?;
+    if reference.attribute("URI") != Some(format!("#{id}").as_str()) {
+        return Err(invalid("reference does not point at the signed element"));
+    }
+
+    let mut enveloped = false;
+    let mut prefixes = Vec::new();
+    if let Some(transforms) = child(*reference, DSIG_NS, "Transforms") {
+        for transform in transforms
+            .children()
+            .filter(|node| node.has_tag_name((DSIG_NS, "Transform")))
+        {
+            match transform.attribute("Algorithm") {
+                Some(ENVELOPED_SIGNATURE) => enveloped = true,
+                Some(EXC_C14N) => prefixes = inclusive_prefixes(transform),
+                _ => return Err(invalid("unsupported transform")),
+            }
+        }
+    }
+    if !enveloped {
+        return Err(invalid("reference is not an enveloped signature"));
+    }
+
+    let digest = child(*reference, DSIG_NS, "DigestMethod")
+        .and_then(|method| method.attribute("Algorithm"))
+        .and_then(reference_digest)
+        .ok_or_else(|| invalid("unsupported digest method"))?;
+    let expected = decode_base64(child(*reference, DSIG_NS, "DigestValue"))?;
+    let canonical = canonicalize(element, Some(signature), &prefixes);
+    let actual = hash(digest, canonical.as_bytes())
+        .map_err(|error| ABError::InternalServerError(format!("Digest failed: {error}")))?;
+    if expected.len() != actual.len() || !memcmp::eq(&expected, &actual) {
+        return Err(invalid("digest mismatch"));
+    }
+
+    let canonical_signed_info = canonicalize(signed_info, None, &inclusive_prefixes(c14n_method));
+    let signature_value = decode_base64(child(signature, DSIG_NS, "SignatureValue"))?;
+    let verified = Verifier::new(signature_digest, key)
+        .and_then(|mut verifier| {
+            verifier.update(canonical_signed_info.as_bytes())?;
+            verifier.verify(&signature_value)
+        })
+        .unwrap_or(false);
+    if !verified {
+        return Err(invalid("signature does not match the IdP certificate"));
+    }
+    Ok(())
+}
+
+/// Exclusive XML canonicalization (without comments) of the subtree at
+/// `apex`, leaving out `exclude`.
+pub(super) fn canonicalize(apex: Node, exclude: Option<Node>, inclusive: &[String]) -> String {
+    let mut output = String::new();
+    write_node(apex, exclude, inclusive, &BTreeMap::new(), &mut output);
+    output
+}
+
+fn write_node(
+    node: Node,
+    exclude: Option<Node>,
+    inclusive: &[String],
+    rendered: &BTreeMap<String, String>,
+    output: &mut String,
+) {
+    if exclude.is_some_and(|excluded| excluded.id() == node.id()) {
+        return;
+    }
+    match node.node_type() {
+        NodeType::Element => write_element(node, exclude, inclusive, rendered, output),
+        NodeType::Text => escape_text(node.text().unwrap_or_default(), output),
+        NodeType::PI => {
+            if let Some(pi) = node.pi() {
+                output.push_str("<?");
+                output.push_str(pi.target);
+                if let Some(value) = pi.value {
+                    output.push(' ');
+                    output.push_str(value);
+                }
+                output.push_str("?>");
+            }
+        }
+        NodeType::Root | NodeType::Comment => {}
+    }
+}
+
+/// The element's qualified name as written in the source document.
+fn element_qname<'input>(node: Node<'_, 'input>) -> &'input str {
+    let input = node.document().input_text();
+    input[node.range().start + 1..]
+        .split(|ch: char| ch.is_whitespace() || ch == '/' || ch == '>')
+        .next()
+        .unwrap_or_default()
+}
+
+fn prefix_of(qname: &str) -> &str {
+    qname
+        .split_once(':')
+        .map(|(prefix, _)| prefix)
+        .unwrap_or("")
+}
+
+fn write_element(
+    node: Node,
+    exclude: Option<Node>,
+    inclusive: &[String],
+    rendered: &BTreeMap<String, String>,
+    output: &mut String,
+) {
+    let input = node.document().input_text();
+    let qname = element_qname(node);
+
+    let mut attributes: Vec<(&str, &str, &str, &str)> = node
+        .attributes()
+        .map(|attribute| {
+            (
+                attribute.namespace().unwrap_or_default(),
+                attribute.name(),
+                &input[attribute.range_qname()],
+                attribute.value(),
+            )
+        })
+        .collect();
+    attributes.sort_by(|left, right| (left.0, left.1).cmp(&(right.0, right.1)));
+
+    // Namespaces are rendered where they are visibly utilized, unless an
+    // output ancestor already declared the same binding.
+    let mut utilized = BTreeSet::from([prefix_of(qname)]);
+    utilized.extend(
+        attributes
+            .iter()
+            .map(|(_, _, qname, _)| prefix_of(qname))
+            .filter(|prefix| !prefix.is_empty()),
+    );
+    utilized.extend(inclusive.iter().map(String::as_str));
+    utilized.remove("xml");
+
+    let mut in_scope = rendered.clone();
+    output.push('<');
+    output.push_str(qname);
+    for prefix in utilized {
+        let uri = if prefix.is_empty() {
+            node.default_namespace().unwrap_or_default()
+        } else {
+            match node.lookup_namespace_uri(Some(prefix)) {
+                Some(uri) => uri,
+                None => continue,
+            }
+        };
+        let current = in_scope.get(prefix).map(String::as_str);
+        let declared = if prefix.is_empty() {
+            current.unwrap_or_default() == uri
+        } else {
+            current == Some(uri)
+        };
+        if declared {
+            continue;
+        }
+        if prefix.is_empty() {
+            output.push_str(" xmlns=\"");
+        } else {
+            output.push_str(" xmlns:");
+            output.push_str(prefix);
+            output.push_str("=\"");
+        }
+        escape_attribute(uri, output);
+        output.push('"');
+        in_scope.insert(prefix.to_string(), uri.to_string());
+    }
+    for (_, _, qname, value) in attributes {
+        output.push(' ');
+        output.push_str(qname);
+        output.push_str("=\"");
+        escape_attribute(value, output);
+        output.push('"');
+    }
+    output.push('>');
+    for child in node.children() {
+        write_node(child, exclude, inclusive, &in_scope, output);
+    }
+    output.push_str("</");
+    output.push_str(qname);
+    output.push('>');
+}
+
+fn escape_text(text: &str, output: &mut String) {
+    for ch in text.chars() {
+        match ch {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '\r' => output.push_str("&#xD;"),
+            ch => output.push(ch),
+        }
+    }
+}
+
+fn escape_attribute(value: &str, output: &mut String) {
+    for ch in value.chars() {
+        match ch {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '"' => output.push_str("&quot;"),
+            '\t' => output.push_str("&#x9;"),
+            '\n' => output.push_str("&#xA;"),
+            '\r' => output.push_str("&#xD;"),
+            ch => output.push(ch),
+        }
+    }
+}
@@ -3,6 +3,7 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use diesel::{r2d2::ConnectionManager, PgConnection};
 use r2d2::Pool;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     middleware::auth::AccessLevel,
@@ -12,6 +13,7 @@ use crate::{
 };
 
 pub mod casbin;
+pub mod cedar;
 pub mod migration;
 pub mod permission;
 
@@ -74,6 +76,98 @@ pub struct AuthzPermissionCheck {
     pub action: String,
 }
 
+/// A policy line of the subject that applies to the checked scope.
+#[derive(Clone, Debug)]
+pub struct AuthzPolicyMatch {
+    pub policy: Vec<String>,
+    pub role: String,
+    /// Roles from `role` down to the checked permission; empty when the role
+    /// does not grant it.
+    pub role_chain: Vec<String>,
+    /// Custom roles (from `upsert_custom_role`) on the chain.
+    pub custom_roles: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AuthzExplanation {
+    pub allowed: bool,
+    pub is_super_admin: bool,
+    pub permission: String,
+    pub note: Option<String>,
+    pub policies: Vec<AuthzPolicyMatch>,
+}
+
+pub const POLICY_DOCUMENT_VERSION: u32 = 1;
+
+/// Role bindings and custom roles of an organisation, in a form that can be
+/// kept in version control and applied back.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthzPolicyDocument {
+    pub version: u32,
+    pub organisation: String,
+    #[serde(default)]
+    pub members: Vec<AuthzMemberBinding>,
+    #[serde(default)]
+    pub roles: Vec<AuthzCustomRole>,
+    /// Applications left out of the document are not changed on import.
+    #[serde(default)]
+    pub applications: Vec<AuthzApplicationPolicy>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthzApplicationPolicy {
+    pub name: String,
+    #[serde(default)]
+    pub members: Vec<AuthzMemberBinding>,
+    #[serde(default)]
+    pub roles: Vec<AuthzCustomRole>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthzMemberBinding {
+    pub subject: String,
+    pub role: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthzCustomRole {
+    pub name: String,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthzChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AuthzMemberChange {
+    pub application: Option<String>,
+    pub subject: String,
+    pub change: AuthzChangeKind,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AuthzRoleChange {
+    pub application: Option<String>,
+    pub role: String,
+    pub change: AuthzChangeKind,
+    pub added_permissions: Vec<String>,
+    pub removed_permissions: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AuthzPolicyDiff {
+    pub applied: bool,
+    pub members: Vec<AuthzMemberChange>,
+    pub roles: Vec<AuthzRoleChange>,
+}
+
 #[async_trait]
 pub trait AuthZProvider: Send + Sync {
     fn kind(&self) -> AuthzProviderKind;
@@ -265,6 +359,98 @@ pub trait AuthZProvider: Send + Sync {
         Ok(false)
     }
 
+    /// `enforce_permission` for a specific object described by `attributes`
+    /// (for releases, `{"dimensions": {...}}`). Providers without
+    /// attribute-based rules ignore them.
+    #[allow(clippy::too_many_arguments)]
+    async fn enforce_permission_with_attributes(
+        &self,
+        state: &AppState,
+        subject: &str,
+        organisation: &str,
+        application: Option<&str>,
+        resource: &str,
+        action: &str,
+        _attributes: &serde_json::Map<String, serde_json::Value>,
+    ) -> airborne_types::Result<bool> {
+        self.enforce_permission(state, subject, organisation, application, resource, action)
+            .await
+    }
+
+    /// The subject's role in the organisation (`application` = `None`) or in
+    /// the application, if they have one.
+    async fn subject_role(
+        &self,
+        _state: &AppState,
+        _organisation: &str,
+        _application: Option<&str>,
+        _subject: &str,
+    ) -> airborne_types::Result<Option<String>> {
+        Err(ABError::BadRequest(
+            "Direct role assignment is not supported by this authorization provider".to_string(),
+        ))
+    }
+
+    /// Sets, or with `None` clears, the subject's role without checking who is
+    /// asking. Callers must authorize the change themselves.
+    async fn assign_role(
+        &self,
+        _state: &AppState,
+        _organisation: &str,
+        _application: Option<&str>,
+        _subject: &str,
+        _role: Option<&str>,
+    ) -> airborne_types::Result<()> {
+        Err(ABError::BadRequest(
+            "Direct role assignment is not supported by this authorization provider".to_string(),
+        ))
+    }
+
+    async fn export_policy_document(
+        &self,
+        _state: &AppState,
+        _organisation: &str,
+    ) -> airborne_types::Result<AuthzPolicyDocument> {
+        Err(ABError::BadRequest(
+            "Policy export is not supported by this authorization provider".to_string(),
+        ))
+    }
+
+    /// Compares `document` with the organisation's current bindings and, unless
+    /// `dry_run`, replaces them in one step. Callers must authorize the import.
+    async fn import_policy_document(
+        &self,
+        _state: &AppState,
+        _document: &AuthzPolicyDocument,
+        _dry_run: bool,
+    ) -> airborne_types::Result<AuthzPolicyDiff> {
+        Err(ABError::BadRequest(
+            "Policy import is not supported by this authorization provider".to_string(),
+        ))
+    }
+
+    /// Same decision as `enforce_permission`, with the policies behind it.
+    async fn explain_permission(
+        &self,
+        state: &AppState,
+        subject: &str,
+        organisation: &str,
+        application: Option<&str>,
+        resource: &str,
+        action: &str,
+    ) -> airborne_types::Result<AuthzExplanation> {
+        let allowed = self
+            .enforce_permission(state, subject, organisation, application, resource, action)
+            .await?;
+        Ok(AuthzExplanation {
+            allowed,
+            is_super_admin: false,
+            permission: format!("{}.{}", resource, action),
+            note: Some("This authorization provider does not expose policy details".to_string()),
+            policies: Vec::new(),
+        })
+    }
+
     async fn enforce_permissions_batch(
         &self,
         state: &AppState,
@@ -294,15 +480,18 @@ pub async fn build_authz_provider(
     bootstrap_super_admins: Vec<String>,
     db_pool: Pool<ConnectionManager<PgConnection>>,
     casbin_auto_load_secs: Option<u64>,
+    cedar_settings: cedar::CedarSettings,
 ) -> airborne_types::Result<Arc<dyn AuthZProvider>> {
+    let roles = casbin::CasbinAuthzProvider::new(
+        bootstrap_super_admins,
+        db_pool.clone(),
+        casbin_auto_load_secs,
+    )
+    .await?;
     match kind {
-        AuthzProviderKind::Casbin => {
-            let provider = casbin::CasbinAuthzProvider::new(
-                bootstrap_super_admins,
-                db_pool,
-                casbin_auto_load_secs,
-            )
-            .await?;
+        AuthzProviderKind::Casbin => Ok(Arc::new(roles)),
+        AuthzProviderKind::Cedar => {
+            let provider = cedar::CedarAuthzProvider::new(roles, db_pool, cedar_settings).await?;
             Ok(Arc::new(provider))
         }
     }
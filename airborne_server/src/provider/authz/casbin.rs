@@ -20,8 +20,9 @@ use crate::{
     middleware::auth::{AccessLevel, ADMIN, OWNER, READ, WRITE},
     provider::authz::{
         permission::{scoped_permission, EndpointPermissionBinding},
-        ApplicationAccessSummary, AuthZProvider, AuthzAccessContext, AuthzPermissionAttribute,
-        AuthzPermissionCheck, AuthzRoleDefinition, AuthzUserInfo, OrganisationAccessSummary,
+        ApplicationAccessSummary, AuthZProvider, AuthzAccessContext, AuthzExplanation,
+        AuthzPermissionAttribute, AuthzPermissionCheck, AuthzPolicyDiff, AuthzPolicyDocument,
+        AuthzPolicyMatch, AuthzRoleDefinition, AuthzUserInfo, OrganisationAccessSummary,
         UserAccessSummary,
     },
     run_blocking, types as airborne_types,
@@ -32,6 +33,8 @@ use crate::{
     },
 };
 
+mod policy_document;
+
 const POLICY_SCOPE_SYSTEM: &str = "system";
 const POLICY_SCOPE_ORG: &str = "org";
 const POLICY_SCOPE_APP: &str = "app";
@@ -206,7 +209,7 @@ impl CasbinAuthzProvider {
         Ok(applied)
     }
 
-    async fn has_super_admin_role(&self, subject: &str) -> airborne_types::Result<bool> {
+    pub(crate) async fn has_super_admin_role(&self, subject: &str) -> airborne_types::Result<bool> {
         let normalized_subject = normalize_subject(subject)?;
         let guard = self.enforcer.read().await;
         guard
@@ -2208,6 +2211,239 @@ impl AuthZProvider for CasbinAuthzProvider {
                 ))
             })
     }
+
+    async fn subject_role(
+        &self,
+        _state: &AppState,
+        organisation: &str,
+        application: Option<&str>,
+        subject: &str,
+    ) -> airborne_types::Result<Option<String>> {
+        let normalized_subject = normalize_subject(subject)?;
+        let role = match application {
+            Some(app_name) => {
+                self.application_user_role(&normalized_subject, organisation, app_name)
+                    .await?
+            }
+            None => {
+                self.organisation_user_role(&normalized_subject, organisation)
+                    .await?
+            }
+        };
+        let (scope, app) = match application {
+            Some(app_name) => (POLICY_SCOPE_APP, app_name),
+            None => (POLICY_SCOPE_ORG, "*"),
+        };
+        Ok(role.map(|key| display_role_name(scope, organisation, app, &key)))
+    }
+
+    async fn assign_role(
+        &self,
+        _state: &AppState,
+        organisation: &str,
+        application: Option<&str>,
+        subject: &str,
+        role: Option<&str>,
+    ) -> airborne_types::Result<()> {
+        let normalized_subject = normalize_subject(subject)?;
+        match (application, role) {
+            (None, Some(role)) => {
+                let normalized_role = validate_org_role(role)?;
+                self.ensure_organisation_exists(organisation).await?;
+                self.ensure_role_assignable(POLICY_SCOPE_ORG, organisation, "*", &normalized_role)
+                    .await?;
+                self.set_org_role(&normalized_subject, organisation, &normalized_role)
+                    .await
+            }
+            (None, None) => {
+                self.ensure_organisation_exists(organisation).await?;
+                self.remove_organisation_membership(&normalized_subject, organisation)
+                    .await
+            }
+            (Some(app_name), Some(role)) => {
+                let normalized_role = validate_app_role(role)?;
+                self.ensure_application_exists(organisation, app_name)
+                    .await?;
+                if self
+                    .organisation_user_role(&normalized_subject, organisation)
+                    .await?
+                    .is_none()
+                {
+                    return Err(ABError::BadRequest("User not found in org".to_string()));
+                }
+                self.ensure_role_assignable(
+                    POLICY_SCOPE_APP,
+                    organisation,
+                    app_name,
+                    &normalized_role,
+                )
+                .await?;
+                self.set_application_role(
+                    &normalized_subject,
+                    organisation,
+                    app_name,
+                    &normalized_role,
+                )
+                .await
+            }
+            (Some(app_name), None) => {
+                self.remove_policies_for_filter(
+                    0,
+                    vec![
+                        normalized_subject.clone(),
+                        POLICY_SCOPE_APP.to_string(),
+                        organisation.to_string(),
+                        app_name.to_string(),
+                    ],
+                )
+                .await?;
+                self.remove_membership(
+                    &normalized_subject,
+                    POLICY_SCOPE_APP,
+                    organisation,
+                    app_name,
+                )
+                .await
+            }
+        }
+    }
+
+    async fn export_policy_document(
+        &self,
+        _state: &AppState,
+        organisation: &str,
+    ) -> airborne_types::Result<AuthzPolicyDocument> {
+        self.export_document(organisation).await
+    }
+
+    async fn import_policy_document(
+        &self,
+        _state: &AppState,
+        document: &AuthzPolicyDocument,
+        dry_run: bool,
+    ) -> airborne_types::Result<AuthzPolicyDiff> {
+        self.import_document(document, dry_run).await
+    }
+
+    async fn explain_permission(
+        &self,
+        state: &AppState,
+        subject: &str,
+        organisation: &str,
+        application: Option<&str>,
+        resource: &str,
+        action: &str,
+    ) -> airborne_types::Result<AuthzExplanation> {
+        let normalized_subject = normalize_subject(subject)?;
+        let is_super_admin = self.has_super_admin_role(&normalized_subject).await?;
+        let allowed = self
+            .enforce_permission(
+                state,
+                &normalized_subject,
+                organisation,
+                application,
+                resource,
+                action,
+            )
+            .await?;
+
+        let scope = if application.is_some() {
+            POLICY_SCOPE_APP
+        } else {
+            POLICY_SCOPE_ORG
+        };
+        let app = application.unwrap_or("*");
+        let permission = scoped_permission(scope, resource, action);
+
+        let note = if is_reserved_role_management_permission(resource) {
+            Some(
+                "Role management is decided by the subject's organisation/application access \
+                 level, not by permission bindings"
+                    .to_string(),
+            )
+        } else if is_super_admin {
+            Some("Super admins bypass endpoint permission checks".to_string())
+        } else {
+            None
+        };
+
+        let guard = self.enforcer.read().await;
+        let groupings = guard.get_grouping_policy();
+        let policies = guard
+            .get_filtered_policy(0, vec![normalized_subject])
+            .into_iter()
+            .filter(|policy| policy_applies(policy, scope, organisation, app))
+            .map(|policy| {
+                let role = policy[4].clone();
+                let role_chain = role_chain(&groupings, &role, &permission);
+                let custom_roles = role_chain
+                    .iter()
+                    .filter(|key| **key != permission && canonical_system_role(key).is_none())
+                    .map(|key| display_role_name(&policy[1], &policy[2], &policy[3], key))
+                    .collect();
+                AuthzPolicyMatch {
+                    role: display_role_name(&policy[1], &policy[2], &policy[3], &role),
+                    policy,
+                    role_chain,
+                    custom_roles,
+                }
+            })
+            .collect();
+
+        Ok(AuthzExplanation {
+            allowed,
+            is_super_admin,
+            permission,
+            note,
+            policies,
+        })
+    }
+}
+
+/// Mirrors the scope/org/app part of the Casbin matcher for one `p` line.
+fn policy_applies(policy: &[String], scope: &str, organisation: &str, application: &str) -> bool {
+    policy.len() >= 5
+        && (policy[1] == POLICY_SCOPE_SYSTEM || policy[1] == scope)
+        && (policy[2] == "*" || policy[2] == organisation)
+        && (policy[3] == "*" || policy[3] == application)
+}
+
+/// Shortest path through the `g` grouping policies from `role` to `target`,
+/// both ends included. Empty when `role` does not reach `target`.
+fn role_chain(groupings: &[Vec<String>], role: &str, target: &str) -> Vec<String> {
+    if role == target {
+        return vec![role.to_string()];
+    }
+
+    let mut children: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for grouping in groupings {
+        if let [parent, child, ..] = grouping.as_slice() {
+            children.entry(parent).or_default().push(child);
+        }
+    }
+
+    let mut previous: BTreeMap<&str, &str> = BTreeMap::new();
+    let mut queue = std::collections::VecDeque::from([role]);
+    while let Some(current) = queue.pop_front() {
+        for child in children.get(current).into_iter().flatten() {
+            if *child == role || previous.contains_key(child) {
+                continue;
+            }
+            previous.insert(child, current);
+            if *child == target {
+                let mut chain = vec![target.to_string()];
+                let mut node = target;
+                while let Some(parent) = previous.get(node) {
+                    chain.push(parent.to_string());
+                    node = parent;
+                }
+                chain.reverse();
+                return chain;
+            }
+            queue.push_back(child);
+        }
+    }
+    Vec::new()
 }
 
 async fn ensure_role_hierarchy(enforcer: &mut Enforcer) -> airborne_types::Result<()> {
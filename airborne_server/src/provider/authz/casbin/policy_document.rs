@@ -0,0 +1,838 @@
+//! Export and import of an organisation's role bindings and custom roles.
+//!
+//! The document describes the desired state of the organisation scope and of
+//! each application it lists. Applications it leaves out keep their state,
+//! except that members removed from the organisation lose their roles there
+//! too. An import compares it with the Casbin policy
+//! and the `authz_role_bindings` table, and when applied rewrites both: role
+//! bindings in one database transaction, then the Casbin policy in a single
+//! `save_policy`. If saving the policy fails, the previous role bindings are
+//! restored and the in-memory policy is reloaded from the database.
+//!
+//! Roles held through an active temporary grant are exported as the role the
+//! member goes back to when the grant ends, so that re-importing a document
+//! never makes a grant permanent.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use casbin::{CoreApi, MgmtApi};
+use diesel::prelude::*;
+use log::info;
+
+use super::{
+    canonical_system_role, display_role_name, encode_role_key, highest_role_from_set,
+    is_reserved_role_management_permission, normalize_subject, parse_permission_key,
+    validate_app_role, validate_org_role, CasbinAuthzProvider, PolicyEntry, POLICY_SCOPE_APP,
+    POLICY_SCOPE_ORG, ROLE_ADMIN, ROLE_OWNER,
+};
+use crate::{
+    authz::grant::STATUS_ACTIVE,
+    provider::authz::{
+        permission::scoped_permission, AuthzApplicationPolicy, AuthzChangeKind, AuthzCustomRole,
+        AuthzMemberBinding, AuthzMemberChange, AuthzPolicyDiff, AuthzPolicyDocument,
+        AuthzRoleChange, POLICY_DOCUMENT_VERSION,
+    },
+    run_blocking, types as airborne_types,
+    types::ABError,
+    utils::db::{
+        models::{AuthzRoleBindingEntry, TemporaryRoleGrantEntry},
+        schema::hyperotaserver::{authz_role_bindings, temporary_role_grants},
+    },
+};
+
+/// Members (subject → role) and custom roles (name → permissions) of one scope.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct ScopeState {
+    members: BTreeMap<String, String>,
+    roles: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// Keyed by application; `None` is the organisation scope.
+type OrgState = BTreeMap<Option<String>, ScopeState>;
+
+/// New `(resource, action)` bindings of a custom role's stored key.
+#[derive(Clone, Debug)]
+struct RoleUpdate {
+    scope: String,
+    role_key: String,
+    permissions: Vec<(String, String)>,
+}
+
+fn scope_of(application: &Option<String>) -> &'static str {
+    if application.is_some() {
+        POLICY_SCOPE_APP
+    } else {
+        POLICY_SCOPE_ORG
+    }
+}
+
+fn app_or_wildcard(application: &Option<String>) -> &str {
+    application.as_deref().unwrap_or("*")
+}
+
+impl CasbinAuthzProvider {
+    async fn current_policy_state(&self, organisation: &str) -> airborne_types::Result<OrgState> {
+        let policies = {
+            let guard = self.enforcer.read().await;
+            guard.get_filtered_policy(2, vec![organisation.to_string()])
+        };
+
+        let mut assigned: BTreeMap<(Option<String>, String), BTreeSet<String>> = BTreeMap::new();
+        for policy in policies {
+            if policy.len() < 5 {
+                continue;
+            }
+            let application = match policy[1].as_str() {
+                POLICY_SCOPE_ORG => None,
+                POLICY_SCOPE_APP => Some(policy[3].clone()),
+                _ => continue,
+            };
+            assigned
+                .entry((application, policy[0].clone()))
+                .or_default()
+                .insert(policy[4].clone());
+        }
+
+        let mut state = OrgState::new();
+        state.entry(None).or_default();
+        for ((application, subject), role_keys) in assigned {
+            let Some(role_key) = highest_role_from_set(&role_keys) else {
+                continue;
+            };
+            let role = display_role_name(
+                scope_of(&application),
+                organisation,
+                app_or_wildcard(&application),
+                &role_key,
+            );
+            state
+                .entry(application)
+                .or_default()
+                .members
+                .insert(subject, role);
+        }
+
+        let org_prefix = format!("{}:", organisation);
+        for binding in self.list_role_bindings(POLICY_SCOPE_ORG).await? {
+            let Some(role) = binding.role_key.strip_prefix(&org_prefix) else {
+                continue;
+            };
+            if canonical_system_role(&binding.role_key).is_some() || role.contains(':') {
+                continue;
+            }
+            state
+                .entry(None)
+                .or_default()
+                .roles
+                .entry(role.to_string())
+                .or_default()
+                .insert(format!("{}.{}", binding.resource, binding.action));
+        }
+        for binding in self.list_role_bindings(POLICY_SCOPE_APP).await? {
+            let Some((application, role)) = binding
+                .role_key
+                .strip_prefix(&org_prefix)
+                .and_then(|rest| rest.split_once(':'))
+            else {
+                continue;
+            };
+            // Roles of applications without members belong to deleted apps.
+            if let Some(scope) = state.get_mut(&Some(application.to_string())) {
+                scope
+                    .roles
+                    .entry(role.to_string())
+                    .or_default()
+                    .insert(format!("{}.{}", binding.resource, binding.action));
+            }
+        }
+        Ok(state)
+    }
+
+    async fn active_grants(
+        &self,
+        organisation: &str,
+    ) -> airborne_types::Result<Vec<TemporaryRoleGrantEntry>> {
+        let organisation = organisation.to_string();
+        let pool = self.db_pool.clone();
+        run_blocking!({
+            let mut conn = pool.get()?;
+            let rows = temporary_role_grants::table
+                .filter(temporary_role_grants::organisation.eq(organisation))
+                .filter(temporary_role_grants::status.eq(STATUS_ACTIVE))
+                .select(TemporaryRoleGrantEntry::as_select())
+                .load::<TemporaryRoleGrantEntry>(&mut conn)?;
+            Ok(rows)
+        })
+    }
+
+    /// Permissions that custom roles in `scope` may be built from.
+    async fn permission_catalog(&self, scope: &str) -> airborne_types::Result<BTreeSet<String>> {
+        Ok(self
+            .list_role_bindings(scope)
+            .await?
+            .into_iter()
+            .filter(|binding| canonical_system_role(&binding.role_key).is_some())
+            .filter(|binding| !is_reserved_role_management_permission(&binding.resource))
+            .map(|binding| format!("{}.{}", binding.resource, binding.action))
+            .collect())
+    }
+
+    pub(super) async fn export_document(
+        &self,
+        organisation: &str,
+    ) -> airborne_types::Result<AuthzPolicyDocument> {
+        self.ensure_organisation_exists(organisation).await?;
+        let mut state = self.current_policy_state(organisation).await?;
+        for grant in self.active_grants(organisation).await? {
+            let subject = normalize_subject(&grant.subject)?;
+            let Some(scope) = state.get_mut(&grant.application) else {
+                continue;
+            };
+            // A member whose role changed since the grant keeps that role
+            if scope.members.get(&subject) != Some(&grant.role) {
+                continue;
+            }
+            match grant.previous_role {
+                Some(previous_role) => scope.members.insert(subject, previous_role),
+                None => scope.members.remove(&subject),
+            };
+        }
+        let org_scope = state.remove(&None).unwrap_or_default();
+        let (members, roles) = scope_to_document(org_scope);
+        let applications = state
+            .into_iter()
+            .filter_map(|(application, scope)| {
+                let (members, roles) = scope_to_document(scope);
+                application.map(|name| AuthzApplicationPolicy {
+                    name,
+                    members,
+                    roles,
+                })
+            })
+            .collect();
+        Ok(AuthzPolicyDocument {
+            version: POLICY_DOCUMENT_VERSION,
+            organisation: organisation.to_string(),
+            members,
+            roles,
+            applications,
+        })
+    }
+
+    pub(super) async fn import_document(
+        &self,
+        document: &AuthzPolicyDocument,
+        dry_run: bool,
+    ) -> airborne_types::Result<AuthzPolicyDiff> {
+        if document.version != POLICY_DOCUMENT_VERSION {
+            return Err(ABError::BadRequest(format!(
+                "Unsupported policy document version {}. Expected {}",
+                document.version, POLICY_DOCUMENT_VERSION
+            )));
+        }
+        let organisation = document.organisation.as_str();
+        self.ensure_organisation_exists(organisation).await?;
+
+        let current = self.current_policy_state(organisation).await?;
+        let catalogs = (
+            self.permission_catalog(POLICY_SCOPE_ORG).await?,
+            self.permission_catalog(POLICY_SCOPE_APP).await?,
+        );
+        let desired = desired_state(document, &current, &catalogs)?;
+        let diff = diff_states(&current, &desired);
+
+        if dry_run || (diff.members.is_empty() && diff.roles.is_empty()) {
+            return Ok(diff);
+        }
+        self.apply_state(organisation, &current, &desired).await?;
+        info!(
+            "Applied policy document to {}: {} member and {} role changes",
+            organisation,
+            diff.members.len(),
+            diff.roles.len()
+        );
+        Ok(AuthzPolicyDiff {
+            applied: true,
+            ..diff
+        })
+    }
+
+    async fn apply_state(
+        &self,
+        organisation: &str,
+        current: &OrgState,
+        desired: &OrgState,
+    ) -> airborne_types::Result<()> {
+        // Role keys whose permissions change, with their new permissions.
+        let mut role_updates: Vec<RoleUpdate> = Vec::new();
+        for (application, scope) in desired {
+            let existing = current.get(application).cloned().unwrap_or_default();
+            let names = existing
+                .roles
+                .keys()
+                .chain(scope.roles.keys())
+                .cloned()
+                .collect::<BTreeSet<_>>();
+            for name in names {
+                let wanted = scope.roles.get(&name);
+                if wanted == existing.roles.get(&name) {
+                    continue;
+                }
+                let permissions = wanted
+                    .into_iter()
+                    .flatten()
+                    .map(|key| parse_permission_key(key))
+                    .collect::<airborne_types::Result<Vec<_>>>()?;
+                role_updates.push(RoleUpdate {
+                    scope: scope_of(application).to_string(),
+                    role_key: encode_role_key(
+                        scope_of(application),
+                        organisation,
+                        app_or_wildcard(application),
+                        &name,
+                    ),
+                    permissions,
+                });
+            }
+        }
+
+        let previous_bindings = self.replace_role_bindings(&role_updates).await?;
+
+        let mut guard = self.enforcer.write().await;
+        guard.enable_auto_save(false);
+        let result: airborne_types::Result<()> = async {
+            let failed = |error: casbin::Error| {
+                ABError::InternalServerError(format!("Failed to update policy: {error}"))
+            };
+            for (application, scope) in desired {
+                let existing = current.get(application).cloned().unwrap_or_default();
+                let subjects = existing
+                    .members
+                    .keys()
+                    .chain(scope.members.keys())
+                    .cloned()
+                    .collect::<BTreeSet<_>>();
+                for subject in subjects {
+                    let wanted = scope.members.get(&subject);
+                    if wanted == existing.members.get(&subject) {
+                        continue;
+                    }
+                    let key = vec![
+                        subject.clone(),
+                        scope_of(application).to_string(),
+                        organisation.to_string(),
+                        app_or_wildcard(application).to_string(),
+                    ];
+                    guard.remove_filtered_policy(0, key).await.map_err(failed)?;
+                    if let Some(role) = wanted {
+                        let entry = PolicyEntry {
+                            subject,
+                            scope: scope_of(application).to_string(),
+                            organisation: organisation.to_string(),
+                            application: app_or_wildcard(application).to_string(),
+                            action: encode_role_key(
+                                scope_of(application),
+                                organisation,
+                                app_or_wildcard(application),
+                                role,
+                            ),
+                        };
+                        guard.add_policy(entry.as_vec()).await.map_err(failed)?;
+                    }
+                }
+            }
+            for RoleUpdate {
+                scope,
+                role_key,
+                permissions,
+            } in &role_updates
+            {
+                let existing =
+                    guard.get_filtered_named_grouping_policy("g", 0, vec![role_key.clone()]);
+                for rule in existing {
+                    guard.remove_grouping_policy(rule).await.map_err(failed)?;
+                }
+                for (resource, action) in permissions {
+                    guard
+                        .add_grouping_policy(vec![
+                            role_key.clone(),
+                            scoped_permission(scope, resource, action),
+                        ])
+                        .await
+                        .map_err(failed)?;
+                }
+            }
+            guard.save_policy().await.map_err(failed)
+        }
+        .await;
+        guard.enable_auto_save(true);
+
+        if let Err(error) = result {
+            let reload = guard.load_policy().await;
+            drop(guard);
+            if let Err(reload_error) = reload {
+                log::error!(
+                    "Failed to reload Casbin policy after an aborted import: {}",
+                    reload_error
+                );
+            }
+            self.restore_role_bindings(&role_updates, previous_bindings)
+                .await?;
+            return Err(error);
+        }
+        drop(guard);
+        self.refresh_membership_cache_from_casbin().await
+    }
+
+    /// Replaces the bindings of each updated role in one transaction and
+    /// returns the rows it removed.
+    async fn replace_role_bindings(
+        &self,
+        updates: &[RoleUpdate],
+    ) -> airborne_types::Result<Vec<AuthzRoleBindingEntry>> {
+        let updates = updates.to_vec();
+        let pool = self.db_pool.clone();
+        run_blocking!({
+            let mut conn = pool.get()?;
+            let previous = conn.transaction::<_, ABError, _>(|conn| {
+                let mut previous = Vec::new();
+                for RoleUpdate {
+                    scope,
+                    role_key,
+                    permissions,
+                } in &updates
+                {
+                    let filter = authz_role_bindings::table
+                        .filter(authz_role_bindings::scope.eq(scope))
+                        .filter(authz_role_bindings::role_key.eq(role_key));
+                    previous.extend(
+                        filter
+                            .select(AuthzRoleBindingEntry::as_select())
+                            .load::<AuthzRoleBindingEntry>(conn)?,
+                    );
+                    diesel::delete(filter).execute(conn)?;
+                    for (resource, action) in permissions {
+                        diesel::insert_into(authz_role_bindings::table)
+                            .values((
+                                authz_role_bindings::scope.eq(scope),
+                                authz_role_bindings::role_key.eq(role_key),
+                                authz_role_bindings::resource.eq(resource),
+                                authz_role_bindings::action.eq(action),
+                            ))
+                            .execute(conn)?;
+                    }
+                }
+                Ok(previous)
+            })?;
+            Ok(previous)
+        })
+    }
+
+    async fn restore_role_bindings(
+        &self,
+        updates: &[RoleUpdate],
+        previous: Vec<AuthzRoleBindingEntry>,
+    ) -> airborne_types::Result<()> {
+        let keys = updates
+            .iter()
+            .map(|update| (update.scope.clone(), update.role_key.clone()))
+            .collect::<Vec<_>>();
+        let pool = self.db_pool.clone();
+        run_blocking!({
+            let mut conn = pool.get()?;
+            conn.transaction::<_, ABError, _>(|conn| {
+                for (scope, role_key) in &keys {
+                    diesel::delete(
+                        authz_role_bindings::table
+                            .filter(authz_role_bindings::scope.eq(scope))
+                            .filter(authz_role_bindings::role_key.eq(role_key)),
+                    )
+                    .execute(conn)?;
+                }
+                for row in &previous {
+                    diesel::insert_into(authz_role_bindings::table)
+                        .values((
+                            authz_role_bindings::scope.eq(&row.scope),
+                            authz_role_bindings::role_key.eq(&row.role_key),
+                            authz_role_bindings::resource.eq(&row.resource),
+                            authz_role_bindings::action.eq(&row.action),
+                            authz_role_bindings::created_at.eq(row.created_at),
+                        ))
+                        .execute(conn)?;
+                }
+                Ok(())
+            })
+        })
+    }
+}
+
+fn scope_to_document(scope: ScopeState) -> (Vec<AuthzMemberBinding>, Vec<AuthzCustomRole>) {
+    let members = scope
+        .members
+        .into_iter()
+        .map(|(subject, role)| AuthzMemberBinding { subject, role })
+        .collect();
+    let roles = scope
+        .roles
+        .into_iter()
+        .map(|(name, permissions)| AuthzCustomRole {
+            name,
+            permissions: permissions.into_iter().collect(),
+        })
+        .collect();
+    (members, roles)
+}
+
+fn scope_from_document(
+    application: Option<&str>,
+    members: &[AuthzMemberBinding],
+    roles: &[AuthzCustomRole],
+    catalog: &BTreeSet<String>,
+) -> airborne_types::Result<ScopeState> {
+    let location = application
+        .map(|app| format!("application '{}'", app))
+        .unwrap_or_else(|| "organisation".to_string());
+    let validate_role = |role: &str| {
+        if application.is_some() {
+            validate_app_role(role)
+        } else {
+            validate_org_role(role)
+        }
+    };
+
+    let mut state = ScopeState::default();
+    for role in roles {
+        let name = validate_role(&role.name)?;
+        if canonical_system_role(&name).is_some() {
+            return Err(ABError::BadRequest(format!(
+                "'{}' in {} is a system role and cannot be redefined",
+                name, location
+            )));
+        }
+        if role.permissions.is_empty() {
+            return Err(ABError::BadRequest(format!(
+                "Custom role '{}' in {} must include at least one permission",
+                name, location
+            )));
+        }
+        let mut permissions = BTreeSet::new();
+        for permission in &role.permissions {
+            let (resource, action) = parse_permission_key(permission)?;
+            let key = format!("{}.{}", resource, action);
+            if !catalog.contains(&key) {
+                return Err(ABError::BadRequest(format!(
+                    "Unknown permission '{}' in custom role '{}'",
+                    permission, name
+                )));
+            }
+            permissions.insert(key);
+        }
+        if state.roles.insert(name.clone(), permissions).is_some() {
+            return Err(ABError::BadRequest(format!(
+                "Custom role '{}' is defined twice in {}",
+                name, location
+            )));
+        }
+    }
+
+    for member in members {
+        let subject = normalize_subject(&member.subject)?;
+        let role = validate_role(&member.role)?;
+        if canonical_system_role(&role).is_none() && !state.roles.contains_key(&role) {
+            return Err(ABError::BadRequest(format!(
+                "{} has role '{}', which is not defined in {}",
+                subject, role, location
+            )));
+        }
+        if state.members.insert(subject.clone(), role).is_some() {
+            return Err(ABError::BadRequest(format!(
+                "{} is listed twice in {}",
+                subject, location
+            )));
+        }
+    }
+    Ok(state)
+}
+
+fn desired_state(
+    document: &AuthzPolicyDocument,
+    current: &OrgState,
+    (org_catalog, app_catalog): &(BTreeSet<String>, BTreeSet<String>),
+) -> airborne_types::Result<OrgState> {
+    let org_scope = scope_from_document(None, &document.members, &document.roles, org_catalog)?;
+    if !org_scope.members.values().any(|role| role == ROLE_OWNER) {
+        return Err(ABError::BadRequest(
+            "The organisation must keep at least one owner".to_string(),
+        ));
+    }
+
+    let mut desired = OrgState::new();
+    for application in &document.applications {
+        let name = application.name.trim().to_string();
+        let key = Some(name.clone());
+        if !current.contains_key(&key) {
+            return Err(ABError::BadRequest(format!(
+                "Application '{}' does not exist in {}",
+                name, document.organisation
+            )));
+        }
+        let scope = scope_from_document(
+            Some(&name),
+            &application.members,
+            &application.roles,
+            app_catalog,
+        )?;
+        if !scope.members.values().any(|role| role == ROLE_ADMIN) {
+            return Err(ABError::BadRequest(format!(
+                "Application '{}' must keep at least one admin",
+                name
+            )));
+        }
+        if let Some(subject) = scope
+            .members
+            .keys()
+            .find(|subject| !org_scope.members.contains_key(*subject))
+        {
+            return Err(ABError::BadRequest(format!(
+                "{} is a member of application '{}' but not of the organisation",
+                subject, name
+            )));
+        }
+        if desired.insert(key, scope).is_some() {
+            return Err(ABError::BadRequest(format!(
+                "Application '{}' is listed twice",
+                name
+            )));
+        }
+    }
+
+    // Like removing a member from the organisation, dropping them from the
+    // document also drops their roles in applications it does not list.
+    for (application, scope) in current {
+        let Some(name) = application else {
+            continue;
+        };
+        if desired.contains_key(application) {
+            continue;
+        }
+        let mut scope = scope.clone();
+        let before = scope.members.len();
+        scope
+            .members
+            .retain(|subject, _| org_scope.members.contains_key(subject));
+        if scope.members.len() == before {
+            continue;
+        }
+        if !scope.members.values().any(|role| role == ROLE_ADMIN) {
+            return Err(ABError::BadRequest(format!(
+                "Removing these members from the organisation leaves application '{}' without an admin; list the application with its new members",
+                name
+            )));
+        }
+        desired.insert(application.clone(), scope);
+    }
+    desired.insert(None, org_scope);
+    Ok(desired)
+}
+
+fn diff_states(current: &OrgState, desired: &OrgState) -> AuthzPolicyDiff {
+    let mut members = Vec::new();
+    let mut roles = Vec::new();
+    for (application, wanted) in desired {
+        let existing = current.get(application).cloned().unwrap_or_default();
+
+        let subjects = existing
+            .members
+            .keys()
+            .chain(wanted.members.keys())
+            .collect::<BTreeSet<_>>();
+        for subject in subjects {
+            let from = existing.members.get(subject);
+            let to = wanted.members.get(subject);
+            let change = match (from, to) {
+                (None, Some(_)) => AuthzChangeKind::Added,
+                (Some(_), None) => AuthzChangeKind::Removed,
+                (Some(from), Some(to)) if from != to => AuthzChangeKind::Changed,
+                _ => continue,
+            };
+            members.push(AuthzMemberChange {
+                application: application.clone(),
+                subject: subject.clone(),
+                change,
+                from: from.cloned(),
+                to: to.cloned(),
+            });
+        }
+
+        let names = existing
+            .roles
+            .keys()
+            .chain(wanted.roles.keys())
+            .collect::<BTreeSet<_>>();
+        let empty = BTreeSet::new();
+        for name in names {
+            let from = existing.roles.get(name);
+            let to = wanted.roles.get(name);
+            let change = match (from, to) {
+                (None, Some(_)) => AuthzChangeKind::Added,
+                (Some(_), None) => AuthzChangeKind::Removed,
+                (Some(from), Some(to)) if from != to => AuthzChangeKind::Changed,
+                _ => continue,
+            };
+            let from = from.unwrap_or(&empty);
+            let to = to.unwrap_or(&empty);
+            roles.push(AuthzRoleChange {
+                application: application.clone(),
+                role: name.clone(),
+                change,
+                added_permissions: to.difference(from).cloned().collect(),
+                removed_permissions: from.difference(to).cloned().collect(),
+            });
+        }
+    }
+    AuthzPolicyDiff {
+        applied: false,
+        members,
+        roles,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(subject: &str, role: &str) -> AuthzMemberBinding {
+        AuthzMemberBinding {
+            subject: subject.to_string(),
+            role: role.to_string(),
+        }
+    }
+
+    #[test]
+    fn diff_reports_member_and_role_changes() {
+        let catalog = ["release.read", "release.create"]
+            .into_iter()
+            .map(str::to_string)
+            .collect::<BTreeSet<_>>();
+        let mut current = OrgState::new();
+        current.insert(
+            None,
+            ScopeState {
+                members: [
+                    ("a@x.com".to_string(), "owner".to_string()),
+                    ("b@x.com".to_string(), "read".to_string()),
+                    ("c@x.com".to_string(), "write".to_string()),
+                ]
+                .into(),
+                roles: [("releaser".to_string(), ["release.read".to_string()].into())].into(),
+            },
+        );
+        let document = AuthzPolicyDocument {
+            version: POLICY_DOCUMENT_VERSION,
+            organisation: "acme".to_string(),
+            members: vec![
+                member("a@x.com", "owner"),
+                member("B@x.com", "releaser"),
+                member("d@x.com", "read"),
+            ],
+            roles: vec![AuthzCustomRole {
+                name: "releaser".to_string(),
+                permissions: vec!["release.create".to_string(), "release.read".to_string()],
+            }],
+            applications: Vec::new(),
+        };
+
+        let desired = desired_state(&document, &current, &(catalog.clone(), catalog)).unwrap();
+        let diff = diff_states(&current, &desired);
+        let changes = diff
+            .members
+            .iter()
+            .map(|change| (change.subject.as_str(), change.change))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                ("b@x.com", AuthzChangeKind::Changed),
+                ("c@x.com", AuthzChangeKind::Removed),
+                ("d@x.com", AuthzChangeKind::Added),
+            ]
+        );
+        assert_eq!(diff.roles.len(), 1);
+        assert_eq!(diff.roles[0].added_permissions, vec!["release.create"]);
+
+        let ownerless = AuthzPolicyDocument {
+            members: vec![member("a@x.com", "admin")],
+            roles: Vec::new(),
+            ..document
+        };
+        assert!(desired_state(&ownerless, &current, &(BTreeSet::new(), BTreeSet::new())).is_err());
+    }
+
+    fn scope(members: &[(&str, &str)]) -> ScopeState {
+        ScopeState {
+            members: members
+                .iter()
+                .map(|(subject, role)| (subject.to_string(), role.to_string()))
+                .collect(),
+            roles: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn removed_members_lose_roles_in_unlisted_applications() {
+        let catalogs = (BTreeSet::new(), BTreeSet::new());
+        let mut current = OrgState::new();
+        current.insert(
+            None,
+            scope(&[
+                ("a@x.com", "owner"),
+                ("b@x.com", "write"),
+                ("c@x.com", "read"),
+            ]),
+        );
+        current.insert(
+            Some("app".to_string()),
+            scope(&[("a@x.com", "admin"), ("b@x.com", "write")]),
+        );
+        current.insert(
+            Some("untouched".to_string()),
+            scope(&[("a@x.com", "admin"), ("c@x.com", "read")]),
+        );
+        let document = AuthzPolicyDocument {
+            version: POLICY_DOCUMENT_VERSION,
+            organisation: "acme".to_string(),
+            members: vec![member("a@x.com", "owner"), member("c@x.com", "read")],
+            roles: Vec::new(),
+            applications: Vec::new(),
+        };
+
+        let desired = desired_state(&document, &current, &catalogs).unwrap();
+        assert_eq!(
+            desired[&Some("app".to_string())],
+            scope(&[("a@x.com", "admin")])
+        );
+        assert!(!desired.contains_key(&Some("untouched".to_string())));
+        let changes = diff_states(&current, &desired)
+            .members
+            .into_iter()
+            .map(|change| (change.application, change.subject, change.change))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                (None, "b@x.com".to_string(), AuthzChangeKind::Removed),
+                (
+                    Some("app".to_string()),
+                    "b@x.com".to_string(),
+                    AuthzChangeKind::Removed
+                ),
+            ]
+        );
+
+        let adminless = AuthzPolicyDocument {
+            members: vec![member("b@x.com", "owner")],
+            ..document
+        };
+        assert!(desired_state(&adminless, &current, &catalogs).is_err());
+    }
+}
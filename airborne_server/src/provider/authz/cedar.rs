@@ -0,0 +1,892 @@
+//! Authorization provider that evaluates Cedar policies.
+//!
+//! Memberships and roles are still stored and managed through Casbin; this
+//! provider only changes how a `resource.action` check is decided. Each check
+//! becomes a Cedar request:
+//!
+//! - principal: `Airborne::User::"<subject>"`, a member of
+//!   `Airborne::OrgRole::"<role>"` and `Airborne::AppRole::"<role>"` (built-in
+//!   roles nest, so an admin is also `in Airborne::OrgRole::"write"`), with
+//!   attributes `super_admin`, `org_role` and `app_role`.
+//! - action: `Airborne::Action::"<resource>.<action>"`.
+//! - resource: `Airborne::Application::"<org>/<app>"` (in
+//!   `Airborne::Organisation::"<org>"`) for application-scope checks, or the
+//!   organisation itself.
+//! - context: `resource`, `action`, `scope` (`org` or `app`), `role_permits`
+//!   (what the role bindings alone would decide) and any attributes the
+//!   endpoint supplies, such as `dimensions` for releases.
+//!
+//! Unless disabled, a baseline policy permitting whatever `role_permits`
+//! allows is always loaded, so operator policies only need to add `forbid`
+//! rules or extra `permit`s.
+
+use std::{
+    collections::BTreeSet,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use cedar_policy::{
+    Authorizer, Context, Decision, Effect, Entities, EntityId, EntityTypeName, EntityUid, Policy,
+    PolicySet, Request,
+};
+use diesel::{
+    pg::PgConnection,
+    prelude::*,
+    r2d2::{ConnectionManager, Pool},
+};
+use log::{info, warn};
+use serde_json::{json, Map, Value};
+use tokio::time::sleep;
+
+use crate::{
+    provider::authz::{
+        casbin::CasbinAuthzProvider, AuthZProvider, AuthzAccessContext, AuthzExplanation,
+        AuthzPermissionAttribute, AuthzPolicyDiff, AuthzPolicyDocument, AuthzRoleDefinition,
+        AuthzUserInfo, UserAccessSummary,
+    },
+    run_blocking, types as airborne_types,
+    types::{ABError, AppState, AuthzProviderKind},
+    utils::db::{models::AuthzCedarPolicyEntry, schema::hyperotaserver::authz_cedar_policies},
+};
+
+const BASELINE_POLICY_ID: &str = "airborne_role_bindings";
+const BASELINE_POLICY: &str = "permit(principal, action, resource) when { context.role_permits };";
+/// Built-in roles and the role each one implies.
+const ROLE_PARENTS: [(&str, Option<&str>); 4] = [
+    ("owner", Some("admin")),
+    ("admin", Some("write")),
+    ("write", Some("read")),
+    ("read", None),
+];
+
+#[derive(Clone, Debug)]
+pub struct CedarSettings {
+    /// Policy file to load. Policies come from `authz_cedar_policies` when unset.
+    pub policy_path: Option<String>,
+    pub reload_secs: u64,
+    pub role_baseline: bool,
+}
+
+#[derive(Clone, Debug)]
+enum PolicySource {
+    File(PathBuf),
+    Database,
+}
+
+pub struct CedarAuthzProvider {
+    roles: CasbinAuthzProvider,
+    policies: Arc<RwLock<PolicySet>>,
+    authorizer: Authorizer,
+}
+
+impl CedarAuthzProvider {
+    pub async fn new(
+        roles: CasbinAuthzProvider,
+        db_pool: Pool<ConnectionManager<PgConnection>>,
+        settings: CedarSettings,
+    ) -> airborne_types::Result<Self> {
+        let source = match settings.policy_path.filter(|path| !path.trim().is_empty()) {
+            Some(path) => PolicySource::File(PathBuf::from(path)),
+            None => PolicySource::Database,
+        };
+        let policies = load_policies(&source, &db_pool, settings.role_baseline).await?;
+        info!(
+            "Loaded {} Cedar policies from {:?}",
+            policies.policies().count(),
+            source
+        );
+        let policies = Arc::new(RwLock::new(policies));
+
+        if settings.reload_secs > 0 {
+            let policies_for_task = Arc::clone(&policies);
+            let interval = Duration::from_secs(settings.reload_secs);
+            tokio::spawn(async move {
+                loop {
+                    sleep(interval).await;
+                    match load_policies(&source, &db_pool, settings.role_baseline).await {
+                        Ok(loaded) => {
+                            if let Ok(mut guard) = policies_for_task.write() {
+                                *guard = loaded;
+                            }
+                        }
+                        Err(error) => {
+                            warn!("Keeping previous Cedar policies: {}", error);
+                        }
+                    }
+                }
+            });
+        }
+
+        Ok(Self {
+            roles,
+            policies,
+            authorizer: Authorizer::new(),
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn evaluate(
+        &self,
+        state: &AppState,
+        subject: &str,
+        organisation: &str,
+        application: Option<&str>,
+        resource: &str,
+        action: &str,
+        attributes: &Map<String, Value>,
+    ) -> airborne_types::Result<CedarDecision> {
+        let role_permits = self
+            .roles
+            .enforce_permission(state, subject, organisation, application, resource, action)
+            .await?;
+        let is_super_admin = self.roles.has_super_admin_role(subject).await?;
+        let org_role = self
+            .roles
+            .subject_role(state, organisation, None, subject)
+            .await?;
+        let app_role = match application {
+            Some(app) => {
+                self.roles
+                    .subject_role(state, organisation, Some(app), subject)
+                    .await?
+            }
+            None => None,
+        };
+
+        let input = CedarInput {
+            subject,
+            organisation,
+            application,
+            resource,
+            action,
+            is_super_admin,
+            org_role: org_role.as_deref(),
+            app_role: app_role.as_deref(),
+            role_permits,
+            attributes,
+        };
+        let (request, entities) = input.build()?;
+        let policies = self
+            .policies
+            .read()
+            .map_err(|_| ABError::InternalServerError("Cedar policy lock poisoned".to_string()))?;
+        let response = self
+            .authorizer
+            .is_authorized(&request, &policies, &entities);
+        let errors = response
+            .diagnostics()
+            .errors()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            warn!(
+                "Cedar evaluation errors for {} on {}.{}: {}",
+                subject,
+                resource,
+                action,
+                errors.join("; ")
+            );
+        }
+        let determining = response
+            .diagnostics()
+            .reason()
+            .map(|id| {
+                let effect = policies
+                    .policy(id)
+                    .map(|policy| match policy.effect() {
+                        Effect::Permit => "permit",
+                        Effect::Forbid => "forbid",
+                    })
+                    .unwrap_or("policy");
+                format!("{} {}", effect, id)
+            })
+            .collect::<BTreeSet<_>>();
+        Ok(CedarDecision {
+            allowed: response.decision() == Decision::Allow,
+            determining: determining.into_iter().collect(),
+            errors,
+        })
+    }
+}
+
+struct CedarDecision {
+    allowed: bool,
+    determining: Vec<String>,
+    errors: Vec<String>,
+}
+
+struct CedarInput<'a> {
+    subject: &'a str,
+    organisation: &'a str,
+    application: Option<&'a str>,
+    resource: &'a str,
+    action: &'a str,
+    is_super_admin: bool,
+    org_role: Option<&'a str>,
+    app_role: Option<&'a str>,
+    role_permits: bool,
+    attributes: &'a Map<String, Value>,
+}
+
+impl CedarInput<'_> {
+    fn build(&self) -> airborne_types::Result<(Request, Entities)> {
+        let organisation_uid = entity_json("Airborne::Organisation", self.organisation);
+        let mut entities = vec![json!({
+            "uid": organisation_uid,
+            "attrs": { "name": self.organisation },
+            "parents": [],
+        })];
+
+        let resource_uid = match self.application {
+            Some(app) => {
+                let uid = entity_json(
+                    "Airborne::Application",
+                    &format!("{}/{}", self.organisation, app),
+                );
+                entities.push(json!({
+                    "uid": uid,
+                    "attrs": { "name": app, "organisation": self.organisation },
+                    "parents": [organisation_uid],
+                }));
+                (
+                    "Airborne::Application",
+                    format!("{}/{}", self.organisation, app),
+                )
+            }
+            None => ("Airborne::Organisation", self.organisation.to_string()),
+        };
+
+        let mut principal_parents = Vec::new();
+        for (role_type, role) in [
+            ("Airborne::OrgRole", self.org_role),
+            ("Airborne::AppRole", self.app_role),
+        ] {
+            entities.extend(role_entities(role_type, role));
+            if let Some(role) = role {
+                principal_parents.push(entity_json(role_type, role));
+            }
+        }
+        entities.push(json!({
+            "uid": entity_json("Airborne::User", self.subject),
+            "attrs": {
+                "super_admin": self.is_super_admin,
+                "org_role": self.org_role.unwrap_or_default(),
+                "app_role": self.app_role.unwrap_or_default(),
+            },
+            "parents": principal_parents,
+        }));
+
+        let mut context = Map::new();
+        for (key, value) in self.attributes {
+            context.insert(key.clone(), cedar_value(value));
+        }
+        context.insert("resource".to_string(), json!(self.resource));
+        context.insert("action".to_string(), json!(self.action));
+        context.insert(
+            "scope".to_string(),
+            json!(if self.application.is_some() {
+                "app"
+            } else {
+                "org"
+            }),
+        );
+        context.insert("role_permits".to_string(), json!(self.role_permits));
+
+        let entities = Entities::from_json_value(Value::Array(entities), None).map_err(|e| {
+            ABError::InternalServerError(format!("Failed to build Cedar entities: {}", e))
+        })?;
+        let context = Context::from_json_value(Value::Object(context), None).map_err(|e| {
+            ABError::BadRequest(format!(
+                "Attributes cannot be used in a Cedar context: {}",
+                e
+            ))
+        })?;
+        let request = Request::new(
+            Some(entity_uid("Airborne::User", self.subject)?),
+            Some(entity_uid(
+                "Airborne::Action",
+                &format!("{}.{}", self.resource, self.action),
+            )?),
+            Some(entity_uid(resource_uid.0, &resource_uid.1)?),
+            context,
+        );
+        Ok((request, entities))
+    }
+}
+
+fn entity_json(entity_type: &str, id: &str) -> Value {
+    json!({ "type": entity_type, "id": id })
+}
+
+fn entity_uid(entity_type: &str, id: &str) -> airborne_types::Result<EntityUid> {
+    let type_name = EntityTypeName::from_str(entity_type).map_err(|e| {
+        ABError::InternalServerError(format!("Invalid Cedar type {}: {}", entity_type, e))
+    })?;
+    let id = EntityId::from_str(id)
+        .map_err(|e| ABError::InternalServerError(format!("Invalid Cedar id {}: {}", id, e)))?;
+    Ok(EntityUid::from_type_name_and_id(type_name, id))
+}
+
+/// The built-in role hierarchy for `role_type`, plus `role` when it is custom.
+fn role_entities(role_type: &str, role: Option<&str>) -> Vec<Value> {
+    let mut entities = ROLE_PARENTS
+        .iter()
+        .map(|(name, parent)| {
+            json!({
+                "uid": entity_json(role_type, name),
+                "attrs": {},
+                "parents": parent.map(|parent| vec![entity_json(role_type, parent)]).unwrap_or_default(),
+            })
+        })
+        .collect::<Vec<_>>();
+    if let Some(role) = role.filter(|role| !ROLE_PARENTS.iter().any(|(name, _)| name == role)) {
+        entities.push(json!({
+            "uid": entity_json(role_type, role),
+            "attrs": {},
+            "parents": [],
+        }));
+    }
+    entities
+}
+
+/// Cedar has no floats or nulls; those become strings so a stray dimension
+/// value cannot make every policy fail to evaluate.
+fn cedar_value(value: &Value) -> Value {
+    match value {
+        Value::Bool(_) | Value::String(_) => value.clone(),
+        Value::Number(number) if number.is_i64() => value.clone(),
+        Value::Number(number) => Value::String(number.to_string()),
+        Value::Null => Value::String(String::new()),
+        Value::Array(items) => Value::Array(items.iter().map(cedar_value).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), cedar_value(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn parse_policy_file(source: &str) -> airborne_types::Result<PolicySet> {
+    PolicySet::from_str(source)
+        .map_err(|e| ABError::InternalServerError(format!("Invalid Cedar policies: {}", e)))
+}
+
+fn parse_policy_rows(rows: &[AuthzCedarPolicyEntry]) -> airborne_types::Result<PolicySet> {
+    let mut policies = PolicySet::new();
+    for row in rows {
+        let policy = Policy::parse(Some(row.id.clone()), &row.policy).map_err(|e| {
+            ABError::InternalServerError(format!("Invalid Cedar policy '{}': {}", row.id, e))
+        })?;
+        policies.add(policy).map_err(|e| {
+            ABError::InternalServerError(format!("Cannot add Cedar policy '{}': {}", row.id, e))
+        })?;
+    }
+    Ok(policies)
+}
+
+async fn load_policies(
+    source: &PolicySource,
+    db_pool: &Pool<ConnectionManager<PgConnection>>,
+    role_baseline: bool,
+) -> airborne_types::Result<PolicySet> {
+    let mut policies = match source {
+        PolicySource::File(path) => {
+            let contents = tokio::fs::read_to_string(path).await.map_err(|e| {
+                ABError::InternalServerError(format!(
+                    "Failed to read Cedar policies from {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            parse_policy_file(&contents)?
+        }
+        PolicySource::Database => {
+            let pool = db_pool.clone();
+            let rows = run_blocking!({
+                let mut conn = pool.get()?;
+                let rows = authz_cedar_policies::table
+                    .filter(authz_cedar_policies::enabled.eq(true))
+                    .order(authz_cedar_policies::id.asc())
+                    .select(AuthzCedarPolicyEntry::as_select())
+                    .load::<AuthzCedarPolicyEntry>(&mut conn)?;
+                Ok(rows)
+            })?;
+            parse_policy_rows(&rows)?
+        }
+    };
+
+    if role_baseline {
+        let baseline = Policy::parse(Some(BASELINE_POLICY_ID.to_string()), BASELINE_POLICY)
+            .map_err(|e| ABError::InternalServerError(format!("Invalid baseline policy: {}", e)))?;
+        policies.add(baseline).map_err(|e| {
+            ABError::InternalServerError(format!("Cannot add baseline Cedar policy: {}", e))
+        })?;
+    }
+    if policies.is_empty() {
+        warn!("No Cedar policies loaded; every permission check will be denied");
+    }
+    Ok(policies)
+}
+
+#[async_trait]
+impl AuthZProvider for CedarAuthzProvider {
+    fn kind(&self) -> AuthzProviderKind {
+        AuthzProviderKind::Cedar
+    }
+
+    async fn bootstrap(&self, state: &AppState) -> airborne_types::Result<()> {
+        self.roles.bootstrap(state).await
+    }
+
+    async fn access_for_request(
+        &self,
+        state: &AppState,
+        subject: &str,
+        organisation: Option<&str>,
+        application: Option<&str>,
+    ) -> airborne_types::Result<AuthzAccessContext> {
+        self.roles
+            .access_for_request(state, subject, organisation, application)
+            .await
+    }
+
+    async fn get_user_access_summary(
+        &self,
+        state: &AppState,
+        subject: &str,
+    ) -> airborne_types::Result<UserAccessSummary> {
+        self.roles.get_user_access_summary(state, subject).await
+    }
+
+    async fn organisation_exists(
+        &self,
+        state: &AppState,
+        organisation: &str,
+    ) -> airborne_types::Result<bool> {
+        self.roles.organisation_exists(state, organisation).await
+    }
+
+    async fn create_organisation(
+        &self,
+        state: &AppState,
+        organisation: &str,
+        owner_subject: &str,
+    ) -> airborne_types::Result<()> {
+        self.roles
+            .create_organisation(state, organisation, owner_subject)
+            .await
+    }
+
+    async fn delete_organisation(
+        &self,
+        state: &AppState,
+        organisation: &str,
+    ) -> airborne_types::Result<()> {
+        self.roles.delete_organisation(state, organisation).await
+    }
+
+    async fn create_application(
+        &self,
+        state: &AppState,
+        organisation: &str,
+        application: &str,
+        creator_subject: &str,
+    ) -> airborne_types::Result<()> {
+        self.roles
+            .create_application(state, organisation, application, creator_subject)
+            .await
+    }
+
+    async fn list_organisation_users(
+        &self,
+        state: &AppState,
+        organisation: &str,
+    ) -> airborne_types::Result<Vec<AuthzUserInfo>> {
+        self.roles
+            .list_organisation_users(state, organisation)
+            .await
+    }
+
+    async fn add_organisation_user(
+        &self,
+        state: &AppState,
+        actor_subject: &str,
+        organisation: &str,
+        target_subject: &str,
+        role: &str,
+    ) -> airborne_types::Result<()> {
+        self.roles
+            .add_organisation_user(state, actor_subject, organisation, target_subject, role)
+            .await
+    }
+
+    async fn update_organisation_user(
+        &self,
+        state: &AppState,
+        actor_subject: &str,
+        organisation: &str,
+        target_subject: &str,
+        role: &str,
+    ) -> airborne_types::Result<()> {
+        self.roles
+            .update_organisation_user(state, actor_subject, organisation, target_subject, role)
+            .await
+    }
+
+    async fn remove_organisation_user(
+        &self,
+        state: &AppState,
+        actor_subject: &str,
+        organisation: &str,
+        target_subject: &str,
+    ) -> airborne_types::Result<()> {
+        self.roles
+            .remove_organisation_user(state, actor_subject, organisation, target_subject)
+            .await
+    }
+
+    async fn transfer_organisation_ownership(
+        &self,
+        state: &AppState,
+        actor_subject: &str,
+        organisation: &str,
+        target_subject: &str,
+    ) -> airborne_types::Result<()> {
+        self.roles
+            .transfer_organisation_ownership(state, actor_subject, organisation, target_subject)
+            .await
+    }
+
+    async fn list_application_users(
+        &self,
+        state: &AppState,
+        organisation: &str,
+        application: &str,
+    ) -> airborne_types::Result<Vec<AuthzUserInfo>> {
+        self.roles
+            .list_application_users(state, organisation, application)
+            .await
+    }
+
+    async fn add_application_user(
+        &self,
+        state: &AppState,
+        actor_subject: &str,
+        organisation: &str,
+        application: &str,
+        target_subject: &str,
+        role: &str,
+    ) -> airborne_types::Result<()> {
+        self.roles
+            .add_application_user(
+                state,
+                actor_subject,
+                organisation,
+                application,
+                target_subject,
+                role,
+            )
+            .await
+    }
+
+    async fn update_application_user(
+        &self,
+        state: &AppState,
+        actor_subject: &str,
+        organisation: &str,
+        application: &str,
+        target_subject: &str,
+        role: &str,
+    ) -> airborne_types::Result<()> {
+        self.roles
+            .update_application_user(
+                state,
+                actor_subject,
+                organisation,
+                application,
+                target_subject,
+                role,
+            )
+            .await
+    }
+
+    async fn remove_application_user(
+        &self,
+        state: &AppState,
+        actor_subject: &str,
+        organisation: &str,
+        application: &str,
+        target_subject: &str,
+    ) -> airborne_types::Result<()> {
+        self.roles
+            .remove_application_user(
+                state,
+                actor_subject,
+                organisation,
+                application,
+                target_subject,
+            )
+            .await
+    }
+
+    async fn list_role_definitions(
+        &self,
+        state: &AppState,
+        actor_subject: &str,
+        organisation: &str,
+        application: Option<&str>,
+    ) -> airborne_types::Result<Vec<AuthzRoleDefinition>> {
+        self.roles
+            .list_role_definitions(state, actor_subject, organisation, application)
+            .await
+    }
+
+    async fn list_available_permissions(
+        &self,
+        state: &AppState,
+        actor_subject: &str,
+        organisation: &str,
+        application: Option<&str>,
+    ) -> airborne_types::Result<Vec<AuthzPermissionAttribute>> {
+        self.roles
+            .list_available_permissions(state, actor_subject, organisation, application)
+            .await
+    }
+
+    async fn upsert_custom_role(
+        &self,
+        state: &AppState,
+        actor_subject: &str,
+        organisation: &str,
+        application: Option<&str>,
+        role: &str,
+        permissions: &[String],
+    ) -> airborne_types::Result<()> {
+        self.roles
+            .upsert_custom_role(
+                state,
+                actor_subject,
+                organisation,
+                application,
+                role,
+                permissions,
+            )
+            .await
+    }
+
+    async fn enforce_permission(
+        &self,
+        state: &AppState,
+        subject: &str,
+        organisation: &str,
+        application: Option<&str>,
+        resource: &str,
+        action: &str,
+    ) -> airborne_types::Result<bool> {
+        self.enforce_permission_with_attributes(
+            state,
+            subject,
+            organisation,
+            application,
+            resource,
+            action,
+            &Map::new(),
+        )
+        .await
+    }
+
+    async fn enforce_permission_with_attributes(
+        &self,
+        state: &AppState,
+        subject: &str,
+        organisation: &str,
+        application: Option<&str>,
+        resource: &str,
+        action: &str,
+        attributes: &Map<String, Value>,
+    ) -> airborne_types::Result<bool> {
+        let decision = self
+            .evaluate(
+                state,
+                subject,
+                organisation,
+                application,
+                resource,
+                action,
+                attributes,
+            )
+            .await?;
+        Ok(decision.allowed)
+    }
+
+    async fn subject_role(
+        &self,
+        state: &AppState,
+        organisation: &str,
+        application: Option<&str>,
+        subject: &str,
+    ) -> airborne_types::Result<Option<String>> {
+        self.roles
+            .subject_role(state, organisation, application, subject)
+            .await
+    }
+
+    async fn assign_role(
+        &self,
+        state: &AppState,
+        organisation: &str,
+        application: Option<&str>,
+        subject: &str,
+        role: Option<&str>,
+    ) -> airborne_types::Result<()> {
+        self.roles
+            .assign_role(state, organisation, application, subject, role)
+            .await
+    }
+
+    async fn export_policy_document(
+        &self,
+        state: &AppState,
+        organisation: &str,
+    ) -> airborne_types::Result<AuthzPolicyDocument> {
+        self.roles.export_policy_document(state, organisation).await
+    }
+
+    async fn import_policy_document(
+        &self,
+        state: &AppState,
+        document: &AuthzPolicyDocument,
+        dry_run: bool,
+    ) -> airborne_types::Result<AuthzPolicyDiff> {
+        self.roles
+            .import_policy_document(state, document, dry_run)
+            .await
+    }
+
+    async fn explain_permission(
+        &self,
+        state: &AppState,
+        subject: &str,
+        organisation: &str,
+        application: Option<&str>,
+        resource: &str,
+        action: &str,
+    ) -> airborne_types::Result<AuthzExplanation> {
+        let mut explanation = self
+            .roles
+            .explain_permission(state, subject, organisation, application, resource, action)
+            .await?;
+        let decision = self
+            .evaluate(
+                state,
+                subject,
+                organisation,
+                application,
+                resource,
+                action,
+                &Map::new(),
+            )
+            .await?;
+        let mut note = format!(
+            "Decided by Cedar ({}); role bindings {}",
+            if decision.determining.is_empty() {
+                "no policy matched".to_string()
+            } else {
+                decision.determining.join(", ")
+            },
+            if explanation.allowed {
+                "permit"
+            } else {
+                "do not permit"
+            }
+        );
+        if !decision.errors.is_empty() {
+            note.push_str(&format!(
+                ". Policies that failed to evaluate: {}",
+                decision.errors.join("; ")
+            ));
+        }
+        explanation.allowed = decision.allowed;
+        explanation.note = Some(note);
+        Ok(explanation)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Whether a caller with app `role` may take `action` on a release
+    /// targeting `dimensions`
+    pub(crate) fn decide(
+        policies: &str,
+        action: &str,
+        role: Option<&str>,
+        dimensions: Value,
+    ) -> bool {
+        let mut policies = parse_policy_file(policies).expect("policies parse");
+        policies
+            .add(Policy::parse(Some(BASELINE_POLICY_ID.to_string()), BASELINE_POLICY).unwrap())
+            .unwrap();
+        let mut attributes = Map::new();
+        if !dimensions.is_null() {
+            attributes.insert("dimensions".to_string(), dimensions);
+        }
+        let input = CedarInput {
+            subject: "dev@example.com",
+            organisation: "acme",
+            application: Some("shop"),
+            resource: "release",
+            action,
+            is_super_admin: false,
+            org_role: None,
+            app_role: role,
+            role_permits: role.is_some(),
+            attributes: &attributes,
+        };
+        let (request, entities) = input.build().expect("request builds");
+        Authorizer::new()
+            .is_authorized(&request, &policies, &entities)
+            .decision()
+            == Decision::Allow
+    }
+
+    #[test]
+    fn attribute_rules_restrict_role_bindings() {
+        let policies = r#"
+            forbid(principal, action == Airborne::Action::"release.create", resource)
+            when { context has dimensions && context.dimensions has env && context.dimensions.env != "staging" }
+            unless { principal in Airborne::AppRole::"admin" };
+        "#;
+        assert!(decide(
+            policies,
+            "create",
+            Some("write"),
+            json!({ "env": "staging" })
+        ));
+        assert!(!decide(
+            policies,
+            "create",
+            Some("write"),
+            json!({ "env": "prod" })
+        ));
+        assert!(decide(
+            policies,
+            "create",
+            Some("owner"),
+            json!({ "env": "prod" })
+        ));
+        assert!(decide(policies, "create", Some("write"), Value::Null));
+        assert!(!decide(
+            policies,
+            "create",
+            None,
+            json!({ "env": "staging" })
+        ));
+    }
+}
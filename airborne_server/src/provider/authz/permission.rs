@@ -1,3 +1,5 @@
+use std::collections::BTreeSet;
+
 use actix_web::web::{Data, ReqData};
 
 use crate::{
@@ -49,6 +51,37 @@ pub fn scoped_permission(scope: &str, resource: &str, action: &str) -> String {
     format!("{scope}:{resource}.{action}")
 }
 
+/// `resource.action` keys of every endpoint that can be checked at
+/// application scope
+pub fn app_permission_keys() -> BTreeSet<String> {
+    inventory::iter::<EndpointPermissionBinding>
+        .into_iter()
+        .filter(|binding| binding.allow_app)
+        .map(|binding| {
+            format!(
+                "{}.{}",
+                binding.resource.trim().to_ascii_lowercase(),
+                binding.action.trim().to_ascii_lowercase()
+            )
+        })
+        .collect()
+}
+
+/// Whether the caller holds `resource.action` in the organisation or
+/// application of the request
+pub async fn holds_permission(
+    state: &AppState,
+    auth: &AuthResponse,
+    resource: &str,
+    action: &str,
+) -> airborne_types::Result<bool> {
+    match enforce(state, auth, resource, action, true, true, None).await {
+        Ok(()) => Ok(true),
+        Err(ABError::Forbidden(_)) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
 pub async fn enforce_endpoint_permission(
     state: &Data<AppState>,
     auth_response: &ReqData<AuthResponse>,
@@ -58,39 +91,67 @@ pub async fn enforce_endpoint_permission(
     allow_app: bool,
 ) -> airborne_types::Result<()> {
     let auth = auth_response.clone().into_inner();
+    enforce(state, &auth, resource, action, allow_org, allow_app, None).await
+}
+
+/// Checks `resource.action` on a specific object, so providers with
+/// attribute-based rules can see it. Runs in addition to the endpoint check.
+pub async fn enforce_resource_permission(
+    state: &AppState,
+    auth: &AuthResponse,
+    resource: &str,
+    action: &str,
+    attributes: serde_json::Map<String, serde_json::Value>,
+) -> airborne_types::Result<()> {
+    enforce(state, auth, resource, action, true, true, Some(&attributes)).await
+}
+
+async fn enforce(
+    state: &AppState,
+    auth: &AuthResponse,
+    resource: &str,
+    action: &str,
+    allow_org: bool,
+    allow_app: bool,
+    attributes: Option<&serde_json::Map<String, serde_json::Value>>,
+) -> airborne_types::Result<()> {
+    if !crate::token::scope::permits(auth.token_permissions.as_deref(), resource, action) {
+        return Err(ABError::Forbidden(format!(
+            "Token does not grant {}.{}",
+            resource, action
+        )));
+    }
     if auth.is_super_admin {
         return Ok(());
     }
 
+    let empty = serde_json::Map::new();
+    let attributes = attributes.unwrap_or(&empty);
     let mut allowed = false;
 
     if allow_app {
-        if let (Some(org), Some(app)) = (auth.organisation.clone(), auth.application.clone()) {
+        if let (Some(org), Some(app)) = (&auth.organisation, &auth.application) {
             allowed = state
                 .authz_provider
-                .enforce_permission(
-                    state.get_ref(),
+                .enforce_permission_with_attributes(
+                    state,
                     &auth.sub,
                     &org.name,
                     Some(&app.name),
                     resource,
                     action,
+                    attributes,
                 )
                 .await?;
         }
     }
 
     if !allowed && allow_org {
-        if let Some(org) = auth.organisation {
+        if let Some(org) = &auth.organisation {
             allowed = state
                 .authz_provider
-                .enforce_permission(
-                    state.get_ref(),
-                    &auth.sub,
-                    &org.name,
-                    None,
-                    resource,
-                    action,
+                .enforce_permission_with_attributes(
+                    state, &auth.sub, &org.name, None, resource, action, attributes,
                 )
                 .await?;
         }
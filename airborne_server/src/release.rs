@@ -13,12 +13,17 @@
 // limitations under the License.
 
 use crate::{
-    file::utils::parse_file_key,
+    file::{types::FileVariant, utils::parse_file_key},
     middleware::auth::{require_org_and_app, Auth, AuthResponse},
+    package::{bundle, types::BundleRelease},
+    provider::authz::permission::enforce_resource_permission,
     release::types::*,
     types as airborne_types,
     types::{ABError, AppState, PaginatedQuery, PaginatedResponse, WithHeaders},
-    utils::{document::dotted_docs_to_nested, workspace::get_workspace_name_for_application},
+    utils::{
+        db::models::FileEntry, document::dotted_docs_to_nested,
+        workspace::get_workspace_name_for_application,
+    },
 };
 use actix_web::{
     error, get, post, put,
@@ -27,6 +32,7 @@ use actix_web::{
 };
 use airborne_authz_macros::authz;
 use aws_smithy_types::Document;
+use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use http::{HeaderValue, StatusCode};
 use log::info;
@@ -37,10 +43,10 @@ use superposition_provider::{AllFeatureProvider, SuperpositionAPIProvider};
 use superposition_sdk::types::builders::{VariantBuilder, VariantUpdateRequestBuilder};
 use superposition_sdk::types::ExperimentStatusType;
 use superposition_sdk::types::VariantType::Experimental;
-mod types;
+pub mod types;
 pub mod utils;
 
-fn encode_url_path(raw_url: &str) -> String {
+pub(crate) fn encode_url_path(raw_url: &str) -> String {
     match url::Url::parse(raw_url) {
         Ok(parsed) => {
             let encoded_path = parsed
@@ -73,6 +79,28 @@ fn encode_url_path(raw_url: &str) -> String {
     }
 }
 
+/// How a stored file is listed in a served manifest.
+pub(crate) fn serve_file(file: &FileEntry) -> ServeFile {
+    ServeFile {
+        file_path: file.file_path.clone(),
+        url: encode_url_path(&file.url),
+        checksum: file.checksum.clone(),
+        size: file.size,
+        variants: file_variants(file),
+    }
+}
+
+/// Precompressed variants of a file, with URLs encoded like the file's own.
+pub(crate) fn file_variants(file: &FileEntry) -> Vec<FileVariant> {
+    crate::file::utils::file_variants(file)
+        .into_iter()
+        .map(|variant| FileVariant {
+            url: encode_url_path(&variant.url),
+            ..variant
+        })
+        .collect()
+}
+
 pub fn add_routes(path: &str) -> Scope {
     Scope::new(path).service(serve_release).service(
         Scope::new("")
@@ -82,6 +110,7 @@ pub fn add_routes(path: &str) -> Scope {
             .service(ramp_release)
             .service(conclude_release)
             .service(get_release)
+            .service(export_release)
             .service(update_release)
             .service(discard_release),
     )
@@ -215,12 +244,7 @@ async fn get_release(
                     files
                         .iter()
                         .find(|file| file.file_path == file_path.clone())
-                        .map(|file| ServeFile {
-                            file_path: file.file_path.clone(),
-                            url: encode_url_path(&file.url),
-                            checksum: file.checksum.clone(),
-                            size: file.size,
-                        })
+                        .map(serve_file)
                 })
                 .collect();
             info!("Important files: {:?}", important_files);
@@ -232,12 +256,7 @@ async fn get_release(
                     files
                         .iter()
                         .find(|file| file.file_path == file_path.clone())
-                        .map(|file| ServeFile {
-                            file_path: file.file_path.clone(),
-                            url: encode_url_path(&file.url),
-                            checksum: file.checksum.clone(),
-                            size: file.size,
-                        })
+                        .map(serve_file)
                 })
                 .collect();
 
@@ -264,17 +283,13 @@ async fn get_release(
                 files
                     .iter()
                     .find(|file| file.file_path == file_path.clone())
-                    .map(|file| ServeFile {
-                        file_path: file.file_path.clone(),
-                        url: encode_url_path(&file.url),
-                        checksum: file.checksum.clone(),
-                        size: file.size,
-                    })
+                    .map(serve_file)
                     .unwrap_or_else(|| ServeFile {
                         file_path: file_path.clone(),
                         url: String::new(),
                         checksum: String::new(),
                         size: 0,
+                        variants: Vec::new(),
                     })
             };
 
@@ -288,6 +303,7 @@ async fn get_release(
                     url: String::new(),
                     checksum: String::new(),
                     size: 0,
+                    variants: Vec::new(),
                 },
                 Vec::new(),
                 Vec::new(),
@@ -346,8 +362,83 @@ async fn get_release(
                 _ => "UNKNOWN".to_string(),
             },
         }),
-        dimensions: exp_details
-            .context
+        dimensions: experiment_dimensions(&exp_details.context),
+    };
+
+    Ok(Json(resp))
+}
+
+#[authz(
+    resource = "release",
+    action = "export",
+    org_roles = ["owner", "admin", "write"],
+    app_roles = ["admin", "write"]
+)]
+#[get("/{release_id}/export")]
+async fn export_release(
+    release_id: Path<String>,
+    auth_response: web::ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Bytes>> {
+    let release_key = release_id.into_inner();
+    let auth_response = auth_response.into_inner();
+    let (organisation, application) = require_org_and_app(
+        auth_response.organisation.clone(),
+        auth_response.application.clone(),
+    )?;
+    bundle::signing_key(&state)?;
+
+    let workspace_name = get_workspace_name_for_application(
+        state.db_pool.clone(),
+        &state.redis_cache,
+        application.clone(),
+        organisation.clone(),
+    )
+    .await
+    .map_err(|_| ABError::InternalServerError("Failed to get workspace name".to_string()))?;
+
+    let exp_details = state
+        .superposition_client
+        .get_experiment()
+        .org_id(state.env.superposition_org_id.clone())
+        .workspace_id(workspace_name)
+        .id(release_key.clone())
+        .send()
+        .await
+        .map_err(|e| {
+            info!("Failed to get experiment details: {:?}", e);
+            ABError::NotFound("Release/Experiment not found".to_string())
+        })?;
+
+    let experimental_variant = exp_details
+        .variants
+        .iter()
+        .find(|v| v.variant_type == superposition_sdk::types::VariantType::Experimental);
+
+    let package_version =
+        utils::extract_integer_from_experiment::<i64>(&experimental_variant, "package.version");
+    let rc_properties = experimental_variant
+        .map(|v| {
+            v.overrides
+                .iter()
+                .filter_map(|(k, v)| {
+                    k.strip_prefix("config.properties.")
+                        .map(|key| (key.to_string(), v.to_owned()))
+                })
+                .collect::<HashMap<String, Document>>()
+        })
+        .unwrap_or_default();
+    let boot_timeout =
+        utils::extract_integer_from_experiment::<i64>(&experimental_variant, "config.boot_timeout");
+    let release_config_timeout = utils::extract_integer_from_experiment::<i64>(
+        &experimental_variant,
+        "config.release_config_timeout",
+    );
+
+    let bundle_release = BundleRelease {
+        boot_timeout: boot_timeout as u64,
+        release_config_timeout: release_config_timeout as u64,
+        config_properties: rc_properties
             .iter()
             .map(|(k, v)| {
                 (
@@ -356,9 +447,44 @@ async fn get_release(
                 )
             })
             .collect(),
+        package_properties: experimental_variant
+            .map(|v| &v.overrides)
+            .and_then(|obj| obj.get("package.properties"))
+            .and_then(utils::document_to_value)
+            .unwrap_or_default(),
+        important: utils::extract_files_from_experiment(&experimental_variant, "package.important"),
+        lazy: utils::extract_files_from_experiment(&experimental_variant, "package.lazy"),
+        resources: utils::extract_files_from_experiment(&experimental_variant, "resources"),
+        dimensions: experiment_dimensions(&exp_details.context),
     };
 
-    Ok(Json(resp))
+    let config = Config {
+        boot_timeout: boot_timeout as u32,
+        release_config_timeout: release_config_timeout as u32,
+        version: utils::extract_string_from_experiment(&experimental_variant, "config.version"),
+        properties: Some(dotted_docs_to_nested(rc_properties)?),
+    };
+
+    let package =
+        bundle::get_package_version(&state, &organisation, &application, package_version as i32)
+            .await?;
+    let archive = bundle::export_bundle(
+        &state,
+        &organisation,
+        &application,
+        package,
+        config,
+        Some((release_key.clone(), bundle_release)),
+    )
+    .await?;
+
+    bundle::archive_response(
+        archive,
+        &format!(
+            "{}-{}-release-{}.airborne.zip",
+            organisation, application, release_key
+        ),
+    )
 }
 
 #[authz(
@@ -378,7 +504,78 @@ async fn create_release(
         auth_response.organisation.clone(),
         auth_response.application.clone(),
     )?;
+    enforce_resource_permission(
+        &state,
+        &auth_response,
+        "release",
+        "create",
+        dimension_attributes(req.dimensions.as_ref()),
+    )
+    .await?;
+
+    let response = create_release_for_application(req, organisation, application, state).await?;
+    Ok(Json(response))
+}
+
+/// Attributes an authorization provider sees for a release: its targeting
+/// dimensions.
+pub(crate) fn dimension_attributes(
+    dimensions: Option<&HashMap<String, Value>>,
+) -> serde_json::Map<String, Value> {
+    let mut attributes = serde_json::Map::new();
+    attributes.insert(
+        "dimensions".to_string(),
+        Value::Object(
+            dimensions
+                .cloned()
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+        ),
+    );
+    attributes
+}
+
+/// The dimensions a stored release's experiment targets
+fn experiment_dimensions(context: &HashMap<String, Document>) -> HashMap<String, Value> {
+    context
+        .iter()
+        .map(|(k, v)| {
+            (
+                k.clone(),
+                utils::document_to_value(v).unwrap_or(Value::Null),
+            )
+        })
+        .collect()
+}
+
+/// Checks `action` on an existing release against the dimensions its
+/// experiment was created with. The request body is not trusted for these,
+/// or a caller could claim dimensions that an attribute rule lets through.
+async fn enforce_release_permission(
+    state: &AppState,
+    auth_response: &AuthResponse,
+    action: &str,
+    context: &HashMap<String, Document>,
+) -> airborne_types::Result<()> {
+    enforce_resource_permission(
+        state,
+        auth_response,
+        "release",
+        action,
+        dimension_attributes(Some(&experiment_dimensions(context))),
+    )
+    .await
+}
 
+/// Creates a release experiment for the given application. Shared by the
+/// `POST /releases` handler and bundle import.
+pub(crate) async fn create_release_for_application(
+    req: Json<CreateReleaseRequest>,
+    organisation: String,
+    application: String,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<CreateReleaseResponse> {
     let workspace_name = get_workspace_name_for_application(
         state.db_pool.clone(),
         &state.redis_cache,
@@ -545,7 +742,7 @@ async fn create_release(
         Value::Object(serde_json::Map::new())
     });
 
-    Ok(Json(CreateReleaseResponse {
+    Ok(CreateReleaseResponse {
         id: experiment_id_for_ramping.clone(),
         created_at: now,
         config: Config {
@@ -562,17 +759,13 @@ async fn create_release(
                 files
                     .iter()
                     .find(|file| file.file_path == file_path.clone())
-                    .map(|file| ServeFile {
-                        file_path: file.file_path.clone(),
-                        url: encode_url_path(&file.url),
-                        checksum: file.checksum.clone(),
-                        size: file.size,
-                    })
+                    .map(serve_file)
                     .unwrap_or_else(|| ServeFile {
                         file_path: file_path.clone(),
                         url: "".to_string(),
                         checksum: "".to_string(),
                         size: 0,
+                        variants: Vec::new(),
                     })
             },
             properties: final_properties.unwrap_or_default(),
@@ -583,12 +776,7 @@ async fn create_release(
                     files
                         .iter()
                         .find(|file| file.file_path == file_path.clone())
-                        .map(|file| ServeFile {
-                            file_path: file.file_path.clone(),
-                            url: encode_url_path(&file.url),
-                            checksum: file.checksum.clone(),
-                            size: file.size,
-                        })
+                        .map(serve_file)
                 })
                 .collect(),
             lazy: response_lazy
@@ -598,12 +786,7 @@ async fn create_release(
                     files
                         .iter()
                         .find(|file| file.file_path == file_path.clone())
-                        .map(|file| ServeFile {
-                            file_path: file.file_path.clone(),
-                            url: encode_url_path(&file.url),
-                            checksum: file.checksum.clone(),
-                            size: file.size,
-                        })
+                        .map(serve_file)
                 })
                 .collect(),
         },
@@ -614,12 +797,7 @@ async fn create_release(
                 files
                     .iter()
                     .find(|file| file.file_path == file_path.clone())
-                    .map(|file| ServeFile {
-                        file_path: file.file_path.clone(),
-                        url: encode_url_path(&file.url),
-                        checksum: file.checksum.clone(),
-                        size: file.size,
-                    })
+                    .map(serve_file)
             })
             .collect(),
         dimensions: dimensions.clone(),
@@ -635,7 +813,7 @@ async fn create_release(
             traffic_percentage: 0, // Default to 100% for new releases
             status: "CREATED".to_string(),
         }),
-    }))
+    })
 }
 
 #[authz(
@@ -728,16 +906,7 @@ async fn list_releases(
             .and_then(utils::document_to_value)
             .unwrap_or_default();
 
-        let dimensions: HashMap<String, Value> = experiment
-            .context
-            .iter()
-            .map(|(k, v)| {
-                (
-                    k.clone(),
-                    utils::document_to_value(v).unwrap_or(Value::Null),
-                )
-            })
-            .collect();
+        let dimensions = experiment_dimensions(&experiment.context);
 
         let rc_package_important =
             utils::extract_files_from_experiment(&experimental_variant, "package.important");
@@ -803,12 +972,7 @@ async fn list_releases(
                         files
                             .iter()
                             .find(|file| file.file_path == file_path.clone())
-                            .map(|file| ServeFile {
-                                file_path: file.file_path.clone(),
-                                url: encode_url_path(&file.url),
-                                checksum: file.checksum.clone(),
-                                size: file.size,
-                            })
+                            .map(serve_file)
                     })
                     .collect();
                 info!("Important files: {:?}", important_files);
@@ -820,12 +984,7 @@ async fn list_releases(
                         files
                             .iter()
                             .find(|file| file.file_path == file_path.clone())
-                            .map(|file| ServeFile {
-                                file_path: file.file_path.clone(),
-                                url: encode_url_path(&file.url),
-                                checksum: file.checksum.clone(),
-                                size: file.size,
-                            })
+                            .map(serve_file)
                     })
                     .collect();
 
@@ -836,12 +995,7 @@ async fn list_releases(
                         files
                             .iter()
                             .find(|file| file.file_path == file_path.clone())
-                            .map(|file| ServeFile {
-                                file_path: file.file_path.clone(),
-                                url: encode_url_path(&file.url),
-                                checksum: file.checksum.clone(),
-                                size: file.size,
-                            })
+                            .map(serve_file)
                     })
                     .collect();
 
@@ -850,17 +1004,13 @@ async fn list_releases(
                     files
                         .iter()
                         .find(|file| file.file_path == file_path.clone())
-                        .map(|file| ServeFile {
-                            file_path: file.file_path.clone(),
-                            url: encode_url_path(&file.url),
-                            checksum: file.checksum.clone(),
-                            size: file.size,
-                        })
+                        .map(serve_file)
                         .unwrap_or_else(|| ServeFile {
                             file_path: file_path.clone(),
                             url: String::new(),
                             checksum: String::new(),
                             size: 0,
+                            variants: Vec::new(),
                         })
                 };
 
@@ -874,6 +1024,7 @@ async fn list_releases(
                         url: String::new(),
                         checksum: String::new(),
                         size: 0,
+                        variants: Vec::new(),
                     },
                     Vec::new(),
                     Vec::new(),
@@ -967,6 +1118,22 @@ async fn ramp_release(
     .await
     .map_err(|e| ABError::InternalServerError(format!("Failed to get workspace name: {}", e)))?;
 
+    let experiment_details = state
+        .superposition_client
+        .get_experiment()
+        .org_id(superposition_org_id_from_env.clone())
+        .workspace_id(workspace_name.clone())
+        .id(experiment_id.to_string())
+        .send()
+        .await
+        .map_err(|e| {
+            info!("Failed to get experiment details: {:?}", e);
+            ABError::InternalServerError(
+                "Failed to get experiment details from Superposition".to_string(),
+            )
+        })?;
+    enforce_release_permission(&state, &auth_response, "ramp", &experiment_details.context).await?;
+
     info!(
         "Ramping experiment {} to {}% traffic for release {} in workspace {} org {}",
         experiment_id,
@@ -1086,6 +1253,13 @@ async fn conclude_release(
                 "Failed to get experiment details from Superposition".to_string(),
             )
         })?;
+    enforce_release_permission(
+        &state,
+        &auth_response,
+        "conclude",
+        &experiment_details.context,
+    )
+    .await?;
 
     let transformed_variant_id = experiment_details
         .variants
@@ -1205,6 +1379,13 @@ async fn discard_release(
                 "Failed to get experiment details from Superposition".to_string(),
             )
         })?;
+    enforce_release_permission(
+        &state,
+        &auth_response,
+        "discard",
+        &experiment_details.context,
+    )
+    .await?;
 
     if experiment_details.status != ExperimentStatusType::Created {
         return Err(ABError::BadRequest(
@@ -1261,7 +1442,7 @@ async fn serve_release_v2(
     serve_release_handler(path, req, query, state).await
 }
 
-async fn get_release_config_from_provider(
+pub(crate) async fn get_release_config_from_provider(
     provider: &Arc<SuperpositionAPIProvider>,
     evaluation_context: &EvaluationContext,
 ) -> Result<OpenFeatureReleaseConfig, ABError> {
@@ -1389,12 +1570,7 @@ async fn serve_release_handler(
                     files
                         .iter()
                         .find(|file| file.file_path == file_path.clone())
-                        .map(|file| ServeFile {
-                            file_path: file.file_path.clone(),
-                            url: encode_url_path(&file.url),
-                            checksum: file.checksum.clone(),
-                            size: file.size,
-                        })
+                        .map(serve_file)
                 })
                 .collect();
 
@@ -1407,12 +1583,7 @@ async fn serve_release_handler(
                     files
                         .iter()
                         .find(|file| file.file_path == file_path.clone())
-                        .map(|file| ServeFile {
-                            file_path: file.file_path.clone(),
-                            url: encode_url_path(&file.url),
-                            checksum: file.checksum.clone(),
-                            size: file.size,
-                        })
+                        .map(serve_file)
                 })
                 .collect();
 
@@ -1424,12 +1595,7 @@ async fn serve_release_handler(
                     files
                         .iter()
                         .find(|file| file.file_path == file_path.clone())
-                        .map(|file| ServeFile {
-                            file_path: file.file_path.clone(),
-                            url: encode_url_path(&file.url),
-                            checksum: file.checksum.clone(),
-                            size: file.size,
-                        })
+                        .map(serve_file)
                 })
                 .collect();
 
@@ -1438,17 +1604,13 @@ async fn serve_release_handler(
                 files
                     .iter()
                     .find(|file| file.file_path == file_path.clone())
-                    .map(|file| ServeFile {
-                        file_path: file.file_path.clone(),
-                        url: encode_url_path(&file.url),
-                        checksum: file.checksum.clone(),
-                        size: file.size,
-                    })
+                    .map(serve_file)
                     .unwrap_or_else(|| ServeFile {
                         file_path: file_path.clone(),
                         url: String::new(),
                         checksum: String::new(),
                         size: 0,
+                        variants: Vec::new(),
                     })
             };
 
@@ -1460,6 +1622,7 @@ async fn serve_release_handler(
                     url: String::new(),
                     checksum: String::new(),
                     size: 0,
+                    variants: Vec::new(),
                 },
                 Vec::new(),
                 Vec::new(),
@@ -1512,7 +1675,6 @@ async fn update_release(
         auth_response.organisation.clone(),
         auth_response.application.clone(),
     )?;
-
     let workspace_name = get_workspace_name_for_application(
         state.db_pool.clone(),
         &state.redis_cache,
@@ -1523,10 +1685,32 @@ async fn update_release(
     .map_err(|e| ABError::InternalServerError(format!("Failed to get workspace name: {}", e)))?;
     let superposition_org_id_from_env = state.env.superposition_org_id.clone();
 
-    let dimensions = req.dimensions.clone().unwrap_or_default();
-
     let release_id = path.into_inner();
 
+    let experiment_details = state
+        .superposition_client
+        .get_experiment()
+        .org_id(superposition_org_id_from_env.clone())
+        .workspace_id(workspace_name.clone())
+        .id(release_id.to_string())
+        .send()
+        .await
+        .map_err(|e| {
+            info!("Failed to get experiment details: {:?}", e);
+            ABError::InternalServerError(
+                "Failed to get experiment details from Superposition".to_string(),
+            )
+        })?;
+    enforce_release_permission(
+        &state,
+        &auth_response,
+        "update",
+        &experiment_details.context,
+    )
+    .await?;
+    // A release keeps the dimensions it was created with
+    let dimensions = experiment_dimensions(&experiment_details.context);
+
     let BuildOverrides {
         final_important,
         package_data,
@@ -1551,20 +1735,6 @@ async fn update_release(
     )
     .await?;
 
-    let experiment_details = state
-        .superposition_client
-        .get_experiment()
-        .org_id(superposition_org_id_from_env.clone())
-        .workspace_id(workspace_name.clone())
-        .id(release_id.to_string())
-        .send()
-        .await
-        .map_err(|e| {
-            info!("Failed to get experiment details: {:?}", e);
-            ABError::InternalServerError(
-                "Failed to get experiment details from Superposition".to_string(),
-            )
-        })?;
     let experiment_variant_id = experiment_details
         .variants
         .iter()
@@ -1658,17 +1828,13 @@ async fn update_release(
                 files
                     .iter()
                     .find(|file| file.file_path == file_path.clone())
-                    .map(|file| ServeFile {
-                        file_path: file.file_path.clone(),
-                        url: encode_url_path(&file.url),
-                        checksum: file.checksum.clone(),
-                        size: file.size,
-                    })
+                    .map(serve_file)
                     .unwrap_or_else(|| ServeFile {
                         file_path: file_path.clone(),
                         url: "".to_string(),
                         checksum: "".to_string(),
                         size: 0,
+                        variants: Vec::new(),
                     })
             },
             properties: final_properties.unwrap_or_default(),
@@ -1679,12 +1845,7 @@ async fn update_release(
                     files
                         .iter()
                         .find(|file| file.file_path == file_path.clone())
-                        .map(|file| ServeFile {
-                            file_path: file.file_path.clone(),
-                            url: encode_url_path(&file.url),
-                            checksum: file.checksum.clone(),
-                            size: file.size,
-                        })
+                        .map(serve_file)
                 })
                 .collect(),
             lazy: response_lazy
@@ -1694,12 +1855,7 @@ async fn update_release(
                     files
                         .iter()
                         .find(|file| file.file_path == file_path.clone())
-                        .map(|file| ServeFile {
-                            file_path: file.file_path.clone(),
-                            url: encode_url_path(&file.url),
-                            checksum: file.checksum.clone(),
-                            size: file.size,
-                        })
+                        .map(serve_file)
                 })
                 .collect(),
         },
@@ -1710,12 +1866,7 @@ async fn update_release(
                 files
                     .iter()
                     .find(|file| file.file_path == file_path.clone())
-                    .map(|file| ServeFile {
-                        file_path: file.file_path.clone(),
-                        url: encode_url_path(&file.url),
-                        checksum: file.checksum.clone(),
-                        size: file.size,
-                    })
+                    .map(serve_file)
             })
             .collect(),
         dimensions: dimensions.clone(),
@@ -1732,3 +1883,98 @@ async fn update_release(
         }),
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::authz::cedar::tests::decide;
+    use serde_json::json;
+
+    fn stored_file(file_path: &str, file_variants: Vec<FileVariant>) -> FileEntry {
+        FileEntry {
+            id: uuid::Uuid::new_v4(),
+            app_id: "app".to_string(),
+            org_id: "org".to_string(),
+            version: 1,
+            tag: None,
+            url: format!("https://cdn.example.com/org/app/{}", file_path),
+            file_path: file_path.to_string(),
+            size: 1000,
+            checksum: "original".to_string(),
+            metadata: json!({}),
+            created_at: Utc::now(),
+            variants: serde_json::to_value(file_variants).unwrap(),
+            failure_reason: None,
+        }
+    }
+
+    #[test]
+    fn served_manifest_lists_variants() {
+        let variant = |encoding: &str, suffix: &str, size: i64| FileVariant {
+            encoding: encoding.to_string(),
+            url: format!("https://cdn.example.com/org/app/main.bundle.js.{}", suffix),
+            size,
+            checksum: format!("{}-checksum", encoding),
+        };
+        let index = stored_file(
+            "main.bundle.js",
+            vec![variant("br", "br", 200), variant("zstd", "zst", 250)],
+        );
+        let image = stored_file("logo.png", Vec::new());
+        let package = ServePackage {
+            name: "app".to_string(),
+            version: "1".to_string(),
+            index: serve_file(&index),
+            properties: json!({}),
+            important: vec![serve_file(&image)],
+            lazy: Vec::new(),
+        };
+
+        let manifest = serde_json::to_value(&package).unwrap();
+        assert_eq!(
+            manifest["index"]["variants"],
+            json!([
+                {
+                    "encoding": "br",
+                    "url": "https://cdn.example.com/org/app/main.bundle.js.br",
+                    "size": 200,
+                    "checksum": "br-checksum",
+                },
+                {
+                    "encoding": "zstd",
+                    "url": "https://cdn.example.com/org/app/main.bundle.js.zst",
+                    "size": 250,
+                    "checksum": "zstd-checksum",
+                },
+            ])
+        );
+        assert!(manifest["important"][0].get("variants").is_none());
+    }
+
+    #[test]
+    fn spoofed_dimensions_are_denied() {
+        let policies = r#"
+            forbid(principal, action in [
+                Airborne::Action::"release.update",
+                Airborne::Action::"release.ramp",
+                Airborne::Action::"release.conclude",
+                Airborne::Action::"release.discard"
+            ], resource)
+            when { context has dimensions && context.dimensions has env && context.dimensions.env == "prod" }
+            unless { principal in Airborne::AppRole::"admin" };
+        "#;
+        let stored = HashMap::from([("env".to_string(), Document::String("prod".to_string()))]);
+        let spoofed = HashMap::from([("env".to_string(), json!("staging"))]);
+
+        // The body alone would have let a write member through
+        let claimed = dimension_attributes(Some(&spoofed))["dimensions"].clone();
+        assert!(decide(policies, "update", Some("write"), claimed));
+
+        let actual =
+            dimension_attributes(Some(&experiment_dimensions(&stored)))["dimensions"].clone();
+        for action in ["update", "ramp", "conclude", "discard"] {
+            assert!(!decide(policies, action, Some("write"), actual.clone()));
+            assert!(decide(policies, action, Some("admin"), actual.clone()));
+        }
+    }
+}
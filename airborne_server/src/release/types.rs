@@ -1,6 +1,9 @@
 use std::collections::{BTreeMap, HashMap};
 
-use crate::utils::db::models::{FileEntry, PackageV2Entry};
+use crate::{
+    file::types::FileVariant,
+    utils::db::models::{FileEntry, PackageV2Entry},
+};
 use aws_smithy_types::Document;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -30,15 +33,17 @@ pub struct PackageRequest {
     pub lazy: Option<Vec<String>>,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct ServeFile {
     pub file_path: String,
     pub url: String,
     pub checksum: String,
     pub size: i64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<FileVariant>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct ServePackage {
     pub name: String,
     pub version: String,
@@ -157,7 +162,7 @@ pub struct ConcludeReleaseResponse {
     pub chosen_variant: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct ServeReleaseResponse {
     pub version: String,
     pub config: Config,
@@ -34,8 +34,8 @@ use superposition_sdk::{
 use url::form_urlencoded;
 
 use crate::{
-    file::utils::parse_file_key,
-    package::utils::parse_package_key,
+    file::utils::{ensure_files_usable, parse_file_key},
+    package::{budget::enforce_size_budget, bundle::find_file_for_key, utils::parse_package_key},
     release::types::*,
     run_blocking, types as airborne_types,
     types::{ABError, AppState},
@@ -792,6 +792,31 @@ pub async fn build_overrides(
             "Some files were missing in DB".to_string(),
         ));
     }
+    ensure_files_usable(&files)?;
+
+    {
+        let lookup = |keys: &[String]| {
+            keys.iter()
+                .filter_map(|key| find_file_for_key(&files, key))
+                .collect::<Vec<_>>()
+        };
+        let important = lookup(
+            &std::iter::once(package_data.index.clone())
+                .chain(final_important.clone().unwrap_or_default())
+                .collect::<Vec<_>>(),
+        );
+        let lazy = lookup(&final_lazy.clone().unwrap_or_default());
+        let resources = lookup(&final_resources.clone().unwrap_or_default());
+        enforce_size_budget(
+            state.db_pool.clone(),
+            organisation.clone(),
+            application.clone(),
+            &important,
+            &lazy,
+            &resources,
+        )
+        .await?;
+    }
 
     let config_version = uuid::Uuid::new_v4().to_string();
 
@@ -0,0 +1,1183 @@
+//! SCIM 2.0 provisioning (RFC 7643/7644) of organisation members.
+//!
+//! An organisation's IdP talks to `/scim/v2` with a long-lived SCIM token,
+//! which also identifies the organisation. Provisioned users and groups are
+//! stored here; the roles people actually hold are derived from group-to-role
+//! mappings and applied through the `AuthZProvider` whenever a user, a group
+//! or a mapping changes (see `sync`). Deactivating or deleting a user in the
+//! IdP removes them from the organisation straight away.
+
+mod filter;
+mod patch;
+mod sync;
+pub mod types;
+
+use std::collections::{HashMap, HashSet};
+
+use actix_web::{
+    delete, get,
+    http::{
+        header::{self, HeaderValue},
+        StatusCode,
+    },
+    patch as patch_route, post, put,
+    web::{self, Json, Path, Query, ReqData},
+    HttpRequest, HttpResponse, Scope,
+};
+use airborne_authz_macros::authz;
+use chrono::Utc;
+use diesel::prelude::*;
+use log::info;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    authz::grant::system_role_level,
+    middleware::auth::{require_scope_name, Auth, AuthResponse},
+    run_blocking,
+    scim::{
+        filter::{get_attribute, parse_filter},
+        patch::apply_patch,
+        types::*,
+    },
+    types as airborne_types,
+    types::{ABError, AppState, ListResponse, WithHeaders},
+    utils::{
+        db::{
+            models::{
+                ScimGroupEntry, ScimGroupMemberEntry, ScimGroupRoleEntry, ScimTokenEntry,
+                ScimUserEntry,
+            },
+            schema::hyperotaserver::{
+                scim_group_members, scim_group_roles, scim_groups, scim_tokens, scim_users,
+            },
+            DbPool,
+        },
+        encryption::generate_random_key,
+    },
+};
+
+const TOKEN_PREFIX: &str = "abscim_";
+const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+const LIST_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+const SERVICE_PROVIDER_CONFIG_SCHEMA: &str =
+    "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+// Attributes the server owns; everything else the IdP sends is kept as-is.
+const SERVER_ATTRIBUTES: [&str; 4] = ["schemas", "id", "meta", "groups"];
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+pub fn add_scopes(path: &str) -> Scope {
+    Scope::new(path)
+        .service(
+            Scope::new("/v2")
+                .service(service_provider_config)
+                .service(list_users)
+                .service(create_user)
+                .service(get_user)
+                .service(replace_user)
+                .service(patch_user)
+                .service(delete_user)
+                .service(list_groups)
+                .service(create_group)
+                .service(get_group)
+                .service(replace_group)
+                .service(patch_group)
+                .service(delete_group),
+        )
+        .service(
+            Scope::new("")
+                .wrap(Auth)
+                .service(list_tokens)
+                .service(create_token)
+                .service(delete_token)
+                .service(list_mappings)
+                .service(create_mapping)
+                .service(delete_mapping),
+        )
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Resolves the SCIM token in the request to the organisation it belongs to.
+async fn authenticate(req: &HttpRequest, state: &AppState) -> airborne_types::Result<String> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .filter(|token| token.starts_with(TOKEN_PREFIX))
+        .ok_or_else(|| ABError::Unauthorized("Missing SCIM token".to_string()))?;
+    let token_hash = hash_token(token);
+    let pool = state.db_pool.clone();
+    run_blocking!({
+        let mut conn = pool.get()?;
+        let organisation =
+            diesel::update(scim_tokens::table.filter(scim_tokens::token_hash.eq(&token_hash)))
+                .set(scim_tokens::last_used_at.eq(Utc::now()))
+                .returning(scim_tokens::organisation)
+                .get_result::<String>(&mut conn)
+                .optional()?
+                .ok_or_else(|| ABError::Unauthorized("Invalid SCIM token".to_string()))?;
+        Ok(organisation)
+    })
+}
+
+fn scim_response(body: Value, status: StatusCode) -> WithHeaders<Json<Value>> {
+    WithHeaders::new(Json(body))
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/scim+json"),
+        )
+        .status(status)
+}
+
+fn string_attribute(resource: &Value, name: &str) -> Option<String> {
+    get_attribute(resource, name)
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+/// Sets `name`, dropping any differently-cased copies the IdP sent.
+fn set_attribute(resource: &mut Map<String, Value>, name: &str, value: Value) {
+    resource.retain(|key, _| !key.eq_ignore_ascii_case(name));
+    resource.insert(name.to_string(), value);
+}
+
+/// The resource as the IdP sent it, minus server-owned attributes.
+fn client_attributes(resource: Value) -> airborne_types::Result<Map<String, Value>> {
+    let Value::Object(mut resource) = resource else {
+        return Err(ABError::BadRequest(
+            "SCIM resource must be a JSON object".to_string(),
+        ));
+    };
+    resource.retain(|key, _| {
+        !SERVER_ATTRIBUTES
+            .iter()
+            .any(|attribute| key.eq_ignore_ascii_case(attribute))
+    });
+    Ok(resource)
+}
+
+fn meta(
+    resource_type: &str,
+    created: chrono::DateTime<Utc>,
+    updated: chrono::DateTime<Utc>,
+) -> Value {
+    json!({
+        "resourceType": resource_type,
+        "created": created,
+        "lastModified": updated,
+    })
+}
+
+fn paginate(
+    resources: Vec<Value>,
+    query: &ScimListQuery,
+) -> airborne_types::Result<WithHeaders<Json<Value>>> {
+    let filter = query.filter.as_deref().map(parse_filter).transpose()?;
+    let matching: Vec<Value> = resources
+        .into_iter()
+        .filter(|resource| {
+            filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(resource))
+        })
+        .collect();
+    let start_index = query.start_index.unwrap_or(1).max(1);
+    let count = query.count.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    let total_results = matching.len();
+    let page: Vec<Value> = matching
+        .into_iter()
+        .skip(start_index - 1)
+        .take(count)
+        .collect();
+    let body = ScimListResponse {
+        schemas: [LIST_SCHEMA],
+        total_results,
+        start_index,
+        items_per_page: page.len(),
+        resources: page,
+    };
+    let body = serde_json::to_value(body)
+        .map_err(|e| ABError::InternalServerError(format!("Failed to encode list: {}", e)))?;
+    Ok(scim_response(body, StatusCode::OK))
+}
+
+#[get("/ServiceProviderConfig")]
+async fn service_provider_config(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Json<Value>>> {
+    authenticate(&req, &state).await?;
+    Ok(scim_response(
+        json!({
+            "schemas": [SERVICE_PROVIDER_CONFIG_SCHEMA],
+            "patch": { "supported": true },
+            "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+            "filter": { "supported": true, "maxResults": MAX_PAGE_SIZE },
+            "changePassword": { "supported": false },
+            "sort": { "supported": false },
+            "etag": { "supported": false },
+            "authenticationSchemes": [{
+                "type": "oauthbearertoken",
+                "name": "OAuth Bearer Token",
+                "description": "Organisation SCIM token issued by POST /scim/tokens",
+                "primary": true,
+            }],
+        }),
+        StatusCode::OK,
+    ))
+}
+
+// ---- Users ----
+
+struct UserFields {
+    user_name: String,
+    external_id: Option<String>,
+    active: bool,
+    subject: String,
+}
+
+/// The primary email, else the first one listed.
+fn primary_email(resource: &Value) -> Option<String> {
+    let emails = get_attribute(resource, "emails")?.as_array()?;
+    emails
+        .iter()
+        .find(|email| get_attribute(email, "primary").and_then(Value::as_bool) == Some(true))
+        .or_else(|| emails.first())
+        .and_then(|email| string_attribute(email, "value"))
+}
+
+fn user_fields(resource: &Value) -> airborne_types::Result<UserFields> {
+    let user_name = string_attribute(resource, "userName")
+        .ok_or_else(|| ABError::BadRequest("userName is required".to_string()))?;
+    // Some IdPs send booleans as strings.
+    let active = match get_attribute(resource, "active") {
+        None | Some(Value::Null) => true,
+        Some(Value::Bool(active)) => *active,
+        Some(Value::String(active)) if active.eq_ignore_ascii_case("true") => true,
+        Some(Value::String(active)) if active.eq_ignore_ascii_case("false") => false,
+        Some(other) => {
+            return Err(ABError::BadRequest(format!(
+                "active must be a boolean, got {}",
+                other
+            )))
+        }
+    };
+    // Authorization subjects are emails; fall back to userName, which IdPs
+    // usually set to the email anyway.
+    let subject = primary_email(resource)
+        .unwrap_or_else(|| user_name.clone())
+        .to_lowercase();
+    Ok(UserFields {
+        external_id: string_attribute(resource, "externalId"),
+        user_name,
+        active,
+        subject,
+    })
+}
+
+fn user_resource(user: &ScimUserEntry, groups: &[(uuid::Uuid, String)]) -> Value {
+    let mut resource = user.resource.as_object().cloned().unwrap_or_default();
+    let mut schemas = vec![Value::from(USER_SCHEMA)];
+    schemas.extend(
+        resource
+            .keys()
+            .filter(|key| key.starts_with("urn:"))
+            .map(|key| Value::from(key.as_str())),
+    );
+    resource.insert("schemas".to_string(), Value::Array(schemas));
+    resource.insert("id".to_string(), Value::from(user.id.to_string()));
+    set_attribute(
+        &mut resource,
+        "userName",
+        Value::from(user.user_name.as_str()),
+    );
+    set_attribute(&mut resource, "active", Value::Bool(user.active));
+    if let Some(external_id) = &user.external_id {
+        set_attribute(
+            &mut resource,
+            "externalId",
+            Value::from(external_id.as_str()),
+        );
+    }
+    if !groups.is_empty() {
+        let groups = groups
+            .iter()
+            .map(|(id, name)| json!({ "value": id.to_string(), "display": name }))
+            .collect();
+        resource.insert("groups".to_string(), Value::Array(groups));
+    }
+    resource.insert(
+        "meta".to_string(),
+        meta("User", user.created_at, user.updated_at),
+    );
+    Value::Object(resource)
+}
+
+async fn find_user(
+    pool: DbPool,
+    organisation: String,
+    id: uuid::Uuid,
+) -> airborne_types::Result<ScimUserEntry> {
+    run_blocking!({
+        let mut conn = pool.get()?;
+        scim_users::table
+            .filter(scim_users::organisation.eq(&organisation))
+            .filter(scim_users::id.eq(id))
+            .select(ScimUserEntry::as_select())
+            .first::<ScimUserEntry>(&mut conn)
+            .optional()?
+            .ok_or_else(|| ABError::NotFound(format!("User {} not found", id)))
+    })
+}
+
+/// `(group id, group name)` pairs of each user.
+async fn groups_of_users(
+    pool: DbPool,
+    user_ids: Vec<uuid::Uuid>,
+) -> airborne_types::Result<HashMap<uuid::Uuid, Vec<(uuid::Uuid, String)>>> {
+    let rows = run_blocking!({
+        let mut conn = pool.get()?;
+        let rows = scim_group_members::table
+            .inner_join(scim_groups::table)
+            .filter(scim_group_members::user_id.eq_any(&user_ids))
+            .order(scim_groups::display_name.asc())
+            .select((
+                scim_group_members::user_id,
+                scim_groups::id,
+                scim_groups::display_name,
+            ))
+            .load::<(uuid::Uuid, uuid::Uuid, String)>(&mut conn)?;
+        Ok(rows)
+    })?;
+    let mut groups: HashMap<uuid::Uuid, Vec<(uuid::Uuid, String)>> = HashMap::new();
+    for (user_id, group_id, name) in rows {
+        groups.entry(user_id).or_default().push((group_id, name));
+    }
+    Ok(groups)
+}
+
+async fn render_user(state: &AppState, user: &ScimUserEntry) -> airborne_types::Result<Value> {
+    let groups = groups_of_users(state.db_pool.clone(), vec![user.id]).await?;
+    Ok(user_resource(
+        user,
+        groups.get(&user.id).map(Vec::as_slice).unwrap_or_default(),
+    ))
+}
+
+/// Stores `resource` as the user (a new one when `existing` is `None`) and
+/// applies the resulting role changes.
+async fn save_user(
+    state: &AppState,
+    organisation: &str,
+    existing: Option<ScimUserEntry>,
+    resource: Value,
+) -> airborne_types::Result<ScimUserEntry> {
+    let resource = Value::Object(client_attributes(resource)?);
+    let fields = user_fields(&resource)?;
+    let now = Utc::now();
+    let user = ScimUserEntry {
+        id: existing
+            .as_ref()
+            .map_or_else(uuid::Uuid::new_v4, |existing| existing.id),
+        organisation: organisation.to_string(),
+        user_name: fields.user_name,
+        external_id: fields.external_id,
+        subject: fields.subject,
+        active: fields.active,
+        resource,
+        created_at: existing
+            .as_ref()
+            .map_or(now, |existing| existing.created_at),
+        updated_at: now,
+    };
+
+    let pool = state.db_pool.clone();
+    let entry = user.clone();
+    let is_new = existing.is_none();
+    run_blocking!({
+        let mut conn = pool.get()?;
+        conn.transaction::<_, ABError, _>(|conn| {
+            let taken = scim_users::table
+                .filter(scim_users::organisation.eq(&entry.organisation))
+                .filter(scim_users::user_name.eq(&entry.user_name))
+                .filter(scim_users::id.ne(entry.id))
+                .count()
+                .get_result::<i64>(conn)?;
+            if taken > 0 {
+                return Err(ABError::Conflict(format!(
+                    "User '{}' already exists",
+                    entry.user_name
+                )));
+            }
+            if is_new {
+                diesel::insert_into(scim_users::table)
+                    .values(&entry)
+                    .execute(conn)?;
+            } else {
+                diesel::update(scim_users::table.find(entry.id))
+                    .set(&entry)
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
+    })?;
+
+    if let Some(existing) = existing.filter(|existing| existing.subject != user.subject) {
+        sync::revoke_user(state, organisation, &existing.subject).await?;
+    }
+    sync::sync_users(state, organisation, vec![user.id]).await?;
+    Ok(user)
+}
+
+#[get("/Users")]
+async fn list_users(
+    req: HttpRequest,
+    query: Query<ScimListQuery>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Json<Value>>> {
+    let organisation = authenticate(&req, &state).await?;
+    let pool = state.db_pool.clone();
+    let users = run_blocking!({
+        let mut conn = pool.get()?;
+        let users = scim_users::table
+            .filter(scim_users::organisation.eq(&organisation))
+            .order(scim_users::created_at.asc())
+            .select(ScimUserEntry::as_select())
+            .load::<ScimUserEntry>(&mut conn)?;
+        Ok(users)
+    })?;
+    let groups = groups_of_users(
+        state.db_pool.clone(),
+        users.iter().map(|user| user.id).collect(),
+    )
+    .await?;
+    let resources = users
+        .iter()
+        .map(|user| {
+            user_resource(
+                user,
+                groups.get(&user.id).map(Vec::as_slice).unwrap_or_default(),
+            )
+        })
+        .collect();
+    paginate(resources, &query)
+}
+
+#[post("/Users")]
+async fn create_user(
+    req: HttpRequest,
+    body: Json<Value>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Json<Value>>> {
+    let organisation = authenticate(&req, &state).await?;
+    let user = save_user(&state, &organisation, None, body.into_inner()).await?;
+    info!(
+        "SCIM: provisioned user {} ({}) in org {}",
+        user.user_name, user.id, organisation
+    );
+    Ok(scim_response(
+        user_resource(&user, &[]),
+        StatusCode::CREATED,
+    ))
+}
+
+#[get("/Users/{id}")]
+async fn get_user(
+    req: HttpRequest,
+    id: Path<uuid::Uuid>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Json<Value>>> {
+    let organisation = authenticate(&req, &state).await?;
+    let user = find_user(state.db_pool.clone(), organisation, id.into_inner()).await?;
+    Ok(scim_response(
+        render_user(&state, &user).await?,
+        StatusCode::OK,
+    ))
+}
+
+#[put("/Users/{id}")]
+async fn replace_user(
+    req: HttpRequest,
+    id: Path<uuid::Uuid>,
+    body: Json<Value>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Json<Value>>> {
+    let organisation = authenticate(&req, &state).await?;
+    let existing = find_user(state.db_pool.clone(), organisation.clone(), id.into_inner()).await?;
+    let user = save_user(&state, &organisation, Some(existing), body.into_inner()).await?;
+    Ok(scim_response(
+        render_user(&state, &user).await?,
+        StatusCode::OK,
+    ))
+}
+
+#[patch_route("/Users/{id}")]
+async fn patch_user(
+    req: HttpRequest,
+    id: Path<uuid::Uuid>,
+    body: Json<PatchRequest>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Json<Value>>> {
+    let organisation = authenticate(&req, &state).await?;
+    let existing = find_user(state.db_pool.clone(), organisation.clone(), id.into_inner()).await?;
+    let mut resource = user_resource(&existing, &[]);
+    apply_patch(&mut resource, &body.operations)?;
+    let user = save_user(&state, &organisation, Some(existing), resource).await?;
+    if !user.active {
+        info!(
+            "SCIM: deactivated user {} ({}) in org {}",
+            user.user_name, user.id, organisation
+        );
+    }
+    Ok(scim_response(
+        render_user(&state, &user).await?,
+        StatusCode::OK,
+    ))
+}
+
+#[delete("/Users/{id}")]
+async fn delete_user(
+    req: HttpRequest,
+    id: Path<uuid::Uuid>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<HttpResponse> {
+    let organisation = authenticate(&req, &state).await?;
+    let user = find_user(state.db_pool.clone(), organisation.clone(), id.into_inner()).await?;
+    // Revoke first so a failure leaves the user around for the IdP to retry.
+    sync::revoke_user(&state, &organisation, &user.subject).await?;
+    let pool = state.db_pool.clone();
+    let user_id = user.id;
+    run_blocking!({
+        let mut conn = pool.get()?;
+        diesel::delete(scim_users::table.find(user_id)).execute(&mut conn)?;
+        Ok(())
+    })?;
+    info!(
+        "SCIM: deprovisioned user {} ({}) from org {}",
+        user.user_name, user.id, organisation
+    );
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// ---- Groups ----
+
+fn group_resource(group: &ScimGroupEntry, members: &[(uuid::Uuid, String)]) -> Value {
+    let mut resource = json!({
+        "schemas": [GROUP_SCHEMA],
+        "id": group.id.to_string(),
+        "displayName": group.display_name,
+        "members": members
+            .iter()
+            .map(|(id, name)| json!({ "value": id.to_string(), "display": name }))
+            .collect::<Vec<_>>(),
+        "meta": meta("Group", group.created_at, group.updated_at),
+    });
+    if let Some(external_id) = &group.external_id {
+        resource["externalId"] = Value::from(external_id.as_str());
+    }
+    resource
+}
+
+fn group_fields(
+    resource: &Value,
+) -> airborne_types::Result<(String, Option<String>, HashSet<uuid::Uuid>)> {
+    let display_name = string_attribute(resource, "displayName")
+        .ok_or_else(|| ABError::BadRequest("displayName is required".to_string()))?;
+    let members = match get_attribute(resource, "members") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Array(members)) => members.clone(),
+        Some(_) => return Err(ABError::BadRequest("members must be a list".to_string())),
+    };
+    let members = members
+        .iter()
+        .map(|member| {
+            string_attribute(member, "value")
+                .and_then(|value| uuid::Uuid::parse_str(&value).ok())
+                .ok_or_else(|| ABError::BadRequest(format!("Invalid group member {}", member)))
+        })
+        .collect::<airborne_types::Result<HashSet<_>>>()?;
+    Ok((
+        display_name,
+        string_attribute(resource, "externalId"),
+        members,
+    ))
+}
+
+async fn find_group(
+    pool: DbPool,
+    organisation: String,
+    id: uuid::Uuid,
+) -> airborne_types::Result<ScimGroupEntry> {
+    run_blocking!({
+        let mut conn = pool.get()?;
+        scim_groups::table
+            .filter(scim_groups::organisation.eq(&organisation))
+            .filter(scim_groups::id.eq(id))
+            .select(ScimGroupEntry::as_select())
+            .first::<ScimGroupEntry>(&mut conn)
+            .optional()?
+            .ok_or_else(|| ABError::NotFound(format!("Group {} not found", id)))
+    })
+}
+
+/// `(user id, userName)` pairs of each group's members.
+async fn members_of_groups(
+    pool: DbPool,
+    group_ids: Vec<uuid::Uuid>,
+) -> airborne_types::Result<HashMap<uuid::Uuid, Vec<(uuid::Uuid, String)>>> {
+    let rows = run_blocking!({
+        let mut conn = pool.get()?;
+        let rows = scim_group_members::table
+            .inner_join(scim_users::table)
+            .filter(scim_group_members::group_id.eq_any(&group_ids))
+            .order(scim_users::user_name.asc())
+            .select((
+                scim_group_members::group_id,
+                scim_users::id,
+                scim_users::user_name,
+            ))
+            .load::<(uuid::Uuid, uuid::Uuid, String)>(&mut conn)?;
+        Ok(rows)
+    })?;
+    let mut members: HashMap<uuid::Uuid, Vec<(uuid::Uuid, String)>> = HashMap::new();
+    for (group_id, user_id, user_name) in rows {
+        members
+            .entry(group_id)
+            .or_default()
+            .push((user_id, user_name));
+    }
+    Ok(members)
+}
+
+async fn render_group(state: &AppState, group: &ScimGroupEntry) -> airborne_types::Result<Value> {
+    let members = members_of_groups(state.db_pool.clone(), vec![group.id]).await?;
+    Ok(group_resource(
+        group,
+        members
+            .get(&group.id)
+            .map(Vec::as_slice)
+            .unwrap_or_default(),
+    ))
+}
+
+/// Stores `resource` as the group (a new one when `existing` is `None`),
+/// replacing its members, and re-syncs everyone who was or is a member.
+async fn save_group(
+    state: &AppState,
+    organisation: &str,
+    existing: Option<ScimGroupEntry>,
+    resource: Value,
+) -> airborne_types::Result<ScimGroupEntry> {
+    let (display_name, external_id, members) = group_fields(&resource)?;
+    let now = Utc::now();
+    let group = ScimGroupEntry {
+        id: existing
+            .as_ref()
+            .map_or_else(uuid::Uuid::new_v4, |existing| existing.id),
+        organisation: organisation.to_string(),
+        display_name,
+        external_id,
+        created_at: existing
+            .as_ref()
+            .map_or(now, |existing| existing.created_at),
+        updated_at: now,
+    };
+
+    let pool = state.db_pool.clone();
+    let entry = group.clone();
+    let is_new = existing.is_none();
+    let affected = run_blocking!({
+        let mut conn = pool.get()?;
+        conn.transaction::<_, ABError, _>(|conn| {
+            let taken = scim_groups::table
+                .filter(scim_groups::organisation.eq(&entry.organisation))
+                .filter(scim_groups::display_name.eq(&entry.display_name))
+                .filter(scim_groups::id.ne(entry.id))
+                .count()
+                .get_result::<i64>(conn)?;
+            if taken > 0 {
+                return Err(ABError::Conflict(format!(
+                    "Group '{}' already exists",
+                    entry.display_name
+                )));
+            }
+            let known = scim_users::table
+                .filter(scim_users::organisation.eq(&entry.organisation))
+                .filter(scim_users::id.eq_any(&members))
+                .count()
+                .get_result::<i64>(conn)?;
+            if known as usize != members.len() {
+                return Err(ABError::BadRequest(
+                    "Group members must be users provisioned in this organisation".to_string(),
+                ));
+            }
+
+            if is_new {
+                diesel::insert_into(scim_groups::table)
+                    .values(&entry)
+                    .execute(conn)?;
+            } else {
+                diesel::update(scim_groups::table.find(entry.id))
+                    .set(&entry)
+                    .execute(conn)?;
+            }
+            let previous = diesel::delete(
+                scim_group_members::table.filter(scim_group_members::group_id.eq(entry.id)),
+            )
+            .returning(scim_group_members::user_id)
+            .get_results::<uuid::Uuid>(conn)?;
+            let rows: Vec<ScimGroupMemberEntry> = members
+                .iter()
+                .map(|user_id| ScimGroupMemberEntry {
+                    group_id: entry.id,
+                    user_id: *user_id,
+                })
+                .collect();
+            diesel::insert_into(scim_group_members::table)
+                .values(&rows)
+                .execute(conn)?;
+
+            let mut affected: HashSet<uuid::Uuid> = previous.into_iter().collect();
+            affected.extend(members.iter().copied());
+            Ok(affected)
+        })
+    })?;
+
+    sync::sync_users(state, organisation, affected.into_iter().collect()).await?;
+    Ok(group)
+}
+
+#[get("/Groups")]
+async fn list_groups(
+    req: HttpRequest,
+    query: Query<ScimListQuery>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Json<Value>>> {
+    let organisation = authenticate(&req, &state).await?;
+    let pool = state.db_pool.clone();
+    let groups = run_blocking!({
+        let mut conn = pool.get()?;
+        let groups = scim_groups::table
+            .filter(scim_groups::organisation.eq(&organisation))
+            .order(scim_groups::display_name.asc())
+            .select(ScimGroupEntry::as_select())
+            .load::<ScimGroupEntry>(&mut conn)?;
+        Ok(groups)
+    })?;
+    let members = members_of_groups(
+        state.db_pool.clone(),
+        groups.iter().map(|group| group.id).collect(),
+    )
+    .await?;
+    let resources = groups
+        .iter()
+        .map(|group| {
+            group_resource(
+                group,
+                members
+                    .get(&group.id)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default(),
+            )
+        })
+        .collect();
+    paginate(resources, &query)
+}
+
+#[post("/Groups")]
+async fn create_group(
+    req: HttpRequest,
+    body: Json<Value>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Json<Value>>> {
+    let organisation = authenticate(&req, &state).await?;
+    let group = save_group(&state, &organisation, None, body.into_inner()).await?;
+    info!(
+        "SCIM: provisioned group {} ({}) in org {}",
+        group.display_name, group.id, organisation
+    );
+    Ok(scim_response(
+        render_group(&state, &group).await?,
+        StatusCode::CREATED,
+    ))
+}
+
+#[get("/Groups/{id}")]
+async fn get_group(
+    req: HttpRequest,
+    id: Path<uuid::Uuid>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Json<Value>>> {
+    let organisation = authenticate(&req, &state).await?;
+    let group = find_group(state.db_pool.clone(), organisation, id.into_inner()).await?;
+    Ok(scim_response(
+        render_group(&state, &group).await?,
+        StatusCode::OK,
+    ))
+}
+
+#[put("/Groups/{id}")]
+async fn replace_group(
+    req: HttpRequest,
+    id: Path<uuid::Uuid>,
+    body: Json<Value>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Json<Value>>> {
+    let organisation = authenticate(&req, &state).await?;
+    let existing = find_group(state.db_pool.clone(), organisation.clone(), id.into_inner()).await?;
+    let group = save_group(&state, &organisation, Some(existing), body.into_inner()).await?;
+    Ok(scim_response(
+        render_group(&state, &group).await?,
+        StatusCode::OK,
+    ))
+}
+
+#[patch_route("/Groups/{id}")]
+async fn patch_group(
+    req: HttpRequest,
+    id: Path<uuid::Uuid>,
+    body: Json<PatchRequest>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<WithHeaders<Json<Value>>> {
+    let organisation = authenticate(&req, &state).await?;
+    let existing = find_group(state.db_pool.clone(), organisation.clone(), id.into_inner()).await?;
+    let mut resource = render_group(&state, &existing).await?;
+    apply_patch(&mut resource, &body.operations)?;
+    let group = save_group(&state, &organisation, Some(existing), resource).await?;
+    Ok(scim_response(
+        render_group(&state, &group).await?,
+        StatusCode::OK,
+    ))
+}
+
+#[delete("/Groups/{id}")]
+async fn delete_group(
+    req: HttpRequest,
+    id: Path<uuid::Uuid>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<HttpResponse> {
+    let organisation = authenticate(&req, &state).await?;
+    let group = find_group(state.db_pool.clone(), organisation.clone(), id.into_inner()).await?;
+    let pool = state.db_pool.clone();
+    let group_id = group.id;
+    let members = run_blocking!({
+        let mut conn = pool.get()?;
+        conn.transaction::<_, ABError, _>(|conn| {
+            let members = diesel::delete(
+                scim_group_members::table.filter(scim_group_members::group_id.eq(group_id)),
+            )
+            .returning(scim_group_members::user_id)
+            .get_results::<uuid::Uuid>(conn)?;
+            diesel::delete(scim_groups::table.find(group_id)).execute(conn)?;
+            Ok(members)
+        })
+    })?;
+    sync::sync_users(&state, &organisation, members).await?;
+    info!(
+        "SCIM: deleted group {} ({}) in org {}",
+        group.display_name, group.id, organisation
+    );
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// ---- Management ----
+
+fn token_info(token: ScimTokenEntry) -> ScimTokenInfo {
+    ScimTokenInfo {
+        id: token.id,
+        description: token.description,
+        created_by: token.created_by,
+        created_at: token.created_at,
+        last_used_at: token.last_used_at,
+    }
+}
+
+fn mapping_info(mapping: ScimGroupRoleEntry) -> ScimGroupRoleInfo {
+    ScimGroupRoleInfo {
+        id: mapping.id,
+        group: mapping.group_name,
+        application: mapping.application,
+        role: mapping.role,
+        created_by: mapping.created_by,
+        created_at: mapping.created_at,
+    }
+}
+
+#[authz(
+    resource = "scim",
+    action = "create",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[post("/tokens")]
+async fn create_token(
+    req: Json<CreateScimTokenRequest>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<CreateScimTokenResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation = require_scope_name(auth_response.organisation.clone(), "organisation")?;
+    let token = format!("{}{}", TOKEN_PREFIX, generate_random_key().await?);
+    let entry = ScimTokenEntry {
+        id: uuid::Uuid::new_v4(),
+        organisation: organisation.clone(),
+        token_hash: hash_token(&token),
+        description: req.into_inner().description,
+        created_by: auth_response.sub.clone(),
+        created_at: Utc::now(),
+        last_used_at: None,
+    };
+    let pool = state.db_pool.clone();
+    let new_entry = entry.clone();
+    run_blocking!({
+        let mut conn = pool.get()?;
+        diesel::insert_into(scim_tokens::table)
+            .values(&new_entry)
+            .execute(&mut conn)?;
+        Ok(())
+    })?;
+
+    info!(
+        "{} created SCIM token {} for org {}",
+        auth_response.sub, entry.id, organisation
+    );
+    Ok(Json(CreateScimTokenResponse {
+        info: token_info(entry),
+        token,
+    }))
+}
+
+#[authz(
+    resource = "scim",
+    action = "read",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[get("/tokens/list")]
+async fn list_tokens(
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<ListResponse<Vec<ScimTokenInfo>>>> {
+    let auth_response = auth_response.into_inner();
+    let organisation = require_scope_name(auth_response.organisation.clone(), "organisation")?;
+    let pool = state.db_pool.clone();
+    let tokens = run_blocking!({
+        let mut conn = pool.get()?;
+        let tokens = scim_tokens::table
+            .filter(scim_tokens::organisation.eq(&organisation))
+            .order(scim_tokens::created_at.asc())
+            .select(ScimTokenEntry::as_select())
+            .load::<ScimTokenEntry>(&mut conn)?;
+        Ok(tokens)
+    })?;
+    Ok(Json(ListResponse {
+        data: tokens.into_iter().map(token_info).collect(),
+    }))
+}
+
+#[authz(
+    resource = "scim",
+    action = "delete",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[delete("/tokens/{id}")]
+async fn delete_token(
+    id: Path<uuid::Uuid>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<DeleteScimResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation = require_scope_name(auth_response.organisation.clone(), "organisation")?;
+    let id = id.into_inner();
+    let pool = state.db_pool.clone();
+    let org = organisation.clone();
+    let deleted = run_blocking!({
+        let mut conn = pool.get()?;
+        let deleted = diesel::delete(
+            scim_tokens::table
+                .filter(scim_tokens::organisation.eq(&org))
+                .filter(scim_tokens::id.eq(id)),
+        )
+        .execute(&mut conn)?;
+        Ok(deleted)
+    })?;
+    if deleted == 0 {
+        return Err(ABError::NotFound(format!("SCIM token {} not found", id)));
+    }
+    info!(
+        "{} revoked SCIM token {} of org {}",
+        auth_response.sub, id, organisation
+    );
+    Ok(Json(DeleteScimResponse { success: true }))
+}
+
+#[authz(
+    resource = "scim",
+    action = "read",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[get("/mappings/list")]
+async fn list_mappings(
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<ListResponse<Vec<ScimGroupRoleInfo>>>> {
+    let auth_response = auth_response.into_inner();
+    let organisation = require_scope_name(auth_response.organisation.clone(), "organisation")?;
+    let pool = state.db_pool.clone();
+    let mappings = run_blocking!({
+        let mut conn = pool.get()?;
+        let mappings = scim_group_roles::table
+            .filter(scim_group_roles::organisation.eq(&organisation))
+            .order(scim_group_roles::group_name.asc())
+            .select(ScimGroupRoleEntry::as_select())
+            .load::<ScimGroupRoleEntry>(&mut conn)?;
+        Ok(mappings)
+    })?;
+    Ok(Json(ListResponse {
+        data: mappings.into_iter().map(mapping_info).collect(),
+    }))
+}
+
+/// Maps a SCIM group to a role, replacing any role the group already had at
+/// that scope, and re-syncs the organisation's provisioned users.
+#[authz(
+    resource = "scim",
+    action = "update",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[post("/mappings")]
+async fn create_mapping(
+    req: Json<ScimGroupRoleRequest>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<ScimGroupRoleInfo>> {
+    let req = req.into_inner();
+    let auth_response = auth_response.into_inner();
+    let organisation = require_scope_name(auth_response.organisation.clone(), "organisation")?;
+    let group = req.group.trim().to_string();
+    let role = req.role.trim().to_ascii_lowercase();
+    let application = req
+        .application
+        .map(|application| application.trim().to_string())
+        .filter(|application| !application.is_empty());
+    if group.is_empty() || role.is_empty() {
+        return Err(ABError::BadRequest(
+            "group and role cannot be empty".to_string(),
+        ));
+    }
+    if role == "owner" {
+        return Err(ABError::BadRequest(
+            "Ownership cannot be assigned through SCIM".to_string(),
+        ));
+    }
+    let actor_level = auth_response
+        .organisation
+        .as_ref()
+        .map_or(0, |org| org.level);
+    if !auth_response.is_super_admin && actor_level < system_role_level(&role).unwrap_or(0) {
+        return Err(ABError::Forbidden(format!(
+            "Cannot map groups to '{}' above your own access level",
+            role
+        )));
+    }
+
+    let mapping = ScimGroupRoleEntry {
+        id: uuid::Uuid::new_v4(),
+        organisation: organisation.clone(),
+        group_name: group,
+        application,
+        role,
+        created_by: auth_response.sub.clone(),
+        created_at: Utc::now(),
+    };
+    let pool = state.db_pool.clone();
+    let entry = mapping.clone();
+    run_blocking!({
+        let mut conn = pool.get()?;
+        conn.transaction::<_, ABError, _>(|conn| {
+            let replaced: Vec<uuid::Uuid> = scim_group_roles::table
+                .filter(scim_group_roles::organisation.eq(&entry.organisation))
+                .select(ScimGroupRoleEntry::as_select())
+                .load::<ScimGroupRoleEntry>(conn)?
+                .into_iter()
+                .filter(|existing| {
+                    existing.group_name.eq_ignore_ascii_case(&entry.group_name)
+                        && existing.application == entry.application
+                })
+                .map(|existing| existing.id)
+                .collect();
+            diesel::delete(scim_group_roles::table.filter(scim_group_roles::id.eq_any(&replaced)))
+                .execute(conn)?;
+            diesel::insert_into(scim_group_roles::table)
+                .values(&entry)
+                .execute(conn)?;
+            Ok(())
+        })
+    })?;
+    sync::sync_organisation(&state, &organisation, &[]).await?;
+
+    info!(
+        "{} mapped SCIM group {} to {} in org {}{}",
+        auth_response.sub,
+        mapping.group_name,
+        mapping.role,
+        organisation,
+        mapping
+            .application
+            .as_deref()
+            .map(|application| format!(" app {}", application))
+            .unwrap_or_default()
+    );
+    Ok(Json(mapping_info(mapping)))
+}
+
+#[authz(
+    resource = "scim",
+    action = "update",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[delete("/mappings/{id}")]
+async fn delete_mapping(
+    id: Path<uuid::Uuid>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<DeleteScimResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation = require_scope_name(auth_response.organisation.clone(), "organisation")?;
+    let id = id.into_inner();
+    let pool = state.db_pool.clone();
+    let org = organisation.clone();
+    let application = run_blocking!({
+        let mut conn = pool.get()?;
+        let application = diesel::delete(
+            scim_group_roles::table
+                .filter(scim_group_roles::organisation.eq(&org))
+                .filter(scim_group_roles::id.eq(id)),
+        )
+        .returning(scim_group_roles::application)
+        .get_result::<Option<String>>(&mut conn)
+        .optional()?
+        .ok_or_else(|| ABError::NotFound(format!("SCIM mapping {} not found", id)))?;
+        Ok(application)
+    })?;
+    let retired: Vec<String> = application.into_iter().collect();
+    sync::sync_organisation(&state, &organisation, &retired).await?;
+
+    info!(
+        "{} removed SCIM mapping {} in org {}",
+        auth_response.sub, id, organisation
+    );
+    Ok(Json(DeleteScimResponse { success: true }))
+}
@@ -0,0 +1,513 @@
+//! SCIM filter expressions (RFC 7644 §3.4.2.2) and PATCH paths (§3.5.2),
+//! evaluated against resources in their JSON form.
+
+use serde_json::Value;
+
+use crate::{types as airborne_types, types::ABError};
+
+const CORE_SCHEMA_PREFIXES: [&str; 2] = [
+    "urn:ietf:params:scim:schemas:core:2.0:User:",
+    "urn:ietf:params:scim:schemas:core:2.0:Group:",
+];
+// Filters are parsed and evaluated recursively, so both how deep groups nest
+// and how long a chain of `and`/`or` gets are bounded
+const MAX_FILTER_DEPTH: usize = 32;
+const MAX_FILTER_CHAIN: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Co,
+    Sw,
+    Ew,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// `attribute` or `attribute.subAttribute`; extension attributes keep their
+/// schema URN in `attribute`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttrPath {
+    pub attribute: String,
+    pub sub_attribute: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Present(AttrPath),
+    Compare(AttrPath, CompareOp, Value),
+    /// `emails[type eq "work"]`: some element of a multi-valued attribute
+    /// matches the inner filter.
+    ValuePath(String, Box<Filter>),
+}
+
+/// Target of a PATCH operation, e.g. `members[value eq "…"]` or
+/// `name.givenName`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatchPath {
+    pub attribute: String,
+    pub filter: Option<Filter>,
+    pub sub_attribute: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+    Word(String),
+    Literal(Value),
+}
+
+fn invalid(message: impl std::fmt::Display) -> ABError {
+    ABError::BadRequest(format!("Invalid SCIM filter: {message}"))
+}
+
+fn tokenize(input: &str) -> airborne_types::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, ch)) = chars.peek() {
+        match ch {
+            ch if ch.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | '[' | ']' => {
+                chars.next();
+                tokens.push(match ch {
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    '[' => Token::OpenBracket,
+                    _ => Token::CloseBracket,
+                });
+            }
+            '"' => {
+                // JSON string rules, so escapes behave as in request bodies.
+                chars.next();
+                let mut escaped = false;
+                let mut end = None;
+                for (index, ch) in chars.by_ref() {
+                    if escaped {
+                        escaped = false;
+                    } else if ch == '\\' {
+                        escaped = true;
+                    } else if ch == '"' {
+                        end = Some(index);
+                        break;
+                    }
+                }
+                let end = end.ok_or_else(|| invalid("unterminated string"))?;
+                let literal = serde_json::from_str(&input[start..=end])
+                    .map_err(|_| invalid("malformed string"))?;
+                tokens.push(Token::Literal(literal));
+            }
+            _ => {
+                let mut end = input.len();
+                while let Some(&(index, ch)) = chars.peek() {
+                    if ch.is_whitespace() || matches!(ch, '(' | ')' | '[' | ']' | '"') {
+                        end = index;
+                        break;
+                    }
+                    chars.next();
+                }
+                let word = &input[start..end];
+                tokens.push(match word.to_ascii_lowercase().as_str() {
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" => Token::Literal(Value::Null),
+                    _ => match word.parse::<serde_json::Number>() {
+                        Ok(number) => Token::Literal(Value::Number(number)),
+                        Err(_) => Token::Word(word.to_string()),
+                    },
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn attr_path(raw: &str) -> airborne_types::Result<AttrPath> {
+    let mut raw = raw;
+    for prefix in CORE_SCHEMA_PREFIXES {
+        if raw.len() > prefix.len() && raw[..prefix.len()].eq_ignore_ascii_case(prefix) {
+            raw = &raw[prefix.len()..];
+        }
+    }
+    // Extension URNs contain dots ("2.0"), so only split after the last ':'.
+    let (schema, rest) = match raw.rfind(':') {
+        Some(index) if raw.starts_with("urn:") => (Some(&raw[..index]), &raw[index + 1..]),
+        _ => (None, raw),
+    };
+    let (attribute, sub_attribute) = match rest.split_once('.') {
+        Some((attribute, sub_attribute)) => (attribute, Some(sub_attribute.to_string())),
+        None => (rest, None),
+    };
+    if attribute.is_empty()
+        || !attribute
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-' || ch == '$')
+    {
+        return Err(invalid(format!("bad attribute path '{raw}'")));
+    }
+    Ok(match schema {
+        Some(schema) => AttrPath {
+            attribute: schema.to_string(),
+            sub_attribute: Some(match sub_attribute {
+                Some(sub_attribute) => format!("{attribute}.{sub_attribute}"),
+                None => attribute.to_string(),
+            }),
+        },
+        None => AttrPath {
+            attribute: attribute.to_string(),
+            sub_attribute,
+        },
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> airborne_types::Result<()> {
+        if self.next() == Some(expected) {
+            Ok(())
+        } else {
+            Err(invalid("unbalanced brackets"))
+        }
+    }
+
+    /// Parses a group nested in `()`, `not ()` or `[]`, one level deeper.
+    fn nested(&mut self) -> airborne_types::Result<Filter> {
+        if self.depth == MAX_FILTER_DEPTH {
+            return Err(invalid(format!(
+                "nested more than {MAX_FILTER_DEPTH} levels deep"
+            )));
+        }
+        self.depth += 1;
+        let filter = self.or_expression();
+        self.depth -= 1;
+        filter
+    }
+
+    fn or_expression(&mut self) -> airborne_types::Result<Filter> {
+        let mut filter = self.and_expression()?;
+        let mut terms = 1;
+        while self.keyword("or") {
+            terms += 1;
+            if terms > MAX_FILTER_CHAIN {
+                return Err(invalid(format!("more than {MAX_FILTER_CHAIN} 'or' terms")));
+            }
+            filter = Filter::Or(Box::new(filter), Box::new(self.and_expression()?));
+        }
+        Ok(filter)
+    }
+
+    fn and_expression(&mut self) -> airborne_types::Result<Filter> {
+        let mut filter = self.factor()?;
+        let mut terms = 1;
+        while self.keyword("and") {
+            terms += 1;
+            if terms > MAX_FILTER_CHAIN {
+                return Err(invalid(format!("more than {MAX_FILTER_CHAIN} 'and' terms")));
+            }
+            filter = Filter::And(Box::new(filter), Box::new(self.factor()?));
+        }
+        Ok(filter)
+    }
+
+    fn factor(&mut self) -> airborne_types::Result<Filter> {
+        if self.keyword("not") {
+            self.expect(Token::Open)?;
+            let filter = self.nested()?;
+            self.expect(Token::Close)?;
+            return Ok(Filter::Not(Box::new(filter)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.position += 1;
+            let filter = self.nested()?;
+            self.expect(Token::Close)?;
+            return Ok(filter);
+        }
+
+        let Some(Token::Word(raw_path)) = self.next() else {
+            return Err(invalid("expected an attribute"));
+        };
+        if self.peek() == Some(&Token::OpenBracket) {
+            self.position += 1;
+            let inner = self.nested()?;
+            self.expect(Token::CloseBracket)?;
+            return Ok(Filter::ValuePath(
+                attr_path(&raw_path)?.attribute,
+                Box::new(inner),
+            ));
+        }
+        let path = attr_path(&raw_path)?;
+
+        let Some(Token::Word(operator)) = self.next() else {
+            return Err(invalid("expected an operator"));
+        };
+        let op = match operator.to_ascii_lowercase().as_str() {
+            "pr" => return Ok(Filter::Present(path)),
+            "eq" => CompareOp::Eq,
+            "ne" => CompareOp::Ne,
+            "co" => CompareOp::Co,
+            "sw" => CompareOp::Sw,
+            "ew" => CompareOp::Ew,
+            "gt" => CompareOp::Gt,
+            "ge" => CompareOp::Ge,
+            "lt" => CompareOp::Lt,
+            "le" => CompareOp::Le,
+            other => return Err(invalid(format!("unknown operator '{other}'"))),
+        };
+        let Some(Token::Literal(value)) = self.next() else {
+            return Err(invalid("expected a value"));
+        };
+        Ok(Filter::Compare(path, op, value))
+    }
+}
+
+pub fn parse_filter(input: &str) -> airborne_types::Result<Filter> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        position: 0,
+        depth: 0,
+    };
+    let filter = parser.or_expression()?;
+    if parser.position != parser.tokens.len() {
+        return Err(invalid("unexpected trailing input"));
+    }
+    Ok(filter)
+}
+
+pub fn parse_patch_path(input: &str) -> airborne_types::Result<PatchPath> {
+    let Some(open) = input.find('[') else {
+        let path = attr_path(input.trim())?;
+        return Ok(PatchPath {
+            attribute: path.attribute,
+            filter: None,
+            sub_attribute: path.sub_attribute,
+        });
+    };
+    let close = input
+        .rfind(']')
+        .filter(|close| *close > open)
+        .ok_or_else(|| invalid("unbalanced brackets"))?;
+    let sub_attribute = match input[close + 1..].trim() {
+        "" => None,
+        rest => Some(
+            rest.strip_prefix('.')
+                .filter(|sub| !sub.is_empty())
+                .ok_or_else(|| invalid(format!("bad path '{input}'")))?
+                .to_string(),
+        ),
+    };
+    Ok(PatchPath {
+        attribute: attr_path(input[..open].trim())?.attribute,
+        filter: Some(parse_filter(&input[open + 1..close])?),
+        sub_attribute,
+    })
+}
+
+/// Case-insensitive attribute lookup, as SCIM attribute names are.
+pub fn get_attribute<'a>(resource: &'a Value, name: &str) -> Option<&'a Value> {
+    resource
+        .as_object()?
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+fn resolve<'a>(resource: &'a Value, path: &AttrPath) -> Vec<&'a Value> {
+    let Some(value) = get_attribute(resource, &path.attribute) else {
+        return Vec::new();
+    };
+    let elements: Vec<&Value> = match value {
+        Value::Array(items) => items.iter().collect(),
+        value => vec![value],
+    };
+    match &path.sub_attribute {
+        Some(sub_attribute) => elements
+            .into_iter()
+            .filter_map(|element| {
+                sub_attribute
+                    .split('.')
+                    .try_fold(element, |value, name| get_attribute(value, name))
+            })
+            .collect(),
+        // A filter on a multi-valued complex attribute compares its `value`.
+        None => elements
+            .into_iter()
+            .map(|element| get_attribute(element, "value").unwrap_or(element))
+            .collect(),
+    }
+}
+
+fn compare(actual: &Value, op: CompareOp, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::String(actual), Value::String(expected)) => {
+            let actual = actual.to_lowercase();
+            let expected = expected.to_lowercase();
+            match op {
+                CompareOp::Eq => actual == expected,
+                CompareOp::Ne => actual != expected,
+                CompareOp::Co => actual.contains(&expected),
+                CompareOp::Sw => actual.starts_with(&expected),
+                CompareOp::Ew => actual.ends_with(&expected),
+                CompareOp::Gt => actual > expected,
+                CompareOp::Ge => actual >= expected,
+                CompareOp::Lt => actual < expected,
+                CompareOp::Le => actual <= expected,
+            }
+        }
+        (Value::Number(actual), Value::Number(expected)) => {
+            let (Some(actual), Some(expected)) = (actual.as_f64(), expected.as_f64()) else {
+                return false;
+            };
+            match op {
+                CompareOp::Eq => actual == expected,
+                CompareOp::Ne => actual != expected,
+                CompareOp::Gt => actual > expected,
+                CompareOp::Ge => actual >= expected,
+                CompareOp::Lt => actual < expected,
+                CompareOp::Le => actual <= expected,
+                CompareOp::Co | CompareOp::Sw | CompareOp::Ew => false,
+            }
+        }
+        (actual, expected) => match op {
+            CompareOp::Eq => actual == expected,
+            CompareOp::Ne => actual != expected,
+            _ => false,
+        },
+    }
+}
+
+fn is_present(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::String(value) => !value.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(fields) => !fields.is_empty(),
+        _ => true,
+    }
+}
+
+impl Filter {
+    /// `attribute eq value` terms when the filter is nothing but those,
+    /// joined by `and`.
+    pub fn equality_terms(&self) -> Option<Vec<(String, Value)>> {
+        match self {
+            Filter::And(left, right) => {
+                let mut terms = left.equality_terms()?;
+                terms.extend(right.equality_terms()?);
+                Some(terms)
+            }
+            Filter::Compare(path, CompareOp::Eq, value) if path.sub_attribute.is_none() => {
+                Some(vec![(path.attribute.clone(), value.clone())])
+            }
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, resource: &Value) -> bool {
+        match self {
+            Filter::And(left, right) => left.matches(resource) && right.matches(resource),
+            Filter::Or(left, right) => left.matches(resource) || right.matches(resource),
+            Filter::Not(inner) => !inner.matches(resource),
+            Filter::Present(path) => resolve(resource, path).into_iter().any(is_present),
+            Filter::Compare(path, CompareOp::Eq, Value::Null) => {
+                !resolve(resource, path).into_iter().any(is_present)
+            }
+            Filter::Compare(path, op, expected) => resolve(resource, path)
+                .into_iter()
+                .any(|actual| compare(actual, *op, expected)),
+            Filter::ValuePath(attribute, inner) => match get_attribute(resource, attribute) {
+                Some(Value::Array(items)) => items.iter().any(|item| inner.matches(item)),
+                Some(value) => inner.matches(value),
+                None => false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{parse_filter, parse_patch_path};
+
+    #[test]
+    fn evaluates_filters_and_parses_patch_paths() {
+        let user = json!({
+            "userName": "Jane.Doe@example.com",
+            "active": true,
+            "name": { "givenName": "Jane" },
+            "emails": [
+                { "value": "jane@home.example", "type": "home" },
+                { "value": "jane.doe@example.com", "type": "work", "primary": true }
+            ],
+            "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User": { "department": "Payments" }
+        });
+        let matches = |filter: &str| parse_filter(filter).unwrap().matches(&user);
+
+        assert!(matches(r#"userName eq "jane.doe@example.com""#));
+        assert!(matches(
+            r#"emails[type eq "work" and value ew "@example.com"]"#
+        ));
+        assert!(!matches(r#"emails[type eq "home" and primary eq true]"#));
+        assert!(matches(r#"name.givenName sw "ja" and active eq true"#));
+        assert!(matches(r#"not (externalId pr) or title eq null"#));
+        assert!(matches(
+            r#"urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:department eq "payments""#
+        ));
+        assert!(parse_filter(r#"userName eq"#).is_err());
+
+        let path = parse_patch_path(r#"members[value eq "2819c223"]"#).unwrap();
+        assert_eq!(path.attribute, "members");
+        assert!(path.filter.is_some());
+        let path = parse_patch_path(r#"emails[type eq "work"].value"#).unwrap();
+        assert_eq!(path.sub_attribute.as_deref(), Some("value"));
+    }
+
+    #[test]
+    fn rejects_deeply_nested_filters() {
+        let nested =
+            |depth: usize| format!("{}userName pr{}", "not (".repeat(depth), ")".repeat(depth));
+        assert!(parse_filter(&nested(32)).is_ok());
+        assert!(parse_filter(&nested(33)).is_err());
+        assert!(
+            parse_filter(&format!("emails{}", "[value pr and emails".repeat(100_000))).is_err()
+        );
+
+        let chain = |terms: usize| vec!["userName pr"; terms].join(" or ");
+        assert!(parse_filter(&chain(100)).is_ok());
+        assert!(parse_filter(&chain(101)).is_err());
+    }
+}
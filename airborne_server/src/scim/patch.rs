@@ -0,0 +1,219 @@
+//! SCIM PATCH (RFC 7644 §3.5.2) applied to a resource's JSON form.
+
+use serde_json::{Map, Value};
+
+use crate::{
+    scim::{
+        filter::{get_attribute, parse_patch_path, PatchPath},
+        types::PatchOperation,
+    },
+    types as airborne_types,
+    types::ABError,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Add,
+    Replace,
+}
+
+fn invalid(message: impl std::fmt::Display) -> ABError {
+    ABError::BadRequest(format!("Invalid SCIM patch: {message}"))
+}
+
+/// The existing key matching `name` case-insensitively, else `name`.
+fn key_for(object: &Map<String, Value>, name: &str) -> String {
+    object
+        .keys()
+        .find(|key| key.eq_ignore_ascii_case(name))
+        .cloned()
+        .unwrap_or_else(|| name.to_string())
+}
+
+fn as_object(value: &mut Value) -> airborne_types::Result<&mut Map<String, Value>> {
+    if value.is_null() {
+        *value = Value::Object(Map::new());
+    }
+    value
+        .as_object_mut()
+        .ok_or_else(|| invalid("path does not point into a complex attribute"))
+}
+
+fn set_nested(target: &mut Value, path: &str, value: Value) -> airborne_types::Result<()> {
+    let mut current = target;
+    let mut names = path.split('.').peekable();
+    while let Some(name) = names.next() {
+        let object = as_object(current)?;
+        let key = key_for(object, name);
+        if names.peek().is_none() {
+            object.insert(key, value);
+            return Ok(());
+        }
+        current = object.entry(key).or_insert(Value::Null);
+    }
+    Ok(())
+}
+
+fn remove_nested(target: &mut Value, path: &str) {
+    let Some((parent, name)) = path.rsplit_once('.') else {
+        if let Some(object) = target.as_object_mut() {
+            let key = key_for(object, path);
+            object.remove(&key);
+        }
+        return;
+    };
+    let parent = parent.split('.').try_fold(target, |value, name| {
+        let object = value.as_object_mut()?;
+        let key = key_for(object, name);
+        object.get_mut(&key)
+    });
+    if let Some(parent) = parent {
+        remove_nested(parent, name);
+    }
+}
+
+fn merge_values(existing: &mut Vec<Value>, value: Value) {
+    let values = match value {
+        Value::Array(values) => values,
+        value => vec![value],
+    };
+    for value in values {
+        if !existing.contains(&value) {
+            existing.push(value);
+        }
+    }
+}
+
+fn set(resource: &mut Value, path: &PatchPath, op: Op, value: Value) -> airborne_types::Result<()> {
+    let object = as_object(resource)?;
+    let key = key_for(object, &path.attribute);
+
+    let Some(filter) = &path.filter else {
+        if let Some(sub_attribute) = &path.sub_attribute {
+            return set_nested(
+                object.entry(key).or_insert(Value::Null),
+                sub_attribute,
+                value,
+            );
+        }
+        match (op, object.get_mut(&key), value) {
+            (Op::Add, Some(Value::Array(existing)), value) => merge_values(existing, value),
+            (Op::Add, Some(Value::Object(existing)), Value::Object(fields)) => {
+                for (name, field) in fields {
+                    let field_key = key_for(existing, &name);
+                    existing.insert(field_key, field);
+                }
+            }
+            (_, _, value) => {
+                object.insert(key, value);
+            }
+        }
+        return Ok(());
+    };
+
+    let elements = object
+        .entry(key)
+        .or_insert_with(|| Value::Array(Vec::new()))
+        .as_array_mut()
+        .ok_or_else(|| invalid("filtered path on a single-valued attribute"))?;
+    let mut matched = false;
+    for element in elements
+        .iter_mut()
+        .filter(|element| filter.matches(element))
+    {
+        matched = true;
+        match &path.sub_attribute {
+            Some(sub_attribute) => set_nested(element, sub_attribute, value.clone())?,
+            None => *element = value.clone(),
+        }
+    }
+    if !matched {
+        // Create the element the filter pins down, e.g. the work email for
+        // `emails[type eq "work"].value`.
+        let seeded = filter
+            .equality_terms()
+            .ok_or_else(|| invalid("no element matches the path filter"))?;
+        let mut element = Value::Object(seeded.into_iter().collect());
+        match &path.sub_attribute {
+            Some(sub_attribute) => set_nested(&mut element, sub_attribute, value)?,
+            None => element = value,
+        }
+        elements.push(element);
+    }
+    Ok(())
+}
+
+fn remove(resource: &mut Value, path: &PatchPath, value: &Value) -> airborne_types::Result<()> {
+    let object = as_object(resource)?;
+    let key = key_for(object, &path.attribute);
+    let Some(existing) = object.get_mut(&key) else {
+        return Ok(());
+    };
+
+    match (&path.filter, &path.sub_attribute) {
+        (Some(filter), sub_attribute) => {
+            let elements = existing
+                .as_array_mut()
+                .ok_or_else(|| invalid("filtered path on a single-valued attribute"))?;
+            match sub_attribute {
+                Some(sub_attribute) => elements
+                    .iter_mut()
+                    .filter(|element| filter.matches(element))
+                    .for_each(|element| remove_nested(element, sub_attribute)),
+                None => elements.retain(|element| !filter.matches(element)),
+            }
+        }
+        (None, Some(sub_attribute)) => remove_nested(existing, sub_attribute),
+        (None, None) => match (existing, value) {
+            // `{"op": "remove", "path": "members", "value": [{"value": "…"}]}`
+            (Value::Array(elements), Value::Array(targets)) if !targets.is_empty() => {
+                let target_values: Vec<&Value> = targets
+                    .iter()
+                    .map(|target| get_attribute(target, "value").unwrap_or(target))
+                    .collect();
+                elements.retain(|element| {
+                    let element_value = get_attribute(element, "value").unwrap_or(element);
+                    !target_values.contains(&element_value)
+                });
+            }
+            _ => {
+                object.remove(&key);
+            }
+        },
+    }
+    Ok(())
+}
+
+pub fn apply_patch(
+    resource: &mut Value,
+    operations: &[PatchOperation],
+) -> airborne_types::Result<()> {
+    for operation in operations {
+        let op = match operation.op.to_ascii_lowercase().as_str() {
+            "add" => Some(Op::Add),
+            "replace" => Some(Op::Replace),
+            "remove" => None,
+            other => return Err(invalid(format!("unknown op '{other}'"))),
+        };
+        match (op, operation.path.as_deref()) {
+            (Some(op), Some(path)) => set(
+                resource,
+                &parse_patch_path(path)?,
+                op,
+                operation.value.clone(),
+            )?,
+            (Some(op), None) => {
+                let fields = operation
+                    .value
+                    .as_object()
+                    .ok_or_else(|| invalid("an operation without a path needs an object value"))?;
+                for (name, value) in fields {
+                    set(resource, &parse_patch_path(name)?, op, value.clone())?;
+                }
+            }
+            (None, Some(path)) => remove(resource, &parse_patch_path(path)?, &operation.value)?,
+            (None, None) => return Err(invalid("remove needs a path")),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,274 @@
+//! Reconciles provisioned users' roles with the roles mapped to their SCIM
+//! groups.
+//!
+//! For an active user, the organisation role is the highest role mapped to
+//! any of their groups (or `read` when only application roles are mapped),
+//! and every application that appears in the organisation's mappings gets the
+//! highest role mapped for it, or none. Inactive or deleted users, and users
+//! whose groups map to nothing, lose their organisation membership entirely.
+//! Organisation owners are never touched, so a misconfigured IdP cannot lock
+//! an organisation out.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use diesel::prelude::*;
+use log::{info, warn};
+
+use crate::{
+    authz::grant::system_role_level,
+    run_blocking, types as airborne_types,
+    types::{ABError, AppState},
+    utils::db::{
+        models::{ScimGroupRoleEntry, ScimUserEntry},
+        schema::hyperotaserver::{scim_group_members, scim_group_roles, scim_groups, scim_users},
+    },
+};
+
+#[derive(Debug, Default, PartialEq)]
+struct DesiredRoles {
+    organisation: Option<String>,
+    /// Every application with mappings, so roles that are no longer mapped
+    /// get cleared.
+    applications: BTreeMap<String, Option<String>>,
+}
+
+fn role_rank(role: &str) -> u8 {
+    system_role_level(role).unwrap_or(0)
+}
+
+fn keep_highest(current: &mut Option<String>, candidate: &str) {
+    if current
+        .as_deref()
+        .is_none_or(|existing| role_rank(candidate) > role_rank(existing))
+    {
+        *current = Some(candidate.to_string());
+    }
+}
+
+fn desired_roles(
+    active: bool,
+    groups: &HashSet<String>,
+    mappings: &[ScimGroupRoleEntry],
+    retired_applications: &[String],
+) -> DesiredRoles {
+    let mut desired = DesiredRoles::default();
+    for application in retired_applications {
+        desired.applications.insert(application.clone(), None);
+    }
+    for mapping in mappings {
+        let slot = match &mapping.application {
+            Some(application) => desired.applications.entry(application.clone()).or_default(),
+            None => &mut desired.organisation,
+        };
+        if active && groups.contains(&mapping.group_name.to_lowercase()) {
+            keep_highest(slot, &mapping.role);
+        }
+    }
+    if desired.organisation.is_none() && desired.applications.values().any(Option::is_some) {
+        desired.organisation = Some("read".to_string());
+    }
+    desired
+}
+
+async fn apply(
+    state: &AppState,
+    organisation: &str,
+    subject: &str,
+    desired: &DesiredRoles,
+) -> airborne_types::Result<()> {
+    let provider = &state.authz_provider;
+    let current = provider
+        .subject_role(state, organisation, None, subject)
+        .await?;
+    if current.as_deref() == Some("owner") {
+        warn!(
+            "SCIM: leaving owner {} of org {} unchanged",
+            subject, organisation
+        );
+        return Ok(());
+    }
+
+    let Some(org_role) = &desired.organisation else {
+        if current.is_some() {
+            provider
+                .assign_role(state, organisation, None, subject, None)
+                .await?;
+            info!("SCIM: removed {} from org {}", subject, organisation);
+        }
+        return Ok(());
+    };
+    if current.as_deref() != Some(org_role.as_str()) {
+        provider
+            .assign_role(state, organisation, None, subject, Some(org_role))
+            .await?;
+        info!(
+            "SCIM: set {}'s role in org {} to {}",
+            subject, organisation, org_role
+        );
+    }
+
+    for (application, role) in &desired.applications {
+        let result = async {
+            let current = provider
+                .subject_role(state, organisation, Some(application), subject)
+                .await?;
+            if current != *role {
+                provider
+                    .assign_role(
+                        state,
+                        organisation,
+                        Some(application),
+                        subject,
+                        role.as_deref(),
+                    )
+                    .await?;
+            }
+            Ok::<_, ABError>(())
+        }
+        .await;
+        // One stale mapping (e.g. to a deleted application) should not stop
+        // the rest of the user's roles from being applied.
+        if let Err(e) = result {
+            warn!(
+                "SCIM: could not sync {}'s role in {}/{}: {}",
+                subject, organisation, application, e
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Brings the given users' roles in line with their groups and status.
+pub(super) async fn sync_users(
+    state: &AppState,
+    organisation: &str,
+    user_ids: Vec<uuid::Uuid>,
+) -> airborne_types::Result<()> {
+    sync(state, organisation, user_ids, &[]).await
+}
+
+/// Like `sync_users`, also clearing roles in `retired_applications`, which
+/// may no longer appear in any mapping.
+async fn sync(
+    state: &AppState,
+    organisation: &str,
+    user_ids: Vec<uuid::Uuid>,
+    retired_applications: &[String],
+) -> airborne_types::Result<()> {
+    if user_ids.is_empty() {
+        return Ok(());
+    }
+    let pool = state.db_pool.clone();
+    let org = organisation.to_string();
+    let (users, memberships, mappings) = run_blocking!({
+        let mut conn = pool.get()?;
+        let users = scim_users::table
+            .filter(scim_users::organisation.eq(&org))
+            .filter(scim_users::id.eq_any(&user_ids))
+            .select(ScimUserEntry::as_select())
+            .load::<ScimUserEntry>(&mut conn)?;
+        let memberships = scim_group_members::table
+            .inner_join(scim_groups::table)
+            .filter(scim_group_members::user_id.eq_any(&user_ids))
+            .select((scim_group_members::user_id, scim_groups::display_name))
+            .load::<(uuid::Uuid, String)>(&mut conn)?;
+        let mappings = scim_group_roles::table
+            .filter(scim_group_roles::organisation.eq(&org))
+            .select(ScimGroupRoleEntry::as_select())
+            .load::<ScimGroupRoleEntry>(&mut conn)?;
+        Ok((users, memberships, mappings))
+    })?;
+
+    let mut groups: HashMap<uuid::Uuid, HashSet<String>> = HashMap::new();
+    for (user_id, group) in memberships {
+        groups
+            .entry(user_id)
+            .or_default()
+            .insert(group.to_lowercase());
+    }
+    for user in users {
+        let user_groups = groups.remove(&user.id).unwrap_or_default();
+        let desired = desired_roles(user.active, &user_groups, &mappings, retired_applications);
+        apply(state, organisation, &user.subject, &desired).await?;
+    }
+    Ok(())
+}
+
+/// Brings every provisioned user of the organisation in line, after its
+/// group-to-role mappings changed. `retired_applications` are applications
+/// whose mappings were just removed.
+pub(super) async fn sync_organisation(
+    state: &AppState,
+    organisation: &str,
+    retired_applications: &[String],
+) -> airborne_types::Result<()> {
+    let pool = state.db_pool.clone();
+    let org = organisation.to_string();
+    let user_ids = run_blocking!({
+        let mut conn = pool.get()?;
+        let ids = scim_users::table
+            .filter(scim_users::organisation.eq(&org))
+            .select(scim_users::id)
+            .load::<uuid::Uuid>(&mut conn)?;
+        Ok(ids)
+    })?;
+    sync(state, organisation, user_ids, retired_applications).await
+}
+
+/// Removes a deprovisioned user from the organisation.
+pub(super) async fn revoke_user(
+    state: &AppState,
+    organisation: &str,
+    subject: &str,
+) -> airborne_types::Result<()> {
+    apply(state, organisation, subject, &DesiredRoles::default()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::Utc;
+
+    use super::desired_roles;
+    use crate::utils::db::models::ScimGroupRoleEntry;
+
+    fn mapping(group: &str, application: Option<&str>, role: &str) -> ScimGroupRoleEntry {
+        ScimGroupRoleEntry {
+            id: uuid::Uuid::new_v4(),
+            organisation: "acme".to_string(),
+            group_name: group.to_string(),
+            application: application.map(str::to_string),
+            role: role.to_string(),
+            created_by: "admin@acme.dev".to_string(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn picks_highest_mapped_roles() {
+        let mappings = vec![
+            mapping("Engineering", None, "write"),
+            mapping("Platform", None, "admin"),
+            mapping("Engineering", Some("checkout"), "read"),
+            mapping("Release Managers", Some("checkout"), "admin"),
+            mapping("Support", Some("wallet"), "read"),
+        ];
+        let groups: HashSet<String> = ["engineering".to_string(), "release managers".to_string()]
+            .into_iter()
+            .collect();
+
+        let desired = desired_roles(true, &groups, &mappings, &[]);
+        assert_eq!(desired.organisation.as_deref(), Some("write"));
+        assert_eq!(desired.applications["checkout"].as_deref(), Some("admin"));
+        assert_eq!(desired.applications["wallet"], None);
+
+        let support: HashSet<String> = ["support".to_string()].into_iter().collect();
+        let desired = desired_roles(true, &support, &mappings, &[]);
+        assert_eq!(desired.organisation.as_deref(), Some("read"));
+
+        let desired = desired_roles(false, &groups, &mappings, &[]);
+        assert_eq!(desired.organisation, None);
+        assert!(desired.applications.values().all(Option::is_none));
+    }
+}
@@ -0,0 +1,87 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Deserialize)]
+pub struct PatchOperation {
+    pub op: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub value: Value,
+}
+
+#[derive(Deserialize)]
+pub struct PatchRequest {
+    #[serde(rename = "Operations")]
+    pub operations: Vec<PatchOperation>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimListQuery {
+    pub filter: Option<String>,
+    /// 1-based, as in RFC 7644 §3.4.2.4.
+    pub start_index: Option<usize>,
+    pub count: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct ScimListResponse {
+    pub schemas: [&'static str; 1],
+    #[serde(rename = "totalResults")]
+    pub total_results: usize,
+    #[serde(rename = "startIndex")]
+    pub start_index: usize,
+    #[serde(rename = "itemsPerPage")]
+    pub items_per_page: usize,
+    #[serde(rename = "Resources")]
+    pub resources: Vec<Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct CreateScimTokenRequest {
+    pub description: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ScimTokenInfo {
+    pub id: uuid::Uuid,
+    pub description: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct CreateScimTokenResponse {
+    #[serde(flatten)]
+    pub info: ScimTokenInfo,
+    /// Bearer token for the IdP; it is only ever returned here.
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ScimGroupRoleRequest {
+    /// SCIM group `displayName`, matched case-insensitively.
+    pub group: String,
+    /// Application the role applies to; the organisation when omitted.
+    pub application: Option<String>,
+    pub role: String,
+}
+
+#[derive(Serialize)]
+pub struct ScimGroupRoleInfo {
+    pub id: uuid::Uuid,
+    pub group: String,
+    pub application: Option<String>,
+    pub role: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct DeleteScimResponse {
+    pub success: bool,
+}
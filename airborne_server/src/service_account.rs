@@ -0,0 +1,589 @@
+//! Organisation-owned service accounts for CI and other automation.
+//!
+//! A service account is an authorization subject of its own
+//! (`serviceaccount:<org>:<name>`) with role bindings managed through the
+//! `AuthZProvider`, so it keeps working when the person who set it up leaves.
+//! It authenticates with a client id/secret pair exchanged at
+//! `POST /service-accounts/token` for an opaque, server-issued access token;
+//! the IdP is not involved. Keys can be rotated with an overlap window during
+//! which old and new keys both work.
+
+pub mod types;
+
+use actix_web::{
+    delete, get, post,
+    web::{self, Json, Path, ReqData},
+    Scope,
+};
+use airborne_authz_macros::authz;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use log::info;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    middleware::auth::{require_scope_name, Auth, AuthResponse},
+    run_blocking,
+    service_account::types::*,
+    types as airborne_types,
+    types::{ABError, AppState, ListResponse},
+    user::types::UserToken,
+    utils::{
+        db::{
+            models::{ServiceAccountEntry, ServiceAccountKeyEntry, ServiceAccountTokenEntry},
+            schema::hyperotaserver::{
+                service_account_keys, service_account_tokens, service_accounts,
+            },
+            DbPool,
+        },
+        encryption::generate_random_key,
+    },
+};
+
+/// Prefix of service-account access tokens, used by the auth middleware to
+/// tell them apart from IdP JWTs.
+pub const TOKEN_PREFIX: &str = "absa_";
+const SUBJECT_PREFIX: &str = "serviceaccount:";
+const DEFAULT_KEY_OVERLAP_SECS: i64 = 24 * 60 * 60;
+const MAX_NAME_LENGTH: usize = 50;
+// Names that would shadow the fixed routes of this scope.
+const RESERVED_NAMES: [&str; 2] = ["list", "token"];
+
+pub fn add_scopes(path: &str) -> Scope {
+    Scope::new(path).service(issue_token).service(
+        Scope::new("")
+            .wrap(Auth)
+            .service(list_service_accounts)
+            .service(create_service_account)
+            .service(rotate_key)
+            .service(revoke_key)
+            .service(delete_service_account),
+    )
+}
+
+pub fn subject(organisation: &str, name: &str) -> String {
+    format!("{}{}:{}", SUBJECT_PREFIX, organisation, name).to_ascii_lowercase()
+}
+
+pub fn is_service_account_subject(subject: &str) -> bool {
+    subject.starts_with(SUBJECT_PREFIX)
+}
+
+fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+fn validate_name(name: &str) -> airborne_types::Result<String> {
+    let name = name.trim().to_ascii_lowercase();
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(ABError::BadRequest(format!(
+            "Service account name must be 1-{} characters",
+            MAX_NAME_LENGTH
+        )));
+    }
+    if !name
+        .chars()
+        .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '-' || ch == '_')
+    {
+        return Err(ABError::BadRequest(
+            "Service account name may only contain letters, digits, '-' and '_'".to_string(),
+        ));
+    }
+    if RESERVED_NAMES.contains(&name.as_str()) {
+        return Err(ABError::BadRequest(format!(
+            "'{}' is reserved and cannot be used as a service account name",
+            name
+        )));
+    }
+    Ok(name)
+}
+
+async fn find_account(
+    pool: DbPool,
+    organisation: String,
+    name: String,
+) -> airborne_types::Result<ServiceAccountEntry> {
+    run_blocking!({
+        let mut conn = pool.get()?;
+        service_accounts::table
+            .filter(service_accounts::organisation.eq(&organisation))
+            .filter(service_accounts::name.eq(&name))
+            .select(ServiceAccountEntry::as_select())
+            .first::<ServiceAccountEntry>(&mut conn)
+            .optional()?
+            .ok_or_else(|| ABError::NotFound(format!("Service account '{}' not found", name)))
+    })
+}
+
+/// Issues a new key for the account. When `overlap` is given, keys that would
+/// outlive it are cut down to expire once the overlap has passed.
+async fn issue_key(
+    pool: DbPool,
+    account_id: uuid::Uuid,
+    overlap: Option<Duration>,
+) -> airborne_types::Result<ServiceAccountKeySecret> {
+    let secret = generate_random_key().await?;
+    let now = Utc::now();
+    let key = ServiceAccountKeyEntry {
+        id: uuid::Uuid::new_v4(),
+        service_account_id: account_id,
+        secret_hash: hash_secret(&secret),
+        created_at: now,
+        expires_at: None,
+        last_used_at: None,
+    };
+    let key_id = key.id;
+
+    run_blocking!({
+        let mut conn = pool.get()?;
+        conn.transaction::<_, ABError, _>(|conn| {
+            if let Some(overlap) = overlap {
+                let cutoff = now + overlap;
+                diesel::update(
+                    service_account_keys::table
+                        .filter(service_account_keys::service_account_id.eq(account_id))
+                        .filter(
+                            service_account_keys::expires_at
+                                .is_null()
+                                .or(service_account_keys::expires_at.gt(cutoff)),
+                        ),
+                )
+                .set(service_account_keys::expires_at.eq(cutoff))
+                .execute(conn)?;
+            }
+            diesel::insert_into(service_account_keys::table)
+                .values(&key)
+                .execute(conn)?;
+            Ok(())
+        })
+    })?;
+
+    Ok(ServiceAccountKeySecret {
+        client_id: key_id,
+        client_secret: secret,
+    })
+}
+
+async fn load_keys(
+    pool: DbPool,
+    account_ids: Vec<uuid::Uuid>,
+) -> airborne_types::Result<Vec<ServiceAccountKeyEntry>> {
+    run_blocking!({
+        let mut conn = pool.get()?;
+        let keys = service_account_keys::table
+            .filter(service_account_keys::service_account_id.eq_any(&account_ids))
+            .order(service_account_keys::created_at.asc())
+            .select(ServiceAccountKeyEntry::as_select())
+            .load::<ServiceAccountKeyEntry>(&mut conn)?;
+        Ok(keys)
+    })
+}
+
+fn account_info(
+    account: ServiceAccountEntry,
+    roles: Vec<String>,
+    keys: &[ServiceAccountKeyEntry],
+) -> ServiceAccountInfo {
+    let now = Utc::now();
+    ServiceAccountInfo {
+        subject: subject(&account.organisation, &account.name),
+        name: account.name,
+        description: account.description,
+        created_by: account.created_by,
+        created_at: account.created_at,
+        roles,
+        keys: keys
+            .iter()
+            .filter(|key| key.service_account_id == account.id)
+            .filter(|key| key.expires_at.is_none_or(|expires_at| expires_at > now))
+            .map(|key| ServiceAccountKeyInfo {
+                id: key.id,
+                created_at: key.created_at,
+                expires_at: key.expires_at,
+                last_used_at: key.last_used_at,
+            })
+            .collect(),
+    }
+}
+
+/// Resolves a service-account access token to its account. Fails once the
+/// token, or the key it was issued with, has expired or been revoked.
+pub async fn authenticate(
+    pool: DbPool,
+    access_token: &str,
+) -> airborne_types::Result<ServiceAccountEntry> {
+    let token_hash = hash_secret(access_token);
+    let (token, key_expires_at, account) = run_blocking!({
+        let mut conn = pool.get()?;
+        let token = service_account_tokens::table
+            .find(&token_hash)
+            .select(ServiceAccountTokenEntry::as_select())
+            .first::<ServiceAccountTokenEntry>(&mut conn)
+            .optional()?
+            .ok_or_else(|| ABError::Unauthorized("Invalid access token".to_string()))?;
+        let key_expires_at = service_account_keys::table
+            .find(token.key_id)
+            .select(service_account_keys::expires_at)
+            .first::<Option<chrono::DateTime<Utc>>>(&mut conn)?;
+        let account = service_accounts::table
+            .find(token.service_account_id)
+            .select(ServiceAccountEntry::as_select())
+            .first::<ServiceAccountEntry>(&mut conn)?;
+        Ok((token, key_expires_at, account))
+    })?;
+
+    let now = Utc::now();
+    if token.expires_at <= now {
+        return Err(ABError::Unauthorized(
+            "Access token has expired".to_string(),
+        ));
+    }
+    if key_expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err(ABError::Unauthorized(
+            "Service account key has been rotated out".to_string(),
+        ));
+    }
+    Ok(account)
+}
+
+#[post("/token")]
+async fn issue_token(
+    req: Json<ServiceAccountTokenRequest>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<UserToken>> {
+    let req = req.into_inner();
+    let pool = state.db_pool.clone();
+    let key_id = req.client_id;
+    let key = run_blocking!({
+        let mut conn = pool.get()?;
+        let key = service_account_keys::table
+            .find(key_id)
+            .select(ServiceAccountKeyEntry::as_select())
+            .first::<ServiceAccountKeyEntry>(&mut conn)
+            .optional()?;
+        Ok(key)
+    })?;
+
+    let now = Utc::now();
+    let key = key
+        .filter(|key| key.secret_hash == hash_secret(&req.client_secret))
+        .ok_or_else(|| ABError::Unauthorized("Invalid credentials".to_string()))?;
+    if key.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err(ABError::Unauthorized(
+            "Service account key has been rotated out".to_string(),
+        ));
+    }
+
+    let access_token = format!("{}{}", TOKEN_PREFIX, generate_random_key().await?);
+    let ttl = state.env.service_account_token_ttl_secs.max(1);
+    let entry = ServiceAccountTokenEntry {
+        token_hash: hash_secret(&access_token),
+        service_account_id: key.service_account_id,
+        key_id: key.id,
+        expires_at: now + Duration::seconds(ttl),
+        created_at: now,
+    };
+    let pool = state.db_pool.clone();
+    run_blocking!({
+        let mut conn = pool.get()?;
+        diesel::delete(
+            service_account_tokens::table.filter(service_account_tokens::expires_at.lt(now)),
+        )
+        .execute(&mut conn)?;
+        diesel::insert_into(service_account_tokens::table)
+            .values(&entry)
+            .execute(&mut conn)?;
+        diesel::update(service_account_keys::table.find(key_id))
+            .set(service_account_keys::last_used_at.eq(now))
+            .execute(&mut conn)?;
+        Ok(())
+    })?;
+
+    info!("Issued service account token with key {}", key_id);
+    Ok(Json(UserToken {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: ttl,
+        refresh_token: String::new(),
+        refresh_expires_in: 0,
+    }))
+}
+
+#[authz(
+    resource = "service_account",
+    action = "create",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[post("")]
+async fn create_service_account(
+    req: Json<CreateServiceAccountRequest>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<CreateServiceAccountResponse>> {
+    let req = req.into_inner();
+    let auth_response = auth_response.into_inner();
+    let organisation = require_scope_name(auth_response.organisation.clone(), "organisation")?;
+    let name = validate_name(&req.name)?;
+    let role = req.role.trim().to_string();
+
+    let account = ServiceAccountEntry {
+        id: uuid::Uuid::new_v4(),
+        organisation: organisation.clone(),
+        name: name.clone(),
+        description: req.description,
+        created_by: auth_response.sub.clone(),
+        created_at: Utc::now(),
+    };
+    let pool = state.db_pool.clone();
+    let new_account = account.clone();
+    run_blocking!({
+        let mut conn = pool.get()?;
+        let inserted = diesel::insert_into(service_accounts::table)
+            .values(&new_account)
+            .on_conflict_do_nothing()
+            .execute(&mut conn)?;
+        if inserted == 0 {
+            return Err(ABError::Conflict(format!(
+                "Service account '{}' already exists",
+                new_account.name
+            )));
+        }
+        Ok(())
+    })?;
+
+    let account_subject = subject(&organisation, &name);
+    if let Err(e) = state
+        .authz_provider
+        .add_organisation_user(
+            state.get_ref(),
+            &auth_response.sub,
+            &organisation,
+            &account_subject,
+            &role,
+        )
+        .await
+    {
+        let pool = state.db_pool.clone();
+        let account_id = account.id;
+        run_blocking!({
+            let mut conn = pool.get()?;
+            diesel::delete(service_accounts::table.find(account_id)).execute(&mut conn)?;
+            Ok(())
+        })?;
+        return Err(e);
+    }
+
+    let key = issue_key(state.db_pool.clone(), account.id, None).await?;
+    let keys = load_keys(state.db_pool.clone(), vec![account.id]).await?;
+
+    info!(
+        "{} created service account {} in org {} with role {}",
+        auth_response.sub, account_subject, organisation, role
+    );
+    Ok(Json(CreateServiceAccountResponse {
+        account: account_info(account, vec![role], &keys),
+        key,
+    }))
+}
+
+#[authz(
+    resource = "service_account",
+    action = "read",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[get("/list")]
+async fn list_service_accounts(
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<ListResponse<Vec<ServiceAccountInfo>>>> {
+    let auth_response = auth_response.into_inner();
+    let organisation = require_scope_name(auth_response.organisation.clone(), "organisation")?;
+
+    let pool = state.db_pool.clone();
+    let org = organisation.clone();
+    let accounts = run_blocking!({
+        let mut conn = pool.get()?;
+        let accounts = service_accounts::table
+            .filter(service_accounts::organisation.eq(&org))
+            .order(service_accounts::name.asc())
+            .select(ServiceAccountEntry::as_select())
+            .load::<ServiceAccountEntry>(&mut conn)?;
+        Ok(accounts)
+    })?;
+    let keys = load_keys(
+        state.db_pool.clone(),
+        accounts.iter().map(|account| account.id).collect(),
+    )
+    .await?;
+    let members = state
+        .authz_provider
+        .list_organisation_users(state.get_ref(), &organisation)
+        .await?;
+
+    let data = accounts
+        .into_iter()
+        .map(|account| {
+            let account_subject = subject(&account.organisation, &account.name);
+            let roles = members
+                .iter()
+                .find(|member| member.username == account_subject)
+                .map(|member| member.roles.clone())
+                .unwrap_or_default();
+            account_info(account, roles, &keys)
+        })
+        .collect();
+
+    Ok(Json(ListResponse { data }))
+}
+
+#[authz(
+    resource = "service_account",
+    action = "update",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[post("/{name}/keys")]
+async fn rotate_key(
+    name: Path<String>,
+    req: Json<RotateKeyRequest>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<ServiceAccountKeySecret>> {
+    let auth_response = auth_response.into_inner();
+    let organisation = require_scope_name(auth_response.organisation.clone(), "organisation")?;
+    let overlap_secs = req.overlap_secs.unwrap_or(DEFAULT_KEY_OVERLAP_SECS);
+    if overlap_secs < 0 {
+        return Err(ABError::BadRequest(
+            "overlap_secs cannot be negative".to_string(),
+        ));
+    }
+
+    let account = find_account(state.db_pool.clone(), organisation, name.into_inner()).await?;
+    let key = issue_key(
+        state.db_pool.clone(),
+        account.id,
+        Some(Duration::seconds(overlap_secs)),
+    )
+    .await?;
+
+    info!(
+        "{} rotated keys of service account {} (overlap {}s)",
+        auth_response.sub,
+        subject(&account.organisation, &account.name),
+        overlap_secs
+    );
+    Ok(Json(key))
+}
+
+#[authz(
+    resource = "service_account",
+    action = "update",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[delete("/{name}/keys/{key_id}")]
+async fn revoke_key(
+    path: Path<(String, uuid::Uuid)>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<DeleteServiceAccountResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation = require_scope_name(auth_response.organisation.clone(), "organisation")?;
+    let (name, key_id) = path.into_inner();
+
+    let account = find_account(state.db_pool.clone(), organisation, name).await?;
+    let pool = state.db_pool.clone();
+    let account_id = account.id;
+    let deleted = run_blocking!({
+        let mut conn = pool.get()?;
+        let deleted = diesel::delete(
+            service_account_keys::table
+                .filter(service_account_keys::id.eq(key_id))
+                .filter(service_account_keys::service_account_id.eq(account_id)),
+        )
+        .execute(&mut conn)?;
+        Ok(deleted)
+    })?;
+    if deleted == 0 {
+        return Err(ABError::NotFound(format!("Key {} not found", key_id)));
+    }
+
+    info!(
+        "{} revoked key {} of service account {}",
+        auth_response.sub,
+        key_id,
+        subject(&account.organisation, &account.name)
+    );
+    Ok(Json(DeleteServiceAccountResponse { success: true }))
+}
+
+#[authz(
+    resource = "service_account",
+    action = "delete",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[delete("/{name}")]
+async fn delete_service_account(
+    name: Path<String>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<DeleteServiceAccountResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation = require_scope_name(auth_response.organisation.clone(), "organisation")?;
+
+    let account = find_account(
+        state.db_pool.clone(),
+        organisation.clone(),
+        name.into_inner(),
+    )
+    .await?;
+    let account_subject = subject(&account.organisation, &account.name);
+    let is_member = state
+        .authz_provider
+        .list_organisation_users(state.get_ref(), &organisation)
+        .await?
+        .iter()
+        .any(|member| member.username == account_subject);
+    if is_member {
+        state
+            .authz_provider
+            .remove_organisation_user(
+                state.get_ref(),
+                &auth_response.sub,
+                &organisation,
+                &account_subject,
+            )
+            .await?;
+    }
+
+    let pool = state.db_pool.clone();
+    let account_id = account.id;
+    run_blocking!({
+        let mut conn = pool.get()?;
+        diesel::delete(service_accounts::table.find(account_id)).execute(&mut conn)?;
+        Ok(())
+    })?;
+
+    info!(
+        "{} deleted service account {}",
+        auth_response.sub, account_subject
+    );
+    Ok(Json(DeleteServiceAccountResponse { success: true }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_names() {
+        assert_eq!(validate_name(" CI-Deploy ").unwrap(), "ci-deploy");
+        assert!(validate_name("ci deploy").is_err());
+        assert!(validate_name("token").is_err());
+        assert!(validate_name("").is_err());
+        assert_eq!(subject("Acme", "ci"), "serviceaccount:acme:ci");
+    }
+}
@@ -0,0 +1,61 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct CreateServiceAccountRequest {
+    pub name: String,
+    pub description: Option<String>,
+    /// Organisation role granted to the account.
+    pub role: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct RotateKeyRequest {
+    /// How long existing keys keep working after the new one is issued.
+    pub overlap_secs: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct ServiceAccountTokenRequest {
+    pub client_id: uuid::Uuid,
+    pub client_secret: String,
+}
+
+#[derive(Serialize)]
+pub struct ServiceAccountKeyInfo {
+    pub id: uuid::Uuid,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct ServiceAccountInfo {
+    pub name: String,
+    /// Authorization subject; use it wherever a user is expected to manage
+    /// the account's application roles.
+    pub subject: String,
+    pub description: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub roles: Vec<String>,
+    pub keys: Vec<ServiceAccountKeyInfo>,
+}
+
+#[derive(Serialize)]
+pub struct ServiceAccountKeySecret {
+    pub client_id: uuid::Uuid,
+    pub client_secret: String,
+}
+
+#[derive(Serialize)]
+pub struct CreateServiceAccountResponse {
+    pub account: ServiceAccountInfo,
+    pub key: ServiceAccountKeySecret,
+}
+
+#[derive(Serialize)]
+pub struct DeleteServiceAccountResponse {
+    pub success: bool,
+}
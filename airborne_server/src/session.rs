@@ -0,0 +1,862 @@
+//! Server-side registry of login sessions.
+//!
+//! Every access and refresh token handed out at login or refresh is recorded,
+//! as a SHA-256 hash, under a session, so sessions can be listed and revoked
+//! before their tokens expire. The auth middleware rejects access tokens of
+//! revoked sessions; their status is cached in Redis when it is configured and
+//! read from Postgres otherwise. Refresh tokens are single-use: presenting one
+//! that was already rotated revokes the whole session, since a copy of it is
+//! in someone else's hands.
+//!
+//! Tokens the registry has never seen (issued before it existed, or the
+//! refresh tokens the server keeps for PATs) work until they expire; a refresh
+//! with one of them starts a new session.
+
+pub mod types;
+
+use std::{collections::HashMap, future::Future};
+
+use actix_web::{
+    delete, get,
+    http::header,
+    post,
+    web::{self, Json, Path, ReqData},
+    HttpRequest, Scope,
+};
+use airborne_authz_macros::authz;
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use log::{info, warn};
+
+use crate::{
+    authz::grant::system_role_level,
+    middleware::auth::{require_scope_name, Auth, AuthResponse},
+    run_blocking,
+    session::types::*,
+    token::scope::token_hash,
+    types as airborne_types,
+    types::{ABError, AppState, ListResponse},
+    user::types::UserToken,
+    utils::{
+        client_ip::client_ip,
+        db::{
+            models::{AuthSessionEntry, AuthSessionTokenEntry},
+            schema::hyperotaserver::{auth_session_tokens, auth_sessions},
+            DbPool,
+        },
+        redis::{RedisCache, RedisKey},
+    },
+};
+
+const KIND_ACCESS: &str = "access";
+const KIND_REFRESH: &str = "refresh";
+// Revocations overwrite the cached status of the session's access tokens, so
+// this only bounds how long unknown tokens stay cached.
+const STATUS_CACHE_TTL_SECS: usize = 300;
+
+pub fn add_scopes(path: &str) -> Scope {
+    Scope::new(path).service(refresh_session).service(
+        Scope::new("")
+            .wrap(Auth)
+            .service(list_own_sessions)
+            .service(revoke_current_session)
+            .service(list_organisation_sessions)
+            .service(revoke_organisation_sessions)
+            .service(list_member_sessions)
+            .service(revoke_member_sessions)
+            .service(revoke_own_session),
+    )
+}
+
+/// Where a login came from, shown when listing sessions.
+#[derive(Default)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn from_request(req: &HttpRequest, state: &AppState) -> Self {
+        ClientInfo {
+            ip: client_ip(req, &state.env.trusted_proxies),
+            user_agent: req
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        }
+    }
+}
+
+fn status_key(cache: &RedisCache, token_hash: &str) -> RedisKey {
+    cache.key_unlabeled("global", "session", &[token_hash])
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Token rows for `token`, and when the session holding them expires.
+fn token_entries(
+    state: &AppState,
+    session_id: uuid::Uuid,
+    token: &UserToken,
+    now: DateTime<Utc>,
+) -> (Vec<AuthSessionTokenEntry>, DateTime<Utc>) {
+    let refresh_ttl = state.env.session_refresh_ttl_secs.max(1);
+    let lifetime = |secs: i64| now + Duration::seconds(if secs > 0 { secs } else { refresh_ttl });
+    let access_expires_at = lifetime(token.expires_in);
+    let mut entries = vec![AuthSessionTokenEntry {
+        token_hash: token_hash(&token.access_token),
+        session_id,
+        kind: KIND_ACCESS.to_string(),
+        created_at: now,
+        expires_at: access_expires_at,
+        rotated_at: None,
+    }];
+    let mut session_expires_at = access_expires_at;
+    if !token.refresh_token.is_empty() {
+        let refresh_expires_at = lifetime(token.refresh_expires_in);
+        session_expires_at = session_expires_at.max(refresh_expires_at);
+        entries.push(AuthSessionTokenEntry {
+            token_hash: token_hash(&token.refresh_token),
+            session_id,
+            kind: KIND_REFRESH.to_string(),
+            created_at: now,
+            expires_at: refresh_expires_at,
+            rotated_at: None,
+        });
+    }
+    (entries, session_expires_at)
+}
+
+/// Inserts the tokens. A refresh token the IdP handed back unchanged (no
+/// rotation on its side) becomes usable again rather than counting as reused.
+fn insert_tokens(
+    conn: &mut PgConnection,
+    entries: &[AuthSessionTokenEntry],
+) -> airborne_types::Result<()> {
+    diesel::insert_into(auth_session_tokens::table)
+        .values(entries)
+        .on_conflict(auth_session_tokens::token_hash)
+        .do_update()
+        .set(auth_session_tokens::rotated_at.eq(None::<DateTime<Utc>>))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Records a new session for `subject` holding `token`.
+pub async fn start(
+    state: &AppState,
+    subject: &str,
+    token: &UserToken,
+    client: ClientInfo,
+) -> airborne_types::Result<uuid::Uuid> {
+    let now = Utc::now();
+    let session_id = uuid::Uuid::new_v4();
+    let (entries, expires_at) = token_entries(state, session_id, token, now);
+    let session = AuthSessionEntry {
+        id: session_id,
+        subject: subject.to_string(),
+        created_at: now,
+        last_refreshed_at: None,
+        expires_at,
+        revoked_at: None,
+        revoked_by: None,
+        revoke_reason: None,
+        client_ip: client.ip,
+        user_agent: client.user_agent,
+    };
+    let pool = state.db_pool.clone();
+    run_blocking!({
+        let mut conn = pool.get()?;
+        conn.transaction::<_, ABError, _>(|conn| {
+            diesel::delete(auth_sessions::table.filter(auth_sessions::expires_at.lt(now)))
+                .execute(conn)?;
+            diesel::insert_into(auth_sessions::table)
+                .values(&session)
+                .execute(conn)?;
+            insert_tokens(conn, &entries)
+        })
+    })?;
+    Ok(session_id)
+}
+
+#[derive(Debug, PartialEq)]
+enum RefreshClaim {
+    /// Not issued through the registry.
+    Unknown,
+    /// Marked as rotated; the refresh may go ahead.
+    Claimed(uuid::Uuid),
+    /// Already rotated before.
+    Reused(uuid::Uuid, String),
+}
+
+/// Whether a refresh with `token` may go ahead. A token that was rotated
+/// before is reused, whoever presents it.
+fn refresh_claim(
+    token: &AuthSessionTokenEntry,
+    session: &AuthSessionEntry,
+    now: DateTime<Utc>,
+) -> airborne_types::Result<RefreshClaim> {
+    if session.revoked_at.is_some() {
+        return Err(ABError::Unauthorized(
+            "Session has been revoked".to_string(),
+        ));
+    }
+    if token.expires_at <= now {
+        return Err(ABError::Unauthorized(
+            "Refresh token has expired".to_string(),
+        ));
+    }
+    if token.rotated_at.is_some() {
+        return Ok(RefreshClaim::Reused(session.id, session.subject.clone()));
+    }
+    Ok(RefreshClaim::Claimed(session.id))
+}
+
+async fn claim_refresh_token(pool: DbPool, hash: String) -> airborne_types::Result<RefreshClaim> {
+    run_blocking!({
+        let mut conn = pool.get()?;
+        conn.transaction::<_, ABError, _>(|conn| {
+            let Some(token) = auth_session_tokens::table
+                .find(&hash)
+                .filter(auth_session_tokens::kind.eq(KIND_REFRESH))
+                .select(AuthSessionTokenEntry::as_select())
+                .first::<AuthSessionTokenEntry>(conn)
+                .optional()?
+            else {
+                return Ok(RefreshClaim::Unknown);
+            };
+            let session = auth_sessions::table
+                .find(token.session_id)
+                .select(AuthSessionEntry::as_select())
+                .first::<AuthSessionEntry>(conn)?;
+            let now = Utc::now();
+            let claim = refresh_claim(&token, &session, now)?;
+            if !matches!(claim, RefreshClaim::Claimed(_)) {
+                return Ok(claim);
+            }
+            // A concurrent refresh with the same token may have claimed it
+            // since it was read
+            let claimed = diesel::update(
+                auth_session_tokens::table
+                    .find(&hash)
+                    .filter(auth_session_tokens::rotated_at.is_null()),
+            )
+            .set(auth_session_tokens::rotated_at.eq(now))
+            .execute(conn)?;
+            if claimed == 0 {
+                return Ok(RefreshClaim::Reused(session.id, session.subject));
+            }
+            Ok(RefreshClaim::Claimed(session.id))
+        })
+    })
+}
+
+/// Runs `refresh` for a refresh token, enforcing single use and recording the
+/// tokens it returns under the same session.
+pub async fn rotate_refresh_token<F>(
+    state: &AppState,
+    refresh_token: &str,
+    refresh: F,
+) -> airborne_types::Result<UserToken>
+where
+    F: Future<Output = airborne_types::Result<UserToken>>,
+{
+    let hash = token_hash(refresh_token);
+    let claim = claim_refresh_token(state.db_pool.clone(), hash.clone()).await?;
+    if let RefreshClaim::Reused(session_id, subject) = claim {
+        warn!(
+            "Refresh token reuse detected for session {} of {}; revoking it",
+            session_id, subject
+        );
+        revoke(state, vec![session_id], None, "refresh token reuse").await?;
+        return Err(ABError::Unauthorized(
+            "Refresh token has already been used".to_string(),
+        ));
+    }
+
+    let token = match refresh.await {
+        Ok(token) => token,
+        Err(e) => {
+            // The client never got a replacement, so let it retry.
+            if let RefreshClaim::Claimed(_) = claim {
+                let pool = state.db_pool.clone();
+                run_blocking!({
+                    let mut conn = pool.get()?;
+                    diesel::update(auth_session_tokens::table.find(&hash))
+                        .set(auth_session_tokens::rotated_at.eq(None::<DateTime<Utc>>))
+                        .execute(&mut conn)?;
+                    Ok(())
+                })?;
+            }
+            return Err(e);
+        }
+    };
+
+    match claim {
+        RefreshClaim::Claimed(session_id) => {
+            let now = Utc::now();
+            let (entries, expires_at) = token_entries(state, session_id, &token, now);
+            let pool = state.db_pool.clone();
+            run_blocking!({
+                let mut conn = pool.get()?;
+                conn.transaction::<_, ABError, _>(|conn| {
+                    insert_tokens(conn, &entries)?;
+                    diesel::update(auth_sessions::table.find(session_id))
+                        .set((
+                            auth_sessions::last_refreshed_at.eq(now),
+                            auth_sessions::expires_at.eq(expires_at),
+                        ))
+                        .execute(conn)?;
+                    Ok(())
+                })
+            })?;
+        }
+        _ => {
+            let token_data = state
+                .authn_provider
+                .verify_access_token(state, &token.access_token)
+                .await?;
+            let subject = state
+                .authz_provider
+                .subject_from_claims(&token_data.claims)?;
+            start(state, &subject, &token, ClientInfo::default()).await?;
+        }
+    }
+    Ok(token)
+}
+
+async fn load_revoked(pool: DbPool, hash: String) -> airborne_types::Result<bool> {
+    run_blocking!({
+        let mut conn = pool.get()?;
+        let revoked_at = auth_session_tokens::table
+            .inner_join(auth_sessions::table)
+            .filter(auth_session_tokens::token_hash.eq(&hash))
+            .filter(auth_session_tokens::kind.eq(KIND_ACCESS))
+            .select(auth_sessions::revoked_at)
+            .first::<Option<DateTime<Utc>>>(&mut conn)
+            .optional()?;
+        Ok(revoked_at.flatten().is_some())
+    })
+}
+
+/// Fails if `access_token` belongs to a revoked session.
+pub async fn ensure_not_revoked(
+    state: &AppState,
+    access_token: &str,
+) -> airborne_types::Result<()> {
+    let hash = token_hash(access_token);
+    let revoked = match &state.redis_cache {
+        Some(cache) => {
+            let key = status_key(cache, &hash);
+            match cache.get::<bool>(&key).await {
+                Ok(Some(revoked)) => revoked,
+                Ok(None) => {
+                    let revoked = load_revoked(state.db_pool.clone(), hash).await?;
+                    // NX: a revocation cached since the lookup above must not
+                    // be overwritten by the stale status read here
+                    if let Err(e) = cache.set_nx_ex(&key, &revoked, STATUS_CACHE_TTL_SECS).await {
+                        warn!("Failed to cache session status: {}", e);
+                    }
+                    revoked
+                }
+                // Already logged by the cache; Postgres has the answer too.
+                Err(_) => load_revoked(state.db_pool.clone(), hash).await?,
+            }
+        }
+        None => load_revoked(state.db_pool.clone(), hash).await?,
+    };
+    if revoked {
+        return Err(ABError::Unauthorized(
+            "Session has been revoked".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Revokes the sessions and returns how many were still active.
+pub async fn revoke(
+    state: &AppState,
+    session_ids: Vec<uuid::Uuid>,
+    revoked_by: Option<&str>,
+    reason: &str,
+) -> airborne_types::Result<usize> {
+    if session_ids.is_empty() {
+        return Ok(0);
+    }
+    let now = Utc::now();
+    let pool = state.db_pool.clone();
+    let revoked_by = revoked_by.map(str::to_string);
+    let reason = reason.to_string();
+    let (revoked, access_tokens) = run_blocking!({
+        let mut conn = pool.get()?;
+        conn.transaction::<_, ABError, _>(|conn| {
+            let revoked = diesel::update(
+                auth_sessions::table
+                    .filter(auth_sessions::id.eq_any(&session_ids))
+                    .filter(auth_sessions::revoked_at.is_null()),
+            )
+            .set((
+                auth_sessions::revoked_at.eq(now),
+                auth_sessions::revoked_by.eq(&revoked_by),
+                auth_sessions::revoke_reason.eq(&reason),
+            ))
+            .returning(auth_sessions::id)
+            .get_results::<uuid::Uuid>(conn)?;
+            let access_tokens = auth_session_tokens::table
+                .filter(auth_session_tokens::session_id.eq_any(&revoked))
+                .filter(auth_session_tokens::kind.eq(KIND_ACCESS))
+                .filter(auth_session_tokens::expires_at.gt(now))
+                .select((
+                    auth_session_tokens::token_hash,
+                    auth_session_tokens::expires_at,
+                ))
+                .load::<(String, DateTime<Utc>)>(conn)?;
+            Ok((revoked, access_tokens))
+        })
+    })?;
+
+    if let Some(cache) = &state.redis_cache {
+        for (hash, expires_at) in access_tokens {
+            let ttl = (expires_at - now).num_seconds().max(1) as usize;
+            if let Err(e) = cache.set_ex(&status_key(cache, &hash), &true, ttl).await {
+                warn!(
+                    "Failed to cache revocation, it may take up to {}s to apply: {}",
+                    STATUS_CACHE_TTL_SECS, e
+                );
+            }
+        }
+    }
+    Ok(revoked.len())
+}
+
+async fn active_sessions(
+    pool: DbPool,
+    subjects: Vec<String>,
+) -> airborne_types::Result<Vec<AuthSessionEntry>> {
+    run_blocking!({
+        let mut conn = pool.get()?;
+        let sessions = auth_sessions::table
+            .filter(auth_sessions::subject.eq_any(&subjects))
+            .filter(auth_sessions::revoked_at.is_null())
+            .filter(auth_sessions::expires_at.gt(Utc::now()))
+            .order(auth_sessions::created_at.desc())
+            .select(AuthSessionEntry::as_select())
+            .load::<AuthSessionEntry>(&mut conn)?;
+        Ok(sessions)
+    })
+}
+
+/// The session the request's access token belongs to, if any.
+async fn current_session(
+    pool: DbPool,
+    req: &HttpRequest,
+) -> airborne_types::Result<Option<uuid::Uuid>> {
+    let Some(access_token) = bearer_token(req) else {
+        return Ok(None);
+    };
+    let hash = token_hash(access_token);
+    run_blocking!({
+        let mut conn = pool.get()?;
+        let session_id = auth_session_tokens::table
+            .find(&hash)
+            .select(auth_session_tokens::session_id)
+            .first::<uuid::Uuid>(&mut conn)
+            .optional()?;
+        Ok(session_id)
+    })
+}
+
+fn session_info(session: AuthSessionEntry, current: Option<uuid::Uuid>) -> SessionInfo {
+    SessionInfo {
+        current: current == Some(session.id),
+        id: session.id,
+        subject: session.subject,
+        created_at: session.created_at,
+        last_refreshed_at: session.last_refreshed_at,
+        expires_at: session.expires_at,
+        client_ip: session.client_ip,
+        user_agent: session.user_agent,
+    }
+}
+
+/// Organisation members whose sessions `auth` may manage: everyone when
+/// `target` is `None`, otherwise just `target`. Members above the caller's
+/// own level are refused.
+async fn manageable_members(
+    state: &AppState,
+    auth: &AuthResponse,
+    target: Option<&str>,
+) -> airborne_types::Result<Vec<String>> {
+    let organisation = require_scope_name(auth.organisation.clone(), "organisation")?;
+    let members = state
+        .authz_provider
+        .list_organisation_users(state, &organisation)
+        .await?;
+    let actor_level = auth.organisation.as_ref().map_or(0, |org| org.level);
+    let outranks_actor = |roles: &[String]| {
+        !auth.is_super_admin
+            && roles
+                .iter()
+                .filter_map(|role| system_role_level(role))
+                .any(|level| level > actor_level)
+    };
+
+    match target {
+        Some(target) => {
+            let target = target.to_lowercase();
+            let member = members
+                .into_iter()
+                .find(|member| member.username == target)
+                .ok_or_else(|| {
+                    ABError::NotFound(format!("{} is not a member of {}", target, organisation))
+                })?;
+            if outranks_actor(&member.roles) {
+                return Err(ABError::Forbidden(format!(
+                    "Cannot manage sessions of {}, who has a higher role",
+                    target
+                )));
+            }
+            Ok(vec![member.username])
+        }
+        None => Ok(members
+            .into_iter()
+            .filter(|member| !outranks_actor(&member.roles))
+            .map(|member| member.username)
+            .collect()),
+    }
+}
+
+/// Of `targets`, those whose sessions `auth` may end. A session is not tied
+/// to an organisation, so ending it logs the target out of all of them; the
+/// caller must therefore hold at least the target's role in every
+/// organisation the target belongs to, not only in the current one.
+async fn revocable_everywhere(
+    state: &AppState,
+    auth: &AuthResponse,
+    targets: Vec<String>,
+) -> airborne_types::Result<Vec<String>> {
+    if auth.is_super_admin {
+        return Ok(targets);
+    }
+    let level = |access: &[String]| {
+        access
+            .iter()
+            .filter_map(|role| system_role_level(role))
+            .max()
+            .unwrap_or(0)
+    };
+    let actor = state
+        .authz_provider
+        .get_user_access_summary(state, &auth.sub)
+        .await?;
+    let actor_levels: HashMap<String, u8> = actor
+        .organisations
+        .iter()
+        .map(|org| (org.name.clone(), level(&org.access)))
+        .collect();
+
+    let mut revocable = Vec::with_capacity(targets.len());
+    for target in targets {
+        let summary = state
+            .authz_provider
+            .get_user_access_summary(state, &target)
+            .await?;
+        let outranked = !summary.is_super_admin
+            && summary.organisations.iter().all(|org| {
+                actor_levels
+                    .get(&org.name)
+                    .is_some_and(|actor_level| *actor_level >= level(&org.access))
+            });
+        if outranked {
+            revocable.push(target);
+        }
+    }
+    Ok(revocable)
+}
+
+#[get("")]
+async fn list_own_sessions(
+    req: HttpRequest,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<ListResponse<Vec<SessionInfo>>>> {
+    let auth_response = auth_response.into_inner();
+    let current = current_session(state.db_pool.clone(), &req).await?;
+    let sessions = active_sessions(state.db_pool.clone(), vec![auth_response.sub]).await?;
+    Ok(Json(ListResponse {
+        data: sessions
+            .into_iter()
+            .map(|session| session_info(session, current))
+            .collect(),
+    }))
+}
+
+/// Logs out: revokes the session the request was made with.
+#[delete("/current")]
+async fn revoke_current_session(
+    req: HttpRequest,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<RevokeSessionsResponse>> {
+    let auth_response = auth_response.into_inner();
+    let session_id = current_session(state.db_pool.clone(), &req)
+        .await?
+        .ok_or_else(|| ABError::NotFound("This token has no server session".to_string()))?;
+    let revoked = revoke(
+        state.get_ref(),
+        vec![session_id],
+        Some(&auth_response.sub),
+        "logout",
+    )
+    .await?;
+    info!("{} logged out of session {}", auth_response.sub, session_id);
+    Ok(Json(RevokeSessionsResponse { revoked }))
+}
+
+#[delete("/{id}")]
+async fn revoke_own_session(
+    id: Path<uuid::Uuid>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<RevokeSessionsResponse>> {
+    let auth_response = auth_response.into_inner();
+    crate::token::scope::require_unrestricted(&auth_response)?;
+    let id = id.into_inner();
+    let sessions = active_sessions(state.db_pool.clone(), vec![auth_response.sub.clone()]).await?;
+    if !sessions.iter().any(|session| session.id == id) {
+        return Err(ABError::NotFound(format!("Session {} not found", id)));
+    }
+    let revoked = revoke(
+        state.get_ref(),
+        vec![id],
+        Some(&auth_response.sub),
+        "revoked by user",
+    )
+    .await?;
+    info!("{} revoked their session {}", auth_response.sub, id);
+    Ok(Json(RevokeSessionsResponse { revoked }))
+}
+
+#[authz(
+    resource = "session",
+    action = "read",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[get("/organisation")]
+async fn list_organisation_sessions(
+    req: HttpRequest,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<ListResponse<Vec<SessionInfo>>>> {
+    let auth_response = auth_response.into_inner();
+    let members = manageable_members(state.get_ref(), &auth_response, None).await?;
+    let current = current_session(state.db_pool.clone(), &req).await?;
+    let sessions = active_sessions(state.db_pool.clone(), members).await?;
+    Ok(Json(ListResponse {
+        data: sessions
+            .into_iter()
+            .map(|session| session_info(session, current))
+            .collect(),
+    }))
+}
+
+/// Revokes every session of every member the caller outranks or matches in
+/// all of the member's organisations, except the caller's own.
+#[authz(
+    resource = "session",
+    action = "delete",
+    org_roles = ["owner"],
+    app_roles = []
+)]
+#[delete("/organisation")]
+async fn revoke_organisation_sessions(
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<RevokeSessionsResponse>> {
+    let auth_response = auth_response.into_inner();
+    let members: Vec<String> = manageable_members(state.get_ref(), &auth_response, None)
+        .await?
+        .into_iter()
+        .filter(|member| *member != auth_response.sub)
+        .collect();
+    let candidates = members.len();
+    let members = revocable_everywhere(state.get_ref(), &auth_response, members).await?;
+    if members.len() < candidates {
+        info!(
+            "{} skipped {} members who hold a higher role in another organisation",
+            auth_response.sub,
+            candidates - members.len()
+        );
+    }
+    let sessions = active_sessions(state.db_pool.clone(), members).await?;
+    let revoked = revoke(
+        state.get_ref(),
+        sessions.into_iter().map(|session| session.id).collect(),
+        Some(&auth_response.sub),
+        "organisation-wide revocation",
+    )
+    .await?;
+    info!(
+        "{} revoked {} sessions across org {:?}",
+        auth_response.sub,
+        revoked,
+        auth_response.organisation.as_ref().map(|org| &org.name)
+    );
+    Ok(Json(RevokeSessionsResponse { revoked }))
+}
+
+#[authz(
+    resource = "session",
+    action = "read",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[get("/users/{subject}")]
+async fn list_member_sessions(
+    req: HttpRequest,
+    subject: Path<String>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<ListResponse<Vec<SessionInfo>>>> {
+    let auth_response = auth_response.into_inner();
+    let members = manageable_members(state.get_ref(), &auth_response, Some(&subject)).await?;
+    let current = current_session(state.db_pool.clone(), &req).await?;
+    let sessions = active_sessions(state.db_pool.clone(), members).await?;
+    Ok(Json(ListResponse {
+        data: sessions
+            .into_iter()
+            .map(|session| session_info(session, current))
+            .collect(),
+    }))
+}
+
+#[authz(
+    resource = "session",
+    action = "delete",
+    org_roles = ["owner", "admin"],
+    app_roles = []
+)]
+#[delete("/users/{subject}")]
+async fn revoke_member_sessions(
+    subject: Path<String>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<RevokeSessionsResponse>> {
+    let auth_response = auth_response.into_inner();
+    let members = manageable_members(state.get_ref(), &auth_response, Some(&subject)).await?;
+    let members = revocable_everywhere(state.get_ref(), &auth_response, members).await?;
+    if members.is_empty() {
+        return Err(ABError::Forbidden(format!(
+            "Cannot revoke sessions of {}, who has a higher role in another organisation",
+            subject
+        )));
+    }
+    let sessions = active_sessions(state.db_pool.clone(), members).await?;
+    let revoked = revoke(
+        state.get_ref(),
+        sessions.into_iter().map(|session| session.id).collect(),
+        Some(&auth_response.sub),
+        "revoked by organisation admin",
+    )
+    .await?;
+    info!(
+        "{} revoked {} sessions of {}",
+        auth_response.sub,
+        revoked,
+        subject.into_inner()
+    );
+    Ok(Json(RevokeSessionsResponse { revoked }))
+}
+
+/// Exchanges a refresh token for new tokens. The presented refresh token stops
+/// working; presenting it again revokes the session.
+#[post("/refresh")]
+async fn refresh_session(
+    req: Json<RefreshTokenRequest>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<Json<UserToken>> {
+    let token = state
+        .authn_provider
+        .refresh_access_token(state.get_ref(), &req.refresh_token)
+        .await?;
+    Ok(Json(token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(now: DateTime<Utc>) -> (AuthSessionTokenEntry, AuthSessionEntry) {
+        let session_id = uuid::Uuid::new_v4();
+        let token = AuthSessionTokenEntry {
+            token_hash: token_hash("refresh-token"),
+            session_id,
+            kind: KIND_REFRESH.to_string(),
+            created_at: now,
+            expires_at: now + Duration::hours(1),
+            rotated_at: None,
+        };
+        let session = AuthSessionEntry {
+            id: session_id,
+            subject: "alice@example.com".to_string(),
+            created_at: now,
+            last_refreshed_at: None,
+            expires_at: now + Duration::hours(1),
+            revoked_at: None,
+            revoked_by: None,
+            revoke_reason: None,
+            client_ip: None,
+            user_agent: None,
+        };
+        (token, session)
+    }
+
+    #[test]
+    fn fresh_refresh_token_is_claimed() {
+        let now = Utc::now();
+        let (token, session) = entries(now);
+        assert_eq!(
+            refresh_claim(&token, &session, now).unwrap(),
+            RefreshClaim::Claimed(session.id)
+        );
+    }
+
+    #[test]
+    fn rotated_refresh_token_is_reuse() {
+        let now = Utc::now();
+        let (mut token, session) = entries(now);
+        token.rotated_at = Some(now - Duration::minutes(1));
+        assert_eq!(
+            refresh_claim(&token, &session, now).unwrap(),
+            RefreshClaim::Reused(session.id, session.subject.clone())
+        );
+    }
+
+    #[test]
+    fn revoked_or_expired_sessions_cannot_refresh() {
+        let now = Utc::now();
+        let (mut token, mut session) = entries(now);
+        // A reused token of a session already revoked for reuse is refused
+        // outright rather than revoking it again
+        token.rotated_at = Some(now - Duration::minutes(1));
+        session.revoked_at = Some(now);
+        assert!(matches!(
+            refresh_claim(&token, &session, now),
+            Err(ABError::Unauthorized(_))
+        ));
+
+        let (mut token, session) = entries(now);
+        token.expires_at = now;
+        assert!(matches!(
+            refresh_claim(&token, &session, now),
+            Err(ABError::Unauthorized(_))
+        ));
+    }
+}
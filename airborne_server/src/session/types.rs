@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+pub struct SessionInfo {
+    pub id: uuid::Uuid,
+    pub subject: String,
+    pub created_at: DateTime<Utc>,
+    pub last_refreshed_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// Whether the request was made with this session's token.
+    pub current: bool,
+}
+
+#[derive(Serialize)]
+pub struct RevokeSessionsResponse {
+    pub revoked: usize,
+}
+
+#[derive(Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
@@ -1,3 +1,4 @@
+pub mod scope;
 pub mod types;
 
 use crate::{
@@ -6,8 +7,9 @@ use crate::{
     token::types::*,
     types as airborne_types,
     types::{ABError, AppState, ListResponse},
-    user::{exchange_code_for_token, types::OAuthLoginRequest, types::UserToken},
+    user::{exchange_code_for_token, types::UserToken},
     utils::{
+        client_ip,
         db::{
             models::UserCredentialsEntry,
             schema::hyperotaserver::user_credentials::{
@@ -25,7 +27,7 @@ use actix_web::{
     web::{self, Json, ReqData},
     HttpRequest, Scope,
 };
-use chrono::{DateTime, Utc};
+use chrono::Utc;
 use diesel::prelude::*;
 
 pub fn add_scopes(path: &str) -> Scope {
@@ -66,6 +68,9 @@ async fn create_token(
         auth_response.organisation.clone(),
         auth_response.application.clone(),
     )?;
+    let restrictions =
+        scope::validate_restrictions(state.get_ref(), &auth_response, req.restrictions.clone())
+            .await?;
 
     let login_credentials = crate::user::types::UserCredentials {
         name: req.name.clone(),
@@ -89,6 +94,11 @@ async fn create_token(
         organisation: org_name.clone(),
         application: app_name.clone(),
         created_at: Utc::now(),
+        permissions: restrictions.permissions,
+        expires_at: restrictions.expires_at,
+        allowed_ips: restrictions.allowed_ips,
+        last_used_at: None,
+        last_used_ip: None,
     };
     let pool = state.db_pool.clone();
     run_blocking!({
@@ -118,7 +128,7 @@ async fn create_token(
 #[post("/oauth")]
 async fn create_token_oauth(
     req: HttpRequest,
-    body: Json<OAuthLoginRequest>,
+    body: Json<OAuthTokenRequest>,
     auth_response: ReqData<AuthResponse>,
     state: web::Data<AppState>,
 ) -> actix_web::Result<Json<PersonalAccessToken>, ABError> {
@@ -126,7 +136,10 @@ async fn create_token_oauth(
         .authn_provider
         .ensure_oidc_login_enabled(state.get_ref())?;
 
-    let oauth_req = body.into_inner();
+    let OAuthTokenRequest {
+        login: oauth_req,
+        restrictions,
+    } = body.into_inner();
 
     let auth_response = auth_response.into_inner();
 
@@ -134,6 +147,8 @@ async fn create_token_oauth(
         auth_response.organisation.clone(),
         auth_response.application.clone(),
     )?;
+    let restrictions =
+        scope::validate_restrictions(state.get_ref(), &auth_response, restrictions).await?;
 
     let token_response =
         match exchange_code_for_token(&oauth_req.code, oauth_req.state.as_deref(), &req, &state)
@@ -182,6 +197,11 @@ async fn create_token_oauth(
         organisation: org_name.clone(),
         application: app_name.clone(),
         created_at: Utc::now(),
+        permissions: restrictions.permissions,
+        expires_at: restrictions.expires_at,
+        allowed_ips: restrictions.allowed_ips,
+        last_used_at: None,
+        last_used_ip: None,
     };
 
     let pool = state.db_pool.clone();
@@ -259,8 +279,9 @@ async fn list_tokens(
             .filter(username.eq(&auth_response.username))
             .filter(cred_org.eq(&organisation))
             .filter(cred_app.eq(&application))
-            .select((uid, created_at))
-            .load::<(uuid::Uuid, DateTime<Utc>)>(&mut conn)
+            .order(created_at.desc())
+            .select(UserCredentialsEntry::as_select())
+            .load::<UserCredentialsEntry>(&mut conn)
             .map_err(|e| {
                 log::error!("[LIST TOKENS] DB fetch failed: {}", e);
                 ABError::InternalServerError(format!("DB fetch failed: {}", e))
@@ -269,9 +290,14 @@ async fn list_tokens(
     })?;
     let tokens = result
         .into_iter()
-        .map(|(id, created)| TokenListEntry {
-            client_id: id,
-            created_at: created,
+        .map(|entry| TokenListEntry {
+            client_id: entry.client_id,
+            created_at: entry.created_at,
+            permissions: entry.permissions,
+            expires_at: entry.expires_at,
+            allowed_ips: entry.allowed_ips,
+            last_used_at: entry.last_used_at,
+            last_used_ip: entry.last_used_ip,
         })
         .collect::<Vec<TokenListEntry>>();
 
@@ -280,6 +306,7 @@ async fn list_tokens(
 
 #[post("issue")]
 async fn issue_token(
+    http_req: HttpRequest,
     req: Json<PersonalAccessToken>,
     state: web::Data<AppState>,
 ) -> airborne_types::Result<Json<UserToken>> {
@@ -301,6 +328,12 @@ async fn issue_token(
 
     log::info!("[ISSUE TOKEN] User credentials loaded successfully");
 
+    let client_ip = client_ip::client_ip(&http_req, &state.env.trusted_proxies);
+    scope::ensure_usable(&user, client_ip.as_deref()).map_err(|e| {
+        log::warn!("[ISSUE TOKEN] Token {} rejected: {}", client_id, e);
+        e
+    })?;
+
     let decrypted_refresh_token = decrypt_string(&user.password, &req.client_secret)
         .await
         .map_err(|e| {
@@ -314,10 +347,25 @@ async fn issue_token(
         &decrypted_refresh_token.chars().take(10).collect::<String>()
     );
 
-    let token = state
+    let mut token = state
         .authn_provider
         .refresh_access_token(state.get_ref(), &decrypted_refresh_token)
         .await?;
+    scope::record_issued_token(
+        state.db_pool.clone(),
+        client_id,
+        &token.access_token,
+        Utc::now() + chrono::Duration::seconds(token.expires_in.max(0)),
+        client_ip,
+    )
+    .await?;
+
+    // A refresh token would mint access tokens that bypass the restrictions,
+    // so restricted PATs only hand out the access token.
+    if scope::is_restricted(&user) {
+        token.refresh_token = String::new();
+        token.refresh_expires_in = 0;
+    }
     log::info!("[ISSUE TOKEN] Token issued successfully");
     Ok(Json(token))
 }
@@ -0,0 +1,282 @@
+//! Restrictions on personal access tokens.
+//!
+//! A PAT may carry a permission subset, an expiry and a list of addresses it
+//! can be used from. The access tokens `issue_token` hands out are IdP JWTs we
+//! cannot annotate, so each one is recorded by hash in `issued_access_tokens`.
+//! The auth middleware looks the bearer up there and, on a hit, applies the
+//! PAT's restrictions to the request on top of the user's own access. Rows
+//! outlive their PAT on purpose: a hash whose PAT was deleted marks a revoked
+//! token rather than an ordinary login.
+
+use std::{collections::BTreeSet, net::IpAddr};
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use ipnet::IpNet;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    middleware::auth::AuthResponse,
+    provider::authz::permission::{app_permission_keys, holds_permission},
+    run_blocking, types as airborne_types,
+    types::{ABError, AppState},
+    utils::db::{
+        models::{IssuedAccessTokenEntry, UserCredentialsEntry},
+        schema::hyperotaserver::{issued_access_tokens, user_credentials},
+        DbPool,
+    },
+};
+
+use super::types::TokenRestrictions;
+
+pub fn token_hash(access_token: &str) -> String {
+    hex::encode(Sha256::digest(access_token.as_bytes()))
+}
+
+pub fn is_restricted(entry: &UserCredentialsEntry) -> bool {
+    entry.permissions.is_some() || entry.expires_at.is_some() || !entry.allowed_ips.is_empty()
+}
+
+/// Whether a permission list (`None` meaning unrestricted) allows `resource.action`.
+pub fn permits(permissions: Option<&[String]>, resource: &str, action: &str) -> bool {
+    permissions.is_none_or(|permissions| permissions.contains(&format!("{}.{}", resource, action)))
+}
+
+/// Refuses tokens carrying a permission subset. Their permissions are
+/// endpoint permissions, so handlers that check access themselves instead of
+/// through `#[authz]` have none a token could grant, and must call this
+/// before changing anything.
+pub fn require_unrestricted(auth: &AuthResponse) -> airborne_types::Result<()> {
+    if auth.token_permissions.is_some() {
+        return Err(ABError::Forbidden(
+            "This token is limited to specific permissions and cannot be used here".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+    value
+        .parse::<IpAddr>()
+        .ok()
+        .or_else(|| value.parse::<std::net::SocketAddr>().ok().map(|a| a.ip()))
+}
+
+fn parse_network(value: &str) -> Option<IpNet> {
+    let value = value.trim();
+    value
+        .parse::<IpNet>()
+        .ok()
+        .or_else(|| value.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+/// Whether `client_ip` falls in one of the allowed addresses or CIDR ranges.
+/// An empty list allows every address.
+pub fn ip_allowed(allowed_ips: &[String], client_ip: Option<&str>) -> bool {
+    if allowed_ips.is_empty() {
+        return true;
+    }
+    let Some(ip) = client_ip.and_then(parse_ip) else {
+        return false;
+    };
+    allowed_ips
+        .iter()
+        .filter_map(|network| parse_network(network))
+        .any(|network| network.contains(&ip))
+}
+
+/// Normalises the requested restrictions. Permissions must be known
+/// application-scoped endpoint permissions the caller holds themselves, so
+/// any member can narrow their own token but nobody can widen it.
+pub async fn validate_restrictions(
+    state: &AppState,
+    auth_response: &AuthResponse,
+    restrictions: TokenRestrictions,
+) -> airborne_types::Result<TokenRestrictions> {
+    if let Some(expires_at) = restrictions.expires_at {
+        if expires_at <= Utc::now() {
+            return Err(ABError::BadRequest(
+                "expires_at must be in the future".to_string(),
+            ));
+        }
+    }
+
+    let mut allowed_ips = Vec::new();
+    for value in &restrictions.allowed_ips {
+        let network = parse_network(value).ok_or_else(|| {
+            ABError::BadRequest(format!("'{}' is not an IP address or CIDR range", value))
+        })?;
+        allowed_ips.push(network.to_string());
+    }
+
+    let permissions = match restrictions.permissions {
+        None => None,
+        Some(requested) => {
+            if requested.is_empty() {
+                return Err(ABError::BadRequest(
+                    "permissions must list at least one permission when given".to_string(),
+                ));
+            }
+            let catalog = app_permission_keys();
+            let mut normalized = BTreeSet::new();
+            for permission in &requested {
+                let key = catalog_key(&catalog, permission).ok_or_else(|| {
+                    ABError::BadRequest(format!("Unknown permission '{}'", permission))
+                })?;
+                let (resource, action) = key.split_once('.').unwrap_or((&key, ""));
+                if !holds_permission(state, auth_response, resource, action).await? {
+                    return Err(ABError::Forbidden(format!(
+                        "You do not hold '{}' and cannot grant it to a token",
+                        key
+                    )));
+                }
+                normalized.insert(key);
+            }
+            Some(normalized.into_iter().collect())
+        }
+    };
+
+    Ok(TokenRestrictions {
+        permissions,
+        expires_at: restrictions.expires_at,
+        allowed_ips,
+    })
+}
+
+/// The catalog key a requested permission names. Accepts both
+/// `release.read` and `release:read`.
+fn catalog_key(catalog: &BTreeSet<String>, permission: &str) -> Option<String> {
+    let key = permission.trim().to_ascii_lowercase().replacen(':', ".", 1);
+    catalog.contains(&key).then_some(key)
+}
+
+/// Rejects an expired PAT or one used from an address it is not allowed from.
+pub fn ensure_usable(
+    entry: &UserCredentialsEntry,
+    client_ip: Option<&str>,
+) -> airborne_types::Result<()> {
+    if entry
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now())
+    {
+        return Err(ABError::Unauthorized("Token has expired".to_string()));
+    }
+    if !ip_allowed(&entry.allowed_ips, client_ip) {
+        return Err(ABError::Unauthorized(
+            "Token cannot be used from this address".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Stamps the PAT as used and remembers the access token issued for it.
+pub async fn record_issued_token(
+    pool: DbPool,
+    client_id: uuid::Uuid,
+    access_token: &str,
+    expires_at: DateTime<Utc>,
+    client_ip: Option<String>,
+) -> airborne_types::Result<()> {
+    let now = Utc::now();
+    let entry = IssuedAccessTokenEntry {
+        token_hash: token_hash(access_token),
+        client_id,
+        expires_at,
+        created_at: now,
+    };
+    run_blocking!({
+        let mut conn = pool.get()?;
+        diesel::update(user_credentials::table.find(client_id))
+            .set((
+                user_credentials::last_used_at.eq(now),
+                user_credentials::last_used_ip.eq(client_ip),
+            ))
+            .execute(&mut conn)?;
+        diesel::delete(
+            issued_access_tokens::table.filter(issued_access_tokens::expires_at.lt(now)),
+        )
+        .execute(&mut conn)?;
+        diesel::insert_into(issued_access_tokens::table)
+            .values(&entry)
+            .on_conflict_do_nothing()
+            .execute(&mut conn)?;
+        Ok(())
+    })
+}
+
+/// Looks up the PAT an access token was issued for. Returns `None` for tokens
+/// that did not come from `issue_token`, and the PAT's permission list
+/// (`None` when unrestricted) otherwise. Fails once the PAT is deleted,
+/// expired or used from a disallowed address.
+pub async fn permissions_for_access_token(
+    pool: DbPool,
+    access_token: &str,
+    client_ip: Option<&str>,
+) -> airborne_types::Result<Option<Option<Vec<String>>>> {
+    let hash = token_hash(access_token);
+    let entry = run_blocking!({
+        let mut conn = pool.get()?;
+        let Some(client_id) = issued_access_tokens::table
+            .find(hash)
+            .select(issued_access_tokens::client_id)
+            .first::<uuid::Uuid>(&mut conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let entry = user_credentials::table
+            .find(client_id)
+            .select(UserCredentialsEntry::as_select())
+            .first::<UserCredentialsEntry>(&mut conn)
+            .optional()?;
+        Ok(Some(entry))
+    })?;
+
+    match entry {
+        None => Ok(None),
+        Some(None) => Err(ABError::Unauthorized("Token has been revoked".to_string())),
+        Some(Some(entry)) => {
+            ensure_usable(&entry, client_ip)?;
+            Ok(Some(entry.permissions))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_addresses_and_ranges() {
+        let allowed = vec!["10.0.0.0/8".to_string(), "2001:db8::1/128".to_string()];
+        assert!(ip_allowed(&allowed, Some("10.1.2.3")));
+        assert!(ip_allowed(&allowed, Some("10.1.2.3:5123")));
+        assert!(ip_allowed(&allowed, Some("2001:db8::1")));
+        assert!(!ip_allowed(&allowed, Some("192.168.0.1")));
+        assert!(!ip_allowed(&allowed, None));
+        assert!(ip_allowed(&[], None));
+    }
+
+    #[test]
+    fn restricts_to_endpoint_permissions() {
+        let catalog = app_permission_keys();
+        assert_eq!(
+            catalog_key(&catalog, "release:read").as_deref(),
+            Some("release.read")
+        );
+        assert_eq!(
+            catalog_key(&catalog, " Release.Create ").as_deref(),
+            Some("release.create")
+        );
+        assert_eq!(catalog_key(&catalog, "release.launch"), None);
+    }
+
+    #[test]
+    fn checks_permission_subset() {
+        let permissions = vec!["release.read".to_string()];
+        assert!(permits(Some(&permissions), "release", "read"));
+        assert!(!permits(Some(&permissions), "release", "create"));
+        assert!(permits(None, "release", "create"));
+    }
+}
@@ -1,10 +1,34 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::user::types::OAuthLoginRequest;
+
 #[derive(Serialize, Deserialize)]
 pub struct UserCredentials {
     pub name: String,
     pub password: String,
+    #[serde(flatten)]
+    pub restrictions: TokenRestrictions,
+}
+
+/// Optional limits on what a personal access token can do. Omitted fields
+/// leave the token with the creating user's full access.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct TokenRestrictions {
+    /// `resource.action` keys from the permission catalog.
+    pub permissions: Option<Vec<String>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// IP addresses or CIDR ranges the token may be used from.
+    pub allowed_ips: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct OAuthTokenRequest {
+    #[serde(flatten)]
+    pub login: OAuthLoginRequest,
+    #[serde(flatten)]
+    pub restrictions: TokenRestrictions,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -22,4 +46,9 @@ pub struct DeleteTokenResponse {
 pub struct TokenListEntry {
     pub client_id: uuid::Uuid,
     pub created_at: DateTime<Utc>,
+    pub permissions: Option<Vec<String>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub allowed_ips: Vec<String>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub last_used_ip: Option<String>,
 }
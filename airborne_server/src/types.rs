@@ -76,6 +76,19 @@ pub struct Environment {
     pub google_spreadsheet_id: String,
     pub cloudfront_distribution_id: String,
     pub default_configs: Vec<SuperpositionDefaultConfig>,
+    pub bundle_signing_key: Option<String>,
+    pub bundle_max_unpacked_bytes: u64,
+    pub upload_validation_hook: Option<String>,
+    pub upload_validation_hook_timeout_secs: u64,
+    pub upload_validation_hook_uid: u32,
+    pub upload_validation_hook_gid: u32,
+    pub upload_validation_hook_max_memory_mb: u64,
+    pub file_mirror_max_bytes: u64,
+    pub service_account_token_ttl_secs: i64,
+    pub temporary_grant_max_secs: i64,
+    pub session_refresh_ttl_secs: i64,
+    pub trusted_proxies: Vec<ipnet::IpNet>,
+    pub analytics_ingest_verify_token: Option<String>,
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -85,6 +98,7 @@ pub enum AuthnProviderKind {
     Oidc,
     Okta,
     Auth0,
+    Saml,
 }
 
 impl AuthnProviderKind {
@@ -94,6 +108,7 @@ impl AuthnProviderKind {
             AuthnProviderKind::Oidc => "oidc",
             AuthnProviderKind::Okta => "okta",
             AuthnProviderKind::Auth0 => "auth0",
+            AuthnProviderKind::Saml => "saml",
         }
     }
 }
@@ -102,12 +117,14 @@ impl AuthnProviderKind {
 #[serde(rename_all = "lowercase")]
 pub enum AuthzProviderKind {
     Casbin,
+    Cedar,
 }
 
 impl AuthzProviderKind {
     pub fn as_str(self) -> &'static str {
         match self {
             AuthzProviderKind::Casbin => "casbin",
+            AuthzProviderKind::Cedar => "cedar",
         }
     }
 }
@@ -118,8 +135,9 @@ impl std::str::FromStr for AuthzProviderKind {
     fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
         match value.to_ascii_lowercase().as_str() {
             "casbin" => Ok(AuthzProviderKind::Casbin),
+            "cedar" => Ok(AuthzProviderKind::Cedar),
             _ => Err(format!(
-                "Unsupported AUTHZ_PROVIDER '{}'. Expected one of: casbin",
+                "Unsupported AUTHZ_PROVIDER '{}'. Expected one of: casbin, cedar",
                 value
             )),
         }
@@ -135,8 +153,9 @@ impl std::str::FromStr for AuthnProviderKind {
             "oidc" => Ok(AuthnProviderKind::Oidc),
             "okta" => Ok(AuthnProviderKind::Okta),
             "auth0" => Ok(AuthnProviderKind::Auth0),
+            "saml" => Ok(AuthnProviderKind::Saml),
             _ => Err(format!(
-                "Unsupported AUTHN_PROVIDER '{}'. Expected one of: keycloak, oidc, okta, auth0",
+                "Unsupported AUTHN_PROVIDER '{}'. Expected one of: keycloak, oidc, okta, auth0, saml",
                 value
             )),
         }
@@ -166,10 +185,18 @@ mod authn_provider_tests {
             AuthnProviderKind::from_str("AUTH0").expect("auth0 should parse"),
             AuthnProviderKind::Auth0
         );
+        assert_eq!(
+            AuthnProviderKind::from_str("Saml").expect("saml should parse"),
+            AuthnProviderKind::Saml
+        );
         assert_eq!(
             AuthzProviderKind::from_str("CASBIN").expect("casbin should parse"),
             AuthzProviderKind::Casbin
         );
+        assert_eq!(
+            AuthzProviderKind::from_str("Cedar").expect("cedar should parse"),
+            AuthzProviderKind::Cedar
+        );
     }
 }
 pub trait AppError: std::error::Error + Send + Sync + 'static {
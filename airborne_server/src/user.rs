@@ -16,14 +16,17 @@ use crate::{
     middleware::auth::{Auth, AuthResponse},
     organisation::{application::types::Application, Organisation},
     provider::authn::AuthnTokenClaims,
+    session::{self, ClientInfo},
     types as airborne_types,
     types::{ABError, AppState},
     user::types::*,
 };
 use actix_web::{
-    get, post,
-    web::{self, Json, Query},
-    HttpRequest, Scope,
+    get,
+    http::header,
+    post,
+    web::{self, Form, Json, Query},
+    HttpRequest, HttpResponse, Scope,
 };
 use log::info;
 use serde_json::json;
@@ -37,6 +40,8 @@ pub fn add_routes(path: &str) -> Scope {
         .service(oauth_login)
         .service(get_oauth_url)
         .service(oauth_signup)
+        .service(saml_metadata)
+        .service(saml_acs)
         .service(Scope::new("").wrap(Auth).service(get_user))
 }
 
@@ -67,6 +72,7 @@ async fn auth_response_from_claims(
         application: None,
         is_super_admin: false,
         username: state.authz_provider.display_name_from_claims(claims),
+        token_permissions: None,
     })
 }
 
@@ -100,6 +106,7 @@ async fn auth_response_from_access_token(
 
 #[post("create")]
 async fn create_user(
+    http_req: HttpRequest,
     req: Json<UserCredentials>,
     state: web::Data<AppState>,
 ) -> airborne_types::Result<Json<User>> {
@@ -112,6 +119,13 @@ async fn create_user(
         .signup_with_password(state.get_ref(), &req)
         .await?;
     let auth_response = auth_response_from_access_token(&token.access_token, state.clone()).await?;
+    session::start(
+        state.get_ref(),
+        &auth_response.sub,
+        &token,
+        ClientInfo::from_request(&http_req, &state),
+    )
+    .await?;
     let mut user_resp = get_user_impl(auth_response, state).await?;
     user_resp.user_token = Some(token);
 
@@ -120,14 +134,21 @@ async fn create_user(
 
 #[post("login")]
 async fn login(
+    http_req: HttpRequest,
     req: Json<UserCredentials>,
     state: web::Data<AppState>,
 ) -> airborne_types::Result<Json<User>> {
-    login_implementation(req.into_inner(), state).await
+    login_implementation(
+        req.into_inner(),
+        ClientInfo::from_request(&http_req, &state),
+        state,
+    )
+    .await
 }
 
 pub async fn login_implementation(
     req: UserCredentials,
+    client: ClientInfo,
     state: web::Data<AppState>,
 ) -> airborne_types::Result<Json<User>> {
     ensure_password_login_supported(state.get_ref())?;
@@ -136,6 +157,7 @@ pub async fn login_implementation(
         .login_with_password(state.get_ref(), &req)
         .await?;
     let auth_response = auth_response_from_access_token(&token.access_token, state.clone()).await?;
+    session::start(state.get_ref(), &auth_response.sub, &token, client).await?;
     let mut user_resp = get_user_impl(auth_response, state).await?;
     user_resp.user_token = Some(token);
 
@@ -217,6 +239,35 @@ async fn get_oauth_url(
     })))
 }
 
+#[get("saml/metadata")]
+async fn saml_metadata(state: web::Data<AppState>) -> airborne_types::Result<HttpResponse> {
+    let metadata = state.authn_provider.saml_metadata()?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/samlmetadata+xml")
+        .body(metadata))
+}
+
+/// Assertion consumer service: the IdP posts its response here, and the
+/// browser continues to the dashboard's OAuth callback with a login code.
+#[post("saml/acs")]
+async fn saml_acs(
+    form: Form<SamlAcsForm>,
+    state: web::Data<AppState>,
+) -> airborne_types::Result<HttpResponse> {
+    let form = form.into_inner();
+    let callback_url = state
+        .authn_provider
+        .complete_saml_login(
+            state.get_ref(),
+            &form.saml_response,
+            form.relay_state.as_deref(),
+        )
+        .await?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, callback_url))
+        .finish())
+}
+
 pub async fn exchange_code_for_token(
     code: &str,
     oauth_state: Option<&str>,
@@ -261,15 +312,22 @@ async fn oauth_login(
             ABError::BadRequest("Invalid token".to_string())
         })?;
     let auth_response = auth_response_from_claims(&token_data.claims, state.clone()).await?;
-    let mut user_resp = get_user_impl(auth_response, state).await?;
-
-    user_resp.user_token = Some(UserToken {
+    let user_token = UserToken {
         access_token: token_response.access_token,
         token_type: token_response.token_type,
         expires_in: token_response.expires_in,
         refresh_token: token_response.refresh_token.unwrap_or_default(),
         refresh_expires_in: token_response.refresh_expires_in.unwrap_or(0),
-    });
+    };
+    session::start(
+        state.get_ref(),
+        &auth_response.sub,
+        &user_token,
+        ClientInfo::from_request(&req, &state),
+    )
+    .await?;
+    let mut user_resp = get_user_impl(auth_response, state).await?;
+    user_resp.user_token = Some(user_token);
 
     Ok(user_resp)
 }
@@ -305,15 +363,22 @@ async fn oauth_signup(
     // For signup, v1 keeps provider-specific behavior and this endpoint is enabled only on
     // providers that support signup.
     let auth_response = auth_response_from_claims(&token_data.claims, state.clone()).await?;
-    let mut user_resp = get_user_impl(auth_response, state).await?;
-
-    user_resp.user_token = Some(UserToken {
+    let user_token = UserToken {
         access_token: token_response.access_token,
         token_type: token_response.token_type,
         expires_in: token_response.expires_in,
         refresh_token: token_response.refresh_token.unwrap_or_default(),
         refresh_expires_in: token_response.refresh_expires_in.unwrap_or(0),
-    });
+    };
+    session::start(
+        state.get_ref(),
+        &auth_response.sub,
+        &user_token,
+        ClientInfo::from_request(&req, &state),
+    )
+    .await?;
+    let mut user_resp = get_user_impl(auth_response, state).await?;
+    user_resp.user_token = Some(user_token);
 
     info!(
         "[OAUTH_SIGNUP] OAuth signup completed successfully for user: {}",
@@ -33,6 +33,14 @@ pub struct OAuthRequest {
     pub state: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct SamlAcsForm {
+    #[serde(rename = "SAMLResponse")]
+    pub saml_response: String,
+    #[serde(rename = "RelayState")]
+    pub relay_state: Option<String>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct UserCredentials {
     pub name: String,
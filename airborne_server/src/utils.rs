@@ -13,6 +13,7 @@
 // limitations under the License.
 
 pub mod advisory_lock;
+pub mod client_ip;
 pub mod db;
 pub mod document;
 pub mod encryption;
@@ -0,0 +1,90 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The address a request came from. `X-Forwarded-For` is only believed when
+//! the connection comes from one of `TRUSTED_PROXIES`; otherwise any client
+//! could claim an address a PAT is restricted to.
+
+use std::net::IpAddr;
+
+use actix_web::HttpRequest;
+use ipnet::IpNet;
+
+const FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// The client's address: the connection's peer, or, behind trusted proxies,
+/// the last address in `X-Forwarded-For` that is not one of them.
+pub fn client_ip(req: &HttpRequest, trusted_proxies: &[IpNet]) -> Option<String> {
+    let peer = req.peer_addr()?.ip();
+    let trusted = |ip: &IpAddr| trusted_proxies.iter().any(|network| network.contains(ip));
+    if !trusted(&peer) {
+        return Some(peer.to_string());
+    }
+
+    // Each proxy appends the address it received the request from, so the
+    // entries are read from the right and the first untrusted one is the
+    // client
+    let mut client = peer;
+    for value in req.headers().get_all(FORWARDED_FOR).rev() {
+        let Ok(value) = value.to_str() else {
+            break;
+        };
+        for entry in value.rsplit(',') {
+            let Ok(ip) = entry.trim().parse::<IpAddr>() else {
+                return Some(client.to_string());
+            };
+            client = ip;
+            if !trusted(&ip) {
+                return Some(ip.to_string());
+            }
+        }
+    }
+    Some(client.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn request(peer: &str, forwarded_for: Option<&str>) -> HttpRequest {
+        let mut req = TestRequest::default().peer_addr(format!("{}:4000", peer).parse().unwrap());
+        if let Some(value) = forwarded_for {
+            req = req.insert_header((FORWARDED_FOR, value));
+        }
+        req.to_http_request()
+    }
+
+    #[test]
+    fn forwarded_for_needs_a_trusted_peer() {
+        let trusted: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+
+        // A direct client cannot claim another address
+        let req = request("203.0.113.7", Some("198.51.100.1"));
+        assert_eq!(client_ip(&req, &trusted).as_deref(), Some("203.0.113.7"));
+        assert_eq!(client_ip(&req, &[]).as_deref(), Some("203.0.113.7"));
+
+        // Behind the proxy, the entry it appended is the client; anything the
+        // client put before it is ignored
+        let req = request("10.0.0.2", Some("198.51.100.1, 203.0.113.7"));
+        assert_eq!(client_ip(&req, &trusted).as_deref(), Some("203.0.113.7"));
+
+        // Chained trusted proxies are skipped
+        let req = request("10.0.0.2", Some("203.0.113.7, 10.0.0.3"));
+        assert_eq!(client_ip(&req, &trusted).as_deref(), Some("203.0.113.7"));
+
+        let req = request("10.0.0.2", None);
+        assert_eq!(client_ip(&req, &trusted).as_deref(), Some("10.0.0.2"));
+    }
+}
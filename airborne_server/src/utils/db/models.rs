@@ -4,8 +4,12 @@ use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::utils::db::schema::hyperotaserver::{
-    authz_memberships, authz_role_bindings, builds, cleanup_outbox, configs, files, packages,
-    packages_v2, release_views, releases, user_credentials, workspace_names,
+    analytics_ingest_keys, auth_session_tokens, auth_sessions, authz_cedar_policies,
+    authz_memberships, authz_role_bindings, builds, cleanup_outbox, configs, files,
+    issued_access_tokens, package_size_budgets, packages, packages_v2, release_views, releases,
+    scim_group_members, scim_group_roles, scim_groups, scim_tokens, scim_users,
+    service_account_keys, service_account_tokens, service_accounts, temporary_role_grants,
+    upload_validation_configs, user_credentials, workspace_names,
 };
 use crate::utils::semver::SemVer;
 
@@ -101,6 +105,11 @@ pub struct FileEntry {
     #[diesel(sql_type = diesel::sql_types::Jsonb)]
     pub metadata: serde_json::Value,
     pub created_at: DateTime<Utc>,
+    #[diesel(sql_type = diesel::sql_types::Jsonb)]
+    #[serde(default)]
+    pub variants: serde_json::Value,
+    #[serde(default)]
+    pub failure_reason: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -144,6 +153,27 @@ pub struct NewPackageV2Entry {
     pub files: Vec<Option<String>>,
 }
 
+#[derive(Queryable, Insertable, AsChangeset, Debug, Selectable, Clone)]
+#[diesel(table_name = package_size_budgets)]
+#[diesel(treat_none_as_null = true)]
+pub struct PackageSizeBudgetEntry {
+    pub org_id: String,
+    pub app_id: String,
+    pub max_important_bytes: Option<i64>,
+    pub max_lazy_bytes: Option<i64>,
+    pub max_file_bytes: Option<i64>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable, AsChangeset, Debug, Selectable, Clone)]
+#[diesel(table_name = upload_validation_configs)]
+pub struct UploadValidationConfigEntry {
+    pub org_id: String,
+    pub app_id: String,
+    pub config: serde_json::Value,
+    pub updated_at: DateTime<Utc>,
+}
+
 #[derive(Queryable, Insertable, Debug, Selectable)]
 #[diesel(table_name = release_views)]
 pub struct ReleaseViewEntry {
@@ -192,6 +222,32 @@ pub struct UserCredentialsEntry {
     pub organisation: String,
     pub application: String,
     pub created_at: DateTime<Utc>,
+    pub permissions: Option<Vec<String>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub allowed_ips: Vec<String>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub last_used_ip: Option<String>,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable)]
+#[diesel(table_name = issued_access_tokens)]
+pub struct IssuedAccessTokenEntry {
+    pub token_hash: String,
+    pub client_id: uuid::Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable, Clone)]
+#[diesel(table_name = authz_cedar_policies)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AuthzCedarPolicyEntry {
+    pub id: String,
+    pub policy: String,
+    pub description: Option<String>,
+    pub enabled: bool,
+    pub updated_by: Option<String>,
+    pub updated_at: DateTime<Utc>,
 }
 
 #[derive(Queryable, Insertable, Debug, Selectable, Clone)]
@@ -237,3 +293,154 @@ pub struct NewAuthzRoleBindingEntry {
     pub resource: String,
     pub action: String,
 }
+
+#[derive(Queryable, Insertable, Debug, Selectable, Clone)]
+#[diesel(table_name = service_accounts)]
+pub struct ServiceAccountEntry {
+    pub id: uuid::Uuid,
+    pub organisation: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable, Clone)]
+#[diesel(table_name = service_account_keys)]
+pub struct ServiceAccountKeyEntry {
+    pub id: uuid::Uuid,
+    pub service_account_id: uuid::Uuid,
+    pub secret_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable)]
+#[diesel(table_name = service_account_tokens)]
+pub struct ServiceAccountTokenEntry {
+    pub token_hash: String,
+    pub service_account_id: uuid::Uuid,
+    pub key_id: uuid::Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable, Clone)]
+#[diesel(table_name = auth_sessions)]
+pub struct AuthSessionEntry {
+    pub id: uuid::Uuid,
+    pub subject: String,
+    pub created_at: DateTime<Utc>,
+    pub last_refreshed_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub revoked_by: Option<String>,
+    pub revoke_reason: Option<String>,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable, Clone)]
+#[diesel(table_name = auth_session_tokens)]
+pub struct AuthSessionTokenEntry {
+    pub token_hash: String,
+    pub session_id: uuid::Uuid,
+    pub kind: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub rotated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable, Clone)]
+#[diesel(table_name = scim_tokens)]
+pub struct ScimTokenEntry {
+    pub id: uuid::Uuid,
+    pub organisation: String,
+    pub token_hash: String,
+    pub description: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Queryable, Insertable, AsChangeset, Debug, Selectable, Clone)]
+#[diesel(table_name = scim_users)]
+pub struct ScimUserEntry {
+    pub id: uuid::Uuid,
+    pub organisation: String,
+    pub user_name: String,
+    pub external_id: Option<String>,
+    pub subject: String,
+    pub active: bool,
+    pub resource: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable, AsChangeset, Debug, Selectable, Clone)]
+#[diesel(table_name = scim_groups)]
+pub struct ScimGroupEntry {
+    pub id: uuid::Uuid,
+    pub organisation: String,
+    pub display_name: String,
+    pub external_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable, Clone)]
+#[diesel(table_name = scim_group_members)]
+pub struct ScimGroupMemberEntry {
+    pub group_id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable, Clone)]
+#[diesel(table_name = scim_group_roles)]
+pub struct ScimGroupRoleEntry {
+    pub id: uuid::Uuid,
+    pub organisation: String,
+    pub group_name: String,
+    pub application: Option<String>,
+    pub role: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable, Clone)]
+#[diesel(table_name = temporary_role_grants)]
+pub struct TemporaryRoleGrantEntry {
+    pub id: uuid::Uuid,
+    pub subject: String,
+    pub organisation: String,
+    pub application: Option<String>,
+    pub role: String,
+    pub previous_role: Option<String>,
+    pub reason: String,
+    pub duration_secs: i64,
+    pub status: String,
+    pub requested_by: String,
+    pub requested_at: DateTime<Utc>,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable, Clone)]
+#[diesel(table_name = analytics_ingest_keys)]
+pub struct AnalyticsIngestKeyEntry {
+    pub id: uuid::Uuid,
+    pub organisation: String,
+    pub application: String,
+    pub key_hash: String,
+    pub encrypted_signing_secret: Option<String>,
+    pub require_signature: bool,
+    pub rate_limit_per_minute: Option<i32>,
+    pub description: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
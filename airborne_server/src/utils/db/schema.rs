@@ -11,6 +11,23 @@ pub mod hyperotaserver {
         pub struct InviteStatus;
     }
 
+    diesel::table! {
+        hyperotaserver.analytics_ingest_keys (id) {
+            id -> Uuid,
+            organisation -> Text,
+            application -> Text,
+            key_hash -> Text,
+            encrypted_signing_secret -> Nullable<Text>,
+            require_signature -> Bool,
+            rate_limit_per_minute -> Nullable<Int4>,
+            description -> Nullable<Text>,
+            created_by -> Text,
+            created_at -> Timestamptz,
+            last_used_at -> Nullable<Timestamptz>,
+            revoked_at -> Nullable<Timestamptz>,
+        }
+    }
+
     diesel::table! {
         hyperotaserver.builds (id) {
             id -> Uuid,
@@ -26,6 +43,43 @@ pub mod hyperotaserver {
         }
     }
 
+    diesel::table! {
+        hyperotaserver.auth_sessions (id) {
+            id -> Uuid,
+            subject -> Text,
+            created_at -> Timestamptz,
+            last_refreshed_at -> Nullable<Timestamptz>,
+            expires_at -> Timestamptz,
+            revoked_at -> Nullable<Timestamptz>,
+            revoked_by -> Nullable<Text>,
+            revoke_reason -> Nullable<Text>,
+            client_ip -> Nullable<Text>,
+            user_agent -> Nullable<Text>,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.auth_session_tokens (token_hash) {
+            token_hash -> Text,
+            session_id -> Uuid,
+            kind -> Text,
+            created_at -> Timestamptz,
+            expires_at -> Timestamptz,
+            rotated_at -> Nullable<Timestamptz>,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.authz_cedar_policies (id) {
+            id -> Text,
+            policy -> Text,
+            description -> Nullable<Text>,
+            enabled -> Bool,
+            updated_by -> Nullable<Text>,
+            updated_at -> Timestamptz,
+        }
+    }
+
     diesel::table! {
         hyperotaserver.authz_memberships (subject, scope, organisation, application) {
             subject -> Text,
@@ -75,6 +129,15 @@ pub mod hyperotaserver {
         }
     }
 
+    diesel::table! {
+        hyperotaserver.issued_access_tokens (token_hash) {
+            token_hash -> Text,
+            client_id -> Uuid,
+            expires_at -> Timestamptz,
+            created_at -> Timestamptz,
+        }
+    }
+
     diesel::table! {
         hyperotaserver.files (id) {
             id -> Uuid,
@@ -88,6 +151,8 @@ pub mod hyperotaserver {
             checksum -> Text,
             metadata -> Jsonb,
             created_at -> Timestamptz,
+            variants -> Jsonb,
+            failure_reason -> Nullable<Text>,
         }
     }
 
@@ -122,6 +187,17 @@ pub mod hyperotaserver {
         }
     }
 
+    diesel::table! {
+        hyperotaserver.package_size_budgets (org_id, app_id) {
+            org_id -> Text,
+            app_id -> Text,
+            max_important_bytes -> Nullable<Int8>,
+            max_lazy_bytes -> Nullable<Int8>,
+            max_file_bytes -> Nullable<Int8>,
+            updated_at -> Timestamptz,
+        }
+    }
+
     diesel::table! {
         hyperotaserver.packages_v2 (id) {
             id -> Uuid,
@@ -159,6 +235,123 @@ pub mod hyperotaserver {
         }
     }
 
+    diesel::table! {
+        hyperotaserver.scim_tokens (id) {
+            id -> Uuid,
+            organisation -> Text,
+            token_hash -> Text,
+            description -> Nullable<Text>,
+            created_by -> Text,
+            created_at -> Timestamptz,
+            last_used_at -> Nullable<Timestamptz>,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.scim_users (id) {
+            id -> Uuid,
+            organisation -> Text,
+            user_name -> Text,
+            external_id -> Nullable<Text>,
+            subject -> Text,
+            active -> Bool,
+            resource -> Jsonb,
+            created_at -> Timestamptz,
+            updated_at -> Timestamptz,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.scim_groups (id) {
+            id -> Uuid,
+            organisation -> Text,
+            display_name -> Text,
+            external_id -> Nullable<Text>,
+            created_at -> Timestamptz,
+            updated_at -> Timestamptz,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.scim_group_members (group_id, user_id) {
+            group_id -> Uuid,
+            user_id -> Uuid,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.scim_group_roles (id) {
+            id -> Uuid,
+            organisation -> Text,
+            group_name -> Text,
+            application -> Nullable<Text>,
+            role -> Text,
+            created_by -> Text,
+            created_at -> Timestamptz,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.service_accounts (id) {
+            id -> Uuid,
+            organisation -> Text,
+            name -> Text,
+            description -> Nullable<Text>,
+            created_by -> Text,
+            created_at -> Timestamptz,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.service_account_keys (id) {
+            id -> Uuid,
+            service_account_id -> Uuid,
+            secret_hash -> Text,
+            created_at -> Timestamptz,
+            expires_at -> Nullable<Timestamptz>,
+            last_used_at -> Nullable<Timestamptz>,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.service_account_tokens (token_hash) {
+            token_hash -> Text,
+            service_account_id -> Uuid,
+            key_id -> Uuid,
+            expires_at -> Timestamptz,
+            created_at -> Timestamptz,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.temporary_role_grants (id) {
+            id -> Uuid,
+            subject -> Text,
+            organisation -> Text,
+            application -> Nullable<Text>,
+            role -> Text,
+            previous_role -> Nullable<Text>,
+            reason -> Text,
+            duration_secs -> Int8,
+            status -> Text,
+            requested_by -> Text,
+            requested_at -> Timestamptz,
+            decided_by -> Nullable<Text>,
+            decided_at -> Nullable<Timestamptz>,
+            expires_at -> Nullable<Timestamptz>,
+            ended_at -> Nullable<Timestamptz>,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.upload_validation_configs (org_id, app_id) {
+            org_id -> Text,
+            app_id -> Text,
+            config -> Jsonb,
+            updated_at -> Timestamptz,
+        }
+    }
+
     diesel::table! {
         hyperotaserver.user_credentials (client_id) {
             client_id -> Uuid,
@@ -167,6 +360,11 @@ pub mod hyperotaserver {
             organisation -> Text,
             application -> Text,
             created_at -> Timestamptz,
+            permissions -> Nullable<Array<Text>>,
+            expires_at -> Nullable<Timestamptz>,
+            allowed_ips -> Array<Text>,
+            last_used_at -> Nullable<Timestamptz>,
+            last_used_ip -> Nullable<Text>,
         }
     }
 
@@ -179,18 +377,41 @@ pub mod hyperotaserver {
         }
     }
 
+    diesel::joinable!(auth_session_tokens -> auth_sessions (session_id));
+    diesel::joinable!(issued_access_tokens -> user_credentials (client_id));
+    diesel::joinable!(scim_group_members -> scim_groups (group_id));
+    diesel::joinable!(scim_group_members -> scim_users (user_id));
+    diesel::joinable!(service_account_keys -> service_accounts (service_account_id));
+    diesel::joinable!(service_account_tokens -> service_accounts (service_account_id));
+
     diesel::allow_tables_to_appear_in_same_query!(
+        analytics_ingest_keys,
+        auth_session_tokens,
+        auth_sessions,
+        authz_cedar_policies,
         authz_memberships,
         authz_role_bindings,
         cleanup_outbox,
         builds,
         configs,
         files,
+        issued_access_tokens,
         organisation_invites,
+        package_size_budgets,
         packages,
         packages_v2,
         release_views,
         releases,
+        scim_group_members,
+        scim_group_roles,
+        scim_groups,
+        scim_tokens,
+        scim_users,
+        service_account_keys,
+        service_account_tokens,
+        service_accounts,
+        temporary_role_grants,
+        upload_validation_configs,
         user_credentials,
         workspace_names,
     );
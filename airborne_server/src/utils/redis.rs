@@ -185,6 +185,38 @@ impl RedisCache {
         Ok(())
     }
 
+    /// SET with TTL (seconds) only when the key does not exist yet (`NX`).
+    /// Returns whether the value was stored.
+    pub async fn set_nx_ex<T: Serialize>(
+        &self,
+        redis_key: &RedisKey,
+        value: &T,
+        ttl_secs: usize,
+    ) -> Result<bool, ABError> {
+        let mut r = (*self.conn).clone();
+        let key = redis_key.key.clone();
+        let payload = serde_json::to_vec(value).map_err(|e| {
+            error!("Failed to encode cache {key}: {e}");
+            CACHE_FAILS.with_label_values(&redis_key.labels).inc();
+            ABError::InternalServerError("service error".to_string())
+        })?;
+
+        let reply: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(payload)
+            .arg("EX")
+            .arg(ttl_secs)
+            .arg("NX")
+            .query_async(&mut r)
+            .await
+            .map_err(|e| {
+                error!("Failed to SET {key}: {e}");
+                CACHE_FAILS.with_label_values(&redis_key.labels).inc();
+                ABError::InternalServerError("service error".to_string())
+            })?;
+        Ok(reply.is_some())
+    }
+
     #[allow(unused)]
     pub async fn del(&self, redis_key: &RedisKey) -> Result<(), ABError> {
         let mut r = (*self.conn).clone();
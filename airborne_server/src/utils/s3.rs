@@ -10,10 +10,10 @@ use crate::types::ABError;
 pub async fn push_file_byte_arr(
     s3_client: &Client,
     bucket_name: String,
-    byte_arr: Vec<u8>,
+    byte_arr: impl Into<bytes::Bytes>,
     filename: String,
 ) -> airborne_types::Result<PutObjectOutput> {
-    let byte_stream = ByteStream::from(byte_arr);
+    let byte_stream = ByteStream::from(byte_arr.into());
 
     s3_client
         .put_object()